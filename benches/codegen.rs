@@ -0,0 +1,72 @@
+// Benchmarks of each phase on documents in `examples/` and on a large
+// document made by repeating a section, so that a slowdown in the lexer, the
+// parser or the code generation shows up. Run them with `cargo bench`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use vesti::parser::maker::{emit, latex_to_string};
+use vesti::{Arena, Lexer, Parser};
+
+const SECTION: &str = r#"\section{Results}
+The sum $\sum_{k=1}^n k^2$ is computed with #(x + y#) and $α → β$.
+begenv center
+    begenv minipage{0.7\textwidth}
+        Use like this! \textbf{bold} and \emph{emphasis}.
+    endenv
+endenv
+- first item with $x_i$
+- second item with 3.5cm
+#- raw \LaTeX -#
+
+"#;
+
+fn documents() -> Vec<(&'static str, String)> {
+    let mut large = String::from("docclass article\nimport amsmath\ndocument\n");
+    for _ in 0..500 {
+        large += SECTION;
+    }
+    vec![
+        (
+            "basicdoc",
+            include_str!("../examples/basicdoc.ves").to_string(),
+        ),
+        (
+            "ch1-example",
+            include_str!("../examples/ch1-example.ves").to_string(),
+        ),
+        ("large", large),
+    ]
+}
+
+fn bench_phases(c: &mut Criterion) {
+    for (name, source) in documents() {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+
+        group.bench_function("lex", |b| b.iter(|| Lexer::new(black_box(&source)).count()));
+        group.bench_function("parse", |b| {
+            b.iter(|| {
+                let arena = Arena::new();
+                Parser::new(Lexer::new(black_box(&source)), &arena)
+                    .parse_latex()
+                    .is_ok()
+            })
+        });
+
+        let arena = Arena::new();
+        let latex = Parser::new(Lexer::new(&source), &arena)
+            .parse_latex()
+            .unwrap();
+        group.bench_function("codegen", |b| b.iter(|| latex_to_string(black_box(&latex))));
+        group.bench_function("emit", |b| {
+            let mut output = Vec::with_capacity(source.len() * 2);
+            b.iter(|| {
+                output.clear();
+                emit(black_box(&latex), &mut output)
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_phases);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+// Cache of expensive blocks which is stored in `.vesti/cache` next to the
+// source. Entries are keyed by a hash of the contents of a block, so that a
+// changed block simply misses the cache. Failing to read or write the cache
+// never fails the compilation, since every entry can be made again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR: &str = ".vesti/cache";
+
+#[derive(Clone, Debug)]
+pub struct BlockCache {
+    // `None` means that nothing is read nor written
+    dir: Option<PathBuf>,
+    // Entries are made again, but the new ones are still stored
+    refresh: bool,
+}
+
+impl BlockCache {
+    pub fn new(source_dir: &Path, refresh: bool) -> Self {
+        Self {
+            dir: Some(source_dir.join(CACHE_DIR)),
+            refresh,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            dir: None,
+            refresh: false,
+        }
+    }
+
+    // `kind` separates blocks which have the same contents but different outputs
+    pub fn key(kind: &str, contents: &str) -> String {
+        let mut hasher = DefaultHasher::new();
+        kind.hash(&mut hasher);
+        contents.hash(&mut hasher);
+        format!("{}-{:016x}", kind, hasher.finish())
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        if self.refresh {
+            return None;
+        }
+        fs::read(self.dir.as_ref()?.join(key)).ok()
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join(key), data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_block_cache() {
+        let dir = env::temp_dir().join(format!("vesti-cache-test-{}", std::process::id()));
+        let cache = BlockCache::new(&dir, false);
+        let key = BlockCache::key("embed", "dmVzdGk=");
+        assert_ne!(key, BlockCache::key("other", "dmVzdGk="));
+        assert_eq!(cache.get(&key), None);
+
+        cache.put(&key, b"vesti");
+        assert_eq!(cache.get(&key).as_deref(), Some(&b"vesti"[..]));
+        assert_eq!(BlockCache::new(&dir, true).get(&key), None);
+        assert_eq!(BlockCache::disabled().get(&key), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,196 @@
+// Chapters imported by `chapter import "chap1.ves"` are parsed as parts of the
+// body of the main document and made into their own latex files, which the
+// main file includes by `\include{chap1}`. Packages which chapters need are
+// imported by the main file. `--only chap1` adds `\includeonly{chap1}`, so the
+// engine skips other chapters but keeps their page numbers from `.aux` files.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind, VestiParseErr};
+use crate::error::warning::VestiWarning;
+use crate::error::{self, VestiErr};
+use crate::exit::CompileFailure;
+use crate::lexer::Lexer;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_spanned, Latex, Statement};
+use crate::parser::{DocumentContext, Parser};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct Chapter<'a> {
+    // name given to `\include`
+    pub name: &'a str,
+    pub file_name: PathBuf,
+    pub source: &'a str,
+    pub latex: Latex<'a>,
+    pub warnings: Vec<VestiWarning>,
+}
+
+// Chapters in the order they are imported. Paths are relative to the main file.
+pub fn chapter_imports<'a>(latex: &Latex<'a>) -> Vec<(&'a str, &'a str)> {
+    let mut chapters = Vec::new();
+    walk_spanned(latex, &mut |stmt| {
+        if let Statement::ChapterImport { name, path } = stmt.node {
+            chapters.push((name, path));
+        }
+    });
+    chapters
+}
+
+// Parse every chapter of the main document whose packages and document class
+// are in `context`. Packages which chapters need but the main file does not
+// import are returned with them. An error is a pretty printed one.
+pub fn parse_chapters<'a>(
+    latex: &Latex<'a>,
+    source_dir: &Path,
+    arena: &'a Arena,
+    context: &DocumentContext,
+    configure: &dyn Fn(&mut Parser),
+) -> Result<(Vec<Chapter<'a>>, BTreeSet<String>), CompileFailure> {
+    let mut chapters = Vec::new();
+    let mut missing = BTreeSet::new();
+    for (name, path) in chapter_imports(latex) {
+        let file_name = source_dir.join(path);
+        let source = fs::read_to_string(&file_name)
+            .map_err(|err| CompileFailure::new(None, err.into(), Some(&file_name)))?;
+        let source = arena.alloc_str(&source);
+
+        let mut parser = Parser::new(Lexer::new(source), arena);
+        configure(&mut parser);
+        parser.set_document_context(context);
+        let chapter = parser
+            .parse_body()
+            .map_err(|err| CompileFailure::new(Some(source), err, Some(&file_name)))?;
+        let mut nested = None;
+        walk_spanned(&chapter, &mut |stmt| {
+            if let Statement::ChapterImport { .. } = stmt.node {
+                nested = nested.or(Some(stmt.span));
+            }
+        });
+        if let Some(span) = nested {
+            let err = VestiErr::make_parse_err(VestiParseErr::NestedChapterErr, Some(span));
+            return Err(CompileFailure::new(Some(source), err, Some(&file_name)));
+        }
+
+        missing.extend(parser.missing_packages(&chapter));
+        chapters.push(Chapter {
+            name,
+            file_name,
+            source,
+            warnings: parser.take_warnings(),
+            latex: chapter,
+        });
+    }
+    Ok((chapters, missing))
+}
+
+// Import packages right before `document` unless the preamble already does
+pub fn import_packages<'a>(latex: &mut Latex<'a>, packages: &BTreeSet<String>, arena: &'a Arena) {
+    let doc_start = match latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart)
+    {
+        Some(doc_start) => doc_start,
+        None => return,
+    };
+    let mut imported = Vec::new();
+    walk_spanned(&latex[..doc_start], &mut |stmt| {
+        if let Statement::Usepackage { name, .. } = stmt.node {
+            imported.push(name);
+        }
+    });
+    let pkgs: Latex = packages
+        .iter()
+        .filter(|name| !imported.contains(&name.as_str()))
+        .map(|name| {
+            Statement::Usepackage {
+                name: arena.alloc_str(name),
+                options: None,
+            }
+            .into()
+        })
+        .collect();
+    if !pkgs.is_empty() {
+        latex.insert(doc_start, Statement::MultiUsepackages { pkgs }.into());
+    }
+}
+
+// `\includeonly` before `document` with chapters given by `--only`
+pub fn include_only<'a>(
+    latex: &mut Latex<'a>,
+    only: &[String],
+    arena: &'a Arena,
+) -> error::Result<()> {
+    let chapters = chapter_imports(latex);
+    if let Some(name) = only
+        .iter()
+        .find(|name| !chapters.iter().any(|(chapter, _)| chapter == name))
+    {
+        return Err(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ChapterNotFoundErr(name.clone())),
+            location: None,
+        });
+    }
+    if let Some(doc_start) = latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart)
+    {
+        let raw = format!("\\includeonly{{{}}}\n", only.join(","));
+        latex.insert(doc_start, Statement::RawLatex(arena.alloc_str(&raw)).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::maker::latex_to_string;
+    use std::env;
+
+    #[test]
+    fn test_chapters() {
+        let dir = env::temp_dir().join(format!("vesti-chapters-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("chapters")).unwrap();
+        fs::write(
+            dir.join("chapters/intro.ves"),
+            "\\chapter{Intro}\n\\includegraphics{cat.png}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("chapters/end.ves"), "\\chapter{End}\n").unwrap();
+        let source = "docclass book\ndocument\nchapter import \"chapters/intro.ves\"\n\
+                      chapter import \"chapters/end.ves\"\n";
+
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let mut latex = parser.parse_latex().unwrap();
+        let (chapters, missing) =
+            parse_chapters(&latex, &dir, &arena, &parser.document_context(), &|_| {}).unwrap();
+        assert_eq!(
+            chapters.iter().map(|c| c.name).collect::<Vec<_>>(),
+            vec!["chapters/intro", "chapters/end"]
+        );
+        assert_eq!(latex_to_string(&chapters[1].latex), "\\chapter{End}\n");
+
+        import_packages(&mut latex, &missing, &arena);
+        include_only(&mut latex, &[String::from("chapters/end")], &arena).unwrap();
+        assert_eq!(
+            latex_to_string(&latex),
+            "\\documentclass{book}\n\\usepackage{graphicx}\n\\includeonly{chapters/end}\n\
+             \\begin{document}\n\\include{chapters/intro}\n\\include{chapters/end}\n\n\
+             \\end{document}\n"
+        );
+        assert!(include_only(&mut latex, &[String::from("intro")], &arena).is_err());
+
+        fs::write(
+            dir.join("chapters/end.ves"),
+            "\\chapter{End}\nchapter import \"chapters/intro.ves\"\n",
+        )
+        .unwrap();
+        let err = parse_chapters(&latex, &dir, &arena, &parser.document_context(), &|_| {})
+            .err()
+            .unwrap();
+        assert!(err.msg.contains("Chapters cannot import other chapters"));
+        assert_eq!(err.code, crate::exit::ExitCode::ParseError);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
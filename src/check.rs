@@ -0,0 +1,41 @@
+// Checks which need the file system, so that they cannot be done in the parser.
+
+use crate::error::err_kind::VestiParseErr;
+use crate::error::{self, VestiErr};
+use crate::parser::ast::{walk_latex, Latex, Statement};
+use std::path::Path;
+
+// Extensions that `\includegraphics` tries when the path has no extension
+const IMAGE_EXTENSIONS: [&str; 6] = ["pdf", "png", "jpg", "jpeg", "eps", "svg"];
+
+pub fn check_image_files(latex: &Latex, base_dir: &Path) -> error::Result<()> {
+    let mut output = Ok(());
+    walk_latex(latex, &mut |stmt| {
+        if output.is_err() {
+            return;
+        }
+        if let Statement::Figure {
+            path, path_span, ..
+        } = stmt
+        {
+            if !image_exists(&base_dir.join(path)) {
+                output = Err(VestiErr::make_parse_err(
+                    VestiParseErr::ImageFileNotFoundErr {
+                        path: path.to_string(),
+                    },
+                    *path_span,
+                ));
+            }
+        }
+    });
+
+    output
+}
+
+fn image_exists(path: &Path) -> bool {
+    path.is_file()
+        || (path.extension().is_none()
+            && IMAGE_EXTENSIONS
+                .iter()
+                .any(|ext| path.with_extension(ext).is_file()))
+}
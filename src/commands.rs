@@ -1,21 +1,78 @@
+use crate::cache::BlockCache;
+use crate::chapters::{self, Chapter};
+use crate::check;
+use crate::config::VestiConfig;
+use crate::console::{self, Verbosity};
+use crate::deps::{self, DepGraph, DepsFormat};
+use crate::directives::FileOverrides;
+use crate::docgen::{self, DocFormat};
+use crate::embed;
+use crate::engine::{self, EnginePlan, InteractionMode, LatexEngineType};
 use crate::error;
 use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
-use crate::error::pretty_print::pretty_print;
+use crate::error::pretty_print::{
+    pretty_print, pretty_print_denied, pretty_print_warning, ColorChoice,
+};
+use crate::error::warning::{VestiWarnKind, VestiWarning, LINT_NAMES};
+use crate::execute;
+use crate::exit::{CompileFailure, ExitCode};
+use crate::expand;
+use crate::export::{self, ExportConfig, ExportFormat};
+use crate::fix;
+use crate::importtex;
+use crate::incremental::IncrementalDoc;
+use crate::initialization::{self, TemplateVars};
 use crate::lexer::Lexer;
-use crate::parser::Parser;
+use crate::lint::{self, LintLevel};
+use crate::metadata::Metadata;
+use crate::modules;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_latex, Latex, Statement};
+use crate::parser::context::{emit_context, latex_to_context};
+use crate::parser::maker::{emit, latex_to_string};
+use crate::parser::markdown::latex_to_markdown;
+use crate::parser::{
+    configure_parser, has_index_entries, insert_index_commands, Definitions, DocumentContext,
+    Parser,
+};
+use crate::pool::{self, WorkerPool};
+use crate::render;
+use crate::report::{BatchReport, FileReport, FileStatus};
+use crate::shutdown;
+use crate::standalone;
+use crate::stats::Stats;
+use crate::symbols;
+use crate::synctex;
+use crate::texdist;
+use crate::timing::{TimingFormat, Timings};
+use crate::typography;
+use crate::watch::WatchOption;
+use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::thread;
-use std::time::{Duration, SystemTime};
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use structopt::clap::{App, Arg, ArgMatches};
 use structopt::StructOpt;
 
+// Latex warnings printed for each file. The others are found in the log.
+const MAX_LATEX_WARNINGS: usize = 10;
+
+// The file name of the source which is read from the standard input
+pub const STDIN_LABEL: &str = "<stdin>";
+
 macro_rules! unwrap_err {
     ($name: ident := $to_unwrap: expr, $source: expr, $file_name: expr) => {
         let $name = match $to_unwrap {
             Ok(inner) => inner,
             Err(err) => {
+                let code = ExitCode::of(&err);
                 println!("{}", pretty_print($source, err, $file_name));
-                std::process::exit(1);
+                code.exit();
             }
         };
     };
@@ -23,8 +80,9 @@ macro_rules! unwrap_err {
         let mut $name = match $to_unwrap {
             Ok(inner) => inner,
             Err(err) => {
+                let code = ExitCode::of(&err);
                 println!("{}", pretty_print($source, err, $file_name));
-                std::process::exit(1);
+                code.exit();
             }
         };
     };
@@ -32,8 +90,9 @@ macro_rules! unwrap_err {
         $name = match $to_unwrap {
             Ok(inner) => inner,
             Err(err) => {
+                let code = ExitCode::of(&err);
                 println!("{}", pretty_print($source, err, $file_name));
-                std::process::exit(1);
+                code.exit();
             }
         };
     };
@@ -41,8 +100,19 @@ macro_rules! unwrap_err {
 
 #[derive(StructOpt)]
 pub enum VestiOpt {
-    /// TODO(#1): In the alpha version, this does nothing at all.
-    Init,
+    /// Make a new vesti project.
+    Init {
+        /// Template of the project: article, beamer, book, letter, cv, standalone or the name of
+        /// a file in `~/.config/vesti/templates/`. Without this, only a bare `source.ves` is made.
+        #[structopt(short, long)]
+        template: Option<String>,
+        /// Author of the project used in templates.
+        #[structopt(long)]
+        author: Option<String>,
+        /// Directory where the project is made.
+        #[structopt(name = "DIR", parse(from_os_str), default_value = ".")]
+        project_dir: PathBuf,
+    },
     Run {
         /// Compile vesti continuously.
         #[structopt(short, long)]
@@ -50,31 +120,650 @@ pub enum VestiOpt {
         /// If this flag is on, then vesti compiles all vesti files in that directory.
         #[structopt(long)]
         all: bool,
+        #[structopt(flatten)]
+        flags: CompileFlags,
+        /// Print the latex file and the engine commands without running anything.
+        #[structopt(long)]
+        dry_run: bool,
+        /// Open the first error location in `$VISUAL` or `$EDITOR` if the engine fails.
+        #[structopt(long)]
+        open_error: bool,
+        /// Print milliseconds spent in each phase of the compilation of each file.
+        #[structopt(long)]
+        timing: bool,
+        /// Print the time spent in each phase as JSON. One line is printed for each file.
+        #[structopt(long)]
+        timing_json: bool,
+        /// Crop the pdf to its contents with `pdfcrop`. This implies `--pdf`.
+        #[structopt(long)]
+        crop: bool,
+        /// Make a png image of the first page with `pdftoppm`. This implies `--pdf`.
+        #[structopt(long)]
+        png: bool,
+        /// Convert the first page into an image: png or svg. This implies `--pdf`.
+        /// This overrides `[export]` in `vesti.toml`.
+        #[structopt(long)]
+        export: Option<ExportFormat>,
+        /// Resolution of the png image made by `--export png`. The default is 300.
+        #[structopt(long, requires = "export")]
+        dpi: Option<u32>,
+        /// Output of the files given before this: tex or pdf. This can be used several
+        /// times like `a.ves --emit tex main.ves --emit pdf`. Other files follow `--pdf`.
+        #[structopt(long, number_of_values = 1)]
+        emit: Vec<EmitKind>,
+        /// Compile a fragment without `docclass`, like an equation or a TikZ picture,
+        /// in the minimal preamble of `[standalone]` in `vesti.toml`.
+        #[structopt(long)]
+        standalone: bool,
+        /// Write the summary of the compiled files as JSON: file, status, seconds,
+        /// warnings and output of each file.
+        #[structopt(long)]
+        report: Option<PathBuf>,
+        /// Do not start other files after this many files are failed.
+        #[structopt(long)]
+        max_errors: Option<usize>,
         /// Input file names or directory name.
-        /// Directory name must type once.
+        /// Directory name must type once. Use `-` to read from the standard input.
         #[structopt(name = "FILE", parse(from_os_str))]
         file_name: Vec<PathBuf>,
     },
+    /// Compile vesti files whenever they are changed.
+    Watch {
+        #[structopt(flatten)]
+        flags: CompileFlags,
+        /// Milliseconds to wait after the last change of a file before compiling it.
+        #[structopt(long, default_value = "300")]
+        debounce: u64,
+        /// Shell command run before each batch of compilations.
+        /// Changed files are given by `VESTI_FILES`.
+        #[structopt(long)]
+        pre_build: Option<String>,
+        /// Shell command run after each batch of compilations, like refreshing a pdf viewer.
+        /// `VESTI_STATUS` is either `ok` or `error`.
+        #[structopt(long)]
+        post_build: Option<String>,
+        /// Keep the previous output instead of clearing the terminal before each batch.
+        #[structopt(long)]
+        no_clear: bool,
+        /// Input file names.
+        #[structopt(name = "FILE", parse(from_os_str), required = true)]
+        file_name: Vec<PathBuf>,
+    },
+    /// Compile targets declared in `vesti.toml` into pdf files.
+    Build {
+        /// Print the latex files and the engine commands without running anything.
+        #[structopt(long)]
+        dry_run: bool,
+        /// Open the first error location in `$VISUAL` or `$EDITOR` if the engine fails.
+        #[structopt(long)]
+        open_error: bool,
+        /// Make expensive blocks again instead of using `.vesti/cache`.
+        #[structopt(long)]
+        refresh: bool,
+        /// Warn every raw latex and report how much raw latex remains in each file.
+        #[structopt(long)]
+        strict_raw: bool,
+        /// Do not import packages of well known commands and keywords which are not imported.
+        #[structopt(long)]
+        no_auto_import: bool,
+        /// Compile untrusted files: only files next to the source can be read, and the
+        /// engine runs without shell escape.
+        #[structopt(long)]
+        safe: bool,
+        /// Run `execute(lang)` blocks of the files. This is ignored with `--safe`.
+        #[structopt(long)]
+        allow_exec: bool,
+        /// Kill the engine or an `execute` block if it runs longer than this many seconds.
+        #[structopt(long)]
+        timeout: Option<u64>,
+        /// Print only errors.
+        #[structopt(short, long)]
+        quiet: bool,
+        /// Print outputs of the engine. Use `-vv` to print commands vesti runs too.
+        #[structopt(short, long, parse(from_occurrences))]
+        verbose: u64,
+        /// Print milliseconds spent in each phase of the compilation of each file.
+        #[structopt(long)]
+        timing: bool,
+        /// Print the time spent in each phase as JSON. One line is printed for each file.
+        #[structopt(long)]
+        timing_json: bool,
+        /// Names of targets. If nothing is given, every target is compiled.
+        #[structopt(name = "TARGET")]
+        targets: Vec<String>,
+    },
+    /// Print the latex code of a `defun` or `defenv` definition with the given arguments.
+    ExpandEnv {
+        /// File where the definition is.
+        #[structopt(short, long, parse(from_os_str))]
+        file: PathBuf,
+        /// Contents put inside of the environment.
+        #[structopt(long, default_value = "")]
+        body: String,
+        /// Name of the definition.
+        #[structopt(name = "NAME")]
+        name: String,
+        /// Arguments of the definition.
+        #[structopt(name = "ARGS")]
+        args: Vec<String>,
+    },
+    /// Search math symbols by their names or descriptions.
+    Symbols {
+        /// Words to search like `right arrow`.
+        #[structopt(name = "QUERY", required = true)]
+        query: Vec<String>,
+    },
+    /// Check vesti documents for mistakes like unused definitions or math commands
+    /// outside of math. Levels of lints are set in `[lint]` of `vesti.toml`.
+    Lint {
+        /// Stop after this many errors, which are denied lints and files that cannot be linted.
+        #[structopt(long)]
+        max_errors: Option<usize>,
+        /// Input file names.
+        #[structopt(name = "FILE", parse(from_os_str), required = true)]
+        file_name: Vec<PathBuf>,
+    },
+    /// Fix lints which can be fixed mechanically, like removing unused definitions.
+    /// The original file is kept as `FILE.bak`.
+    Fix {
+        /// Print problems which would be fixed without changing files.
+        #[structopt(long)]
+        dry_run: bool,
+        /// Remove empty environments too, which are only reported without this.
+        #[structopt(long)]
+        remove_empty_envs: bool,
+        /// Input file names.
+        #[structopt(name = "FILE", parse(from_os_str), required = true)]
+        file_name: Vec<PathBuf>,
+    },
+    /// Print files included by `\input` and `\include` from a vesti file, recursively.
+    Deps {
+        /// Output format: tree or dot for Graphviz.
+        #[structopt(long, default_value = "tree")]
+        format: DepsFormat,
+        /// Main vesti file.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+    },
+    /// Compare every vesti file under a directory with its latex snapshot.
+    Test {
+        /// Write generated latex files as new snapshots.
+        #[structopt(long)]
+        bless: bool,
+        /// Show differences with an external tool like `diff -u` instead of the builtin diff.
+        #[structopt(long)]
+        diff_tool: Option<String>,
+        /// Directory containing the tests.
+        #[structopt(name = "DIR", parse(from_os_str), default_value = "tests")]
+        dir: PathBuf,
+    },
+    /// Print statistics of a vesti document like the number of sections and words.
+    Stats {
+        /// Print the statistics as JSON.
+        #[structopt(long)]
+        json: bool,
+        /// Input file name.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+    },
+    /// Print the syntax tree of a vesti document as JSON with spans of statements.
+    Ast {
+        /// Print the JSON in one line.
+        #[structopt(long)]
+        compact: bool,
+        /// Input file name.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+    },
+    /// Render a math expression written in vesti into a png image.
+    RenderMath {
+        /// Math expression like `\\sum_{k=1}^n k^2`.
+        #[structopt(name = "EXPR")]
+        expr: String,
+        /// Path of the png image.
+        #[structopt(long, parse(from_os_str))]
+        png: PathBuf,
+        /// Resolution of the image.
+        #[structopt(long, default_value = "300")]
+        dpi: u32,
+        /// LaTeX engine used to render the math.
+        #[structopt(short, long, default_value = "pdflatex")]
+        engine: LatexEngineType,
+    },
+    /// Find the vesti line of a position in the pdf. This can be used as the editor
+    /// command of pdf viewers, like `vesti synctex-edit --open %{input}:%{line}`.
+    SynctexEdit {
+        /// Either `file.tex:line` or `page:x:y:file.pdf`.
+        #[structopt(name = "LOCATION")]
+        location: String,
+        /// Open the line in `$VISUAL` or `$EDITOR` instead of printing it.
+        #[structopt(long)]
+        open: bool,
+    },
+    /// Find the position in the pdf of a vesti line.
+    SynctexView {
+        /// Vesti file compiled with `--pdf`.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+        /// Line of the vesti file.
+        #[structopt(name = "LINE")]
+        line: usize,
+    },
+    /// Check the TeX installation: find engines and biber, print their versions and
+    /// compile a small document with each engine.
+    Doctor {
+        /// Only find programs and print their versions without compiling.
+        #[structopt(long)]
+        no_compile: bool,
+    },
+    /// Install a minimal TeX distribution which vesti uses if TeX is not found.
+    SetupTex {
+        /// Install it again even if it is already installed.
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Write a reference of macros defined by `defun` and `defenv` in a project, with
+    /// their `///` doc comments.
+    Doc {
+        /// Output format: markdown or html.
+        #[structopt(long, default_value = "markdown")]
+        format: DocFormat,
+        /// File where the reference is written. The reference is printed without this.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Directory of the project.
+        #[structopt(name = "DIR", parse(from_os_str), default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Print the metadata of a vesti document.
+    Meta {
+        /// Print the metadata as JSON.
+        #[structopt(long)]
+        json: bool,
+        /// Input file name.
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+    },
+}
+
+// Options of compiling each file, which `vesti run` and `vesti watch` share
+#[derive(StructOpt)]
+pub struct CompileFlags {
+    /// Define a flag used at `#if` directives. This can be used several times.
+    #[structopt(short = "D", long = "define", number_of_values = 1)]
+    defines: Vec<String>,
+    /// Compile the generated latex file into a pdf file.
+    #[structopt(long)]
+    pdf: bool,
+    /// LaTeX engine used to make a pdf file. This implies `--pdf`.
+    /// If this is not given, the engine is chosen from packages the document uses.
+    #[structopt(short, long)]
+    engine: Option<LatexEngineType>,
+    /// Interaction mode of the engine: batchmode, nonstopmode or errorstopmode.
+    /// This overrides both the keyword in the file and `vesti.toml`.
+    #[structopt(long)]
+    interaction: Option<InteractionMode>,
+    /// Make expensive blocks again instead of using `.vesti/cache`.
+    #[structopt(long)]
+    refresh: bool,
+    /// Warn every raw latex and report how much raw latex remains in each file.
+    #[structopt(long)]
+    strict_raw: bool,
+    /// Do not import packages of well known commands and keywords which are not imported.
+    #[structopt(long)]
+    no_auto_import: bool,
+    /// Read `$...$` and `$$...$$` as math like latex.
+    #[structopt(long)]
+    dollar_math: bool,
+    /// Compile untrusted files: only files next to the source can be read, and the
+    /// engine runs without shell escape.
+    #[structopt(long)]
+    safe: bool,
+    /// Run `execute(lang)` blocks of the files. This is ignored with `--safe`.
+    #[structopt(long)]
+    allow_exec: bool,
+    /// Kill the engine or an `execute` block if it runs longer than this many seconds.
+    #[structopt(long)]
+    timeout: Option<u64>,
+    /// Print only errors.
+    #[structopt(short, long)]
+    quiet: bool,
+    /// Print outputs of the engine. Use `-vv` to print commands vesti runs too.
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u64,
+    /// Compile only this chapter of `chapter import` with `\includeonly`. Other
+    /// chapters keep their page numbers from the last build. This can be used several times.
+    #[structopt(long, number_of_values = 1)]
+    only: Vec<String>,
+    /// Output format: latex, context or markdown. ConTeXt files are compiled by `context`,
+    /// and markdown files are written next to the sources as `.md` without pdf files.
+    #[structopt(long, default_value = "latex")]
+    target: OutputTarget,
+    /// The number of files compiled at once. The default is the number of cpus.
+    #[structopt(short, long)]
+    jobs: Option<usize>,
+}
+
+impl CompileFlags {
+    fn compile_option(&self) -> CompileOption {
+        CompileOption {
+            defines: self.defines.clone(),
+            is_pdf: self.target != OutputTarget::Markdown && (self.pdf || self.engine.is_some()),
+            engine: self.engine,
+            interaction: self.interaction,
+            refresh: self.refresh,
+            strict_raw: self.strict_raw,
+            no_auto_import: self.no_auto_import,
+            dollar_math: self.dollar_math,
+            is_safe: self.safe,
+            allow_exec: self.allow_exec,
+            timeout: self.timeout.map(Duration::from_secs),
+            verbosity: Verbosity::from_flags(self.quiet, self.verbose),
+            target: self.target,
+            only: self.only.clone(),
+            ..CompileOption::default()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmitKind {
+    Tex,
+    Pdf,
+}
+
+impl FromStr for EmitKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tex" => Ok(Self::Tex),
+            "pdf" => Ok(Self::Pdf),
+            _ => Err(format!("unknown output `{}`. Use one of tex and pdf", s)),
+        }
+    }
+}
+
+// The format which vesti files are transpiled into
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputTarget {
+    #[default]
+    Latex,
+    Context,
+    Markdown,
+}
+
+impl FromStr for OutputTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latex" => Ok(Self::Latex),
+            "context" => Ok(Self::Context),
+            "markdown" => Ok(Self::Markdown),
+            _ => Err(format!(
+                "unknown target `{}`. Use one of latex, context and markdown",
+                s
+            )),
+        }
+    }
+}
+
+impl OutputTarget {
+    pub fn output_file_name(self, file_name: &Path) -> PathBuf {
+        match self {
+            Self::Latex | Self::Context => output_file_name(file_name),
+            Self::Markdown => file_name.with_extension("md"),
+        }
+    }
+
+    pub fn generate(self, latex: &Latex) -> String {
+        match self {
+            Self::Latex => latex_to_string(latex),
+            Self::Context => latex_to_context(latex),
+            Self::Markdown => latex_to_markdown(latex),
+        }
+    }
+}
+
+// `--emit` applies to the files which are given before it and after the
+// previous `--emit`. `None` means that the file follows `--pdf`.
+fn emits_of_files(matches: &ArgMatches, emit: &[EmitKind]) -> Vec<Option<EmitKind>> {
+    let file_indices: Vec<usize> = matches
+        .indices_of("FILE")
+        .map(|indices| indices.collect())
+        .unwrap_or_default();
+    let emits: Vec<(usize, EmitKind)> = matches
+        .indices_of("emit")
+        .map(|indices| indices.zip(emit.iter().copied()).collect())
+        .unwrap_or_default();
+
+    file_indices
+        .into_iter()
+        .map(|file_idx| {
+            emits
+                .iter()
+                .find(|(emit_idx, _)| *emit_idx > file_idx)
+                .map(|(_, emit)| *emit)
+        })
+        .collect()
+}
+
+// Options of `vesti run` which are used to compile each file
+#[derive(Clone, Default)]
+pub struct CompileOption {
+    pub is_continuous: bool,
+    pub defines: Vec<String>,
+    pub is_pdf: bool,
+    pub engine: Option<LatexEngineType>,
+    pub interaction: Option<InteractionMode>,
+    pub open_error: bool,
+    pub is_dry_run: bool,
+    pub refresh: bool,
+    pub strict_raw: bool,
+    pub no_auto_import: bool,
+    pub dollar_math: bool,
+    pub is_safe: bool,
+    pub allow_exec: bool,
+    pub crop: bool,
+    // conversion of the pdf which overrides the one of `vesti.toml`
+    pub export: Option<ExportConfig>,
+    pub timeout: Option<Duration>,
+    pub verbosity: Verbosity,
+    pub timing: Option<TimingFormat>,
+    pub target: OutputTarget,
+    // chapters compiled by `\includeonly`
+    pub only: Vec<String>,
+    pub standalone: bool,
+}
+
+// The command line parser with options shared by every subcommand
+pub fn app() -> App<'static, 'static> {
+    VestiOpt::clap().arg(
+        Arg::with_name("color")
+            .long("color")
+            .global(true)
+            .takes_value(true)
+            .possible_values(&["auto", "always", "never"])
+            .help("When to color messages: auto, always or never"),
+    )
+}
+
+// `--color` can be given both before and after the subcommand
+pub fn color_choice(matches: &ArgMatches) -> ColorChoice {
+    let subcommand = matches.subcommand().1;
+    matches
+        .value_of("color")
+        .or_else(|| subcommand.and_then(|matches| matches.value_of("color")))
+        .and_then(|choice| choice.parse().ok())
+        .unwrap_or(ColorChoice::Auto)
 }
 
 impl VestiOpt {
-    pub fn is_continuous_compile(&self) -> bool {
-        if let Self::Run { continuous, .. } = self {
-            *continuous
+    pub fn compile_option(&self) -> CompileOption {
+        if let Self::Run {
+            continuous,
+            flags,
+            open_error,
+            dry_run,
+            crop,
+            png,
+            export,
+            dpi,
+            timing,
+            timing_json,
+            standalone,
+            ..
+        } = self
+        {
+            let option = flags.compile_option();
+            CompileOption {
+                is_continuous: *continuous,
+                is_pdf: option.is_pdf
+                    || (option.target != OutputTarget::Markdown
+                        && (*crop || *png || export.is_some())),
+                open_error: *open_error,
+                is_dry_run: *dry_run,
+                crop: *crop,
+                export: match export {
+                    Some(format) => Some(ExportConfig::new(
+                        *format,
+                        dpi.unwrap_or(export::DEFAULT_DPI),
+                    )),
+                    None => png.then(|| ExportConfig::new(ExportFormat::Png, render::PNG_DPI)),
+                },
+                timing: TimingFormat::from_flags(*timing, *timing_json),
+                standalone: *standalone,
+                ..option
+            }
+        } else if let Self::Build {
+            dry_run,
+            open_error,
+            refresh,
+            strict_raw,
+            no_auto_import,
+            safe,
+            allow_exec,
+            timeout,
+            quiet,
+            verbose,
+            timing,
+            timing_json,
+            ..
+        } = self
+        {
+            CompileOption {
+                is_pdf: true,
+                open_error: *open_error,
+                is_dry_run: *dry_run,
+                refresh: *refresh,
+                strict_raw: *strict_raw,
+                no_auto_import: *no_auto_import,
+                is_safe: *safe,
+                allow_exec: *allow_exec,
+                timeout: timeout.map(Duration::from_secs),
+                verbosity: Verbosity::from_flags(*quiet, *verbose),
+                timing: TimingFormat::from_flags(*timing, *timing_json),
+                ..CompileOption::default()
+            }
+        } else if let Self::Watch { flags, .. } = self {
+            CompileOption {
+                is_continuous: true,
+                ..flags.compile_option()
+            }
         } else {
-            false
+            CompileOption::default()
+        }
+    }
+
+    pub fn watch_option(&self) -> WatchOption {
+        match self {
+            Self::Watch {
+                debounce,
+                pre_build,
+                post_build,
+                no_clear,
+                ..
+            } => WatchOption {
+                debounce: Duration::from_millis(*debounce),
+                pre_build: pre_build.clone(),
+                post_build: post_build.clone(),
+                clear: !no_clear,
+                jobs: self.jobs(),
+            },
+            _ => WatchOption {
+                jobs: self.jobs(),
+                ..WatchOption::default()
+            },
         }
     }
 
+    // `--max-errors` of `vesti run`
+    pub fn max_errors(&self) -> Option<usize> {
+        match self {
+            Self::Run { max_errors, .. } => *max_errors,
+            _ => None,
+        }
+    }
+
+    // The JSON file of `--report` where the summary of `vesti run` is written
+    pub fn report_file(&self) -> Option<&Path> {
+        match self {
+            Self::Run { report, .. } => report.as_deref(),
+            _ => None,
+        }
+    }
+
+    // The number of workers compiling files at once
+    pub fn jobs(&self) -> usize {
+        let jobs = match self {
+            Self::Run { flags, .. } | Self::Watch { flags, .. } => flags.jobs,
+            _ => None,
+        };
+        jobs.map_or_else(pool::default_workers, |jobs| jobs.max(1))
+    }
+
+    // Options for each file of `take_file_name` where `--emit` is applied.
+    // `matches` should be the ones from which this is made.
+    pub fn file_compile_options(
+        &self,
+        matches: &ArgMatches,
+        file_num: usize,
+    ) -> Vec<CompileOption> {
+        let option = self.compile_option();
+        let (emits, is_all) = match (self, matches.subcommand_matches("run")) {
+            (Self::Run { emit, all, .. }, Some(matches)) => (emits_of_files(matches, emit), *all),
+            _ => (Vec::new(), false),
+        };
+
+        (0..file_num)
+            .map(|idx| {
+                // every file in the directory follows the one given by `--all`
+                let emit = if is_all {
+                    emits.first()
+                } else {
+                    emits.get(idx)
+                };
+                let mut option = option.clone();
+                match emit {
+                    Some(Some(EmitKind::Tex)) => option.is_pdf = false,
+                    Some(Some(EmitKind::Pdf)) => {
+                        option.is_pdf = option.target != OutputTarget::Markdown
+                    }
+                    _ => {}
+                }
+                option
+            })
+            .collect()
+    }
+
     pub fn take_file_name(&self) -> error::Result<Vec<PathBuf>> {
         let mut output: Vec<PathBuf> = Vec::new();
+        if let Self::Watch { file_name, .. } = self {
+            return Ok(file_name.clone());
+        }
 
-        if let Self::Run {
-            continuous: _,
-            all,
-            file_name,
-        } = self
-        {
+        if let Self::Run { all, file_name, .. } = self {
             if !all {
                 return Ok(file_name.clone());
             }
@@ -117,41 +806,1464 @@ impl VestiOpt {
     }
 }
 
-fn output_file_name(file_name: &Path) -> PathBuf {
+// The directory of a file where a relative path like `main.ves` is in `.`
+fn parent_dir(file_name: &Path) -> &Path {
+    match file_name.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    }
+}
+
+pub fn output_file_name(file_name: &Path) -> PathBuf {
     file_name.with_extension("tex")
 }
 
-fn take_time(file_name: &Path) -> error::Result<SystemTime> {
-    let path = file_name;
-    Ok(path.metadata()?.modified()?)
+// The ConTeXt engine compiles only ConTeXt files, so choosing it by `--engine`,
+// `%!engine` or vesti.toml also chooses the ConTeXt target
+fn output_target(option: &CompileOption, latex: &Latex) -> OutputTarget {
+    let engine = option.engine.or_else(|| LatexEngineType::from_latex(latex));
+    match engine {
+        Some(LatexEngineType::Context) if option.target == OutputTarget::Latex => {
+            OutputTarget::Context
+        }
+        _ => option.target,
+    }
 }
 
-pub fn compile_vesti(file_name: PathBuf, is_continuous: bool) {
-    let mut init_compile = true;
-    let output = output_file_name(&file_name);
-    unwrap_err!(mut init_time := take_time(&file_name), None, None);
-    let mut now_time = init_time;
+// The engine given by the command line wins over the one of the file directive.
+// If neither is given, it is chosen from the document and the choice is reported.
+fn select_engine(
+    file_name: &Path,
+    engine: Option<LatexEngineType>,
+    latex: &Latex,
+) -> LatexEngineType {
+    if let Some(engine) = engine.or_else(|| LatexEngineType::from_latex(latex)) {
+        return engine;
+    }
+
+    let (engine, feature) = LatexEngineType::detect(latex);
+    match feature {
+        Some(feature) => println!(
+            "{}: compile with {} since `{}` is used. Use `--engine` or `%!engine` to override it.",
+            file_name.display(),
+            engine.command(),
+            feature,
+        ),
+        None => println!(
+            "{}: compile with {}. Use `--engine` or `%!engine` to override it.",
+            file_name.display(),
+            engine.command(),
+        ),
+    }
+    engine
+}
+
+fn report_engine_failure(tex_file: &Path, err: &error::VestiErr, open_error: bool) {
+    let log_file = match &err.err_kind {
+        VestiErrKind::UtilErr(VestiCommandUtilErr::LatexCompileErr { log, .. }) => log,
+        _ => return,
+    };
+    let artifacts = match engine::retain_failure_artifacts(tex_file, Path::new(log_file)) {
+        Ok(artifacts) => artifacts,
+        Err(err) => {
+            println!("{}", pretty_print(None, err, Some(tex_file)));
+            return;
+        }
+    };
+
+    println!(
+        "The failed latex file is kept at {}",
+        artifacts.tex_file.display()
+    );
+    if let Some(log_file) = &artifacts.log_file {
+        println!("The log file is kept at {}", log_file.display());
+    }
+    if let Some(line) = artifacts.error_line {
+        println!(
+            "The first error is at {}:{}",
+            artifacts.tex_file.display(),
+            line
+        );
+        if open_error {
+            open_in_editor(&artifacts.tex_file, line);
+        }
+    }
+}
 
-    loop {
-        if init_compile || init_time != now_time {
-            let source = fs::read_to_string(&file_name).expect("Opening file error occurred!");
-            let mut parser = Parser::new(Lexer::new(&source));
-            unwrap_err!(contents := parser.make_latex_format(), Some(source.as_ref()), Some(&file_name));
-            drop(parser);
+// Warnings of the engine are summarized since the log has lots of other lines
+fn report_latex_warnings(file_name: &Path, log_file: &Path) {
+    let warnings = engine::log_warnings(log_file);
+    if warnings.is_empty() {
+        return;
+    }
+    let mut report = format!("{}: {} latex warnings", file_name.display(), warnings.len());
+    for warning in warnings.iter().take(MAX_LATEX_WARNINGS) {
+        report += &format!("\n    {}", warning);
+    }
+    if warnings.len() > MAX_LATEX_WARNINGS {
+        report += &format!(
+            "\n    ... and {} more in {}",
+            warnings.len() - MAX_LATEX_WARNINGS,
+            log_file.display()
+        );
+    }
+    console::print_block(&report);
+}
 
-            fs::write(&output, contents).expect("File write failed.");
+// Summary of `--strict-raw` which counts raw latex including allowed ones
+fn report_raw_latex(file_name: &Path, latex: &Latex) {
+    let (mut count, mut lines) = (0, 0);
+    walk_latex(latex, &mut |stmt| {
+        if let Statement::RawLatex(raw) = stmt {
+            count += 1;
+            lines += raw.lines().filter(|line| !line.trim().is_empty()).count();
+        }
+    });
+    println!(
+        "{}: {} raw latex statements ({} lines) remain",
+        file_name.display(),
+        count,
+        lines
+    );
+}
 
-            if !is_continuous {
-                break;
+// Most editors, like vim, emacs and nano, understand `+<line>`
+fn open_in_editor(file_name: &Path, line: usize) {
+    let editor = match std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")) {
+        Ok(editor) if !editor.trim().is_empty() => editor,
+        _ => {
+            println!("Neither `VISUAL` nor `EDITOR` is set, so the error is not opened.");
+            return;
+        }
+    };
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap();
+    if Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(file_name)
+        .status()
+        .is_err()
+    {
+        println!("Cannot run the editor `{}`", editor);
+    }
+}
+
+// The latex file is streamed, so a large document is not made in memory at once
+fn write_latex_file(output: &Path, latex: &Latex, target: OutputTarget) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(output)?);
+    match target {
+        OutputTarget::Context => emit_context(latex, &mut writer)?,
+        _ => emit(latex, &mut writer)?,
+    }
+    writer.flush()
+}
+
+// Compile a vesti file once. If it fails, the pretty printed error is returned.
+pub fn compile_once(
+    file_name: &Path,
+    source: &str,
+    output: &Path,
+    option: &CompileOption,
+    config: &VestiConfig,
+) -> Result<(), String> {
+    compile_timed(file_name, source, output, option, config, None)
+        .map(|_| ())
+        .map_err(|failure| failure.msg)
+}
+
+// Same as `compile_once`, but only changed lines of the source are parsed again
+// if `doc` has the last version of the file. Watch mode uses this.
+pub fn compile_incremental(
+    file_name: &Path,
+    source: &str,
+    output: &Path,
+    option: &CompileOption,
+    config: &VestiConfig,
+    doc: &mut Option<IncrementalDoc>,
+) -> Result<(), String> {
+    compile_timed(file_name, source, output, option, config, Some(doc))
+        .map(|_| ())
+        .map_err(|failure| failure.msg)
+}
+
+// The number of warnings of vesti is returned if the compilation succeeds
+fn compile_timed(
+    file_name: &Path,
+    source: &str,
+    output: &Path,
+    option: &CompileOption,
+    config: &VestiConfig,
+    doc: Option<&mut Option<IncrementalDoc>>,
+) -> Result<usize, CompileFailure> {
+    let mut timings = Timings::new(file_name);
+    if option.timing.is_some() {
+        timings.measure("lex", || Lexer::new(source).count());
+    }
+    let result = compile_phases(file_name, source, output, option, config, doc, &mut timings);
+    if let Some(format) = option.timing {
+        console::print_block(&timings.report(format));
+    }
+    result
+}
+
+// Parse a whole document, or a fragment in the preamble of `--standalone`
+fn parse_document<'a>(
+    parser: &mut Parser<'a>,
+    source: &str,
+    option: &CompileOption,
+    config: &VestiConfig,
+    arena: &'a Arena,
+) -> error::Result<Latex<'a>> {
+    if !(option.standalone && standalone::is_fragment(source)) {
+        return parser.parse_latex();
+    }
+    let body = parser.parse_body()?;
+    let missing = parser.missing_packages(&body);
+    Ok(standalone::wrap_fragment(
+        body,
+        &missing,
+        &config.standalone,
+        arena,
+    ))
+}
+
+// Parse chapters of the main document and do what is done for the main file
+// before it is made into latex. Packages of chapters are imported by `latex`.
+fn prepare_chapters<'a>(
+    latex: &mut Latex<'a>,
+    source_dir: &Path,
+    arena: &'a Arena,
+    context: &DocumentContext,
+    configure: &dyn Fn(&mut Parser),
+    option: &CompileOption,
+    config: &VestiConfig,
+) -> Result<Vec<Chapter<'a>>, CompileFailure> {
+    let (mut chapters, packages) =
+        chapters::parse_chapters(latex, source_dir, arena, context, configure)?;
+    chapters::import_packages(latex, &packages, arena);
+
+    let cache = BlockCache::new(source_dir, option.refresh);
+    let is_allowed = option.allow_exec && !option.is_safe;
+    for chapter in &mut chapters {
+        let file_name = Some(chapter.file_name.as_path());
+        importtex::import_tex_files(&mut chapter.latex, source_dir, arena)
+            .map_err(|err| CompileFailure::new(Some(chapter.source), err, file_name))?;
+        if option.verbosity >= Verbosity::Normal {
+            for warning in &chapter.warnings {
+                console::print_block(&pretty_print_warning(
+                    Some(chapter.source),
+                    warning,
+                    file_name,
+                ));
             }
-            if !init_compile {
-                println!("Press Ctrl+C to finish the program.");
+        }
+        if !option.is_dry_run {
+            execute::run_blocks(
+                &mut chapter.latex,
+                &config.execute,
+                is_allowed,
+                &cache,
+                source_dir,
+                option.timeout,
+            )
+            .map_err(|err| CompileFailure::new(Some(chapter.source), err, file_name))?;
+        }
+        check::check_image_files(&chapter.latex, source_dir)
+            .map_err(|err| CompileFailure::new(Some(chapter.source), err, file_name))?;
+    }
+    Ok(chapters)
+}
+
+fn write_markdown_file(
+    file_name: &Path,
+    output: &Path,
+    latex: &Latex,
+    option: &CompileOption,
+    timings: &mut Timings,
+) -> Result<(), CompileFailure> {
+    if option.is_dry_run {
+        println!("{}", file_name.display());
+        println!("markdown file:  {}", output.display());
+        return Ok(());
+    }
+    timings
+        .measure("codegen", || fs::write(output, latex_to_markdown(latex)))
+        .map_err(|err| CompileFailure::new(None, error::VestiErr::from(err), Some(file_name)))
+}
+
+// The configuration of vesti.toml with the options of the command line
+fn configure_compile(parser: &mut Parser, config: &VestiConfig, option: &CompileOption) {
+    configure_parser(parser, config);
+    let defines: Vec<&String> = option.defines.iter().chain(&config.defines).collect();
+    parser.set_defines(&defines);
+    if option.dollar_math {
+        parser.set_dollar_math(true);
+    }
+    parser.set_strict_raw(option.strict_raw);
+    parser.set_auto_import(!option.no_auto_import);
+    parser.set_safe(option.is_safe);
+}
+
+// What every way of compiling a document does around parsing, from files to
+// the standard input and snapshots. Included vesti files are resolved before
+// the document is parsed, so that their definitions are checked with it, and
+// modules, latex files and outputs of `execute` blocks are pasted after it.
+pub struct FrontEnd<'c> {
+    file_name: &'c Path,
+    source: &'c str,
+    option: &'c CompileOption,
+    config: &'c VestiConfig,
+    cache: BlockCache,
+    graph: Option<DepGraph>,
+    imports: Definitions,
+}
+
+impl<'c> FrontEnd<'c> {
+    pub fn new(
+        file_name: &'c Path,
+        source: &'c str,
+        option: &'c CompileOption,
+        config: &'c VestiConfig,
+        timings: &mut Timings,
+    ) -> Result<Self, CompileFailure> {
+        // the standard input does not have a directory to keep the cache in
+        let cache = if option.is_dry_run || file_name == Path::new(STDIN_LABEL) {
+            BlockCache::disabled()
+        } else {
+            BlockCache::new(parent_dir(file_name), option.refresh)
+        };
+        let configure_options = |parser: &mut Parser| {
+            configure_compile(parser, config, option);
+            parser.set_block_cache(cache.clone());
+        };
+        // Functions and labels of included vesti files are checked with every
+        // file of the document
+        let graph = timings.measure("modules", || {
+            DepGraph::resolve_imports(file_name, source, &configure_options)
+        })?;
+        let imports = graph
+            .as_ref()
+            .map(DepGraph::imported_definitions)
+            .unwrap_or_default();
+        Ok(Self {
+            file_name,
+            source,
+            option,
+            config,
+            cache,
+            graph,
+            imports,
+        })
+    }
+
+    // Parsers of the document, its chapters and its modules
+    pub fn configure(&self, parser: &mut Parser) {
+        configure_compile(parser, self.config, self.option);
+        parser.set_block_cache(self.cache.clone());
+        parser.set_imports(&self.imports);
+    }
+
+    pub fn expand<'a>(
+        &self,
+        latex: &mut Latex<'a>,
+        arena: &'a Arena,
+        timings: &mut Timings,
+    ) -> Result<(), CompileFailure> {
+        let (file_name, source) = (self.file_name, self.source);
+        let source_dir = parent_dir(file_name);
+        // A cycle of includes makes the engine recurse until its input stack
+        // is exhausted, so it is reported before any file is made
+        match &self.graph {
+            Some(graph) => graph.check_cycle()?,
+            None if deps::has_includes(latex) => timings.measure("deps", || {
+                DepGraph::build_from_latex(file_name, latex).and_then(|graph| graph.check_cycle())
+            })?,
+            None => {}
+        }
+        let configure = |parser: &mut Parser| self.configure(parser);
+        modules::import_modules(latex, source, file_name, source_dir, arena, &configure)?;
+        importtex::import_tex_files(latex, source_dir, arena)
+            .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+        if !self.option.is_dry_run {
+            let is_allowed = self.option.allow_exec && !self.option.is_safe;
+            timings
+                .measure("execute", || {
+                    execute::run_blocks(
+                        latex,
+                        &self.config.execute,
+                        is_allowed,
+                        &self.cache,
+                        source_dir,
+                        self.option.timeout,
+                    )
+                })
+                .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+        }
+        Ok(())
+    }
+}
+
+fn compile_phases(
+    file_name: &Path,
+    source: &str,
+    output: &Path,
+    option: &CompileOption,
+    config: &VestiConfig,
+    doc: Option<&mut Option<IncrementalDoc>>,
+    timings: &mut Timings,
+) -> Result<usize, CompileFailure> {
+    let build_dir = parent_dir(output);
+    let source_dir = parent_dir(file_name);
+    let front = FrontEnd::new(file_name, source, option, config, timings)?;
+    let configure = |parser: &mut Parser| front.configure(parser);
+    let print_warnings = |warnings: &[VestiWarning]| {
+        if option.verbosity >= Verbosity::Normal {
+            for warning in warnings {
+                console::print_block(&pretty_print_warning(
+                    Some(source),
+                    warning,
+                    Some(file_name),
+                ));
             }
+        }
+        warnings.len()
+    };
 
-            init_compile = false;
-            init_time = now_time;
+    let arena = Arena::new();
+    let is_fragment = option.standalone && standalone::is_fragment(source);
+    let (mut latex, context, mut warning_count) = match doc.filter(|_| !is_fragment) {
+        Some(doc) => {
+            let parsed = timings.measure("parse", || match doc {
+                Some(doc) => doc.update(source, &configure).map(|_| ()),
+                None => IncrementalDoc::new(source, &configure).map(|new| *doc = Some(new)),
+            });
+            parsed.map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+            let doc = doc.as_ref().unwrap();
+            let warning_count = print_warnings(doc.warnings());
+            (doc.latex(), doc.context().clone(), warning_count)
         }
-        unwrap_err!(now_time = take_time(&file_name), None, None);
-        thread::sleep(Duration::from_millis(500));
+        None => {
+            let mut parser = Parser::new(Lexer::new(source), &arena);
+            configure(&mut parser);
+            let latex = timings
+                .measure("parse", || {
+                    parse_document(&mut parser, source, option, config, &arena)
+                })
+                .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+            let warning_count = print_warnings(&parser.take_warnings());
+            (latex, parser.document_context(), warning_count)
+        }
+    };
+    if option.strict_raw {
+        report_raw_latex(file_name, &latex);
+    }
+    front.expand(&mut latex, &arena, timings)?;
+    if option.target == OutputTarget::Markdown {
+        return write_markdown_file(file_name, output, &latex, option, timings)
+            .map(|_| warning_count);
+    }
+
+    let mut chapters = timings.measure("chapters", || {
+        prepare_chapters(
+            &mut latex, source_dir, &arena, &context, &configure, option, config,
+        )
+    })?;
+    warning_count += chapters
+        .iter()
+        .map(|chapter| chapter.warnings.len())
+        .sum::<usize>();
+    if chapters
+        .iter()
+        .any(|chapter| has_index_entries(&chapter.latex))
+    {
+        insert_index_commands(&mut latex);
+    }
+    if !option.only.is_empty() {
+        chapters::include_only(&mut latex, &option.only, &arena)
+            .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+        chapters.retain(|chapter| option.only.iter().any(|name| name == chapter.name));
+    }
+
+    if !option.is_dry_run {
+        embed::materialize(&latex, build_dir)
+            .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+    }
+    check::check_image_files(&latex, source_dir)
+        .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+
+    // The engine decides how unicode math is written, so it is chosen before
+    // the latex file is made
+    let target = output_target(option, &latex);
+    let engine = if target == OutputTarget::Context {
+        option.is_pdf.then_some(LatexEngineType::Context)
+    } else {
+        (option.is_pdf || !config.keep_unicode_math.is_empty())
+            .then(|| select_engine(file_name, option.engine, &latex))
+    };
+    if engine.is_some_and(|engine| config.keep_unicode_math.contains(&engine)) {
+        symbols::keep_unicode_math(&mut latex);
+        for chapter in &mut chapters {
+            symbols::keep_unicode_math(&mut chapter.latex);
+        }
+    }
+    if config.smart_typography {
+        typography::smart_typography(&mut latex);
+        for chapter in &mut chapters {
+            typography::smart_typography(&mut chapter.latex);
+        }
+    }
+
+    // Chapters are written next to the main latex file, since `\include`
+    // finds them from the directory where the engine runs
+    let chapter_outputs: Vec<(PathBuf, &Latex)> = chapters
+        .iter()
+        .map(|chapter| {
+            let output = build_dir.join(format!("{}.tex", chapter.name));
+            (output, &chapter.latex)
+        })
+        .collect();
+    if option.is_dry_run {
+        println!("{}", file_name.display());
+        println!("latex file:  {}", output.display());
+        for (output, _) in &chapter_outputs {
+            println!("chapter file:  {}", output.display());
+        }
+    } else {
+        timings
+            .measure("codegen", || {
+                write_latex_file(output, &latex, target)?;
+                for (output, latex) in &chapter_outputs {
+                    if let Some(parent) = output.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    write_latex_file(output, latex, target)?;
+                }
+                Ok(())
+            })
+            .map_err(|err: io::Error| {
+                CompileFailure::new(None, error::VestiErr::from(err), Some(file_name))
+            })?;
+    }
+
+    if let Some(engine) = engine.filter(|_| option.is_pdf) {
+        let interaction = option
+            .interaction
+            .or_else(|| InteractionMode::from_latex(&latex))
+            .or(config.interaction)
+            .unwrap_or_default();
+        let bib_backend = engine::bib_backend_to_run(&latex);
+        let mut plan = EnginePlan::new(engine, interaction, output, bib_backend);
+        if engine::has_index_to_make(&latex) {
+            plan.add_index();
+        }
+        if option.is_safe {
+            plan.make_safe();
+        }
+        plan.timeout = option.timeout;
+        plan.verbosity = option.verbosity;
+        plan.label = file_name.display().to_string();
+        if source_dir != build_dir {
+            // The engine runs in the build directory, so a relative path is not valid there
+            let source_dir = source_dir
+                .canonicalize()
+                .unwrap_or_else(|_| source_dir.to_path_buf());
+            plan.texinputs = Some(source_dir);
+        }
+        // `latex` makes a dvi file, so there is no pdf to compress, crop nor convert
+        let has_pdf = engine != LatexEngineType::Latex;
+        let pdf = output.with_extension("pdf");
+        let compress = match &config.compress {
+            Some(compress) if has_pdf => Some(compress.command(&pdf)),
+            _ => None,
+        };
+        let export = match option.export.as_ref().or(config.export.as_ref()) {
+            Some(export) if has_pdf => Some(
+                export
+                    .command(&pdf)
+                    .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?,
+            ),
+            _ => None,
+        };
+
+        if option.is_dry_run {
+            println!("{}", plan);
+            if let Some(compress) = &compress {
+                println!("compress commands:\n{}", compress);
+            }
+            if option.crop && has_pdf {
+                println!("crop: {}", pdf.display());
+            }
+            if let Some(export) = &export {
+                println!("export command:\n{}", export);
+            }
+        } else {
+            if let Err(err) = plan.run_timed(timings) {
+                report_engine_failure(output, &err, option.open_error);
+                return Err(CompileFailure::new(None, err, Some(file_name)));
+            }
+            if option.verbosity >= Verbosity::Normal {
+                report_latex_warnings(file_name, &plan.engine.log_file);
+            }
+            if let Some(compress) = &compress {
+                timings
+                    .measure("compress", || compress.run())
+                    .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+            }
+            if option.crop && has_pdf {
+                render::crop_pdf(&pdf)
+                    .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+            }
+            if let Some(export) = &export {
+                timings
+                    .measure("export", || export.run())
+                    .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+            }
+        }
+    }
+
+    Ok(warning_count)
+}
+
+// The number of warnings is returned as `compile_timed` does
+fn compile_vesti(file_name: &Path, option: &CompileOption) -> Result<usize, CompileFailure> {
+    let source = fs::read_to_string(file_name)
+        .map_err(|err| CompileFailure::new(None, err.into(), Some(file_name)))?;
+    let config = VestiConfig::find(file_name)
+        .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+
+    let output = FileOverrides::scan(&source).output_file_name(file_name, option.target);
+    compile_timed(file_name, &source, &output, option, &config, None)
+}
+
+// The pdf file is the last output with `--pdf`, except for `latex` making a dvi file
+fn final_output(file_name: &Path, option: &CompileOption) -> PathBuf {
+    let output = FileOverrides::of_file(file_name).output_file_name(file_name, option.target);
+    match option.engine {
+        _ if !option.is_pdf || option.is_dry_run || option.target == OutputTarget::Markdown => {
+            output
+        }
+        Some(LatexEngineType::Latex) => output.with_extension("dvi"),
+        _ => output.with_extension("pdf"),
+    }
+}
+
+// Compile files with at most `jobs` files at once. A line is printed whenever
+// a file is done, and with several files, the summary table is printed at the
+// end. The summary is written to `report` as JSON if it is given. Files which
+// are not started yet are skipped after `max_errors` files are failed.
+// Returns the exit code of the first failed file.
+pub fn compile_files(
+    files: Vec<(PathBuf, CompileOption)>,
+    jobs: usize,
+    report: Option<&Path>,
+    max_errors: Option<usize>,
+) -> ExitCode {
+    let total = files.len();
+    let is_quiet = files
+        .iter()
+        .all(|(_, option)| option.verbosity == Verbosity::Quiet);
+    let done = Arc::new(AtomicUsize::new(0));
+    let errors = Arc::new(AtomicUsize::new(0));
+    // results with indices of files, since workers finish in any order
+    let results = Arc::new(Mutex::new(Vec::new()));
+    let started = Instant::now();
+    {
+        let pool = WorkerPool::new(jobs.min(total));
+        for (idx, (file_name, option)) in files.into_iter().enumerate() {
+            let done = Arc::clone(&done);
+            let errors = Arc::clone(&errors);
+            let results = Arc::clone(&results);
+            pool.execute(Box::new(move || {
+                // Files waiting for a worker are not compiled after the interruption
+                // or too many errors
+                let is_stopped = max_errors.is_some_and(|max| errors.load(Ordering::SeqCst) >= max);
+                if shutdown::is_requested() || is_stopped {
+                    return;
+                }
+                let file_started = Instant::now();
+                let result = compile_vesti(&file_name, &option);
+                let duration = file_started.elapsed();
+                if shutdown::is_requested() {
+                    return;
+                }
+                let count = done.fetch_add(1, Ordering::SeqCst) + 1;
+                let progress = format!(
+                    "[{:>width$}/{}]",
+                    count,
+                    total,
+                    width = total.to_string().len()
+                );
+                let (code, warnings) = match result {
+                    Ok(warnings) => {
+                        if option.verbosity > Verbosity::Quiet {
+                            console::print_block(&format!(
+                                "{} ok    {} ({:.1}s)",
+                                progress,
+                                file_name.display(),
+                                duration.as_secs_f64()
+                            ));
+                        }
+                        (ExitCode::Success, warnings)
+                    }
+                    Err(failure) => {
+                        console::print_block(&format!(
+                            "{} error {}\n{}",
+                            progress,
+                            file_name.display(),
+                            failure.msg
+                        ));
+                        errors.fetch_add(1, Ordering::SeqCst);
+                        (failure.code, 0)
+                    }
+                };
+                let output = final_output(&file_name, &option);
+                let status = match code {
+                    ExitCode::Success => FileStatus::Ok,
+                    _ => FileStatus::Error,
+                };
+                results.lock().unwrap().push((
+                    idx,
+                    code,
+                    FileReport {
+                        file: file_name,
+                        status,
+                        duration,
+                        warnings,
+                        output,
+                    },
+                ));
+            }));
+        }
+        // dropping the pool waits every compilation
+    }
+
+    let mut results = std::mem::take(&mut *results.lock().unwrap());
+    results.sort_by_key(|(idx, _, _)| *idx);
+    let code = results
+        .iter()
+        .map(|(_, code, _)| *code)
+        .find(|code| *code != ExitCode::Success)
+        .unwrap_or(ExitCode::Success);
+    let summary = BatchReport {
+        files: results.into_iter().map(|(_, _, report)| report).collect(),
+        total: started.elapsed(),
+    };
+    if total > 1 && !is_quiet && !shutdown::is_requested() {
+        println!("{}", summary);
+    }
+    if summary.files.len() < total && !shutdown::is_requested() {
+        println!(
+            "Stopped after {} errors: {} files are not compiled",
+            errors.load(Ordering::SeqCst),
+            total - summary.files.len()
+        );
+    }
+    if let Some(report) = report {
+        if let Err(err) = summary.write_json(report) {
+            println!("Cannot write the report `{}`: {}", report.display(), err);
+            return ExitCode::IoError;
+        }
+    }
+    code
+}
+
+// Read a vesti source from the standard input. The latex code is written to the
+// standard output, but if a pdf is needed, it is compiled in a temporary directory.
+// Errors are written to the standard error so that they are not mixed with the latex code.
+// The build directory of the standard input is removed when its compile is
+// done. It is kept if the engine fails, since the latex file and the log kept
+// in it are printed with their paths.
+struct StdinBuildDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl Drop for StdinBuildDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+// Outputs are moved out of the build directory before it is removed, like
+// `stdin.pdf` to `/tmp/vesti-stdin-1234.pdf`
+fn move_stdin_outputs(output: &Path) -> io::Result<()> {
+    let build_dir = output.parent().unwrap_or(Path::new("."));
+    for ext in ["pdf", "dvi", "png", "svg"] {
+        let file = output.with_extension(ext);
+        if !file.is_file() {
+            continue;
+        }
+        let kept = build_dir.with_extension(ext);
+        fs::rename(&file, &kept)?;
+        println!("{}", kept.display());
+    }
+    Ok(())
+}
+
+pub fn compile_stdin(option: CompileOption) {
+    let file_name = Path::new(STDIN_LABEL);
+    let mut source = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut source) {
+        eprintln!("{}", pretty_print(None, err.into(), Some(file_name)));
+        ExitCode::IoError.exit();
+    }
+    let config = match VestiConfig::find_from_dir(Path::new(".")) {
+        Ok(config) => config,
+        Err(err) => {
+            let failure = CompileFailure::new(None, err, Some(file_name));
+            eprintln!("{}", failure.msg);
+            failure.code.exit();
+        }
+    };
+
+    let result = if option.is_pdf {
+        let mut build_dir = StdinBuildDir {
+            path: env::temp_dir().join(format!("vesti-stdin-{}", std::process::id())),
+            keep: false,
+        };
+        let output = build_dir.path.join("stdin.tex");
+        let result = fs::create_dir_all(&build_dir.path)
+            .map_err(|err| CompileFailure::new(None, err.into(), Some(file_name)))
+            .and_then(|_| compile_timed(file_name, &source, &output, &option, &config, None))
+            .and_then(|_| {
+                if option.is_dry_run {
+                    return Ok(());
+                }
+                move_stdin_outputs(&output)
+                    .map_err(|err| CompileFailure::new(None, err.into(), Some(file_name)))
+            });
+        build_dir.keep = matches!(&result, Err(failure) if failure.code == ExitCode::EngineError);
+        result.map(|_| ())
+    } else {
+        let mut timings = Timings::new(file_name);
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(&source), &arena);
+        let to_failure = |err| CompileFailure::new(Some(&source), err, Some(file_name));
+        // includes of the standard input are resolved against the current directory
+        let result = FrontEnd::new(file_name, &source, &option, &config, &mut timings)
+            .and_then(|front| {
+                front.configure(&mut parser);
+                let mut latex = parse_document(&mut parser, &source, &option, &config, &arena)
+                    .map_err(to_failure)?;
+                front.expand(&mut latex, &arena, &mut timings)?;
+                Ok(latex)
+            })
+            .and_then(|mut latex| {
+                if option.target != OutputTarget::Latex {
+                    // A closed pipe is ignored as the latex output does
+                    let _ = io::stdout()
+                        .lock()
+                        .write_all(option.target.generate(&latex).as_bytes());
+                    return Ok(None);
+                }
+                check::check_image_files(&latex, Path::new(".")).map_err(to_failure)?;
+                let engine = option
+                    .engine
+                    .or_else(|| LatexEngineType::from_latex(&latex));
+                if engine.is_some_and(|engine| config.keep_unicode_math.contains(&engine)) {
+                    symbols::keep_unicode_math(&mut latex);
+                }
+                if config.smart_typography {
+                    typography::smart_typography(&mut latex);
+                }
+                Ok(Some(latex))
+            })
+            .and_then(|latex| {
+                let Some(latex) = latex else {
+                    return Ok(());
+                };
+                // A closed pipe like `vesti run - | head` is not an error
+                let mut stdout = io::stdout().lock();
+                let written = match output_target(&option, &latex) {
+                    OutputTarget::Context => emit_context(&latex, &mut stdout),
+                    _ => emit(&latex, &mut stdout),
+                };
+                match written {
+                    Err(err) if err.kind() != io::ErrorKind::BrokenPipe => Err(
+                        CompileFailure::new(None, error::VestiErr::from(err), Some(file_name)),
+                    ),
+                    _ => Ok(()),
+                }
+            });
+        for warning in parser.take_warnings() {
+            eprintln!(
+                "{}",
+                pretty_print_warning(Some(&source), &warning, Some(file_name))
+            );
+        }
+        result
+    };
+
+    if let Err(failure) = result {
+        eprintln!("{}", failure.msg);
+        failure.code.exit();
+    }
+}
+
+pub fn build_targets(names: &[String], option: CompileOption) {
+    unwrap_err!(
+        config_path := VestiConfig::find_file(Path::new(".")).ok_or(error::VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ConfigNotFoundErr),
+            location: None,
+        }),
+        None,
+        None
+    );
+    unwrap_err!(config := VestiConfig::from_file(&config_path), None, Some(&config_path));
+    let config_dir = parent_dir(&config_path);
+
+    let names: Vec<&String> = if names.is_empty() {
+        config.targets.keys().collect()
+    } else {
+        names.iter().collect()
+    };
+    if names.is_empty() {
+        println!("There is no target in `{}`", config_path.display());
+        return;
+    }
+
+    for name in names {
+        unwrap_err!(
+            target := config.targets.get(name).ok_or(error::VestiErr {
+                err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::TargetNotFoundErr(
+                    name.clone()
+                )),
+                location: None,
+            }),
+            None,
+            Some(&config_path)
+        );
+        let file_name = config_dir.join(&target.source);
+        unwrap_err!(source := fs::read_to_string(&file_name).map_err(error::VestiErr::from), None, Some(&file_name));
+        // the output of the target wins over the directive of the file
+        let output_name = match &target.output {
+            Some(output_name) => PathBuf::from(format!("{}.tex", output_name)),
+            None => {
+                FileOverrides::scan(&source).output_file_name(&target.source, OutputTarget::Latex)
+            }
+        };
+        let output = config_dir.join(output_name);
+        if !option.is_dry_run {
+            unwrap_err!(_dir := fs::create_dir_all(parent_dir(&output)).map_err(error::VestiErr::from), None, Some(&output));
+        }
+
+        let mut option = option.clone();
+        option.engine = target.engine;
+        if option.verbosity > Verbosity::Quiet {
+            println!("Building target `{}`", name);
+        }
+        let target_config = config.for_target(target);
+        if let Err(failure) =
+            compile_timed(&file_name, &source, &output, &option, &target_config, None)
+        {
+            println!("{}", failure.msg);
+            failure.code.exit();
+        }
+    }
+}
+
+pub fn init_project(project_dir: &Path, template: Option<&str>, author: Option<String>) {
+    let vars = TemplateVars::new(project_dir, author);
+    unwrap_err!(
+        created := initialization::init_project(project_dir, template, &vars),
+        None,
+        Some(project_dir)
+    );
+    for path in created {
+        println!("created {}", path.display());
+    }
+}
+
+pub fn expand_env(file_name: &Path, name: &str, args: &[String], body: &str) {
+    unwrap_err!(source := fs::read_to_string(file_name).map_err(error::VestiErr::from), None, Some(file_name));
+    unwrap_err!(config := VestiConfig::find(file_name), None, Some(file_name));
+
+    let configure = |parser: &mut Parser| configure_parser(parser, &config);
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(&source), &arena);
+    configure(&mut parser);
+    unwrap_err!(mut latex := parser.parse_latex(), Some(source.as_ref()), Some(file_name));
+    // definitions of modules can be expanded with their prefixes
+    let source_dir = parent_dir(file_name);
+    if let Err(failure) = modules::import_modules(
+        &mut latex, &source, file_name, source_dir, &arena, &configure,
+    ) {
+        println!("{}", failure.msg);
+        failure.code.exit();
+    }
+    unwrap_err!(
+        expanded := expand::expand_definition(&latex, name, args, body),
+        None,
+        Some(file_name)
+    );
+    println!("{}", expanded);
+}
+
+pub fn setup_tex(force: bool) {
+    unwrap_err!(bin_dir := texdist::setup_tex(force), None, None);
+    let dist = texdist::distribution_of(&bin_dir);
+    println!("{} is installed in {}", dist, bin_dir.display());
+    println!(
+        "Install more packages with `{} <package>`",
+        dist.install_command(&bin_dir)
+    );
+}
+
+pub fn render_math(expr: &str, png: &Path, dpi: u32, engine: LatexEngineType) {
+    let source = render::math_document(expr);
+    let file_name = Path::new("<math>");
+    unwrap_err!(
+        _rendered := render::render_math(&source, png, dpi, engine),
+        Some(source.as_ref()),
+        Some(file_name)
+    );
+    println!("{}", png.display());
+}
+
+pub fn synctex_edit(location: &str, open: bool) {
+    unwrap_err!(found := synctex::edit(location), None, None);
+    let (file_name, line) = found;
+    if open {
+        open_in_editor(&file_name, line);
+    } else {
+        println!("{}:{}", file_name.display(), line);
+    }
+}
+
+pub fn synctex_view(file_name: &Path, line: usize) {
+    unwrap_err!(position := synctex::view(file_name, line), None, Some(file_name));
+    for field in position {
+        println!("{}", field);
+    }
+}
+
+pub fn print_symbols(query: &str) {
+    let found = symbols::search(query);
+    if found.is_empty() {
+        println!("No symbol matches `{}`", query);
+        return;
+    }
+
+    let vesti_width = found
+        .iter()
+        .map(|symbol| symbol.vesti.unwrap_or(symbol.latex).len())
+        .max()
+        .unwrap_or_default();
+    let latex_width = found
+        .iter()
+        .map(|symbol| symbol.latex.len())
+        .max()
+        .unwrap_or_default();
+    for symbol in found {
+        let package = match symbol.package {
+            Some(package) => format!(" (needs {})", package),
+            None => String::new(),
+        };
+        println!(
+            "{:vesti_width$}  {:latex_width$}  {}{}",
+            symbol.vesti.unwrap_or(symbol.latex),
+            symbol.latex,
+            symbol.description,
+            package,
+            vesti_width = vesti_width,
+            latex_width = latex_width,
+        );
+    }
+}
+
+pub fn print_metadata(file_name: &Path, is_json: bool) {
+    unwrap_err!(source := fs::read_to_string(file_name).map_err(error::VestiErr::from), None, Some(file_name));
+    unwrap_err!(config := VestiConfig::find(file_name), None, Some(file_name));
+
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(&source), &arena);
+    configure_parser(&mut parser, &config);
+    unwrap_err!(latex := parser.parse_latex(), Some(source.as_ref()), Some(file_name));
+
+    let metadata = Metadata::from_latex(&latex);
+    if is_json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&metadata).expect("Serializing metadata failed.")
+        );
+    } else {
+        println!("{}", metadata);
+    }
+}
+
+// Print lints of each file. Returns false if a denied lint is found or a file
+// cannot be parsed.
+// Warnings of the parser and lints after parsing which are not allowed by attributes
+fn collect_lints(
+    file_name: &Path,
+    source: &str,
+    config: &VestiConfig,
+) -> error::Result<Vec<VestiWarning>> {
+    let configure = |parser: &mut Parser| configure_parser(parser, config);
+    // Included files which cannot be parsed are reported when they are linted
+    let imports = DepGraph::resolve_imports(file_name, source, &configure)
+        .ok()
+        .flatten()
+        .map(|graph| graph.imported_definitions())
+        .unwrap_or_default();
+
+    let arena = Arena::new();
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    configure(&mut parser);
+    parser.set_imports(&imports);
+    let latex = parser.parse_latex()?;
+    let mut warnings = parser.take_warnings();
+    let allowed = parser.take_allowed_regions();
+    warnings.extend(lint::lint_latex(&latex, source, &config.lint, &allowed));
+    Ok(warnings)
+}
+
+// Errors are files which cannot be linted and denied lints. Lints are not
+// checked anymore after `max_errors` errors are printed.
+pub fn lint_files(files: &[PathBuf], max_errors: Option<usize>) -> ExitCode {
+    let mut code = ExitCode::Success;
+    let mut errors = 0;
+    for file_name in files {
+        if max_errors.is_some_and(|max| errors >= max) {
+            println!("Stopped after {} errors", errors);
+            break;
+        }
+        let file_code = lint_file(file_name, &mut errors, max_errors);
+        if code == ExitCode::Success {
+            code = file_code;
+        }
+    }
+    code
+}
+
+fn lint_file(file_name: &Path, errors: &mut usize, max_errors: Option<usize>) -> ExitCode {
+    let mut fail = |source: Option<&str>, err: error::VestiErr| {
+        *errors += 1;
+        let code = ExitCode::of(&err);
+        println!("{}", pretty_print(source, err, Some(file_name)));
+        code
+    };
+    let source = match fs::read_to_string(file_name) {
+        Ok(source) => source,
+        Err(err) => return fail(None, err.into()),
+    };
+    let config = match VestiConfig::find(file_name) {
+        Ok(config) => config,
+        Err(err) => return fail(None, err),
+    };
+
+    let mut warnings = match collect_lints(file_name, &source, &config) {
+        Ok(warnings) => warnings,
+        Err(err) => return fail(Some(&source), err),
+    };
+    for name in config.lint.levels.keys() {
+        if !LINT_NAMES.contains(&name.as_str()) {
+            warnings.push(VestiWarning {
+                warn_kind: VestiWarnKind::UnknownLint { name: name.clone() },
+                location: None,
+            });
+        }
+    }
+
+    let mut denied = 0;
+    for warning in &warnings {
+        if max_errors.is_some_and(|max| *errors >= max) {
+            break;
+        }
+        match config.lint.level(warning) {
+            LintLevel::Allow => {}
+            LintLevel::Warn => println!(
+                "{}",
+                pretty_print_warning(Some(&source), warning, Some(file_name))
+            ),
+            LintLevel::Deny => {
+                denied += 1;
+                *errors += 1;
+                println!(
+                    "{}",
+                    pretty_print_denied(Some(&source), warning, Some(file_name))
+                );
+            }
+        }
+    }
+
+    if denied == 0 {
+        ExitCode::Success
+    } else {
+        ExitCode::DeniedWarning
+    }
+}
+
+// Fix lints which are not allowed by `vesti.toml`. The original file is kept
+// with `.bak` appended, and a file is not changed if the fixed one is invalid.
+pub fn fix_files(files: &[PathBuf], is_dry_run: bool, remove_empty_envs: bool) -> bool {
+    let mut is_ok = true;
+    for file_name in files {
+        is_ok &= fix_file(file_name, is_dry_run, remove_empty_envs);
+    }
+    is_ok
+}
+
+fn fix_file(file_name: &Path, is_dry_run: bool, remove_empty_envs: bool) -> bool {
+    let result = fs::read_to_string(file_name)
+        .map_err(|err| pretty_print(None, err.into(), Some(file_name)))
+        .and_then(|source| {
+            let config = VestiConfig::find(file_name)
+                .map_err(|err| pretty_print(None, err, Some(file_name)))?;
+            let warnings = collect_lints(file_name, &source, &config)
+                .map_err(|err| pretty_print(Some(&source), err, Some(file_name)))?;
+            Ok((source, config, warnings))
+        });
+    let (source, config, warnings) = match result {
+        Ok(inner) => inner,
+        Err(msg) => {
+            println!("{}", msg);
+            return false;
+        }
+    };
+
+    let warnings: Vec<&VestiWarning> = warnings
+        .iter()
+        .filter(|warning| config.lint.level(warning) != LintLevel::Allow)
+        .collect();
+    // Empty environments might be placeholders, so they are removed only with
+    // `--remove-empty-envs`
+    if !remove_empty_envs {
+        for warning in &warnings {
+            if matches!(warning.warn_kind, VestiWarnKind::EmptyEnvironment { .. }) {
+                println!(
+                    "{}",
+                    pretty_print_warning(Some(&source), warning, Some(file_name))
+                );
+            }
+        }
+    }
+    let fixable: Vec<(&VestiWarning, fix::Edit)> = warnings
+        .into_iter()
+        .filter_map(|warning| {
+            fix::edit_of(warning, &source, remove_empty_envs).map(|edit| (warning, edit))
+        })
+        .collect();
+    if is_dry_run {
+        for (warning, _) in &fixable {
+            println!(
+                "{}",
+                pretty_print_warning(Some(&source), warning, Some(file_name))
+            );
+        }
+        println!(
+            "{}: {} problems can be fixed",
+            file_name.display(),
+            fixable.len()
+        );
+        return true;
+    }
+    if fixable.is_empty() {
+        println!("{}: nothing to fix", file_name.display());
+        return true;
+    }
+
+    let (fixed, applied) =
+        fix::apply_edits(&source, fixable.into_iter().map(|(_, edit)| edit).collect());
+    let arena = Arena::new();
+    if let Err(err) = Parser::new(Lexer::new(&fixed), &arena).parse_latex() {
+        println!("{}", pretty_print(Some(&fixed), err, Some(file_name)));
+        println!(
+            "{}: fixes are not applied since the fixed file is invalid",
+            file_name.display()
+        );
+        return false;
+    }
+    let mut backup = file_name.as_os_str().to_owned();
+    backup.push(".bak");
+    let backup = PathBuf::from(backup);
+    let written = fs::copy(file_name, &backup).and_then(|_| fs::write(file_name, fixed));
+    if let Err(err) = written {
+        println!("{}", pretty_print(None, err.into(), Some(file_name)));
+        return false;
+    }
+    println!(
+        "{}: fixed {} problems (backup: {})",
+        file_name.display(),
+        applied,
+        backup.display()
+    );
+    true
+}
+
+// Returns false if the graph cannot be made or includes make a cycle
+pub fn print_deps(file_name: &Path, format: DepsFormat) -> bool {
+    let graph = match DepGraph::build(file_name) {
+        Ok(graph) => graph,
+        Err(msg) => {
+            println!("{}", msg);
+            return false;
+        }
+    };
+    match format {
+        DepsFormat::Tree => print!("{}", graph.to_tree()),
+        DepsFormat::Dot => print!("{}", graph.to_dot()),
+    }
+
+    if let Err(msg) = graph.check_cycle() {
+        eprintln!("{}", msg);
+        return false;
+    }
+    true
+}
+
+// Files which cannot be parsed are reported and skipped. Returns false if
+// there is such a file.
+pub fn write_doc(dir: &Path, format: DocFormat, output: Option<&Path>) -> bool {
+    unwrap_err!(files := docgen::project_sources(dir), None, Some(dir));
+    let mut is_ok = true;
+    let mut docs = Vec::new();
+    for file_name in &files {
+        let source = match fs::read_to_string(file_name) {
+            Ok(source) => source,
+            Err(err) => {
+                println!("{}", pretty_print(None, err.into(), Some(file_name)));
+                is_ok = false;
+                continue;
+            }
+        };
+        if !docgen::has_definitions(&source) {
+            continue;
+        }
+        let config = match VestiConfig::find(file_name) {
+            Ok(config) => config,
+            Err(err) => {
+                println!("{}", pretty_print(None, err, Some(file_name)));
+                is_ok = false;
+                continue;
+            }
+        };
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(&source), &arena);
+        configure_parser(&mut parser, &config);
+        match parser.parse_latex() {
+            Ok(latex) => {
+                let relative = file_name.strip_prefix(dir).unwrap_or(file_name);
+                docs.extend(docgen::macro_docs(&latex, relative));
+            }
+            Err(err) => {
+                println!("{}", pretty_print(Some(&source), err, Some(file_name)));
+                is_ok = false;
+            }
+        }
+    }
+
+    let reference = docgen::render(&docs, format);
+    match output {
+        Some(output) => {
+            if let Err(err) = fs::write(output, reference) {
+                println!("{}", pretty_print(None, err.into(), Some(output)));
+                return false;
+            }
+            println!("{} macros are written in {}", docs.len(), output.display());
+        }
+        None => print!("{}", reference),
+    }
+    is_ok
+}
+
+pub fn print_stats(file_name: &Path, is_json: bool) {
+    unwrap_err!(source := fs::read_to_string(file_name).map_err(error::VestiErr::from), None, Some(file_name));
+    unwrap_err!(config := VestiConfig::find(file_name), None, Some(file_name));
+
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(&source), &arena);
+    configure_parser(&mut parser, &config);
+    unwrap_err!(latex := parser.parse_latex(), Some(source.as_ref()), Some(file_name));
+
+    let stats = Stats::from_latex(&latex);
+    if is_json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats).expect("Serializing statistics failed.")
+        );
+    } else {
+        println!("{}", stats);
+    }
+}
+
+// Statements are printed as they are made by the parser. Unlike the latex file,
+// false branches of `#if` are kept with `enabled: false`.
+pub fn print_ast(file_name: &Path, is_compact: bool) {
+    unwrap_err!(source := fs::read_to_string(file_name).map_err(error::VestiErr::from), None, Some(file_name));
+    unwrap_err!(config := VestiConfig::find(file_name), None, Some(file_name));
+
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(&source), &arena);
+    configure_parser(&mut parser, &config);
+    unwrap_err!(latex := parser.parse_latex(), Some(source.as_ref()), Some(file_name));
+
+    let json = if is_compact {
+        serde_json::to_string(&latex)
+    } else {
+        serde_json::to_string_pretty(&latex)
+    };
+    println!("{}", json.expect("Serializing the syntax tree failed."));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_compile_options() {
+        let args = [
+            "vesti", "run", "a.ves", "b.ves", "--emit", "tex", "main.ves", "--emit", "pdf", "c.ves",
+        ];
+        let matches = VestiOpt::clap().get_matches_from(args);
+        let opt = VestiOpt::from_clap(&matches);
+        let is_pdf: Vec<bool> = opt
+            .file_compile_options(&matches, 4)
+            .into_iter()
+            .map(|option| option.is_pdf)
+            .collect();
+        assert_eq!(is_pdf, vec![false, false, true, false]);
+
+        let matches = VestiOpt::clap().get_matches_from(["vesti", "run", "--pdf", "a.ves"]);
+        let opt = VestiOpt::from_clap(&matches);
+        assert!(opt.file_compile_options(&matches, 1)[0].is_pdf);
+    }
+
+    #[test]
+    fn test_context_engine_directive() {
+        let dir = env::temp_dir().join(format!("vesti-context-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file_name = dir.join("main.ves");
+        let output = dir.join("main.tex");
+        let source = "%!engine: context\ndocclass article\ndocument\nfoo\n";
+
+        let option = CompileOption::default();
+        compile_once(
+            &file_name,
+            source,
+            &output,
+            &option,
+            &VestiConfig::default(),
+        )
+        .unwrap();
+        let context = fs::read_to_string(&output).unwrap();
+        assert!(context.contains("\\starttext"), "{}", context);
+
+        // an explicit target other than latex is kept
+        let option = CompileOption {
+            target: OutputTarget::Markdown,
+            ..CompileOption::default()
+        };
+        let arena = Arena::new();
+        let latex = Parser::new(Lexer::new(source), &arena)
+            .parse_latex()
+            .unwrap();
+        assert_eq!(output_target(&option, &latex), OutputTarget::Markdown);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,156 @@
+// Post processing of the pdf made by the engine. This downsamples images and
+// linearizes the pdf since many submission systems limit the size of pdfs.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressTool {
+    Ghostscript,
+    Qpdf,
+}
+
+fn default_dpi() -> u32 {
+    150
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CompressConfig {
+    pub tool: CompressTool,
+    /// Resolution of downsampled images. Only ghostscript downsamples images.
+    #[serde(default = "default_dpi")]
+    pub dpi: u32,
+}
+
+pub struct CompressCommand {
+    program: &'static str,
+    args: Vec<String>,
+    pdf_file: PathBuf,
+    // The tool writes to this file, and then it replaces the original pdf.
+    tmp_file: PathBuf,
+}
+
+impl CompressConfig {
+    pub fn command(&self, pdf_file: &Path) -> CompressCommand {
+        let tmp_file = pdf_file.with_extension("compressed.pdf");
+        let (program, args) = match self.tool {
+            CompressTool::Ghostscript => (
+                "gs",
+                vec![
+                    String::from("-sDEVICE=pdfwrite"),
+                    String::from("-dNOPAUSE"),
+                    String::from("-dBATCH"),
+                    String::from("-dQUIET"),
+                    String::from("-dFastWebView=true"),
+                    String::from("-dDownsampleColorImages=true"),
+                    String::from("-dDownsampleGrayImages=true"),
+                    String::from("-dDownsampleMonoImages=true"),
+                    format!("-dColorImageResolution={}", self.dpi),
+                    format!("-dGrayImageResolution={}", self.dpi),
+                    format!("-dMonoImageResolution={}", self.dpi),
+                    format!("-sOutputFile={}", tmp_file.display()),
+                    pdf_file.display().to_string(),
+                ],
+            ),
+            CompressTool::Qpdf => (
+                "qpdf",
+                vec![
+                    String::from("--linearize"),
+                    String::from("--object-streams=generate"),
+                    String::from("--compress-streams=y"),
+                    String::from("--recompress-flate"),
+                    pdf_file.display().to_string(),
+                    tmp_file.display().to_string(),
+                ],
+            ),
+        };
+
+        CompressCommand {
+            program,
+            args,
+            pdf_file: pdf_file.to_path_buf(),
+            tmp_file,
+        }
+    }
+}
+
+impl CompressCommand {
+    pub fn run(&self) -> error::Result<()> {
+        let output = Command::new(self.program)
+            .args(&self.args)
+            .output()
+            .map_err(|err| {
+                if err.kind() == io::ErrorKind::NotFound {
+                    VestiErr {
+                        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::EngineNotFoundErr(
+                            self.program.to_string(),
+                        )),
+                        location: None,
+                    }
+                } else {
+                    VestiErr::from(err)
+                }
+            })?;
+
+        if !output.status.success() {
+            let _ = fs::remove_file(&self.tmp_file);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(VestiErr {
+                err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::PdfCompressErr {
+                    tool: self.program.to_string(),
+                    msg: stderr.lines().next().unwrap_or_default().trim().to_string(),
+                }),
+                location: None,
+            });
+        }
+        fs::rename(&self.tmp_file, &self.pdf_file)?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CompressCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  {}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        write!(
+            f,
+            "\n  mv {} {}",
+            self.tmp_file.display(),
+            self.pdf_file.display()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress_config() {
+        let config: CompressConfig = toml::from_str("tool = \"qpdf\"").unwrap();
+        assert_eq!(
+            config,
+            CompressConfig {
+                tool: CompressTool::Qpdf,
+                dpi: 150,
+            }
+        );
+        assert_eq!(
+            config.command(Path::new("main.pdf")).to_string(),
+            "  qpdf --linearize --object-streams=generate --compress-streams=y \
+             --recompress-flate main.pdf main.compressed.pdf\n  mv main.compressed.pdf main.pdf"
+        );
+        assert!(toml::from_str::<CompressConfig>("tool = \"zip\"").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::compress::CompressConfig;
+use crate::engine::{InteractionMode, LatexEngineType};
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use crate::export::ExportConfig;
+use crate::headings::HeadingConfig;
+use crate::lint::LintConfig;
+use crate::notify::NotifyConfig;
+use crate::standalone::StandaloneConfig;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE_NAME: &str = "vesti.toml";
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct VestiConfig {
+    /// Flags which are considered to be defined at `#if` directives.
+    pub defines: Vec<String>,
+    /// Words which become `\operatorname{...}` in math mode, like `tr` or `rank`.
+    pub math_operators: Vec<String>,
+    /// Engines which keep unicode characters like `α` in math mode instead of `\\alpha`.
+    pub keep_unicode_math: Vec<LatexEngineType>,
+    /// Interaction mode of the latex engine.
+    pub interaction: Option<InteractionMode>,
+    /// Compress the pdf after the engine makes it.
+    pub compress: Option<CompressConfig>,
+    /// Convert the first page of the pdf into an image after the engine makes it.
+    pub export: Option<ExportConfig>,
+    /// Notifications of watch mode.
+    pub notify: NotifyConfig,
+    /// Documents made from the same source tree by `vesti build <target>`.
+    pub targets: BTreeMap<String, TargetConfig>,
+    /// Levels of lints which `vesti lint` checks.
+    pub lint: LintConfig,
+    /// Interpreters of `execute(lang)` blocks like `python = "python3"`.
+    pub execute: BTreeMap<String, String>,
+    /// Preamble of fragments compiled by `--standalone`.
+    pub standalone: StandaloneConfig,
+    /// Labels of headings like `section "Introduction"`.
+    pub headings: HeadingConfig,
+    /// Write quotes and dashes of texts in their typographic forms, like `"a"` as ``` ``a'' ```.
+    pub smart_typography: bool,
+    /// Write every `defenv` as `\NewDocumentEnvironment`, not only ones with argument specifications.
+    pub xparse_environments: bool,
+    /// Read `$...$` and `$$...$$` as math like latex, for documents moved from latex.
+    pub dollar_math: bool,
+}
+
+// A target overrides the global configuration. Its defines are added to the
+// global ones, so that several targets can differ only by defines.
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Main vesti file of the target relative to `vesti.toml`.
+    pub source: PathBuf,
+    #[serde(default)]
+    pub defines: Vec<String>,
+    pub engine: Option<LatexEngineType>,
+    /// Name of the generated files without an extension. The default is the name of the source.
+    pub output: Option<String>,
+    pub interaction: Option<InteractionMode>,
+    pub compress: Option<CompressConfig>,
+    pub export: Option<ExportConfig>,
+}
+
+impl VestiConfig {
+    // Find `vesti.toml` from the directory of the given file up to the root.
+    // If there is no such file, then the default configuration is used.
+    pub fn find(file_name: &Path) -> error::Result<Self> {
+        match Self::find_file_of(file_name) {
+            Some(config_path) => Self::from_file(&config_path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    // `vesti.toml` which is used to compile the given file
+    pub fn find_file_of(file_name: &Path) -> Option<PathBuf> {
+        let file_name = file_name
+            .canonicalize()
+            .unwrap_or_else(|_| file_name.to_path_buf());
+        Self::find_file(file_name.parent()?)
+    }
+
+    // Find `vesti.toml` from the given directory up to the root.
+    pub fn find_from_dir(dir: &Path) -> error::Result<Self> {
+        match Self::find_file(dir) {
+            Some(config_path) => Self::from_file(&config_path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn find_file(dir: &Path) -> Option<PathBuf> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        dir.ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|config_path| config_path.is_file())
+    }
+
+    // The configuration used to compile the given target
+    pub fn for_target(&self, target: &TargetConfig) -> Self {
+        Self {
+            defines: self
+                .defines
+                .iter()
+                .chain(&target.defines)
+                .cloned()
+                .collect(),
+            math_operators: self.math_operators.clone(),
+            keep_unicode_math: self.keep_unicode_math.clone(),
+            interaction: target.interaction.or(self.interaction),
+            compress: target.compress.clone().or_else(|| self.compress.clone()),
+            export: target.export.clone().or_else(|| self.export.clone()),
+            notify: self.notify.clone(),
+            targets: BTreeMap::new(),
+            lint: self.lint.clone(),
+            execute: self.execute.clone(),
+            standalone: self.standalone.clone(),
+            headings: self.headings.clone(),
+            smart_typography: self.smart_typography,
+            xparse_environments: self.xparse_environments,
+            dollar_math: self.dollar_math,
+        }
+    }
+
+    pub fn from_file(path: &Path) -> error::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ConfigParseErr(err.to_string())),
+            location: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::export::ExportFormat;
+
+    #[test]
+    fn test_target_config() {
+        let source = r#"
+defines = ["final"]
+interaction = "batchmode"
+
+[targets.handout]
+source = "main.ves"
+defines = ["handout"]
+
+[targets.slides]
+source = "main.ves"
+defines = ["slides"]
+engine = "xelatex"
+output = "slides-v1"
+interaction = "errorstopmode"
+
+[targets.slides.export]
+format = "svg"
+"#;
+        let config: VestiConfig = toml::from_str(source).unwrap();
+        assert_eq!(config.targets.len(), 2);
+
+        let handout = &config.targets["handout"];
+        assert_eq!(handout.engine, None);
+        let handout_config = config.for_target(handout);
+        assert_eq!(handout_config.defines, vec!["final", "handout"]);
+        assert_eq!(handout_config.interaction, Some(InteractionMode::Batchmode));
+        assert_eq!(handout_config.export, None);
+
+        let slides = &config.targets["slides"];
+        assert_eq!(slides.engine, Some(LatexEngineType::Xelatex));
+        assert_eq!(slides.output.as_deref(), Some("slides-v1"));
+        let slides_config = config.for_target(slides);
+        assert_eq!(slides_config.defines, vec!["final", "slides"]);
+        assert_eq!(
+            slides_config.interaction,
+            Some(InteractionMode::Errorstopmode)
+        );
+        assert_eq!(
+            slides_config.export.map(|export| export.format),
+            Some(ExportFormat::Svg)
+        );
+
+        assert!(toml::from_str::<VestiConfig>("[targets.foo]\ndefines = []").is_err());
+    }
+}
@@ -0,0 +1,45 @@
+// Output shared by compilations running at once. Every message is printed as a
+// whole block with one lock, so that lines of different files are not mixed.
+
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+static OUTPUT: Mutex<()> = Mutex::new(());
+
+// How much vesti prints while compiling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Only errors
+    Quiet,
+    // Warnings and one line for each compiled file
+    #[default]
+    Normal,
+    // Outputs of engines as they are printed
+    Verbose,
+    // Commands run by vesti too
+    Trace,
+}
+
+impl Verbosity {
+    // `-q` wins over any number of `-v`
+    pub fn from_flags(quiet: bool, verbose: u64) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, _) => Self::Trace,
+        }
+    }
+}
+
+pub fn print_block(text: &str) {
+    let _guard = OUTPUT.lock().unwrap_or_else(|err| err.into_inner());
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", text);
+    let _ = stdout.flush();
+}
+
+// A line of an engine is prefixed with the file which the engine compiles
+pub fn print_engine_line(label: &str, line: &str) {
+    print_block(&format!("{} | {}", label, line));
+}
@@ -0,0 +1,539 @@
+// The include graph of a multi-file document. Files are included by
+// `\input{...}`, `\include{...}`, `chapter import` and modules of
+// `import defs.ves`, which are resolved like the engine does:
+// relative to the directory of the main file, with `.tex` made from `.ves`.
+// Only vesti files are parsed to follow their includes, and their definitions
+// are kept so that the files importing them can be checked with them.
+
+use crate::config::VestiConfig;
+use crate::error::err_kind::VestiParseErr;
+use crate::error::pretty_print::pretty_print;
+use crate::error::VestiErr;
+use crate::lexer::Lexer;
+use crate::location::Span;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_spanned, ArgNeed, Latex, Statement};
+use crate::parser::maker::latex_to_string;
+use crate::parser::{configure_parser, Definitions, Parser};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+const INCLUDE_COMMANDS: [&str; 2] = ["input", "include"];
+// A source without these words cannot include any file
+const INCLUDE_WORDS: [&str; 4] = ["\\input", "\\include", "chapter", ".ves"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DepsFormat {
+    Tree,
+    Dot,
+}
+
+impl FromStr for DepsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Self::Tree),
+            "dot" => Ok(Self::Dot),
+            _ => Err(format!("unknown format `{}`. Use one of tree and dot", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IncludeKind {
+    Input,
+    Chapter,
+    // Definitions of a module get the prefix given by `as`
+    Module { prefix: Option<String> },
+}
+
+// A name given to an include with its span
+type IncludeName = (String, Span, IncludeKind);
+
+#[derive(Debug)]
+pub struct Include {
+    // index of the included file in `DepGraph::files`
+    pub file: usize,
+    pub span: Span,
+    pub kind: IncludeKind,
+}
+
+#[derive(Debug)]
+pub struct DepFile {
+    pub path: PathBuf,
+    pub exists: bool,
+    // Chapters of `chapter import` have only the body of the document
+    pub is_chapter: bool,
+    pub includes: Vec<Include>,
+    // Definitions of a vesti file. The main file does not have them.
+    pub definitions: Definitions,
+}
+
+#[derive(Debug)]
+pub struct DepGraph {
+    root_dir: PathBuf,
+    // The main file is the first one
+    pub files: Vec<DepFile>,
+}
+
+impl DepGraph {
+    // Parse the main file and every vesti file included from it. An error is
+    // a pretty printed one of the file which cannot be read or parsed.
+    pub fn build(main_file: &Path) -> Result<Self, String> {
+        Self::build_with(main_file, None)
+    }
+
+    // The graph of a main file which is parsed already. The standard input
+    // cannot be read again, and its includes are resolved against the current
+    // directory as `<stdin>` has no parent.
+    pub fn build_from_latex(main_file: &Path, latex: &Latex) -> Result<Self, String> {
+        Self::build_with(main_file, Some(include_names(latex)))
+    }
+
+    // The module resolution pass which runs before the main file is parsed to
+    // be compiled. The main file is parsed once more to find its includes, so
+    // a source which does not mention any include is not parsed. The graph is
+    // `None` if the main file does not include anything.
+    pub fn resolve_imports(
+        main_file: &Path,
+        source: &str,
+        configure: &dyn Fn(&mut Parser),
+    ) -> Result<Option<Self>, String> {
+        if !INCLUDE_WORDS.iter().any(|word| source.contains(word)) {
+            return Ok(None);
+        }
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        configure(&mut parser);
+        // An error of the main file is reported when it is compiled
+        let latex = match parser.parse_latex() {
+            Ok(latex) if has_includes(&latex) => latex,
+            _ => return Ok(None),
+        };
+        Self::build_from_latex(main_file, &latex).map(Some)
+    }
+
+    // Definitions of every vesti file which the main file includes directly
+    // or through other files, with prefixes of modules
+    pub fn imported_definitions(&self) -> Definitions {
+        let mut definitions = Definitions::default();
+        for include in self.files.iter().flat_map(|file| &file.includes) {
+            let included = &self.files[include.file].definitions;
+            match &include.kind {
+                IncludeKind::Module {
+                    prefix: Some(prefix),
+                } => definitions.merge(&included.with_prefix(prefix)),
+                _ => definitions.merge(included),
+            }
+        }
+        definitions
+    }
+
+    fn build_with(
+        main_file: &Path,
+        mut main_includes: Option<Vec<IncludeName>>,
+    ) -> Result<Self, String> {
+        let root_dir = match main_file.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let mut graph = Self {
+            root_dir,
+            files: Vec::new(),
+        };
+        let mut indices: HashMap<PathBuf, usize> = HashMap::new();
+        graph.add_file(main_file.to_path_buf(), false, &mut indices);
+
+        let mut next = 0;
+        while next < graph.files.len() {
+            let idx = next;
+            next += 1;
+            let path = graph.files[idx].path.clone();
+            // the main file is the first one
+            let names = match main_includes.take() {
+                Some(names) => names,
+                None if !graph.files[idx].exists
+                    || path.extension().is_none_or(|ext| ext != "ves") =>
+                {
+                    continue
+                }
+                None => {
+                    let (names, definitions) = parse_file(&path, graph.files[idx].is_chapter)?;
+                    graph.files[idx].definitions = definitions;
+                    names
+                }
+            };
+            for (name, span, kind) in names {
+                // paths of modules are written with their extensions
+                let path = match kind {
+                    IncludeKind::Module { .. } => graph.root_dir.join(&name),
+                    _ => graph.resolve(&name),
+                };
+                let is_chapter = kind == IncludeKind::Chapter;
+                let file = graph.add_file(path, is_chapter, &mut indices);
+                graph.files[idx].includes.push(Include { file, span, kind });
+            }
+        }
+        Ok(graph)
+    }
+
+    fn add_file(
+        &mut self,
+        path: PathBuf,
+        is_chapter: bool,
+        indices: &mut HashMap<PathBuf, usize>,
+    ) -> usize {
+        let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+        *indices.entry(key).or_insert_with(|| {
+            self.files.push(DepFile {
+                exists: path.is_file(),
+                is_chapter,
+                path,
+                includes: Vec::new(),
+                definitions: Definitions::default(),
+            });
+            self.files.len() - 1
+        })
+    }
+
+    // `\input{chapters/intro}` is `chapters/intro.ves` if it exists, since the
+    // latex file is made from it
+    fn resolve(&self, name: &str) -> PathBuf {
+        let stem = name.strip_suffix(".tex").unwrap_or(name);
+        let vesti_file = self.root_dir.join(format!("{}.ves", stem));
+        if vesti_file.is_file() {
+            vesti_file
+        } else {
+            self.root_dir.join(format!("{}.tex", stem))
+        }
+    }
+
+    // Name of a file relative to the directory of the main file
+    pub fn display_name(&self, idx: usize) -> String {
+        let path = &self.files[idx].path;
+        path.strip_prefix(&self.root_dir)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    // Includes which make a cycle as `(file, include)`, starting from the
+    // include which is found first from the main file
+    pub fn find_cycle(&self) -> Option<Vec<(usize, &Include)>> {
+        let mut on_stack = vec![false; self.files.len()];
+        let mut visited = vec![false; self.files.len()];
+        let mut stack: Vec<(usize, &Include)> = Vec::new();
+        self.find_cycle_from(0, &mut on_stack, &mut visited, &mut stack)
+    }
+
+    fn find_cycle_from<'a>(
+        &'a self,
+        idx: usize,
+        on_stack: &mut [bool],
+        visited: &mut [bool],
+        stack: &mut Vec<(usize, &'a Include)>,
+    ) -> Option<Vec<(usize, &'a Include)>> {
+        visited[idx] = true;
+        on_stack[idx] = true;
+        for include in &self.files[idx].includes {
+            stack.push((idx, include));
+            if on_stack[include.file] {
+                let start = stack.iter().position(|(file, _)| *file == include.file)?;
+                return Some(stack[start..].to_vec());
+            }
+            if !visited[include.file] {
+                if let Some(cycle) = self.find_cycle_from(include.file, on_stack, visited, stack) {
+                    return Some(cycle);
+                }
+            }
+            stack.pop();
+        }
+        on_stack[idx] = false;
+        None
+    }
+
+    // The error of a cycle points to the include in the file where the cycle
+    // is entered, and lists every include of the cycle
+    pub fn check_cycle(&self) -> Result<(), String> {
+        let cycle = match self.find_cycle() {
+            Some(cycle) => cycle,
+            None => return Ok(()),
+        };
+        let (first_file, first_include) = cycle[0];
+        let names = cycle
+            .iter()
+            .map(|(file, include)| {
+                format!(
+                    "{}:{}:{} includes {}",
+                    self.display_name(*file),
+                    include.span.start.row(),
+                    include.span.start.column(),
+                    self.display_name(include.file)
+                )
+            })
+            .collect();
+        let err = VestiErr::make_parse_err(
+            VestiParseErr::CircularIncludeErr { cycle: names },
+            Some(first_include.span),
+        );
+        let file_name = &self.files[first_file].path;
+        let source = fs::read_to_string(file_name).ok();
+        Err(pretty_print(source.as_deref(), err, Some(file_name)))
+    }
+
+    pub fn to_tree(&self) -> String {
+        let mut output = format!("{}\n", self.display_name(0));
+        let mut ancestors = vec![0];
+        self.write_tree(&mut output, 0, "", &mut ancestors);
+        output
+    }
+
+    fn write_tree(
+        &self,
+        output: &mut String,
+        idx: usize,
+        prefix: &str,
+        ancestors: &mut Vec<usize>,
+    ) {
+        let includes = &self.files[idx].includes;
+        for (i, include) in includes.iter().enumerate() {
+            let is_last = i + 1 == includes.len();
+            let file = &self.files[include.file];
+            let note = if ancestors.contains(&include.file) {
+                " (cycle)"
+            } else if !file.exists {
+                " (missing)"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                output,
+                "{}{} {}{}",
+                prefix,
+                if is_last { "└──" } else { "├──" },
+                self.display_name(include.file),
+                note
+            );
+            if note.is_empty() {
+                let prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+                ancestors.push(include.file);
+                self.write_tree(output, include.file, &prefix, ancestors);
+                ancestors.pop();
+            }
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph vesti {\n");
+        for (idx, file) in self.files.iter().enumerate() {
+            let style = if file.exists { "" } else { " [style=dashed]" };
+            let _ = writeln!(output, "    {:?}{};", self.display_name(idx), style);
+        }
+        for (idx, file) in self.files.iter().enumerate() {
+            for include in &file.includes {
+                let _ = writeln!(
+                    output,
+                    "    {:?} -> {:?};",
+                    self.display_name(idx),
+                    self.display_name(include.file)
+                );
+            }
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+// Only documents which include other files need the graph to be checked
+pub fn has_includes(latex: &Latex) -> bool {
+    let mut found = false;
+    walk_spanned(latex, &mut |stmt| match &stmt.node {
+        Statement::LatexFunction { name, .. } => found |= INCLUDE_COMMANDS.contains(name),
+        Statement::ChapterImport { .. } | Statement::ImportModule { .. } => found = true,
+        _ => {}
+    });
+    found
+}
+
+// Names given to `\input`, `\include`, `chapter import` and modules with their
+// spans, and definitions of the file
+fn parse_file(
+    file_name: &Path,
+    is_chapter: bool,
+) -> Result<(Vec<IncludeName>, Definitions), String> {
+    let source = fs::read_to_string(file_name)
+        .map_err(|err| pretty_print(None, err.into(), Some(file_name)))?;
+    let config =
+        VestiConfig::find(file_name).map_err(|err| pretty_print(None, err, Some(file_name)))?;
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(&source), &arena);
+    configure_parser(&mut parser, &config);
+    let latex = if is_chapter {
+        parser.parse_body()
+    } else {
+        parser.parse_latex()
+    };
+    let latex = latex.map_err(|err| pretty_print(Some(&source), err, Some(file_name)))?;
+    Ok((include_names(&latex), parser.definitions()))
+}
+
+fn include_names(latex: &Latex) -> Vec<IncludeName> {
+    let mut names = Vec::new();
+    walk_spanned(latex, &mut |stmt| match &stmt.node {
+        Statement::LatexFunction { name, args } if INCLUDE_COMMANDS.contains(name) => {
+            if let Some((_, arg)) = args.iter().find(|(need, _)| *need == ArgNeed::MainArg) {
+                let name = latex_to_string(arg).trim().to_string();
+                names.push((name, stmt.span, IncludeKind::Input));
+            }
+        }
+        Statement::ChapterImport { name, .. } => {
+            names.push((name.to_string(), stmt.span, IncludeKind::Chapter))
+        }
+        Statement::ImportModule { path, prefix, .. } => {
+            let prefix = prefix.map(str::to_string);
+            names.push((path.to_string(), stmt.span, IncludeKind::Module { prefix }))
+        }
+        _ => {}
+    });
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_dep_graph() {
+        let dir = env::temp_dir().join(format!("vesti-deps-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("chapters")).unwrap();
+        let main_file = dir.join("main.ves");
+        fs::write(
+            &main_file,
+            "docclass book\ndocument\n\\input{chapters/a}\nchapter import \"chapters/b.ves\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("chapters/a.ves"),
+            "docstartmode\n\\input{chapters/c.tex}\n\\input{chapters/b}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("chapters/b.ves"), "foo\n").unwrap();
+
+        let graph = DepGraph::build(&main_file).unwrap();
+        assert!(graph.find_cycle().is_none());
+        assert_eq!(
+            graph.to_tree(),
+            "main.ves\n\
+             ├── chapters/a.ves\n\
+             │   ├── chapters/c.tex (missing)\n\
+             │   └── chapters/b.ves\n\
+             └── chapters/b.ves\n"
+        );
+        assert_eq!(
+            graph.to_dot(),
+            "digraph vesti {\n    \"main.ves\";\n    \"chapters/a.ves\";\n    \
+             \"chapters/b.ves\";\n    \"chapters/c.tex\" [style=dashed];\n    \
+             \"main.ves\" -> \"chapters/a.ves\";\n    \"main.ves\" -> \"chapters/b.ves\";\n    \
+             \"chapters/a.ves\" -> \"chapters/c.tex\";\n    \
+             \"chapters/a.ves\" -> \"chapters/b.ves\";\n}\n"
+        );
+
+        // a document from the standard input is not read again
+        let arena = Arena::new();
+        let source = "document\n\\input{chapters/a}\nchapter import \"chapters/b.ves\"\n";
+        let latex = Parser::new(Lexer::new(source), &arena)
+            .parse_latex()
+            .unwrap();
+        let graph = DepGraph::build_from_latex(&dir.join("<stdin>"), &latex).unwrap();
+        assert_eq!(
+            graph.to_tree(),
+            "<stdin>\n\
+             ├── chapters/a.ves\n\
+             │   ├── chapters/c.tex (missing)\n\
+             │   └── chapters/b.ves\n\
+             └── chapters/b.ves\n"
+        );
+
+        fs::write(dir.join("chapters/b.ves"), "foo\n\\input{main}\n").unwrap();
+        let graph = DepGraph::build(&main_file).unwrap();
+        let err = graph.check_cycle().unwrap_err();
+        assert!(err.contains("main.ves:3:1 includes chapters/a.ves"));
+        assert!(err.contains("chapters/b.ves:2:1 includes main.ves"));
+        let cycle: Vec<(String, usize)> = graph
+            .find_cycle()
+            .unwrap()
+            .into_iter()
+            .map(|(file, include)| (graph.display_name(file), include.span.start.row()))
+            .collect();
+        assert_eq!(
+            cycle,
+            vec![
+                (String::from("main.ves"), 3),
+                (String::from("chapters/a.ves"), 3),
+                (String::from("chapters/b.ves"), 2),
+            ]
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_resolve_imports() {
+        let dir = env::temp_dir().join(format!("vesti-modules-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main_file = dir.join("main.ves");
+        fs::write(
+            dir.join("defs.ves"),
+            "defun pair (#!1#!2) { (#!1, #!2) }\n\\input{more}\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("more.ves"),
+            "docstartmode\n\\section{More}\\label{sec:more}\n",
+        )
+        .unwrap();
+        fs::write(dir.join("chap.ves"), "\\chapter{Chap}\\label{chap:a}\n").unwrap();
+
+        let configure = |_: &mut Parser| {};
+        let source = "docclass book\ndocument\n\\input{defs}\nchapter import \"chap.ves\"\n";
+        let graph = DepGraph::resolve_imports(&main_file, source, &configure)
+            .unwrap()
+            .unwrap();
+        let definitions = graph.imported_definitions();
+        assert_eq!(
+            definitions.functions.into_iter().collect::<Vec<_>>(),
+            vec![(String::from("pair"), 2)]
+        );
+        assert_eq!(
+            definitions.labels.into_iter().collect::<Vec<_>>(),
+            vec![String::from("chap:a"), String::from("sec:more")]
+        );
+
+        // functions of a module get its prefix
+        let source = "docclass book\nimport defs.ves as m\ndocument\n";
+        let graph = DepGraph::resolve_imports(&main_file, source, &configure)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            graph
+                .imported_definitions()
+                .functions
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![(String::from("mpair"), 2)]
+        );
+
+        // a source without includes is not parsed at all
+        let source = "docclass book\ndocument\n\\[\n";
+        assert!(DepGraph::resolve_imports(&main_file, source, &configure)
+            .unwrap()
+            .is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
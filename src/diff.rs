@@ -0,0 +1,408 @@
+// Render the difference between an expected latex file and a generated one.
+// Changed lines are compared word by word so that a small regression can be
+// found directly from the terminal or CI logs.
+
+use crate::error;
+use std::path::Path;
+use std::process::Command;
+
+const BOLD_TEXT: &str = "\x1b[1m";
+const REMOVED_COLOR: &str = "\x1b[38;5;9m";
+const ADDED_COLOR: &str = "\x1b[38;5;10m";
+const HUNK_COLOR: &str = "\x1b[38;5;12m";
+const HIGHLIGHT: &str = "\x1b[7m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Edit<T> {
+    Same(T),
+    Removed(T),
+    Added(T),
+}
+
+// Furthest reaching x of each diagonal `k = x - y`, where `k` can be negative
+struct Frontier {
+    offset: isize,
+    xs: Vec<usize>,
+}
+
+impl Frontier {
+    fn new(max_d: usize) -> Self {
+        Self {
+            offset: max_d as isize,
+            xs: vec![0; 2 * max_d + 1],
+        }
+    }
+
+    fn get(&self, k: isize) -> usize {
+        self.xs[(k + self.offset) as usize]
+    }
+
+    fn set(&mut self, k: isize, x: usize) {
+        self.xs[(k + self.offset) as usize] = x;
+    }
+}
+
+// Myers' diff in linear space. The middle snake of the shortest edit script
+// splits both slices, and each half is solved again, so that large generated
+// files do not need a table of the size of both of them.
+fn diff_slices<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> Vec<Edit<&'a T>> {
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = Frontier::new(max_d);
+    let mut backward = Frontier::new(max_d);
+    let mut output = Vec::with_capacity(old.len().max(new.len()));
+    diff_range(old, new, &mut forward, &mut backward, &mut output);
+    output
+}
+
+fn diff_range<'a, T: PartialEq>(
+    old: &'a [T],
+    new: &'a [T],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+    output: &mut Vec<Edit<&'a T>>,
+) {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    output.extend(old[..prefix].iter().map(Edit::Same));
+    if old_mid.is_empty() || new_mid.is_empty() {
+        output.extend(old_mid.iter().map(Edit::Removed));
+        output.extend(new_mid.iter().map(Edit::Added));
+    } else {
+        let (x, y) = middle_snake(old_mid, new_mid, forward, backward);
+        diff_range(&old_mid[..x], &new_mid[..y], forward, backward, output);
+        diff_range(&old_mid[x..], &new_mid[y..], forward, backward, output);
+    }
+    output.extend(old[old.len() - suffix..].iter().map(Edit::Same));
+}
+
+// Search the shortest edit script from both ends at once, and return the
+// point where two searches meet. Both slices are not empty and differ in
+// their first and last items, so the point splits them into smaller problems.
+fn middle_snake<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    forward: &mut Frontier,
+    backward: &mut Frontier,
+) -> (usize, usize) {
+    let (n, m) = (old.len(), new.len());
+    let delta = n as isize - m as isize;
+    let is_odd = delta % 2 != 0;
+    forward.set(1, 0);
+    backward.set(1, 0);
+
+    for d in 0..=(n + m).div_ceil(2) as isize {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward.get(k - 1) < forward.get(k + 1)) {
+                forward.get(k + 1)
+            } else {
+                forward.get(k - 1) + 1
+            };
+            let y = (x as isize - k) as usize;
+            let start = (x, y);
+            if x < n && y < m {
+                x += old[x..]
+                    .iter()
+                    .zip(&new[y..])
+                    .take_while(|(o, n)| o == n)
+                    .count();
+            }
+            forward.set(k, x);
+            if is_odd && (k - delta).abs() < d && x + backward.get(delta - k) >= n {
+                return start;
+            }
+        }
+
+        // `x` of the backward search is counted from the end of slices
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward.get(k - 1) < backward.get(k + 1)) {
+                backward.get(k + 1)
+            } else {
+                backward.get(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            if x < n && y < m {
+                let common = old[..n - x]
+                    .iter()
+                    .rev()
+                    .zip(new[..m - y].iter().rev())
+                    .take_while(|(o, n)| o == n)
+                    .count();
+                x += common;
+                y += common;
+            }
+            backward.set(k, x);
+            if !is_odd && (k - delta).abs() <= d && x + forward.get(delta - k) >= n {
+                return (n - x, m - y);
+            }
+        }
+    }
+    unreachable!("two searches of an edit script always meet")
+}
+
+// Split a line into words and whitespaces so that joining them gives the line back.
+fn split_words(line: &str) -> Vec<&str> {
+    let mut output = Vec::new();
+    let mut start = 0;
+    let mut is_space = None;
+    for (i, chr) in line.char_indices() {
+        if is_space.is_some_and(|is_space| is_space != chr.is_whitespace()) {
+            output.push(&line[start..i]);
+            start = i;
+        }
+        is_space = Some(chr.is_whitespace());
+    }
+    if start < line.len() {
+        output.push(&line[start..]);
+    }
+    output
+}
+
+fn word_diff_lines(old: &str, new: &str) -> (String, String) {
+    let old_words = split_words(old);
+    let new_words = split_words(new);
+    let mut removed = String::from(REMOVED_COLOR) + "- ";
+    let mut added = String::from(ADDED_COLOR) + "+ ";
+
+    for edit in diff_slices(&old_words, &new_words) {
+        match edit {
+            Edit::Same(word) => {
+                removed += word;
+                added += word;
+            }
+            Edit::Removed(word) => {
+                removed = removed + BOLD_TEXT + HIGHLIGHT + word + RESET_COLOR + REMOVED_COLOR;
+            }
+            Edit::Added(word) => {
+                added = added + BOLD_TEXT + HIGHLIGHT + word + RESET_COLOR + ADDED_COLOR;
+            }
+        }
+    }
+
+    (removed + RESET_COLOR, added + RESET_COLOR)
+}
+
+// Changed lines are shown with `context` unchanged lines around them.
+// If the numbers of removed and added lines in a change are equal, then
+// each pair of lines is compared word by word.
+pub fn render_diff(expected: &str, actual: &str, context: usize) -> String {
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let edits = diff_slices(&expected_lines, &actual_lines);
+
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Same(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+
+    // Merge ranges of edits which should be printed
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(edits.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut output = String::new();
+    for (start, end) in hunks {
+        let line_num = edits[..start]
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Added(_)))
+            .count()
+            + 1;
+        output = output + HUNK_COLOR + &format!("@@ line {} @@", line_num) + RESET_COLOR + "\n";
+
+        let mut i = start;
+        while i < end {
+            if let Edit::Same(line) = edits[i] {
+                output = output + "  " + line + "\n";
+                i += 1;
+                continue;
+            }
+
+            let mut removed: Vec<&str> = Vec::new();
+            let mut added: Vec<&str> = Vec::new();
+            while i < end {
+                match edits[i] {
+                    Edit::Removed(line) => removed.push(line),
+                    Edit::Added(line) => added.push(line),
+                    Edit::Same(_) => break,
+                }
+                i += 1;
+            }
+
+            if removed.len() == added.len() {
+                let mut added_output = String::new();
+                for (old, new) in removed.iter().zip(&added) {
+                    let (removed_line, added_line) = word_diff_lines(old, new);
+                    output = output + &removed_line + "\n";
+                    added_output = added_output + &added_line + "\n";
+                }
+                output += &added_output;
+            } else {
+                for line in removed {
+                    output = output + REMOVED_COLOR + "- " + line + RESET_COLOR + "\n";
+                }
+                for line in added {
+                    output = output + ADDED_COLOR + "+ " + line + RESET_COLOR + "\n";
+                }
+            }
+        }
+    }
+
+    output
+}
+
+// Run an external diff tool like `diff -u` or `delta` instead of the builtin one.
+pub fn run_diff_tool(tool: &str, expected: &Path, actual: &Path) -> error::Result<()> {
+    let mut words = tool.split_whitespace();
+    let program = match words.next() {
+        Some(program) => program,
+        None => return Ok(()),
+    };
+    Command::new(program)
+        .args(words)
+        .arg(expected)
+        .arg(actual)
+        .status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words("\\begin{document}  foo bar"),
+            vec!["\\begin{document}", "  ", "foo", " ", "bar"]
+        );
+        assert_eq!(split_words(""), Vec::<&str>::new());
+    }
+
+    // Compare with the length of the longest common subsequence of small slices
+    #[test]
+    fn test_diff_slices() {
+        let mut seed = 0x2545f491u32;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        for _ in 0..500 {
+            let old: Vec<u32> = (0..next() % 12).map(|_| next() % 4).collect();
+            let new: Vec<u32> = (0..next() % 12).map(|_| next() % 4).collect();
+            let edits = diff_slices(&old, &new);
+
+            let (mut old_back, mut new_back, mut same) = (Vec::new(), Vec::new(), 0);
+            for edit in edits {
+                match edit {
+                    Edit::Same(item) => {
+                        old_back.push(*item);
+                        new_back.push(*item);
+                        same += 1;
+                    }
+                    Edit::Removed(item) => old_back.push(*item),
+                    Edit::Added(item) => new_back.push(*item),
+                }
+            }
+            assert_eq!((&old_back, &new_back), (&old, &new));
+
+            let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in (0..old.len()).rev() {
+                for j in (0..new.len()).rev() {
+                    lcs[i][j] = if old[i] == new[j] {
+                        lcs[i + 1][j + 1] + 1
+                    } else {
+                        lcs[i + 1][j].max(lcs[i][j + 1])
+                    };
+                }
+            }
+            assert_eq!(same, lcs[0][0], "{:?} {:?}", old, new);
+        }
+    }
+
+    // A table of both slices would take hundreds of gigabytes here
+    #[test]
+    fn test_diff_large_slices() {
+        let old: Vec<usize> = (0..300000).collect();
+        let new: Vec<usize> = (0..300000)
+            .map(|i| if i % 1000 == 0 { i + 1 } else { i })
+            .collect();
+        let edits = diff_slices(&old, &new);
+        assert_eq!(
+            edits
+                .iter()
+                .filter(|edit| matches!(edit, Edit::Same(_)))
+                .count(),
+            old.len() - 300
+        );
+    }
+
+    #[test]
+    fn test_render_diff() {
+        let expected = "a\nb\nc\nd\ne\nf\n";
+        let actual = "a\nb\nc\nX\ne\nf\ng\n";
+
+        assert_eq!(render_diff(expected, expected, 1), "");
+        assert_eq!(
+            render_diff(expected, actual, 1),
+            format!(
+                "{hunk}@@ line 3 @@{reset}\n  c\n\
+                 {red}- {bold}{hl}d{reset}{red}{reset}\n\
+                 {green}+ {bold}{hl}X{reset}{green}{reset}\n  e\n  f\n\
+                 {green}+ g{reset}\n",
+                hunk = HUNK_COLOR,
+                red = REMOVED_COLOR,
+                green = ADDED_COLOR,
+                bold = BOLD_TEXT,
+                hl = HIGHLIGHT,
+                reset = RESET_COLOR,
+            )
+        );
+    }
+
+    #[test]
+    fn test_word_diff_lines() {
+        let (removed, added) = word_diff_lines("\\section{Intro} text", "\\section{Intro} test");
+        assert_eq!(
+            removed,
+            format!(
+                "{red}- \\section{{Intro}} {bold}{hl}text{reset}{red}{reset}",
+                red = REMOVED_COLOR,
+                bold = BOLD_TEXT,
+                hl = HIGHLIGHT,
+                reset = RESET_COLOR,
+            )
+        );
+        assert_eq!(
+            added,
+            format!(
+                "{green}+ \\section{{Intro}} {bold}{hl}test{reset}{green}{reset}",
+                green = ADDED_COLOR,
+                bold = BOLD_TEXT,
+                hl = HIGHLIGHT,
+                reset = RESET_COLOR,
+            )
+        );
+    }
+}
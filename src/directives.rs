@@ -0,0 +1,102 @@
+// Directives like `%!vesti output: thesis_main` and `%!vesti watch: false`
+// which change how a file is compiled, so that options of a file live with it
+// rather than in long command lines. They are read before the file is parsed,
+// and invalid ones are left to the parser which reports them.
+
+use crate::commands::{CompileOption, OutputTarget};
+use crate::lexer::token::TokenType;
+use crate::lexer::Lexer;
+use crate::parser::ast::FileDirective;
+use crate::parser::parse_file_directive;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FileOverrides {
+    pub output: Option<String>,
+    pub watch: Option<bool>,
+}
+
+impl FileOverrides {
+    // Directives are placed before `document`, so the rest is not lexed
+    pub fn scan(source: &str) -> Self {
+        let mut overrides = Self::default();
+        let tokens = Lexer::new(source)
+            .map(|tok| tok.token)
+            .take_while(|token| token.toktype != TokenType::Document);
+        for token in tokens {
+            if token.toktype != TokenType::FileDirective {
+                continue;
+            }
+            match parse_file_directive(&token.literal) {
+                Ok(FileDirective::Output(name)) => overrides.output = Some(name.to_string()),
+                Ok(FileDirective::Watch(watch)) => overrides.watch = Some(watch),
+                _ => {}
+            }
+        }
+        overrides
+    }
+
+    // A file which cannot be read has no directives. Its error is reported
+    // when it is compiled.
+    pub fn of_file(file_name: &Path) -> Self {
+        fs::read_to_string(file_name)
+            .map(|source| Self::scan(&source))
+            .unwrap_or_default()
+    }
+
+    // The output is next to the source as the default one is
+    pub fn output_file_name(&self, file_name: &Path, target: OutputTarget) -> PathBuf {
+        let output = target.output_file_name(file_name);
+        match (&self.output, output.extension()) {
+            (Some(name), Some(ext)) => {
+                output.with_file_name(format!("{}.{}", name, ext.to_string_lossy()))
+            }
+            _ => output,
+        }
+    }
+}
+
+// `%!vesti watch:` of each file overrides `--continuous` and `vesti watch`
+pub fn apply_watch_overrides(files: &mut [(PathBuf, CompileOption)]) {
+    for (file_name, option) in files {
+        if let Some(watch) = FileOverrides::of_file(file_name).watch {
+            option.is_continuous = watch;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_overrides() {
+        let source = "%!vesti output: thesis_main\n%!vesti watch: false\n%!engine: xelatex\n\
+                      docclass article\ndocument\n%!vesti output: ignored\n";
+        let overrides = FileOverrides::scan(source);
+        assert_eq!(
+            overrides,
+            FileOverrides {
+                output: Some(String::from("thesis_main")),
+                watch: Some(false),
+            }
+        );
+        assert_eq!(
+            overrides.output_file_name(Path::new("src/thesis.ves"), OutputTarget::Latex),
+            Path::new("src/thesis_main.tex")
+        );
+        assert_eq!(
+            overrides.output_file_name(Path::new("thesis.ves"), OutputTarget::Markdown),
+            Path::new("thesis_main.md")
+        );
+
+        // invalid directives are reported by the parser
+        let overrides = FileOverrides::scan("%!vesti output: ../main\n%!vesti watch: no\ndocument");
+        assert_eq!(overrides, FileOverrides::default());
+        assert_eq!(
+            overrides.output_file_name(Path::new("thesis.ves"), OutputTarget::Latex),
+            Path::new("thesis.tex")
+        );
+    }
+}
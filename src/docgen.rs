@@ -0,0 +1,305 @@
+// `vesti doc` writes a reference of macros which the files of a project define
+// by `defun` and `defenv`. Each entry has the signature of the macro and the
+// text of `///` comments right before its definition.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use crate::lexer::token::TokenType;
+use crate::lexer::Lexer;
+use crate::parser::argspec::{parse_arg_spec, ArgSpec};
+use crate::parser::ast::{walk_latex, Latex, Statement};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for DocFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(format!(
+                "unknown format `{}`. Use one of markdown and html",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MacroDoc {
+    pub name: String,
+    pub is_environment: bool,
+    // how the macro is used, like `\vec{#1}`
+    pub signature: String,
+    // default value of the optional argument of an environment
+    pub default: Option<String>,
+    pub doc: Option<String>,
+    // file of the definition relative to the project
+    pub file: PathBuf,
+}
+
+// Vesti files of the project which define macros. Hidden directories like
+// `.vesti` are skipped.
+pub fn project_sources(dir: &Path) -> error::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let walker = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.')
+        });
+    for entry in walker {
+        let entry = entry.map_err(|_| VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::TakeFilesErr),
+            location: None,
+        })?;
+        if entry.path().extension().is_some_and(|ext| ext == "ves") {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Files like chapters have no preamble, so they are not parsed at all
+pub fn has_definitions(source: &str) -> bool {
+    Lexer::new(source).any(|tok| matches!(tok.token.toktype, TokenType::Defun | TokenType::Defenv))
+}
+
+// `#1#2` is written as `{#1}{#2}`. Parameters with delimiters are kept as they are.
+fn function_signature(name: &str, params: &str) -> String {
+    let is_plain = params.split('#').enumerate().all(|(idx, param)| {
+        if idx == 0 {
+            param.is_empty()
+        } else {
+            param.len() == 1 && param.chars().all(|chr| chr.is_ascii_digit())
+        }
+    });
+    if !is_plain {
+        return format!("\\{}{}", name, params);
+    }
+    let mut signature = format!("\\{}", name);
+    for param in params.split('#').skip(1) {
+        let _ = write!(signature, "{{#{}}}", param);
+    }
+    signature
+}
+
+// Optional arguments of a specification are written in brackets, like
+// `\name*[#2]{#3}` for `s O{x} m`. `head` is `\name` or `\begin{name}`.
+fn spec_signature(head: String, spec: &str) -> String {
+    let mut signature = head;
+    let args = match parse_arg_spec(spec) {
+        Some(args) => args,
+        None => return signature,
+    };
+    for (idx, arg) in args.iter().enumerate() {
+        let num = idx + 1;
+        let _ = match arg {
+            ArgSpec::Mandatory => write!(signature, "{{#{}}}", num),
+            ArgSpec::Optional(_) => write!(signature, "[#{}]", num),
+            ArgSpec::Star(token) => write!(signature, "{}", token),
+            ArgSpec::Delimited { open, close, .. } => {
+                write!(signature, "{}#{}{}", open, num, close)
+            }
+            ArgSpec::Until(tokens) => write!(signature, "#{}{}", num, tokens),
+            ArgSpec::Verbatim => write!(signature, "|#{}|", num),
+        };
+    }
+    signature
+}
+
+fn environment_signature(name: &str, args_num: u8, has_optional: bool) -> String {
+    let mut signature = format!("\\begin{{{}}}", name);
+    for num in 1..=args_num {
+        if num == 1 && has_optional {
+            signature.push_str("[#1]");
+        } else {
+            let _ = write!(signature, "{{#{}}}", num);
+        }
+    }
+    signature
+}
+
+pub fn macro_docs(latex: &Latex, file: &Path) -> Vec<MacroDoc> {
+    let mut docs = Vec::new();
+    walk_latex(latex, &mut |stmt| match stmt {
+        Statement::FunctionDefine {
+            name,
+            params,
+            spec,
+            doc,
+            ..
+        } => docs.push(MacroDoc {
+            name: name.to_string(),
+            is_environment: false,
+            signature: match spec {
+                Some(spec) => spec_signature(format!("\\{}", name), spec),
+                None => function_signature(name, params),
+            },
+            default: None,
+            doc: doc.map(String::from),
+            file: file.to_path_buf(),
+        }),
+        Statement::EnvironmentDefine {
+            name,
+            args_num,
+            optional_arg,
+            spec,
+            doc,
+            ..
+        } => docs.push(MacroDoc {
+            name: name.to_string(),
+            is_environment: true,
+            signature: match spec {
+                Some(spec) => spec_signature(format!("\\begin{{{}}}", name), spec),
+                None => environment_signature(name, *args_num, optional_arg.is_some()),
+            },
+            default: optional_arg.map(String::from),
+            doc: doc.map(String::from),
+            file: file.to_path_buf(),
+        }),
+        _ => {}
+    });
+    docs
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn render(docs: &[MacroDoc], format: DocFormat) -> String {
+    let mut output = String::new();
+    let functions = docs.iter().filter(|doc| !doc.is_environment);
+    let environments = docs.iter().filter(|doc| doc.is_environment);
+    match format {
+        DocFormat::Markdown => {
+            output.push_str("# Macros\n");
+            for (title, docs) in [
+                ("Functions", functions.collect::<Vec<_>>()),
+                ("Environments", environments.collect()),
+            ] {
+                if docs.is_empty() {
+                    continue;
+                }
+                let _ = write!(output, "\n## {}\n", title);
+                for doc in docs {
+                    let _ = write!(
+                        output,
+                        "\n### `{}`\n\n```latex\n{}\n```\n\n",
+                        doc.name, doc.signature
+                    );
+                    if let Some(default) = &doc.default {
+                        let _ = writeln!(output, "The default of `#1` is `{}`.\n", default);
+                    }
+                    if let Some(text) = &doc.doc {
+                        let _ = writeln!(output, "{}\n", text);
+                    }
+                    let _ = writeln!(output, "Defined in `{}`.", doc.file.display());
+                }
+            }
+        }
+        DocFormat::Html => {
+            output.push_str(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>Macros</title>\n</head>\n<body>\n<h1>Macros</h1>\n",
+            );
+            for (title, docs) in [
+                ("Functions", functions.collect::<Vec<_>>()),
+                ("Environments", environments.collect()),
+            ] {
+                if docs.is_empty() {
+                    continue;
+                }
+                let _ = writeln!(output, "<h2>{}</h2>", title);
+                for doc in docs {
+                    let _ = writeln!(
+                        output,
+                        "<h3 id=\"{0}\"><code>{0}</code></h3>\n<pre><code>{1}</code></pre>",
+                        escape_html(&doc.name),
+                        escape_html(&doc.signature)
+                    );
+                    if let Some(default) = &doc.default {
+                        let _ = writeln!(
+                            output,
+                            "<p>The default of <code>#1</code> is <code>{}</code>.</p>",
+                            escape_html(default)
+                        );
+                    }
+                    for paragraph in doc.doc.iter().flat_map(|text| text.split("\n\n")) {
+                        let _ = writeln!(output, "<p>{}</p>", escape_html(paragraph.trim()));
+                    }
+                    let _ = writeln!(
+                        output,
+                        "<p>Defined in <code>{}</code>.</p>",
+                        escape_html(&doc.file.display().to_string())
+                    );
+                }
+            }
+            output.push_str("</body>\n</html>\n");
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_macro_docs() {
+        let source = r#"docclass article
+/// A bold vector.
+///
+/// Use it in math mode.
+defun vec (#!1) { \mathbf{#!1} }
+defun pair (#!1#!2) { (#!1, #!2) }
+defun norm [s O{2} m] { \IfBooleanTF{#!1}{#!3}{#!3_{#!2}} }
+  /// A box with a title.
+defenv titled (2, Note) { \fbox{#!1} } { }
+document
+/// is a text here
+"#;
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let latex = parser.parse_latex().unwrap();
+        assert!(has_definitions(source));
+        let docs = macro_docs(&latex, Path::new("main.ves"));
+        assert_eq!(
+            docs.iter()
+                .map(|doc| doc.signature.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "\\vec{#1}",
+                "\\pair{#1}{#2}",
+                "\\norm*[#2]{#3}",
+                "\\begin{titled}[#1]{#2}"
+            ]
+        );
+        assert_eq!(
+            docs[0].doc.as_deref(),
+            Some("A bold vector.\n\nUse it in math mode.")
+        );
+        assert_eq!(docs[1].doc, None);
+        assert_eq!(docs[3].default.as_deref(), Some("Note"));
+
+        let markdown = render(&docs, DocFormat::Markdown);
+        assert!(markdown.contains(
+            "### `vec`\n\n```latex\n\\vec{#1}\n```\n\nA bold vector.\n\nUse it in math mode.\n\n"
+        ));
+        assert!(markdown.contains("## Environments\n\n### `titled`"));
+        let html = render(&docs, DocFormat::Html);
+        assert!(html.contains("<p>A bold vector.</p>\n<p>Use it in math mode.</p>"));
+    }
+}
@@ -0,0 +1,253 @@
+// `vesti doctor` checks the TeX installation which vesti uses: programs found
+// in `PATH` with their versions, and whether a small document compiles with
+// each engine. Each problem is reported with what can be done about it.
+
+use crate::config::VestiConfig;
+use crate::engine::{self, EnginePlan, InteractionMode, LatexEngineType};
+use crate::error::pretty_print::pretty_print;
+use crate::texdist::{self, TexDist};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+const ENGINES: [LatexEngineType; 4] = [
+    LatexEngineType::Pdflatex,
+    LatexEngineType::Xelatex,
+    LatexEngineType::Lualatex,
+    LatexEngineType::Tectonic,
+];
+const BIB_TOOLS: [&str; 2] = ["biber", "bibtex"];
+const TEST_DOCUMENT: &str = "docclass article\ndocument\nHello, vesti!\n";
+// Tectonic downloads its bundle at the first run, which takes a while
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(120);
+// Lines of the log shown when the test document is failed
+const LOG_LINES: usize = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Ok,
+    Missing,
+    Failed(Vec<String>),
+}
+
+#[derive(Debug)]
+pub struct ProgramReport {
+    pub name: &'static str,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+    pub status: Status,
+    // what can be done if the status is not ok
+    pub help: Option<String>,
+}
+
+// The first line of `--version`, like `pdfTeX 3.141592653-2.6-1.40.26 (TeX Live 2024)`
+fn version_of(path: &Path) -> Option<String> {
+    let output = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(String::from)
+}
+
+// Compile the test document in `dir`. The directory is kept if it is failed,
+// so that the log can be read.
+fn compile_test_document(engine: LatexEngineType, dir: &Path) -> Status {
+    let tex_file = dir.join("doctor.tex");
+    let compiled = crate::make_latex_format(TEST_DOCUMENT)
+        .and_then(|latex| {
+            fs::create_dir_all(dir)?;
+            fs::write(&tex_file, latex)?;
+            Ok(())
+        })
+        .and_then(|_| {
+            let mut plan = EnginePlan::new(engine, InteractionMode::Batchmode, &tex_file, None);
+            plan.timeout = Some(COMPILE_TIMEOUT);
+            plan.run()
+        });
+    match compiled {
+        Ok(()) => {
+            let _ = fs::remove_dir_all(dir);
+            Status::Ok
+        }
+        Err(err) => {
+            let mut lines = engine::last_lines(&tex_file.with_extension("log"), LOG_LINES);
+            if lines.is_empty() {
+                lines.push(pretty_print(None, err, None));
+            }
+            Status::Failed(lines)
+        }
+    }
+}
+
+fn missing_help(name: &str, dist: Option<(TexDist, &Path)>) -> String {
+    match (name, dist) {
+        ("tectonic", _) => {
+            String::from("install tectonic from https://tectonic-typesetting.github.io")
+        }
+        (_, Some((dist, bin_dir))) => {
+            // packages are named after the TeX engine rather than the format
+            let package = match name {
+                "pdflatex" => "pdftex",
+                "xelatex" => "xetex",
+                "lualatex" => "luatex",
+                _ => name,
+            };
+            format!(
+                "install it with `{} {}`",
+                dist.install_command(bin_dir),
+                package
+            )
+        }
+        (_, None) => String::from("run `vesti setup-tex` to install TinyTeX"),
+    }
+}
+
+fn failed_help(engine: LatexEngineType, dir: &Path) -> String {
+    let log = dir.join("doctor.log");
+    match engine {
+        LatexEngineType::Tectonic => format!(
+            "tectonic needs the network at the first run; see {}",
+            log.display()
+        ),
+        _ => format!(
+            "see {}; `vesti setup-tex --force` installs a working TinyTeX",
+            log.display()
+        ),
+    }
+}
+
+// Programs are checked one by one, and engines compile the test document
+// unless `compile` is false
+pub fn check_programs(compile: bool) -> Vec<ProgramReport> {
+    let dist_dir = texdist::active_bin_dir();
+    let dist = dist_dir
+        .as_deref()
+        .map(|dir| (texdist::distribution_of(dir), dir));
+    let work_dir = env::temp_dir().join(format!("vesti-doctor-{}", std::process::id()));
+
+    let mut reports = Vec::new();
+    let programs = ENGINES
+        .iter()
+        .map(|engine| (engine.command(), Some(*engine)))
+        .chain(BIB_TOOLS.iter().map(|tool| (*tool, None)));
+    for (name, engine) in programs {
+        let path = texdist::find_in_path(name);
+        let version = path.as_deref().and_then(version_of);
+        let (status, help) = match (&path, engine) {
+            (None, _) => (Status::Missing, Some(missing_help(name, dist))),
+            (Some(_), Some(engine)) if compile => {
+                let dir = work_dir.join(name);
+                match compile_test_document(engine, &dir) {
+                    Status::Ok => (Status::Ok, None),
+                    status => (status, Some(failed_help(engine, &dir))),
+                }
+            }
+            (Some(_), _) => (Status::Ok, None),
+        };
+        reports.push(ProgramReport {
+            name,
+            path,
+            version,
+            status,
+            help,
+        });
+    }
+    // It is empty unless some engine is failed
+    let _ = fs::remove_dir(&work_dir);
+    reports
+}
+
+impl fmt::Display for ProgramReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Ok => "ok",
+            Status::Missing => "missing",
+            Status::Failed(_) => "failed",
+        };
+        let line = format!(
+            "{:<9} {:<8} {}",
+            self.name,
+            status,
+            self.version.as_deref().unwrap_or_default()
+        );
+        f.write_str(line.trim_end())?;
+        if let Some(path) = &self.path {
+            write!(f, "\n          {}", path.display())?;
+        }
+        if let Status::Failed(lines) = &self.status {
+            for line in lines {
+                write!(f, "\n          | {}", line)?;
+            }
+        }
+        if let Some(help) = &self.help {
+            write!(f, "\n          help: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+// Print the report. Returns false if no engine can compile a document.
+pub fn run_doctor(compile: bool) -> bool {
+    match texdist::active_bin_dir() {
+        Some(bin_dir) => println!(
+            "distribution: {} in {}",
+            texdist::distribution_of(&bin_dir),
+            bin_dir.display()
+        ),
+        None => println!("distribution: none found"),
+    }
+    match VestiConfig::find_file(Path::new(".")) {
+        Some(config_path) => match VestiConfig::from_file(&config_path) {
+            Ok(_) => println!("config:       {}", config_path.display()),
+            Err(err) => println!("{}", pretty_print(None, err, Some(&config_path))),
+        },
+        None => println!("config:       no vesti.toml"),
+    }
+    println!();
+
+    let reports = check_programs(compile);
+    for report in &reports {
+        println!("{}", report);
+    }
+    reports.iter().any(|report| {
+        report.status == Status::Ok && ENGINES.iter().any(|engine| engine.command() == report.name)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_program_report() {
+        let report = ProgramReport {
+            name: "xelatex",
+            path: None,
+            version: None,
+            status: Status::Missing,
+            help: Some(missing_help("xelatex", None)),
+        };
+        assert_eq!(
+            report.to_string(),
+            "xelatex   missing\n          help: run `vesti setup-tex` to install TinyTeX"
+        );
+
+        let bin_dir = Path::new("/texlive/bin");
+        assert_eq!(
+            missing_help("biber", Some((TexDist::TexLive, bin_dir))),
+            format!(
+                "install it with `{} install biber`",
+                bin_dir.join("tlmgr").display()
+            )
+        );
+    }
+}
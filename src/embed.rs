@@ -0,0 +1,100 @@
+// Embedded assets are written in the vesti source as base64 blocks.
+// They are materialized into the build directory at compile time.
+
+use crate::error;
+use crate::parser::ast::{walk_latex, Latex, Statement};
+use std::fs;
+use std::path::{Component, Path};
+
+pub fn decode_base64(source: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(source.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for chr in source.chars().filter(|chr| !chr.is_whitespace()) {
+        let value = match chr {
+            'A'..='Z' => chr as u32 - 'A' as u32,
+            'a'..='z' => chr as u32 - 'a' as u32 + 26,
+            '0'..='9' => chr as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            '=' => {
+                padding += 1;
+                continue;
+            }
+            _ => return None,
+        };
+        // no data is allowed after the padding
+        if padding > 0 {
+            return None;
+        }
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if padding > 2 {
+        return None;
+    }
+    Some(output)
+}
+
+// Embedded file names must stay inside of the build directory.
+pub fn is_valid_embed_path(path: &str) -> bool {
+    let path = Path::new(path);
+    !path.as_os_str().is_empty()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+pub fn materialize(latex: &Latex, build_dir: &Path) -> error::Result<()> {
+    let mut files = Vec::new();
+    walk_latex(latex, &mut |stmt| {
+        if let Statement::EmbeddedFile { name, data } = stmt {
+            files.push((name, data));
+        }
+    });
+
+    for (name, data) in files {
+        let path = build_dir.join(name);
+        // Do not touch the file if it is not changed
+        if fs::read(&path).ok().as_ref() == Some(data) {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("dmVzdGk=").unwrap(), b"vesti");
+        assert_eq!(decode_base64("dmVz\n dGkh").unwrap(), b"vesti!");
+        assert_eq!(decode_base64("dmVzdA==").unwrap(), b"vest");
+        assert_eq!(decode_base64("dm#zdGk="), None);
+        assert_eq!(decode_base64("dm=Vz"), None);
+    }
+
+    #[test]
+    fn test_embed_path() {
+        assert!(is_valid_embed_path("logo.png"));
+        assert!(is_valid_embed_path("./figures/logo.png"));
+        assert!(!is_valid_embed_path("../logo.png"));
+        assert!(!is_valid_embed_path("/etc/passwd"));
+        assert!(!is_valid_embed_path(""));
+    }
+}
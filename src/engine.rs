@@ -0,0 +1,990 @@
+// Run a latex engine on the generated latex file to make a pdf.
+// If the document cites something, bibtex or biber runs between engine passes,
+// and so does makeindex if it has index entries.
+
+use crate::console::{self, Verbosity};
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use crate::metadata::Metadata;
+use crate::parser::ast::{walk_latex, BibBackend, FileDirective, Latex, Statement};
+use crate::shutdown::{self, Waited};
+use crate::timing::Timings;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+// Packages, environments and functions which need a specific engine
+const LUALATEX_FEATURES: [&str; 6] = [
+    "luacode",
+    "luacode*",
+    "luatexja",
+    "luatexja-fontspec",
+    "luaotfload",
+    "directlua",
+];
+const XELATEX_FEATURES: [&str; 8] = [
+    "fontspec",
+    "unicode-math",
+    "polyglossia",
+    "xeCJK",
+    "ctex",
+    "setmainfont",
+    "setCJKmainfont",
+    "setmathfont",
+];
+
+// The engine reruns at most this number of times to resolve cross references
+const MAX_RERUN: usize = 3;
+// Lines of the log shown when the engine is killed by the timeout
+const LOG_TAIL_LINES: usize = 8;
+// Lines joined into one warning at most
+const MAX_WARNING_LINES: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatexEngineType {
+    Latex,
+    Pdflatex,
+    Xelatex,
+    Lualatex,
+    // The `tectonic` program, which downloads what a document needs, so a TeX
+    // distribution is not required. It runs bibtex and reruns by itself, so
+    // vesti only runs it once.
+    Tectonic,
+    // ConTeXt also runs as many passes as it needs. This is used by `--target context`.
+    Context,
+}
+
+impl LatexEngineType {
+    pub fn command(&self) -> &'static str {
+        match self {
+            Self::Latex => "latex",
+            Self::Pdflatex => "pdflatex",
+            Self::Xelatex => "xelatex",
+            Self::Lualatex => "lualatex",
+            Self::Tectonic => "tectonic",
+            Self::Context => "context",
+        }
+    }
+
+    // The engine given by `%!engine: ...` at the top of the file
+    pub fn from_latex(latex: &Latex) -> Option<Self> {
+        latex.iter().rev().find_map(|stmt| match &stmt.node {
+            Statement::FileDirective(FileDirective::Engine(engine)) => Some(*engine),
+            _ => None,
+        })
+    }
+
+    // Pick an engine from the features which the document uses.
+    // The feature which makes the choice is also returned to report it.
+    pub fn detect(latex: &Latex) -> (Self, Option<String>) {
+        let mut xelatex_feature: Option<String> = None;
+        let mut lualatex_feature: Option<String> = None;
+        walk_latex(latex, &mut |stmt| {
+            let name = match stmt {
+                Statement::Usepackage { name, .. } => *name,
+                Statement::Environment { name, .. } => *name,
+                Statement::LatexFunction { name, .. } => name.trim_end(),
+                _ => return,
+            };
+            if lualatex_feature.is_none() && LUALATEX_FEATURES.contains(&name) {
+                lualatex_feature = Some(name.to_string());
+            } else if xelatex_feature.is_none() && XELATEX_FEATURES.contains(&name) {
+                xelatex_feature = Some(name.to_string());
+            }
+        });
+
+        // lua features cannot be used in xelatex, but xelatex features are
+        // also available in lualatex.
+        if lualatex_feature.is_some() {
+            (Self::Lualatex, lualatex_feature)
+        } else if xelatex_feature.is_some() {
+            (Self::Xelatex, xelatex_feature)
+        } else {
+            (Self::Pdflatex, None)
+        }
+    }
+}
+
+impl FromStr for LatexEngineType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latex" => Ok(Self::Latex),
+            "pdflatex" => Ok(Self::Pdflatex),
+            "xelatex" => Ok(Self::Xelatex),
+            "lualatex" => Ok(Self::Lualatex),
+            "tectonic" => Ok(Self::Tectonic),
+            "context" => Ok(Self::Context),
+            _ => Err(format!(
+                "unknown engine `{}`. Use one of latex, pdflatex, xelatex, lualatex, tectonic and context",
+                s
+            )),
+        }
+    }
+}
+
+// How the engine interacts with the user when an error occurs.
+// Only `errorstopmode` waits for the input of the user.
+#[derive(Clone, Copy, PartialEq, Default, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InteractionMode {
+    Batchmode,
+    #[default]
+    Nonstopmode,
+    Errorstopmode,
+}
+
+impl InteractionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Batchmode => "batchmode",
+            Self::Nonstopmode => "nonstopmode",
+            Self::Errorstopmode => "errorstopmode",
+        }
+    }
+
+    // The last interaction keyword in the document wins
+    pub fn from_latex(latex: &Latex) -> Option<Self> {
+        latex.iter().rev().find_map(|stmt| match &stmt.node {
+            Statement::InteractionMode(mode) => Some(*mode),
+            _ => None,
+        })
+    }
+}
+
+impl FromStr for InteractionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "batchmode" => Ok(Self::Batchmode),
+            "nonstopmode" => Ok(Self::Nonstopmode),
+            "errorstopmode" => Ok(Self::Errorstopmode),
+            _ => Err(format!(
+                "unknown interaction mode `{}`. Use one of batchmode, nonstopmode and errorstopmode",
+                s
+            )),
+        }
+    }
+}
+
+// The bibliography backend runs only if a bib file is imported and something is cited.
+pub fn bib_backend_to_run(latex: &Latex) -> Option<BibBackend> {
+    let mut backend = None;
+    walk_latex(latex, &mut |stmt| {
+        if let Statement::ImportBib { backend: b, .. } | Statement::BibStyle { backend: b, .. } =
+            stmt
+        {
+            backend = Some(*b);
+        }
+    });
+
+    if Metadata::from_latex(latex).citations.is_empty() {
+        None
+    } else {
+        backend
+    }
+}
+
+// makeindex runs only if vesti adds `\makeindex`, which it does when the
+// document or its chapters have index entries
+pub fn has_index_to_make(latex: &Latex) -> bool {
+    latex.iter().any(|stmt| stmt.node == Statement::MakeIndex)
+}
+
+// `canonicalize` on windows makes a verbatim path like `\\?\C:\doc`, which
+// engines cannot read, so the prefix is removed
+pub fn tex_path(path: &Path) -> PathBuf {
+    let verbatim = match path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+        Some(verbatim) => verbatim,
+        None => return path.to_path_buf(),
+    };
+    match verbatim.strip_prefix(r"UNC\") {
+        Some(share) => PathBuf::from(format!(r"\\{}", share)),
+        None => PathBuf::from(verbatim),
+    }
+}
+
+// A command which vesti runs in the directory of the latex file
+pub struct EngineCommand {
+    pub program: &'static str,
+    pub args: Vec<String>,
+    pub log_file: PathBuf,
+    pub is_interactive: bool,
+    // Reading and writing files outside of the working directory are not allowed
+    pub is_safe: bool,
+}
+
+impl EngineCommand {
+    fn run(&self, plan: &EnginePlan) -> error::Result<()> {
+        let mut command = Command::new(self.program);
+        command.args(&self.args).current_dir(&plan.work_dir);
+        if self.is_safe {
+            // kpathsea refuses absolute paths and `..` in the paranoid mode
+            command.env("openin_any", "p").env("openout_any", "p");
+        }
+        if let Some(dir) = &plan.texinputs {
+            // The empty path at the end makes the engine search default directories too
+            if let Ok(paths) = env::join_paths([tex_path(dir).as_path(), Path::new("")]) {
+                command.env("TEXINPUTS", paths);
+            }
+        }
+        if plan.verbosity >= Verbosity::Trace {
+            console::print_block(&format!("{} | $ {}", plan.label, self));
+        }
+        run_command(&mut command, self, plan)
+    }
+}
+
+impl fmt::Display for EngineCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            if arg.contains(char::is_whitespace) {
+                write!(f, " '{}'", arg)?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Commands to make a pdf. The engine runs first, and if there is a bibliography
+// or an index, their commands and the engine run again. Then the engine reruns at most
+// `max_rerun` times while the log asks it.
+pub struct EnginePlan {
+    pub work_dir: PathBuf,
+    // Additional directory where the engine finds input files like images
+    pub texinputs: Option<PathBuf>,
+    pub engine: EngineCommand,
+    pub bib: Option<EngineCommand>,
+    pub index: Option<EngineCommand>,
+    pub max_rerun: usize,
+    // Each command is killed if it runs longer than this
+    pub timeout: Option<Duration>,
+    pub verbosity: Verbosity,
+    // Lines of the engine are prefixed with this when they are printed
+    pub label: String,
+}
+
+impl EnginePlan {
+    pub fn new(
+        engine: LatexEngineType,
+        interaction: InteractionMode,
+        tex_file: &Path,
+        bib_backend: Option<BibBackend>,
+    ) -> Self {
+        let work_dir = match tex_file.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let tex_name = tex_file
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let job_name = tex_file
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let log_file = tex_file.with_extension("log");
+        let tex_name_label = tex_name.clone();
+
+        if engine == LatexEngineType::Tectonic {
+            return Self {
+                work_dir: work_dir.to_path_buf(),
+                texinputs: None,
+                engine: EngineCommand {
+                    program: engine.command(),
+                    args: vec![
+                        String::from("--keep-logs"),
+                        String::from("--synctex"),
+                        tex_name,
+                    ],
+                    log_file,
+                    is_interactive: false,
+                    is_safe: false,
+                },
+                bib: None,
+                index: None,
+                max_rerun: 0,
+                timeout: None,
+                verbosity: Verbosity::default(),
+                label: tex_name_label,
+            };
+        }
+
+        let is_interactive = interaction == InteractionMode::Errorstopmode;
+        if engine == LatexEngineType::Context {
+            let mut args = vec![String::from("--synctex")];
+            if !is_interactive {
+                args.push(format!("--{}", interaction.as_str()));
+            }
+            args.push(tex_name);
+            return Self {
+                work_dir: work_dir.to_path_buf(),
+                texinputs: None,
+                engine: EngineCommand {
+                    program: engine.command(),
+                    args,
+                    log_file,
+                    is_interactive,
+                    is_safe: false,
+                },
+                bib: None,
+                index: None,
+                max_rerun: 0,
+                timeout: None,
+                verbosity: Verbosity::default(),
+                label: tex_name_label,
+            };
+        }
+
+        // `vesti synctex-edit` relates positions in the pdf to vesti files with this
+        let mut args = vec![
+            String::from("-synctex=1"),
+            format!("-interaction={}", interaction.as_str()),
+        ];
+        if !is_interactive {
+            args.push(String::from("-halt-on-error"));
+        }
+        args.push(tex_name);
+
+        let bib = bib_backend.map(|backend| EngineCommand {
+            program: match backend {
+                BibBackend::Bibtex => "bibtex",
+                BibBackend::Biber => "biber",
+            },
+            args: vec![job_name],
+            log_file: tex_file.with_extension("blg"),
+            is_interactive: false,
+            is_safe: false,
+        });
+
+        Self {
+            work_dir: work_dir.to_path_buf(),
+            texinputs: None,
+            engine: EngineCommand {
+                program: engine.command(),
+                args,
+                log_file,
+                is_interactive,
+                is_safe: false,
+            },
+            bib,
+            index: None,
+            max_rerun: MAX_RERUN,
+            timeout: None,
+            verbosity: Verbosity::default(),
+            label: tex_name_label,
+        }
+    }
+
+    // Run the engine without shell escape and without reading files outside of
+    // the working directory. `--untrusted` of tectonic disables shell escape even
+    // if the document or other options ask it.
+    pub fn make_safe(&mut self) {
+        let flag = if self.engine.program == LatexEngineType::Context.command() {
+            "--paranoid"
+        } else if self.engine.program == LatexEngineType::Tectonic.command() {
+            "--untrusted"
+        } else {
+            "-no-shell-escape"
+        };
+        self.engine.args.insert(0, String::from(flag));
+        self.engine.is_safe = true;
+        if let Some(bib) = &mut self.bib {
+            bib.is_safe = true;
+        }
+        if let Some(index) = &mut self.index {
+            index.is_safe = true;
+        }
+    }
+
+    // makeindex sorts the entries which the first pass writes into `.idx`. Tectonic
+    // and ConTeXt run their passes by themselves, so nothing can run between them.
+    pub fn add_index(&mut self) {
+        let engine = self.engine.program;
+        if engine == LatexEngineType::Tectonic.command()
+            || engine == LatexEngineType::Context.command()
+        {
+            return;
+        }
+        self.index = Some(EngineCommand {
+            program: "makeindex",
+            args: vec![self
+                .engine
+                .log_file
+                .with_extension("idx")
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()],
+            log_file: self.engine.log_file.with_extension("ilg"),
+            is_interactive: false,
+            is_safe: self.engine.is_safe,
+        });
+    }
+
+    // The number of engine passes without reruns
+    pub fn passes(&self) -> usize {
+        if self.bib.is_some() || self.index.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    pub fn run(&self) -> error::Result<()> {
+        self.run_timed(&mut Timings::default())
+    }
+
+    // Each run of a command is added to `timings` like `pdflatex #2`
+    pub fn run_timed(&self, timings: &mut Timings) -> error::Result<()> {
+        let mut passes = 0;
+        let mut run_engine = |timings: &mut Timings| {
+            passes += 1;
+            let name = format!("{} #{}", self.engine.program, passes);
+            timings.measure(name, || self.engine.run(self))
+        };
+        run_engine(timings)?;
+        if let Some(bib) = &self.bib {
+            timings.measure(bib.program, || bib.run(self))?;
+        }
+        if let Some(index) = &self.index {
+            timings.measure(index.program, || index.run(self))?;
+        }
+        if self.passes() > 1 {
+            run_engine(timings)?;
+        }
+        for _ in 0..self.max_rerun {
+            if !needs_rerun(&self.engine.log_file) {
+                break;
+            }
+            run_engine(timings)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for EnginePlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "working dir: {}", self.work_dir.display())?;
+        if let Some(dir) = &self.texinputs {
+            writeln!(f, "TEXINPUTS:   {}", dir.display())?;
+        }
+        if let Some(timeout) = self.timeout {
+            writeln!(f, "timeout:     {}s", timeout.as_secs())?;
+        }
+        writeln!(f, "commands:")?;
+        writeln!(f, "  {}", self.engine)?;
+        if let Some(bib) = &self.bib {
+            writeln!(f, "  {}", bib)?;
+        }
+        if let Some(index) = &self.index {
+            writeln!(f, "  {}", index)?;
+        }
+        if self.passes() > 1 {
+            writeln!(f, "  {}", self.engine)?;
+        }
+        if self.max_rerun > 0 {
+            write!(
+                f,
+                "engine passes: {} (and at most {} reruns if cross references change)",
+                self.passes(),
+                self.max_rerun
+            )
+        } else {
+            write!(f, "engine passes: {}", self.passes())
+        }
+    }
+}
+
+// An interactive command shares the terminal with vesti. Otherwise the output
+// is discarded, or printed line by line with the file name if it is verbose.
+fn run_command(
+    command: &mut Command,
+    engine: &EngineCommand,
+    plan: &EnginePlan,
+) -> error::Result<()> {
+    let name = engine.program;
+    let log_file = &engine.log_file;
+    let timeout = plan.timeout;
+    let is_streamed = !engine.is_interactive && plan.verbosity >= Verbosity::Verbose;
+    if !engine.is_interactive {
+        let output = || {
+            if is_streamed {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            }
+        };
+        command
+            .stdin(Stdio::null())
+            .stdout(output())
+            .stderr(output());
+    }
+    let mut child = command.spawn().map_err(|err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            VestiErr {
+                err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::EngineNotFoundErr(
+                    name.to_string(),
+                )),
+                location: None,
+            }
+        } else {
+            VestiErr::from(err)
+        }
+    })?;
+
+    let mut readers = Vec::new();
+    if is_streamed {
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(stream_lines(stdout, plan.label.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(stream_lines(stderr, plan.label.clone()));
+        }
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let waited = shutdown::wait_child(&mut child, deadline);
+    // The pipes are closed when the engine exits, so readers finish soon
+    for reader in readers {
+        let _ = reader.join();
+    }
+    match waited? {
+        Waited::Exited(status) if status.success() => Ok(()),
+        Waited::Interrupted => Err(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::CancelledErr(name.to_string())),
+            location: None,
+        }),
+        Waited::TimedOut => Err(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::EngineTimeoutErr {
+                engine: name.to_string(),
+                secs: timeout.unwrap_or_default().as_secs(),
+                log_tail: last_lines(log_file, LOG_TAIL_LINES),
+            }),
+            location: None,
+        }),
+        Waited::Exited(_) => Err(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::LatexCompileErr {
+                engine: name.to_string(),
+                log: log_file.display().to_string(),
+            }),
+            location: None,
+        }),
+    }
+}
+
+fn stream_lines<R: Read + Send + 'static>(output: R, label: String) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut output = BufReader::new(output);
+        let mut line = Vec::new();
+        // Engines do not always print valid utf8
+        while matches!(output.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+            let text = String::from_utf8_lossy(&line);
+            console::print_engine_line(&label, text.trim_end());
+            line.clear();
+        }
+    })
+}
+
+// Warnings in the log of the last run. A warning continues to the next lines
+// until its sentence ends, since engines break long lines.
+pub fn log_warnings(log_file: &Path) -> Vec<String> {
+    match fs::read(log_file) {
+        Ok(log) => warnings_in_log(&String::from_utf8_lossy(&log)),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn warnings_in_log(log: &str) -> Vec<String> {
+    let mut warnings: Vec<String> = Vec::new();
+    let mut lines = log.lines();
+    while let Some(line) = lines.next() {
+        if !line.contains("Warning:") {
+            continue;
+        }
+        let mut warning = line.trim().to_string();
+        for _ in 0..MAX_WARNING_LINES {
+            if warning.ends_with('.') {
+                break;
+            }
+            match lines.next() {
+                Some(next) if !next.trim().is_empty() => {
+                    // Package warnings continue with `(package)` and spaces
+                    let next = next.trim_start();
+                    let next = match next.strip_prefix('(') {
+                        Some(rest) => rest.split_once(')').map_or(next, |(_, rest)| rest),
+                        None => next,
+                    };
+                    warning.push(' ');
+                    warning.push_str(next.trim());
+                }
+                _ => break,
+            }
+        }
+        if !warnings.contains(&warning) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+// The last non empty lines of a log which show where the engine stopped
+pub fn last_lines(log_file: &Path, count: usize) -> Vec<String> {
+    let log = match fs::read(log_file) {
+        Ok(log) => log,
+        Err(_) => return Vec::new(),
+    };
+    let log = String::from_utf8_lossy(&log);
+    let mut lines: Vec<String> = log
+        .lines()
+        .rev()
+        .filter(|line| !line.trim().is_empty())
+        .take(count)
+        .map(String::from)
+        .collect();
+    lines.reverse();
+    lines
+}
+
+fn needs_rerun(log_file: &Path) -> bool {
+    match fs::read(log_file) {
+        Ok(log) => {
+            let log = String::from_utf8_lossy(&log);
+            log.contains("Rerun to get") || log.contains("Label(s) may have changed")
+        }
+        Err(_) => false,
+    }
+}
+
+// Generated files of a failed compilation are kept at `failed/<name>/`
+// in the build directory so that they are not overwritten by the next run.
+pub struct FailureArtifacts {
+    pub tex_file: PathBuf,
+    pub log_file: Option<PathBuf>,
+    // The line of the kept latex file where the first error occurs
+    pub error_line: Option<usize>,
+}
+
+pub fn retain_failure_artifacts(
+    tex_file: &Path,
+    log_file: &Path,
+) -> error::Result<FailureArtifacts> {
+    let build_dir = match tex_file.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let failed_dir = build_dir
+        .join("failed")
+        .join(tex_file.file_stem().unwrap_or_default());
+    fs::create_dir_all(&failed_dir)?;
+
+    let kept_tex = failed_dir.join(tex_file.file_name().unwrap_or_default());
+    fs::copy(tex_file, &kept_tex)?;
+
+    let (kept_log, error_line) = match fs::read(log_file) {
+        Ok(log) => {
+            let kept_log = failed_dir.join(log_file.file_name().unwrap_or_default());
+            fs::write(&kept_log, &log)?;
+            (
+                Some(kept_log),
+                first_error_line(&String::from_utf8_lossy(&log)),
+            )
+        }
+        Err(_) => (None, None),
+    };
+
+    Ok(FailureArtifacts {
+        tex_file: kept_tex,
+        log_file: kept_log,
+        error_line,
+    })
+}
+
+// TeX reports an error with a line starting with `!`, and the line
+// number appears later as `l.<number>`.
+fn first_error_line(log: &str) -> Option<usize> {
+    log.lines()
+        .skip_while(|line| !line.starts_with('!'))
+        .find_map(|line| {
+            let rest = line.strip_prefix("l.")?;
+            let number: String = rest
+                .chars()
+                .take_while(|chr| chr.is_ascii_digit())
+                .collect();
+            number.parse().ok()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::err_kind::VestiParseErr;
+    use crate::lexer::token::TokenType;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_bib_backend_to_run() {
+        let source1 = "importbib \"refs.bib\" (backend=biber)\ndocument\nSee @[knuth84].";
+        let source2 = "importbib \"refs.bib\"\ndocument\nNothing is cited.";
+        let source3 = "document\nSee \\cite{knuth84}.";
+        let source4 = "bibstyle (numeric, backend: biber)\ndocument\nSee @[knuth84].";
+
+        let arena = Arena::new();
+        let latex1 = Parser::new(Lexer::new(source1), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex2 = Parser::new(Lexer::new(source2), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex3 = Parser::new(Lexer::new(source3), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex4 = Parser::new(Lexer::new(source4), &arena)
+            .parse_latex()
+            .unwrap();
+        assert_eq!(bib_backend_to_run(&latex1), Some(BibBackend::Biber));
+        assert_eq!(bib_backend_to_run(&latex2), None);
+        assert_eq!(bib_backend_to_run(&latex3), None);
+        assert_eq!(bib_backend_to_run(&latex4), Some(BibBackend::Biber));
+    }
+
+    #[test]
+    fn test_tex_path() {
+        assert_eq!(
+            tex_path(Path::new(r"\\?\C:\doc\figures")),
+            PathBuf::from(r"C:\doc\figures")
+        );
+        assert_eq!(
+            tex_path(Path::new(r"\\?\UNC\server\share")),
+            PathBuf::from(r"\\server\share")
+        );
+        assert_eq!(tex_path(Path::new("/home/doc")), PathBuf::from("/home/doc"));
+    }
+
+    #[test]
+    fn test_engine_plan() {
+        let plan1 = EnginePlan::new(
+            LatexEngineType::Pdflatex,
+            InteractionMode::Nonstopmode,
+            Path::new("docs/main.tex"),
+            Some(BibBackend::Biber),
+        );
+        let plan2 = EnginePlan::new(
+            LatexEngineType::Tectonic,
+            InteractionMode::Errorstopmode,
+            Path::new("main.tex"),
+            Some(BibBackend::Bibtex),
+        );
+        let mut plan3 = EnginePlan::new(
+            LatexEngineType::Context,
+            InteractionMode::Batchmode,
+            Path::new("main.tex"),
+            Some(BibBackend::Bibtex),
+        );
+        plan3.make_safe();
+        let mut plan4 = EnginePlan::new(
+            LatexEngineType::Xelatex,
+            InteractionMode::Batchmode,
+            Path::new("main.tex"),
+            None,
+        );
+        plan4.add_index();
+
+        assert_eq!(
+            plan1.to_string(),
+            r#"working dir: docs
+commands:
+  pdflatex -synctex=1 -interaction=nonstopmode -halt-on-error main.tex
+  biber main
+  pdflatex -synctex=1 -interaction=nonstopmode -halt-on-error main.tex
+engine passes: 2 (and at most 3 reruns if cross references change)"#
+        );
+        assert_eq!(
+            plan2.to_string(),
+            r#"working dir: .
+commands:
+  tectonic --keep-logs --synctex main.tex
+engine passes: 1"#
+        );
+        assert_eq!(
+            plan3.to_string(),
+            r#"working dir: .
+commands:
+  context --paranoid --synctex --batchmode main.tex
+engine passes: 1"#
+        );
+        assert_eq!(
+            plan4.to_string(),
+            r#"working dir: .
+commands:
+  xelatex -synctex=1 -interaction=batchmode -halt-on-error main.tex
+  makeindex main.idx
+  xelatex -synctex=1 -interaction=batchmode -halt-on-error main.tex
+engine passes: 2 (and at most 3 reruns if cross references change)"#
+        );
+    }
+
+    #[test]
+    fn test_tectonic_plan() {
+        let plan = EnginePlan::new(
+            LatexEngineType::Tectonic,
+            InteractionMode::Errorstopmode,
+            Path::new("docs/main.tex"),
+            Some(BibBackend::Biber),
+        );
+        assert_eq!(plan.work_dir, Path::new("docs"));
+        assert_eq!(plan.engine.program, "tectonic");
+        assert_eq!(
+            plan.engine.args,
+            vec!["--keep-logs", "--synctex", "main.tex"]
+        );
+        assert_eq!(plan.engine.log_file, Path::new("docs/main.log"));
+        // tectonic never waits for the input of the user
+        assert!(!plan.engine.is_interactive);
+        assert!(plan.bib.is_none());
+        assert_eq!((plan.max_rerun, plan.passes()), (0, 1));
+    }
+
+    #[test]
+    fn test_make_safe() {
+        let engines = [
+            (LatexEngineType::Latex, "-no-shell-escape"),
+            (LatexEngineType::Pdflatex, "-no-shell-escape"),
+            (LatexEngineType::Xelatex, "-no-shell-escape"),
+            (LatexEngineType::Lualatex, "-no-shell-escape"),
+            (LatexEngineType::Tectonic, "--untrusted"),
+            (LatexEngineType::Context, "--paranoid"),
+        ];
+        for (engine, flag) in engines {
+            let mut plan = EnginePlan::new(
+                engine,
+                InteractionMode::Nonstopmode,
+                Path::new("main.tex"),
+                Some(BibBackend::Biber),
+            );
+            plan.add_index();
+            plan.make_safe();
+            assert_eq!(plan.engine.args[0], flag);
+            assert!(plan.engine.is_safe);
+            assert!(plan.bib.iter().chain(&plan.index).all(|cmd| cmd.is_safe));
+        }
+    }
+
+    #[test]
+    fn test_first_error_line() {
+        let log = r#"This is pdfTeX, Version 3.141592653
+l.3 is not an error
+! Undefined control sequence.
+l.12 \foo
+          {bar}
+! Emergency stop.
+l.20"#;
+        assert_eq!(first_error_line(log), Some(12));
+        assert_eq!(first_error_line("no error here\nl.3"), None);
+    }
+
+    #[test]
+    fn test_warnings_in_log() {
+        let log = r#"(./main.aux)
+LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 5.
+
+Package hyperref Warning: Token not allowed in a PDF string (Unicode):
+(hyperref)                removing `math shift' on input line 9.
+
+LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 5.
+
+Overfull \hbox (1.2pt too wide) in paragraph at lines 3--4
+"#;
+        assert_eq!(
+            warnings_in_log(log),
+            vec![
+                String::from("LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 5."),
+                String::from("Package hyperref Warning: Token not allowed in a PDF string (Unicode): removing `math shift' on input line 9."),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detect_engine() {
+        let source1 = "import fontspec\ndocument\nfoo";
+        let source2 = "import fontspec\ndocument\nbegenv luacode\nfoo\nendenv";
+        let source3 = "docclass article\n\\setmainfont{Noto Serif}\ndocument";
+        let source4 = "import amsmath\ndocument\nfoo";
+
+        let arena = Arena::new();
+        let latex1 = Parser::new(Lexer::new(source1), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex2 = Parser::new(Lexer::new(source2), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex3 = Parser::new(Lexer::new(source3), &arena)
+            .parse_latex()
+            .unwrap();
+        let latex4 = Parser::new(Lexer::new(source4), &arena)
+            .parse_latex()
+            .unwrap();
+        assert_eq!(
+            LatexEngineType::detect(&latex1),
+            (LatexEngineType::Xelatex, Some(String::from("fontspec")))
+        );
+        assert_eq!(
+            LatexEngineType::detect(&latex2),
+            (LatexEngineType::Lualatex, Some(String::from("luacode")))
+        );
+        assert_eq!(
+            LatexEngineType::detect(&latex3),
+            (LatexEngineType::Xelatex, Some(String::from("setmainfont")))
+        );
+        assert_eq!(
+            LatexEngineType::detect(&latex4),
+            (LatexEngineType::Pdflatex, None)
+        );
+    }
+
+    #[test]
+    fn test_interaction_mode_from_latex() {
+        let source1 = "batchmode
+errorstopmode
+document
+a nonstopmode is a text here";
+        let source2 = "document
+batchmode";
+
+        let arena = Arena::new();
+        let latex1 = Parser::new(Lexer::new(source1), &arena)
+            .parse_latex()
+            .unwrap();
+        let err2 = Parser::new(Lexer::new(source2), &arena)
+            .parse_latex()
+            .unwrap_err();
+        assert_eq!(
+            InteractionMode::from_latex(&latex1),
+            Some(InteractionMode::Errorstopmode)
+        );
+        assert_eq!(
+            err2.err_kind,
+            VestiErrKind::ParseErr(VestiParseErr::AfterDocumentErr {
+                got: TokenType::Batchmode
+            })
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use super::VError;
 use crate::lexer::token::TokenType;
+use crate::location::Location;
 
 #[derive(Debug, PartialEq)]
 pub enum VestiErrKind {
@@ -42,11 +43,171 @@ pub enum VestiParseErr {
     BegenvIsNotClosedErr,
     EndenvIsUsedWithoutBegenvPairErr,
     BegenvNameMissErr,
+    IfDirectiveIsNotClosedErr,
+    DirectiveIsUsedWithoutIfPairErr {
+        got: TokenType,
+    },
+    IfConditionMissErr,
+    DepthOutOfRangeErr {
+        got: i64,
+    },
+    NoChapterInDocclassErr {
+        docclass: String,
+    },
+    PackageIsNotImportedErr {
+        name: String,
+    },
+    BegtblIsNotClosedErr,
+    InvalidBase64Err,
+    InvalidEmbedPathErr {
+        path: String,
+    },
+    TextBeforeDocumentErr {
+        text: String,
+    },
+    InvalidImgOptionErr {
+        name: String,
+    },
+    ImageFileNotFoundErr {
+        path: String,
+    },
+    InvalidBibOptionErr {
+        keyword: String,
+        name: String,
+    },
+    InvalidBibBackendErr {
+        name: String,
+    },
+    DuplicatedImportbibErr,
+    PrintbibWithoutImportbibErr,
+    BiblatexNatbibConflictErr,
+    BibstyleConflictErr,
+    RawLatexBlockIsNotClosedErr,
+    InvalidAttributeErr,
+    CodeblockIsNotClosedErr,
+    DefinitionNameMissErr,
+    UnsafePathErr {
+        path: String,
+    },
+    ExecuteIsNotClosedErr,
+    ExecuteNotAllowedErr {
+        lang: String,
+    },
+    InterpreterNotFoundErr {
+        lang: String,
+    },
+    ExecuteFailedErr {
+        lang: String,
+        msg: String,
+    },
+    CircularIncludeErr {
+        cycle: Vec<String>,
+    },
+    FileDirectiveAfterDocumentErr,
+    UnknownFileDirectiveErr {
+        name: String,
+    },
+    InvalidFileDirectiveValueErr {
+        key: String,
+        value: String,
+    },
+    InvalidChapterPathErr {
+        path: String,
+    },
+    NestedChapterErr,
+    InvalidArgSpecErr {
+        spec: String,
+    },
+    RedefinitionParamsErr,
+    NestedMathFontErr {
+        outer: String,
+        inner: String,
+    },
+    InvalidQuantityErr {
+        quantity: String,
+    },
+    InvalidUnitErr {
+        unit: String,
+        part: String,
+    },
+    DuplicatedMetadataErr {
+        name: String,
+    },
+    DuplicatedDocumentErr,
+    AfterDocumentErr {
+        got: TokenType,
+    },
+    UnclosedDelimiterErr {
+        open: String,
+        expected: TokenType,
+        // where the delimiter should be closed, or the end of the file
+        before: Option<Location>,
+    },
+    UnopenedDelimiterErr {
+        got: String,
+    },
+    InvalidImportTexOptionErr {
+        name: String,
+    },
+    TexFileNotFoundErr {
+        path: String,
+    },
+    SplitTexWithoutDocumentErr,
+    InvalidIndexTermErr {
+        term: String,
+    },
+    InvalidModulePathErr {
+        path: String,
+    },
+    InvalidModulePrefixErr {
+        prefix: String,
+    },
+    ModuleNotFoundErr {
+        path: String,
+    },
+    ModuleWithBodyErr,
+    NestedModuleErr,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, PartialEq)]
 pub enum VestiCommandUtilErr {
     IOErr(std::io::ErrorKind),
     NoFilenameInputErr,
     TakeFilesErr,
+    ConfigParseErr(String),
+    EngineNotFoundErr(String),
+    LatexCompileErr {
+        engine: String,
+        log: String,
+    },
+    PdfCompressErr {
+        tool: String,
+        msg: String,
+    },
+    FileAlreadyExistsErr(String),
+    ConfigNotFoundErr,
+    TargetNotFoundErr(String),
+    TemplateNotFoundErr(String),
+    DefinitionNotFoundErr(String),
+    ArgumentCountMismatchErr {
+        expected: usize,
+        got: usize,
+    },
+    TexSetupErr {
+        step: String,
+        msg: String,
+    },
+    ImageConvertErr {
+        tool: String,
+        msg: String,
+    },
+    SynctexErr(String),
+    CancelledErr(String),
+    EngineTimeoutErr {
+        engine: String,
+        secs: u64,
+        log_tail: Vec<String>,
+    },
+    ChapterNotFoundErr(String),
 }
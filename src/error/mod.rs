@@ -1,5 +1,6 @@
 pub mod err_kind;
 pub mod pretty_print;
+pub mod warning;
 
 use crate::lexer::token::TokenType;
 use crate::location::Span;
@@ -61,6 +62,21 @@ impl VError for VestiErrKind {
     }
 }
 
+// How a delimiter the parser expects is written in vesti
+fn closing_token(expected: &TokenType) -> Option<&'static str> {
+    match expected {
+        TokenType::Doublequote => Some("`\"`"),
+        TokenType::Rsqbrace => Some("`]`"),
+        TokenType::Rbrace => Some("`}`"),
+        TokenType::Rparen => Some("`)`"),
+        TokenType::TextMathEnd => Some("`\\)` or `mnd`"),
+        TokenType::InlineMathEnd => Some("`\\]` or `dmnd`"),
+        TokenType::RightBig => Some("`#)` or `#}`"),
+        TokenType::Etxt => Some("`etxt`"),
+        _ => None,
+    }
+}
+
 impl VError for VestiParseErr {
     fn err_code(&self) -> u16 {
         match self {
@@ -76,6 +92,58 @@ impl VError for VestiParseErr {
             Self::BegenvIsNotClosedErr => 0x0109,
             Self::EndenvIsUsedWithoutBegenvPairErr => 0x0109,
             Self::BegenvNameMissErr => 0x0110,
+            Self::IfDirectiveIsNotClosedErr => 0x0111,
+            Self::DirectiveIsUsedWithoutIfPairErr { .. } => 0x0111,
+            Self::IfConditionMissErr => 0x0112,
+            Self::DepthOutOfRangeErr { .. } => 0x0113,
+            Self::NoChapterInDocclassErr { .. } => 0x0114,
+            Self::PackageIsNotImportedErr { .. } => 0x0115,
+            Self::BegtblIsNotClosedErr => 0x0116,
+            Self::InvalidBase64Err => 0x0117,
+            Self::InvalidEmbedPathErr { .. } => 0x0118,
+            Self::TextBeforeDocumentErr { .. } => 0x0119,
+            Self::InvalidImgOptionErr { .. } => 0x011A,
+            Self::ImageFileNotFoundErr { .. } => 0x011B,
+            Self::InvalidBibOptionErr { .. } => 0x011C,
+            Self::InvalidBibBackendErr { .. } => 0x011D,
+            Self::DuplicatedImportbibErr => 0x011E,
+            Self::PrintbibWithoutImportbibErr => 0x011F,
+            Self::FileDirectiveAfterDocumentErr => 0x0120,
+            Self::UnknownFileDirectiveErr { .. } => 0x0121,
+            Self::InvalidFileDirectiveValueErr { .. } => 0x0122,
+            Self::BiblatexNatbibConflictErr => 0x0123,
+            Self::BibstyleConflictErr => 0x0124,
+            Self::RawLatexBlockIsNotClosedErr => 0x0125,
+            Self::InvalidAttributeErr => 0x0126,
+            Self::CodeblockIsNotClosedErr => 0x0127,
+            Self::DefinitionNameMissErr => 0x0128,
+            Self::UnsafePathErr { .. } => 0x0129,
+            Self::ExecuteIsNotClosedErr => 0x012A,
+            Self::ExecuteNotAllowedErr { .. } => 0x012B,
+            Self::InterpreterNotFoundErr { .. } => 0x012C,
+            Self::ExecuteFailedErr { .. } => 0x012D,
+            Self::CircularIncludeErr { .. } => 0x012E,
+            Self::InvalidChapterPathErr { .. } => 0x012F,
+            Self::NestedChapterErr => 0x0130,
+            Self::InvalidArgSpecErr { .. } => 0x0131,
+            Self::RedefinitionParamsErr => 0x0132,
+            Self::NestedMathFontErr { .. } => 0x0133,
+            Self::InvalidQuantityErr { .. } => 0x0134,
+            Self::InvalidUnitErr { .. } => 0x0135,
+            Self::DuplicatedMetadataErr { .. } => 0x0136,
+            Self::DuplicatedDocumentErr => 0x0137,
+            Self::AfterDocumentErr { .. } => 0x0138,
+            Self::UnclosedDelimiterErr { .. } => 0x0139,
+            Self::UnopenedDelimiterErr { .. } => 0x013A,
+            Self::InvalidImportTexOptionErr { .. } => 0x013B,
+            Self::TexFileNotFoundErr { .. } => 0x013C,
+            Self::SplitTexWithoutDocumentErr => 0x013D,
+            Self::InvalidIndexTermErr { .. } => 0x013E,
+            Self::InvalidModulePathErr { .. } => 0x013F,
+            Self::InvalidModulePrefixErr { .. } => 0x0140,
+            Self::ModuleNotFoundErr { .. } => 0x0141,
+            Self::ModuleWithBodyErr => 0x0142,
+            Self::NestedModuleErr => 0x0143,
         }
     }
     fn err_str(&self) -> String {
@@ -89,15 +157,133 @@ impl VError for VestiParseErr {
             Self::ParseIntErr => String::from("Parsing integer error occurs"),
             Self::ParseFloatErr => String::from("Parsing float error occurs"),
             Self::InvalidTokToParse { got } => format!("Type `{:?}` is not parsable", got),
-            Self::BracketMismatchErr { expected } => {
-                format!("Cannot find `{:?}` delimiter", expected)
-            }
+            Self::BracketMismatchErr { expected } => match closing_token(expected) {
+                Some(close) => format!("Cannot find {} to close the delimiter", close),
+                None => format!("Cannot find `{:?}` delimiter", expected),
+            },
             Self::BracketNumberMatchedErr => String::from("Delimiter pair does not matched"),
             Self::BegenvIsNotClosedErr => String::from("`begenv` is not closed"),
             Self::EndenvIsUsedWithoutBegenvPairErr => {
                 String::from("`endenv` is used without `begenv` pair")
             }
             Self::BegenvNameMissErr => String::from("Missing environment name"),
+            Self::IfDirectiveIsNotClosedErr => String::from("`#if` is not closed"),
+            Self::DirectiveIsUsedWithoutIfPairErr { got } => {
+                format!("`{:?}` is used without `#if` pair", got)
+            }
+            Self::IfConditionMissErr => String::from("Missing condition of `#if`"),
+            Self::DepthOutOfRangeErr { got } => format!("Depth `{}` is out of range", got),
+            Self::NoChapterInDocclassErr { docclass } => {
+                format!("`minitoc` cannot be used in `{}`", docclass)
+            }
+            Self::PackageIsNotImportedErr { name } => {
+                format!("Package `{}` is not imported", name)
+            }
+            Self::BegtblIsNotClosedErr => String::from("`begtbl` is not closed"),
+            Self::InvalidBase64Err => String::from("Embedded data is not a valid base64"),
+            Self::InvalidEmbedPathErr { path } => {
+                format!("Cannot embed a file into `{}`", path)
+            }
+            Self::TextBeforeDocumentErr { text } => {
+                format!("Text `{}` is found before `document`", text)
+            }
+            Self::InvalidImgOptionErr { name } => {
+                format!("`{}` is not an option of `importimg`", name)
+            }
+            Self::ImageFileNotFoundErr { path } => {
+                format!("Cannot find the image file `{}`", path)
+            }
+            Self::InvalidBibOptionErr { keyword, name } => {
+                format!("`{}` is not an option of `{}`", name, keyword)
+            }
+            Self::InvalidBibBackendErr { name } => {
+                format!("`{}` is not a bibliography backend", name)
+            }
+            Self::DuplicatedImportbibErr => String::from("`importbib` is used more than once"),
+            Self::PrintbibWithoutImportbibErr => {
+                String::from("`printbib` is used without `importbib`")
+            }
+            Self::BiblatexNatbibConflictErr => {
+                String::from("`biblatex` and `natbib` cannot be used together")
+            }
+            Self::BibstyleConflictErr => {
+                String::from("`bibstyle` conflicts with the bibliography configuration")
+            }
+            Self::RawLatexBlockIsNotClosedErr => String::from("`latex%` block is not closed"),
+            Self::InvalidAttributeErr => String::from("Invalid attribute"),
+            Self::CodeblockIsNotClosedErr => String::from("`codeblock` is not closed"),
+            Self::DefinitionNameMissErr => String::from("Name of the definition is missing"),
+            Self::UnsafePathErr { path } => {
+                format!("`{}` cannot be read in the safe mode", path)
+            }
+            Self::ExecuteIsNotClosedErr => String::from("`execute` block is not closed"),
+            Self::ExecuteNotAllowedErr { lang } => {
+                format!("`execute({})` block is not allowed to run", lang)
+            }
+            Self::InterpreterNotFoundErr { lang } => {
+                format!("There is no interpreter for `{}`", lang)
+            }
+            Self::ExecuteFailedErr { lang, .. } => format!("`execute({})` block failed", lang),
+            Self::CircularIncludeErr { .. } => String::from("Files include each other in a cycle"),
+            Self::FileDirectiveAfterDocumentErr => {
+                String::from("File directives must be placed before `document`")
+            }
+            Self::UnknownFileDirectiveErr { name } => {
+                format!("`{}` is not a file directive", name)
+            }
+            Self::InvalidFileDirectiveValueErr { key, value } => {
+                format!("`{}` is not a valid value of `{}`", value, key)
+            }
+            Self::InvalidChapterPathErr { path } => {
+                format!("`{}` cannot be imported as a chapter", path)
+            }
+            Self::NestedChapterErr => String::from("Chapters cannot import other chapters"),
+            Self::InvalidArgSpecErr { spec } => {
+                format!("`{}` is not a valid argument specification", spec)
+            }
+            Self::RedefinitionParamsErr => {
+                String::from("`defun!` and `defun?` cannot take delimited parameters")
+            }
+            Self::NestedMathFontErr { outer, inner } => {
+                format!("Math font `{}` is used inside of `{}`", inner, outer)
+            }
+            Self::InvalidQuantityErr { quantity } => {
+                format!("`qty({})` has no value or no unit", quantity)
+            }
+            Self::InvalidUnitErr { unit, part } if part.is_empty() => {
+                format!("Unit `{}` is empty", unit)
+            }
+            Self::InvalidUnitErr { unit, part } => {
+                format!("Cannot read `{}` of the unit `{}`", part, unit)
+            }
+            Self::DuplicatedMetadataErr { name } => {
+                format!("`{}` of the document is given twice", name)
+            }
+            Self::DuplicatedDocumentErr => String::from("`document` is used twice"),
+            Self::AfterDocumentErr { got } => {
+                format!("Type `{:?}` must be placed before `document`", got)
+            }
+            Self::UnclosedDelimiterErr { open, .. } => format!("`{}` is not closed", open),
+            Self::UnopenedDelimiterErr { got } => {
+                format!("`{}` does not close any delimiter", got)
+            }
+            Self::InvalidImportTexOptionErr { name } => {
+                format!("`{}` is not an option of `importtex`", name)
+            }
+            Self::TexFileNotFoundErr { path } => format!("Cannot find the latex file `{}`", path),
+            Self::SplitTexWithoutDocumentErr => {
+                String::from("A latex file cannot be split without `document`")
+            }
+            Self::InvalidIndexTermErr { term } => format!("`{}` is not a valid index entry", term),
+            Self::InvalidModulePathErr { path } => {
+                format!("`{}` is not a path of a vesti module", path)
+            }
+            Self::InvalidModulePrefixErr { prefix } => {
+                format!("`{}` is not a valid module prefix", prefix)
+            }
+            Self::ModuleNotFoundErr { path } => format!("Cannot find the vesti module `{}`", path),
+            Self::ModuleWithBodyErr => String::from("A vesti module cannot have `document`"),
+            Self::NestedModuleErr => String::from("Modules cannot import other modules"),
         }
     }
     fn err_detail_str(&self) -> Vec<String> {
@@ -125,16 +311,20 @@ impl VError for VestiParseErr {
                 ],
                 _ => Vec::new(),
             },
-            Self::BracketMismatchErr { expected } => {
-                vec![format!("Cannot find `{:?}` delimiter", expected)]
-            }
+            Self::BracketMismatchErr { expected } => match closing_token(expected) {
+                Some(close) => vec![
+                    String::from("this delimiter is not closed"),
+                    format!("help: close it with {}", close),
+                ],
+                None => vec![format!("Cannot find `{:?}` delimiter", expected)],
+            },
             Self::BracketNumberMatchedErr => vec![
                 String::from("cannot find a bracket that matches with that one"),
                 String::from("help: close a bracket with an appropriate one"),
             ],
             Self::BegenvIsNotClosedErr => vec![
                 String::from("cannot find `endenv` to close this environment"),
-                String::from("check that `endenv` is properly located"),
+                String::from("help: add `endenv` after the body of this environment"),
             ],
             Self::EndenvIsUsedWithoutBegenvPairErr => vec![
                 String::from("`endenv` is used, but there is no `begenv` to be pair with it"),
@@ -145,6 +335,219 @@ impl VError for VestiParseErr {
                 String::from("find its name part. type its name."),
                 String::from("example: begenv foo"),
             ],
+            Self::IfDirectiveIsNotClosedErr => vec![
+                String::from("cannot find `#endif` to close this directive"),
+                String::from("help: add `#endif` after the lines of this directive"),
+            ],
+            Self::DirectiveIsUsedWithoutIfPairErr { .. } => vec![
+                String::from("there is no `#if` to be pair with this directive"),
+                String::from("help: add `#if` before this directive"),
+            ],
+            Self::IfConditionMissErr => vec![
+                String::from("`#if` is used in here, but vesti cannot"),
+                String::from("find its condition. type a flag name."),
+                String::from("example: #if draft"),
+            ],
+            Self::DepthOutOfRangeErr { .. } => vec![
+                String::from("depth must be between -1 and 5"),
+                String::from("-1 means `part` and 5 means `subparagraph`"),
+            ],
+            Self::NoChapterInDocclassErr { docclass } => vec![
+                format!("docclass `{}` has no chapters", docclass),
+                String::from("help: use a docclass like `book` or `report`"),
+            ],
+            Self::PackageIsNotImportedErr { name } => vec![
+                format!("this statement needs the package `{}`", name),
+                format!("help: add `import {}` before `document`", name),
+            ],
+            Self::BegtblIsNotClosedErr => vec![
+                String::from("cannot find `endtbl` to close this table"),
+                String::from("help: add `endtbl` after the last row of this table"),
+            ],
+            Self::InvalidBase64Err => vec![
+                String::from("only `A-Z`, `a-z`, `0-9`, `+`, `/` and `=` padding"),
+                String::from("can be used in the embedded data"),
+            ],
+            Self::InvalidEmbedPathErr { .. } => vec![
+                String::from("embedded file must be placed in the build directory"),
+                String::from("help: use a relative path without `..`"),
+            ],
+            Self::TextBeforeDocumentErr { .. } => vec![
+                String::from("texts cannot be placed in the preamble"),
+                String::from("help: add `document` before this text to start the body,"),
+                String::from("or add `docstartmode` at the top if this file has no preamble"),
+            ],
+            Self::InvalidImgOptionErr { .. } => vec![
+                String::from("available options are `caption`, `label`, `placement`"),
+                String::from("and options of `\\includegraphics` like `width` or `scale`"),
+            ],
+            Self::UnsafePathErr { .. } => vec![
+                String::from("only files in the directory of this file can be read"),
+                String::from("help: use a relative path without `..`, or compile without `--safe`"),
+            ],
+            Self::ImageFileNotFoundErr { .. } => vec![
+                String::from("the path is relative to the directory of this file"),
+                String::from("check that the image file exists"),
+            ],
+            Self::InvalidBibOptionErr { .. } => {
+                vec![String::from("available options are `style` and `backend`")]
+            }
+            Self::InvalidBibBackendErr { .. } => {
+                vec![String::from("available backends are `bibtex` and `biber`")]
+            }
+            Self::DuplicatedImportbibErr => vec![
+                String::from("only one bibliography file can be imported"),
+                String::from("merge bib files into one if several files are needed"),
+            ],
+            Self::FileDirectiveAfterDocumentErr => {
+                vec![String::from("help: move this line to the top of the file")]
+            }
+            Self::UnknownFileDirectiveErr { .. } => {
+                vec![String::from(
+                    "available directives are `engine`, `output` and `watch`",
+                )]
+            }
+            Self::InvalidFileDirectiveValueErr { key, .. } => {
+                let detail = match key.as_str() {
+                    "output" => "the output is a file name without directories like `thesis_main`",
+                    "watch" => "the value is either `true` or `false`",
+                    _ => "available engines are latex, pdflatex, xelatex, lualatex, tectonic and context",
+                };
+                vec![String::from(detail)]
+            }
+            Self::PrintbibWithoutImportbibErr => vec![String::from(
+                "help: import a bib file in the preamble like `importbib \"refs.bib\"`",
+            )],
+            Self::BiblatexNatbibConflictErr => vec![
+                String::from("`backend: biber` uses biblatex and `backend: bibtex` uses natbib"),
+                String::from("help: remove one of them"),
+            ],
+            Self::BibstyleConflictErr => vec![
+                String::from("`bibstyle` must be used once before `importbib`"),
+                String::from("help: remove `style` and `backend` options of `importbib`"),
+            ],
+            Self::RawLatexBlockIsNotClosedErr => vec![String::from(
+                "help: add a line `%end` with as many `%` as the line `latex%` has",
+            )],
+            Self::InvalidAttributeErr => vec![String::from(
+                "an attribute should be like `#[allow(lint1, lint2)]`",
+            )],
+            Self::CodeblockIsNotClosedErr => {
+                vec![String::from("help: add a line `endcode` after the code")]
+            }
+            Self::DefinitionNameMissErr => {
+                vec![String::from(
+                    "help: write the name like `defun foo { ... }`",
+                )]
+            }
+            Self::ExecuteIsNotClosedErr => {
+                vec![String::from("help: add a line `}` after the code")]
+            }
+            Self::ExecuteNotAllowedErr { .. } => vec![
+                String::from("blocks run programs of this machine when the file is compiled"),
+                String::from("help: compile with `--allow-exec` and without `--safe` if this file is trusted"),
+            ],
+            Self::InterpreterNotFoundErr { lang } => vec![format!(
+                "help: set the interpreter in vesti.toml like `[execute]` and `{} = \"...\"`",
+                lang
+            )],
+            Self::ExecuteFailedErr { msg, .. } => msg.lines().map(String::from).collect(),
+            Self::CircularIncludeErr { cycle } => {
+                let mut detail = vec![String::from("the cycle of includes is")];
+                detail.extend(cycle.iter().map(|include| format!("    {}", include)));
+                detail.push(String::from("help: remove one of these includes"));
+                detail
+            }
+            Self::InvalidChapterPathErr { .. } => vec![
+                String::from("the latex file of a chapter is made next to the main one"),
+                String::from("help: use a relative path without `..` nor spaces"),
+            ],
+            Self::NestedChapterErr => vec![
+                String::from("latex cannot `\\include` a file in an included file"),
+                String::from("help: import every chapter in the main file"),
+            ],
+            Self::InvalidArgSpecErr { .. } => vec![
+                String::from("each argument is one of m, o, O{default}, s, t<token>, r<open><close>,"),
+                String::from("R<open><close>{default}, d<open><close>, D<open><close>{default}, u{tokens} and v"),
+                String::from("help: write at most nine arguments like `defun name [s O{x} m] { body }`"),
+            ],
+            Self::RedefinitionParamsErr => vec![
+                String::from("`\\renewcommand` and `\\providecommand` take only parameters like `#!1#!2`"),
+                String::from("help: use an argument specification like `defun! name [m u{;}]`"),
+            ],
+            Self::NestedMathFontErr { .. } => vec![
+                String::from("the inner font replaces the outer one, so the outer one does nothing"),
+                String::from("help: remove one of them"),
+            ],
+            Self::InvalidQuantityErr { .. } => vec![
+                String::from("a quantity is written like `qty(9.81, m/s^2)`"),
+            ],
+            Self::InvalidUnitErr { .. } => vec![
+                String::from("units are symbols like `kg`, `m/s^2` or `J/(mol K)`"),
+                String::from("help: write macros of siunitx like `\\metre\\per\\second` for other units"),
+            ],
+            Self::DuplicatedMetadataErr { .. } => vec![
+                String::from("a document has only one title and one date"),
+                String::from("help: write `author` lines as many as authors"),
+            ],
+            Self::DuplicatedDocumentErr => vec![
+                String::from("the body starts at the first `document`"),
+                String::from("help: remove this line"),
+            ],
+            Self::AfterDocumentErr { got } => vec![
+                String::from("keywords of the preamble are not texts of the body"),
+                format!("help: move `{:?}` before `document` keyword", got),
+            ],
+            Self::UnclosedDelimiterErr {
+                expected, before, ..
+            } => {
+                let close = closing_token(expected).unwrap_or_default();
+                vec![match before {
+                    Some(loc) => format!(
+                        "help: close it with {} before {}:{}",
+                        close,
+                        loc.row(),
+                        loc.column()
+                    ),
+                    None => format!("help: close it with {} before the end of the file", close),
+                }]
+            }
+            Self::UnopenedDelimiterErr { .. } => vec![String::from(
+                "help: remove it, or open a delimiter before it",
+            )],
+            Self::InvalidImportTexOptionErr { .. } => vec![String::from(
+                "available option is `split`, which puts the preamble of the file into the preamble",
+            )],
+            Self::TexFileNotFoundErr { .. } => vec![String::from(
+                "the path is relative to the directory of the vesti file",
+            )],
+            Self::SplitTexWithoutDocumentErr => vec![
+                String::from("this file has no preamble where the one of the latex file goes"),
+                String::from("help: remove `(split)` to paste the whole file"),
+            ],
+            Self::InvalidIndexTermErr { .. } => vec![
+                String::from("each level of an entry separated by `!` should not be empty"),
+                String::from("help: write a subentry like `index \"group!abelian\"`"),
+            ],
+            Self::InvalidModulePathErr { .. } => vec![
+                String::from("a module is a vesti file whose definitions are imported"),
+                String::from("help: write a path ending with `.ves` like `import defs.ves as m`"),
+            ],
+            Self::InvalidModulePrefixErr { .. } => vec![
+                String::from("the prefix is put before names of latex commands"),
+                String::from("help: use only ASCII letters"),
+            ],
+            Self::ModuleNotFoundErr { .. } => vec![String::from(
+                "the path is relative to the directory of the vesti file",
+            )],
+            Self::ModuleWithBodyErr => vec![
+                String::from("a module is pasted into the preamble of the document"),
+                String::from("help: keep only definitions and imports in the module"),
+            ],
+            Self::NestedModuleErr => vec![
+                String::from("prefixes of nested modules would be mixed up"),
+                String::from("help: import every module in the main file"),
+            ],
         }
     }
 }
@@ -155,6 +558,22 @@ impl VError for VestiCommandUtilErr {
             Self::IOErr(_) => 0x0001,
             Self::NoFilenameInputErr => 0x0002,
             Self::TakeFilesErr => 0x0003,
+            Self::ConfigParseErr(_) => 0x0004,
+            Self::EngineNotFoundErr(_) => 0x0005,
+            Self::LatexCompileErr { .. } => 0x0006,
+            Self::PdfCompressErr { .. } => 0x0007,
+            Self::FileAlreadyExistsErr(_) => 0x0008,
+            Self::ConfigNotFoundErr => 0x0009,
+            Self::TargetNotFoundErr(_) => 0x000A,
+            Self::TemplateNotFoundErr(_) => 0x000B,
+            Self::DefinitionNotFoundErr(_) => 0x000C,
+            Self::ArgumentCountMismatchErr { .. } => 0x000D,
+            Self::TexSetupErr { .. } => 0x000E,
+            Self::ImageConvertErr { .. } => 0x000F,
+            Self::SynctexErr(_) => 0x0010,
+            Self::CancelledErr(_) => 0x0011,
+            Self::EngineTimeoutErr { .. } => 0x0012,
+            Self::ChapterNotFoundErr(_) => 0x0013,
         }
     }
     fn err_str(&self) -> String {
@@ -162,6 +581,40 @@ impl VError for VestiCommandUtilErr {
             Self::IOErr(err) => format!("IO error `{:?}` occurs", err),
             Self::NoFilenameInputErr => String::from("No file name or path is given"),
             Self::TakeFilesErr => String::from("Error occurs while taking files"),
+            Self::ConfigParseErr(msg) => format!("Cannot parse `vesti.toml`: {}", msg),
+            Self::EngineNotFoundErr(engine) => format!("Cannot run `{}`", engine),
+            Self::LatexCompileErr { engine, log } => {
+                format!("`{}` failed. See `{}` for details", engine, log)
+            }
+            Self::PdfCompressErr { tool, msg } => {
+                format!("`{}` cannot compress the pdf: {}", tool, msg)
+            }
+            Self::FileAlreadyExistsErr(path) => format!("`{}` already exists", path),
+            Self::ConfigNotFoundErr => String::from("Cannot find `vesti.toml`"),
+            Self::TargetNotFoundErr(name) => {
+                format!("Target `{}` is not declared in `vesti.toml`", name)
+            }
+            Self::TemplateNotFoundErr(name) => format!("Cannot find the template `{}`", name),
+            Self::DefinitionNotFoundErr(name) => {
+                format!("Cannot find the definition of `{}`", name)
+            }
+            Self::ArgumentCountMismatchErr { expected, got } => {
+                format!("Expected {} arguments, but {} are given", expected, got)
+            }
+            Self::TexSetupErr { step, msg } => {
+                format!("Cannot {} the TeX distribution: {}", step, msg)
+            }
+            Self::ImageConvertErr { tool, msg } => {
+                format!("`{}` cannot make the image: {}", tool, msg)
+            }
+            Self::SynctexErr(msg) => format!("SyncTeX failed: {}", msg),
+            Self::CancelledErr(name) => format!("`{}` is killed by the interruption", name),
+            Self::EngineTimeoutErr { engine, secs, .. } => {
+                format!("`{}` is killed since it runs more than {}s", engine, secs)
+            }
+            Self::ChapterNotFoundErr(name) => {
+                format!("Chapter `{}` is not imported by `chapter import`", name)
+            }
         }
     }
     fn err_detail_str(&self) -> Vec<String> {
@@ -171,6 +624,51 @@ impl VError for VestiCommandUtilErr {
                 String::from("it might be a vesti's bug. If so, let me know."),
                 String::from("Report it at https://github.com/e0328eric/vesti"),
             ],
+            Self::FileAlreadyExistsErr(_) => vec![String::from(
+                "help: remove the file or initialize the project in another directory",
+            )],
+            Self::ConfigNotFoundErr => vec![String::from(
+                "help: run `vesti build` in a directory which has `vesti.toml`",
+            )],
+            Self::TargetNotFoundErr(name) => vec![format!(
+                "help: add `[targets.{}]` with its `source` to `vesti.toml`",
+                name
+            )],
+            Self::TemplateNotFoundErr(name) => vec![
+                String::from(
+                    "builtin templates are article, beamer, book, letter, cv and standalone",
+                ),
+                format!("help: add `~/.config/vesti/templates/{}.ves`", name),
+            ],
+            Self::DefinitionNotFoundErr(_) => vec![String::from(
+                "help: define it with `defun` or `defenv` in the preamble of the file",
+            )],
+            Self::EngineNotFoundErr(_) => vec![String::from(
+                "help: install TeX, or run `vesti setup-tex` to install a minimal one",
+            )],
+            Self::TexSetupErr { .. } => vec![String::from(
+                "help: `curl` and `tar` are needed to install the TeX distribution",
+            )],
+            Self::SynctexErr(_) => vec![String::from(
+                "help: compile the file with `--pdf` first, and check that `synctex` is installed",
+            )],
+            Self::EngineTimeoutErr { log_tail, .. } => {
+                let mut detail = Vec::new();
+                if !log_tail.is_empty() {
+                    detail.push(String::from("the log ends with:"));
+                    detail.extend(log_tail.iter().map(|line| format!("    {}", line)));
+                }
+                detail.push(String::from(
+                    "help: the engine might wait for an input or loop forever. Check the last lines of the log",
+                ));
+                detail
+            }
+            Self::ImageConvertErr { .. } => vec![String::from(
+                "help: `pdftoppm` comes with poppler, and `pdfcrop` comes with TeX Live",
+            )],
+            Self::ChapterNotFoundErr(_) => vec![String::from(
+                "help: give `--only` the name of a chapter without `.ves`",
+            )],
             _ => Vec::new(),
         }
     }
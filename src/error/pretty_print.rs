@@ -1,96 +1,371 @@
+use super::err_kind::{VestiErrKind, VestiParseErr};
+use super::warning::VestiWarning;
 use super::VError;
 use super::VestiErr;
 use crate::location::Span;
+use crate::parser::balance;
+use std::env;
+use std::io::{self, IsTerminal};
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 const BOLD_TEXT: &str = "\x1b[1m";
 const ERR_COLOR: &str = "\x1b[38;5;9m";
+const WARN_COLOR: &str = "\x1b[38;5;11m";
 const ERR_TITLE_COLOR: &str = "\x1b[38;5;15m";
 const BLUE_COLOR: &str = "\x1b[38;5;12m";
 const RESET_COLOR: &str = "\x1b[0m";
 
+// Lines of a span which are printed. Longer spans are cut in the middle.
+const MAX_SPAN_LINES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    // Colors only if the output is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "unknown color choice `{}`. Use one of auto, always and never",
+                s
+            )),
+        }
+    }
+}
+
+static COLOR_CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+pub fn set_color_choice(choice: ColorChoice) {
+    COLOR_CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+fn use_color() -> bool {
+    match COLOR_CHOICE.load(Ordering::Relaxed) {
+        x if x == ColorChoice::Always as u8 => true,
+        x if x == ColorChoice::Never as u8 => false,
+        _ => env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+// Escape codes used for a message. All of them are empty without colors.
+struct Palette {
+    bold: &'static str,
+    severity: &'static str,
+    title: &'static str,
+    blue: &'static str,
+    reset: &'static str,
+}
+
+impl Palette {
+    fn new(severity: Severity) -> Self {
+        if !use_color() {
+            return Self {
+                bold: "",
+                severity: "",
+                title: "",
+                blue: "",
+                reset: "",
+            };
+        }
+        Self {
+            bold: BOLD_TEXT,
+            severity: match severity {
+                Severity::Error => ERR_COLOR,
+                Severity::Warning => WARN_COLOR,
+            },
+            title: ERR_TITLE_COLOR,
+            blue: BLUE_COLOR,
+            reset: RESET_COLOR,
+        }
+    }
+}
+
 pub fn pretty_print(
     source: Option<&str>,
     vesti_error: VestiErr,
     filepath: Option<&Path>,
 ) -> String {
-    let lines = source.map(|inner| inner.lines());
+    // The parser stops at the first delimiter which it cannot match, so every
+    // unmatched one in the source is shown instead
+    if let (Some(source), VestiErrKind::ParseErr(err)) = (source, &vesti_error.err_kind) {
+        if matches!(
+            err,
+            VestiParseErr::BracketMismatchErr { .. } | VestiParseErr::BracketNumberMatchedErr
+        ) {
+            let errs = balance::unbalanced_delimiters(source);
+            if !errs.is_empty() {
+                return errs
+                    .into_iter()
+                    .map(|err| print_error(Some(source), err, filepath))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+            }
+        }
+    }
+    print_error(source, vesti_error, filepath)
+}
+
+fn print_error(source: Option<&str>, vesti_error: VestiErr, filepath: Option<&Path>) -> String {
     let VestiErr {
         ref err_kind,
         ref location,
     } = vesti_error;
-    let err_code = err_kind.err_code();
-    let err_str = err_kind.err_str();
+    let title = format!("error[E{0:04X}]", err_kind.err_code());
+
+    print_message(
+        source,
+        (&title, Severity::Error),
+        &err_kind.err_str(),
+        &err_kind.err_detail_str(),
+        location,
+        filepath,
+    )
+}
+
+pub fn pretty_print_warning(
+    source: Option<&str>,
+    warning: &VestiWarning,
+    filepath: Option<&Path>,
+) -> String {
+    print_lint(source, warning, filepath, Severity::Warning)
+}
+
+// A warning whose lint is denied by `vesti.toml` is shown as an error
+pub fn pretty_print_denied(
+    source: Option<&str>,
+    warning: &VestiWarning,
+    filepath: Option<&Path>,
+) -> String {
+    print_lint(source, warning, filepath, Severity::Error)
+}
+
+fn print_lint(
+    source: Option<&str>,
+    warning: &VestiWarning,
+    filepath: Option<&Path>,
+    severity: Severity,
+) -> String {
+    let VestiWarning {
+        ref warn_kind,
+        ref location,
+    } = warning;
+    let title = match severity {
+        Severity::Error => format!("error[{}]", warn_kind.lint_name()),
+        Severity::Warning => format!("warning[{}]", warn_kind.lint_name()),
+    };
+
+    print_message(
+        source,
+        (&title, severity),
+        &warn_kind.warn_str(),
+        &warn_kind.warn_detail_str(),
+        location,
+        filepath,
+    )
+}
+
+fn print_message(
+    source: Option<&str>,
+    (title, severity): (&str, Severity),
+    msg: &str,
+    details: &[String],
+    location: &Option<Span>,
+    filepath: Option<&Path>,
+) -> String {
+    let p = Palette::new(severity);
     let mut output = String::with_capacity(400);
 
     // Make error code and error title format
-    output = output + BOLD_TEXT + ERR_COLOR;
     output += &format!(
-        " error[E{0:04X}]{color:}: {1}",
-        err_code,
-        err_str,
-        color = ERR_TITLE_COLOR
+        "{bold}{color} {title}{title_color}: {msg}{reset}\n",
+        bold = p.bold,
+        color = p.severity,
+        title = title,
+        title_color = p.title,
+        msg = msg,
+        reset = p.reset
     );
-    output = output + RESET_COLOR + "\n";
-
-    if let Some(Span { start, end }) = location {
-        let start_row_num = format!("{} ", start.row());
-
-        // If the filepath of the given input one is found, print it with error location
-        if let Some(m_filepath) = filepath {
-            output = output
-                + &" ".repeat(start_row_num.len())
-                + BOLD_TEXT
-                + BLUE_COLOR
-                + "--> "
-                + RESET_COLOR
-                + m_filepath.to_str().unwrap()
-                + &format!(":{}:{}\n", start.row(), start.column())
+
+    let Span { start, end } = match location {
+        Some(span) => *span,
+        None => {
+            // Errors without a location like failures of engines still say which
+            // file is compiled and how to fix it
+            if let Some(m_filepath) = filepath {
+                output += &format!(
+                    "{}{}  --> {}{}\n",
+                    p.bold,
+                    p.blue,
+                    p.reset,
+                    m_filepath.display()
+                );
+            }
+            for msg in details {
+                output += &format!("{}{}   = {}{}\n", p.bold, p.blue, p.reset, msg);
+            }
+            return output;
         }
+    };
+    let end = if end.row() < start.row() { start } else { end };
+    let gutter = end.row().to_string().len() + 1;
+    let margin = format!("{}{}{}|   ", p.bold, p.blue, " ".repeat(gutter + 1));
+
+    // If the filepath of the given input one is found, print it with error location
+    if let Some(m_filepath) = filepath {
+        output += &format!(
+            "{}{}{}--> {}{}:{}:{}\n",
+            " ".repeat(gutter),
+            p.bold,
+            p.blue,
+            p.reset,
+            m_filepath.display(),
+            start.row(),
+            start.column()
+        );
+    }
+    output += &format!("{}{}{}|\n", p.bold, p.blue, " ".repeat(gutter + 1));
 
-        output = output
-            + BOLD_TEXT
-            + BLUE_COLOR
-            + &" ".repeat(start_row_num.len().saturating_add(1))
-            + "|\n "
-            + &start_row_num
-            + "|   "
-            + RESET_COLOR;
-        if let Some(mut inner) = lines {
-            output += inner.nth(start.row() - 1).unwrap();
+    let lines: Vec<&str> = source.map_or_else(Vec::new, |inner| inner.lines().collect());
+    let line_at = |row: usize| lines.get(row - 1).copied().unwrap_or_default();
+    let rows: Vec<usize> = (start.row()..=end.row()).collect();
+    let is_cut = rows.len() > MAX_SPAN_LINES;
+    for (i, &row) in rows.iter().enumerate() {
+        // Only the first lines and the last line are printed for a long span
+        if is_cut && i == MAX_SPAN_LINES - 1 {
+            output += &format!("{}{}...{}\n", p.bold, p.blue, p.reset);
         }
-        output += "\n";
+        if is_cut && i >= MAX_SPAN_LINES - 1 && row != end.row() {
+            continue;
+        }
+        let line = line_at(row);
+        output += &format!(
+            "{}{} {:<width$}|   {}{}\n",
+            p.bold,
+            p.blue,
+            row,
+            p.reset,
+            line,
+            width = gutter
+        );
+        // The underline spans from the start column to the end column,
+        // and a line in the middle of a span is underlined entirely
+        let first_col = if row == start.row() {
+            start.column()
+        } else {
+            1
+        };
+        let last_col = if row == end.row() {
+            end.column()
+        } else {
+            unicode_width::UnicodeWidthStr::width(line) + 1
+        };
+        if row != end.row() {
+            output += &format!(
+                "{}{}{}{}{}\n",
+                margin,
+                " ".repeat(first_col.saturating_sub(1)),
+                p.severity,
+                "^".repeat(last_col.saturating_sub(first_col).max(1)),
+                p.reset
+            );
+        }
+    }
 
-        // Print an error message with multiple lines
-        let padding_space = end.column().saturating_sub(start.column()) + 1;
-        output = output
-            + BOLD_TEXT
-            + BLUE_COLOR
-            + &" ".repeat(start_row_num.len().saturating_add(1))
-            + "|   "
-            + &" ".repeat(start.column().saturating_sub(1))
-            + ERR_COLOR
-            + &"^".repeat(end.column().saturating_sub(start.column()))
-            + " ";
-
-        for (i, msg) in err_kind.err_detail_str().iter().enumerate() {
-            if i == 0 {
-                output = output + msg + "\n";
-            } else {
-                output = output
-                    + BOLD_TEXT
-                    + BLUE_COLOR
-                    + &" ".repeat(start_row_num.len().saturating_add(1))
-                    + "|   "
-                    + &" ".repeat(start.column().saturating_sub(1))
-                    + ERR_COLOR
-                    + &" ".repeat(padding_space)
-                    + msg
-                    + "\n";
-            }
+    // Print an error message with multiple lines
+    let first_col = if start.row() == end.row() {
+        start.column()
+    } else {
+        1
+    };
+    let carets = end.column().saturating_sub(first_col).max(1);
+    output += &format!(
+        "{}{}{}{} ",
+        margin,
+        " ".repeat(first_col.saturating_sub(1)),
+        p.severity,
+        "^".repeat(carets)
+    );
+    if details.is_empty() {
+        output += "\n";
+    }
+    for (i, msg) in details.iter().enumerate() {
+        if i == 0 {
+            output = output + msg + "\n";
+        } else {
+            output += &format!(
+                "{}{}{}{}\n",
+                margin,
+                " ".repeat(first_col.saturating_sub(1) + carets + 1),
+                p.severity,
+                msg
+            );
         }
     }
-    output += RESET_COLOR;
+    output += p.reset;
 
     output
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::location::Location;
+
+    fn location(row: usize, col: usize) -> Location {
+        let mut loc = Location::default();
+        for _ in 1..row {
+            loc.move_next_line();
+        }
+        for _ in 1..col {
+            loc.move_right(None);
+        }
+        loc
+    }
+
+    #[test]
+    fn test_print_multiline_span() {
+        set_color_choice(ColorChoice::Never);
+        let source = "document\nbegenv foo\nbar\nendenv";
+        let span = Span {
+            start: location(2, 8),
+            end: location(3, 4),
+        };
+        let expected = r#" error[E0000]: message
+  --> main.ves:2:8
+   |
+ 2 |   begenv foo
+   |          ^^^
+ 3 |   bar
+   |   ^^^ detail
+   |       help
+"#;
+        assert_eq!(
+            print_message(
+                Some(source),
+                ("error[E0000]", Severity::Error),
+                "message",
+                &[String::from("detail"), String::from("help")],
+                &Some(span),
+                Some(Path::new("main.ves")),
+            ),
+            expected
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// Warnings do not stop the compilation. Each kind of warning is a lint which
+// can be silenced by `#[allow(name)]` attached to a statement. Lints from
+// `unused_definition` to `long_line` are checked only by `vesti lint`.
+
+use crate::location::Span;
+
+#[derive(Debug, PartialEq)]
+pub enum VestiWarnKind {
+    DuplicatedImport {
+        name: String,
+    },
+    UnknownLint {
+        name: String,
+    },
+    // This is reported only in the strict mode
+    RawLatex,
+    UndefinedReference {
+        label: String,
+    },
+    ArgumentCountMismatch {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    // `package` is `None` for commands of latex itself
+    RedefinedCommand {
+        name: String,
+        package: Option<String>,
+    },
+    UnusedDefinition {
+        name: String,
+        is_env: bool,
+    },
+    EmptyEnvironment {
+        name: String,
+    },
+    MathCommandInText {
+        name: String,
+    },
+    DollarInRawLatex,
+    NonAsciiLabel {
+        label: String,
+    },
+    LongLine {
+        length: usize,
+        limit: usize,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VestiWarning {
+    pub warn_kind: VestiWarnKind,
+    pub location: Option<Span>,
+}
+
+// A statement with `#[allow(...)]`. Lints which run after parsing use this to
+// skip warnings inside of the statement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AllowedRegion {
+    pub span: Span,
+    pub lints: Vec<String>,
+}
+
+impl AllowedRegion {
+    pub fn allows(&self, warning: &VestiWarning) -> bool {
+        self.lints
+            .iter()
+            .any(|lint| lint == warning.warn_kind.lint_name())
+            && warning
+                .location
+                .is_some_and(|location| self.span.contains(&location))
+    }
+}
+
+pub const LINT_NAMES: [&str; 12] = [
+    "duplicated_import",
+    "unknown_lint",
+    "raw_latex",
+    "undefined_reference",
+    "argument_count",
+    "redefinition",
+    "unused_definition",
+    "empty_environment",
+    "math_in_text",
+    "dollar_in_raw",
+    "non_ascii_label",
+    "long_line",
+];
+
+impl VestiWarnKind {
+    pub fn lint_name(&self) -> &'static str {
+        match self {
+            Self::DuplicatedImport { .. } => "duplicated_import",
+            Self::UnknownLint { .. } => "unknown_lint",
+            Self::RawLatex => "raw_latex",
+            Self::UndefinedReference { .. } => "undefined_reference",
+            Self::ArgumentCountMismatch { .. } => "argument_count",
+            Self::RedefinedCommand { .. } => "redefinition",
+            Self::UnusedDefinition { .. } => "unused_definition",
+            Self::EmptyEnvironment { .. } => "empty_environment",
+            Self::MathCommandInText { .. } => "math_in_text",
+            Self::DollarInRawLatex => "dollar_in_raw",
+            Self::NonAsciiLabel { .. } => "non_ascii_label",
+            Self::LongLine { .. } => "long_line",
+        }
+    }
+
+    pub fn warn_str(&self) -> String {
+        match self {
+            Self::DuplicatedImport { name } => format!("`{}` is imported more than once", name),
+            Self::UnknownLint { name } => format!("Unknown lint `{}`", name),
+            Self::RawLatex => String::from("Raw latex is used"),
+            Self::UndefinedReference { label } => format!("Label `{}` is not defined", label),
+            Self::ArgumentCountMismatch {
+                name,
+                expected,
+                got,
+            } => format!(
+                "Function `\\{}` takes {} arguments but {} are given",
+                name, expected, got
+            ),
+            Self::RedefinedCommand {
+                name,
+                package: Some(package),
+            } => format!(
+                "`defun` redefines `\\{}` of the package `{}`",
+                name, package
+            ),
+            Self::RedefinedCommand {
+                name,
+                package: None,
+            } => format!("`defun` redefines `\\{}` of latex", name),
+            Self::UnusedDefinition { name, is_env: true } => {
+                format!("Environment `{}` is defined but never used", name)
+            }
+            Self::UnusedDefinition {
+                name,
+                is_env: false,
+            } => {
+                format!("Function `\\{}` is defined but never used", name)
+            }
+            Self::EmptyEnvironment { name } => format!("Environment `{}` is empty", name),
+            Self::MathCommandInText { name } => {
+                format!("Math command `\\{}` is used outside of math mode", name)
+            }
+            Self::DollarInRawLatex => String::from("`$` is used in raw latex"),
+            Self::NonAsciiLabel { label } => {
+                format!("Label `{}` has non-ASCII characters", label)
+            }
+            Self::LongLine { length, limit } => {
+                format!(
+                    "Line has {} characters which is longer than {}",
+                    length, limit
+                )
+            }
+        }
+    }
+
+    pub fn warn_detail_str(&self) -> Vec<String> {
+        match self {
+            Self::DuplicatedImport { .. } => vec![String::from("help: remove this import")],
+            Self::UnknownLint { .. } => vec![format!(
+                "available lints are {}",
+                LINT_NAMES
+                    .iter()
+                    .map(|name| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )],
+            Self::RawLatex => vec![String::from(
+                "help: write it in vesti, or keep it with `#[allow(raw_latex)]`",
+            )],
+            Self::UndefinedReference { label } => vec![format!(
+                "help: define it with `\\label{{{}}}`, or allow it if a latex file defines it",
+                label
+            )],
+            Self::ArgumentCountMismatch { expected, .. } => vec![format!(
+                "help: the function is defined by `defun` with {} parameters",
+                expected
+            )],
+            Self::RedefinedCommand { .. } => vec![
+                String::from("help: write `defun!` to redefine it by `\\renewcommand`,"),
+                String::from("or `defun?` to keep the existing one by `\\providecommand`"),
+            ],
+            Self::UnusedDefinition { .. } => vec![String::from("help: remove this definition")],
+            Self::EmptyEnvironment { .. } => {
+                vec![String::from("help: remove it or write its contents")]
+            }
+            Self::MathCommandInText { name } => vec![format!(
+                "help: write it inside of math like `\\( \\{} \\)`",
+                name
+            )],
+            Self::DollarInRawLatex => vec![String::from(
+                "help: use `\\(` and `\\)` of vesti, or `\\$` for a dollar sign",
+            )],
+            Self::NonAsciiLabel { .. } => vec![String::from(
+                "help: some packages like hyperref cannot handle such labels",
+            )],
+            Self::LongLine { .. } => vec![String::from(
+                "help: break the line, or change `max_line_length` in `[lint]` of `vesti.toml`",
+            )],
+        }
+    }
+}
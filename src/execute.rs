@@ -0,0 +1,251 @@
+// Running `execute(lang) { ... }` blocks when a document is compiled. The
+// standard output of a block becomes raw latex, and it is cached by the hash
+// of the block with its interpreter so that an unchanged block runs once.
+// Blocks can run anything, so they run only with `--allow-exec`. Interpreters
+// are killed by Ctrl+C and by `--timeout` as engines are.
+
+use crate::cache::BlockCache;
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind, VestiParseErr};
+use crate::error::{self, VestiErr};
+use crate::location::Span;
+use crate::parser::ast::{walk_latex_mut, walk_spanned, Latex, Statement};
+use crate::shutdown::{self, Waited};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Interpreters used when `[execute]` of vesti.toml does not have the language
+const DEFAULT_INTERPRETERS: &[(&str, &str)] = &[("python", "python3"), ("sh", "sh")];
+
+fn interpreter<'a>(lang: &str, interpreters: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    interpreters.get(lang).map(String::as_str).or_else(|| {
+        DEFAULT_INTERPRETERS
+            .iter()
+            .find(|(name, _)| *name == lang)
+            .map(|(_, command)| *command)
+    })
+}
+
+// Run every block of the document in `work_dir` and keep their outputs. Each
+// block is killed if it runs longer than `timeout`.
+pub fn run_blocks(
+    latex: &mut Latex,
+    interpreters: &BTreeMap<String, String>,
+    is_allowed: bool,
+    cache: &BlockCache,
+    work_dir: &Path,
+    timeout: Option<Duration>,
+) -> error::Result<()> {
+    let mut blocks: Vec<(Span, &str, &str)> = Vec::new();
+    walk_spanned(latex, &mut |stmt| {
+        if let Statement::ExecuteBlock { lang, code, .. } = &stmt.node {
+            blocks.push((stmt.span, lang, code));
+        }
+    });
+
+    let mut outputs: HashMap<(&str, &str), String> = HashMap::new();
+    for (span, lang, code) in blocks {
+        if !is_allowed {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::ExecuteNotAllowedErr {
+                    lang: lang.to_string(),
+                },
+                Some(span),
+            ));
+        }
+        let command = match interpreter(lang, interpreters) {
+            Some(command) => command,
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::InterpreterNotFoundErr {
+                        lang: lang.to_string(),
+                    },
+                    Some(span),
+                ))
+            }
+        };
+        let cache_key = BlockCache::key("execute", &format!("{}\n{}", command, code));
+        let output = match cache.get(&cache_key) {
+            Some(output) => String::from_utf8_lossy(&output).into_owned(),
+            None => {
+                let output = run_block(command, code, work_dir, timeout).map_err(|msg| {
+                    if shutdown::is_requested() {
+                        return VestiErr {
+                            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::CancelledErr(
+                                command.to_string(),
+                            )),
+                            location: None,
+                        };
+                    }
+                    VestiErr::make_parse_err(
+                        VestiParseErr::ExecuteFailedErr {
+                            lang: lang.to_string(),
+                            msg,
+                        },
+                        Some(span),
+                    )
+                })?;
+                cache.put(&cache_key, output.as_bytes());
+                output
+            }
+        };
+        outputs.insert((lang, code), output);
+    }
+
+    walk_latex_mut(latex, &mut |stmt| {
+        if let Statement::ExecuteBlock { lang, code, output } = stmt {
+            *output = outputs.get(&(*lang, *code)).cloned();
+        }
+    });
+    Ok(())
+}
+
+// The code is given by the standard input of the interpreter. It is written
+// and the outputs are read from other threads while the interpreter is waited,
+// since a block which prints more than a pipe holds before reading all of its
+// code would wait for vesti forever.
+fn run_block(
+    command: &str,
+    code: &str,
+    work_dir: &Path,
+    timeout: Option<Duration>,
+) -> Result<String, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("the interpreter is empty")?;
+    let mut child = Command::new(program)
+        .args(words)
+        .current_dir(work_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("cannot run `{}`: {}", command, err))?;
+    let stdin = child.stdin.take();
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let (written, waited, stdout, stderr) = thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin {
+            Some(mut stdin) => stdin.write_all(code.as_bytes()),
+            None => Ok(()),
+        });
+        let read_all = |pipe: Option<Box<dyn Read + Send>>| {
+            scope.spawn(move || {
+                let mut buf = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut buf);
+                }
+                buf
+            })
+        };
+        let stdout_reader = read_all(stdout.map(|pipe| Box::new(pipe) as _));
+        let stderr_reader = read_all(stderr.map(|pipe| Box::new(pipe) as _));
+        let waited = shutdown::wait_child(&mut child, deadline);
+        (
+            writer.join(),
+            waited,
+            stdout_reader.join().unwrap_or_default(),
+            stderr_reader.join().unwrap_or_default(),
+        )
+    });
+    let status = match waited.map_err(|err| format!("cannot run `{}`: {}", command, err))? {
+        Waited::Exited(status) => status,
+        Waited::Interrupted => return Err(format!("`{}` is killed by the interruption", command)),
+        Waited::TimedOut => {
+            return Err(format!(
+                "`{}` is killed after {} seconds",
+                command,
+                timeout.unwrap_or_default().as_secs()
+            ))
+        }
+    };
+    match written {
+        // the interpreter may exit without reading the whole code
+        Ok(Ok(())) => {}
+        Ok(Err(err)) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Ok(Err(err)) => return Err(format!("cannot write the code to `{}`: {}", command, err)),
+        Err(_) => return Err(format!("cannot write the code to `{}`", command)),
+    }
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        return Err(format!(
+            "`{}` exited with {}\n{}",
+            command,
+            status,
+            stderr.trim_end()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::maker::latex_to_string;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_run_blocks() {
+        let source = "docclass article\ndocument\nexecute(sh) {\necho '\\\\textbf{hi}'\n}\n";
+        let arena = Arena::new();
+        let mut latex = Parser::new(Lexer::new(source), &arena)
+            .parse_latex()
+            .unwrap();
+        let interpreters = BTreeMap::new();
+        let work_dir = Path::new(".");
+
+        let err = run_blocks(
+            &mut latex,
+            &interpreters,
+            false,
+            &BlockCache::disabled(),
+            work_dir,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.err_kind,
+            crate::error::err_kind::VestiErrKind::ParseErr(VestiParseErr::ExecuteNotAllowedErr {
+                lang: String::from("sh")
+            })
+        );
+
+        run_blocks(
+            &mut latex,
+            &interpreters,
+            true,
+            &BlockCache::disabled(),
+            work_dir,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            latex_to_string(&latex),
+            "\\documentclass{article}\n\\begin{document}\n\\textbf{hi}\n\n\\end{document}\n"
+        );
+    }
+    #[test]
+    fn test_run_block_with_large_output() {
+        // The first line prints more than a pipe holds while the rest of the
+        // code is not written yet
+        let code = format!(
+            "head -c 200000 /dev/zero | tr '\\0' a\n# {}\n",
+            "x".repeat(200000)
+        );
+        let output = run_block("sh", &code, Path::new("."), None).unwrap();
+        assert_eq!(output.len(), 200000);
+    }
+
+    #[test]
+    fn test_run_block_with_timeout() {
+        let started = Instant::now();
+        let timeout = Some(Duration::from_millis(100));
+        let msg = run_block("sh", "exec sleep 30\n", Path::new("."), timeout).unwrap_err();
+        assert!(msg.contains("is killed after"), "{}", msg);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,118 @@
+// Exit codes of vesti. CI can tell a mistake in a vesti file from a failure of
+// latex with them, while every failure used to exit with 1.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::pretty_print::pretty_print;
+use crate::error::VestiErr;
+use crate::shutdown;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success,
+    // wrong usage of vesti, like an unknown target or an invalid `vesti.toml`
+    Failure,
+    // the vesti file is invalid
+    ParseError,
+    // the engine or a tool run after it fails
+    EngineError,
+    IoError,
+    // lints denied by `vesti.toml`
+    DeniedWarning,
+    Interrupted,
+}
+
+impl ExitCode {
+    pub fn of(err: &VestiErr) -> Self {
+        match &err.err_kind {
+            VestiErrKind::ParseErr(_) => Self::ParseError,
+            VestiErrKind::UtilErr(err) => match err {
+                VestiCommandUtilErr::IOErr(_) => Self::IoError,
+                VestiCommandUtilErr::EngineNotFoundErr(_)
+                | VestiCommandUtilErr::LatexCompileErr { .. }
+                | VestiCommandUtilErr::EngineTimeoutErr { .. }
+                | VestiCommandUtilErr::PdfCompressErr { .. }
+                | VestiCommandUtilErr::ImageConvertErr { .. } => Self::EngineError,
+                VestiCommandUtilErr::CancelledErr(_) => Self::Interrupted,
+                _ => Self::Failure,
+            },
+        }
+    }
+
+    pub fn code(self) -> i32 {
+        match self {
+            Self::Success => 0,
+            Self::Failure => 1,
+            Self::ParseError => 2,
+            Self::EngineError => 3,
+            Self::IoError => 4,
+            Self::DeniedWarning => 5,
+            Self::Interrupted => shutdown::INTERRUPTED_EXIT_CODE,
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+// A pretty printed error of compiling a file with the exit code of its kind
+#[derive(Debug, PartialEq)]
+pub struct CompileFailure {
+    pub code: ExitCode,
+    pub msg: String,
+}
+
+impl CompileFailure {
+    pub fn new(source: Option<&str>, err: VestiErr, file_name: Option<&Path>) -> Self {
+        Self {
+            code: ExitCode::of(&err),
+            msg: pretty_print(source, err, file_name),
+        }
+    }
+}
+
+// Errors which are printed already, like a cycle of includes
+impl From<String> for CompileFailure {
+    fn from(msg: String) -> Self {
+        Self {
+            code: ExitCode::Failure,
+            msg,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::err_kind::VestiParseErr;
+
+    #[test]
+    fn test_exit_code_of_error() {
+        let err = |err_kind| VestiErr {
+            err_kind,
+            location: None,
+        };
+        let codes = [
+            (VestiErrKind::ParseErr(VestiParseErr::EOFErr), 2),
+            (
+                VestiErrKind::UtilErr(VestiCommandUtilErr::IOErr(std::io::ErrorKind::NotFound)),
+                4,
+            ),
+            (
+                VestiErrKind::UtilErr(VestiCommandUtilErr::LatexCompileErr {
+                    engine: String::from("pdflatex"),
+                    log: String::new(),
+                }),
+                3,
+            ),
+            (
+                VestiErrKind::UtilErr(VestiCommandUtilErr::ConfigNotFoundErr),
+                1,
+            ),
+        ];
+        for (err_kind, code) in codes {
+            assert_eq!(ExitCode::of(&err(err_kind)).code(), code);
+        }
+    }
+}
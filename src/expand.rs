@@ -0,0 +1,170 @@
+// Instantiation of definitions made by `defun` and `defenv` with sample
+// arguments, which is used by `vesti expand-env` to test definitions without
+// compiling a whole document.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use crate::parser::argspec::parse_arg_spec;
+use crate::parser::ast::{walk_latex, Latex, Statement};
+use crate::parser::maker::latex_to_string;
+
+// Replace `#n` with the n-th argument and `##` with `#`
+fn substitute(template: &str, args: &[String]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr != '#' {
+            output.push(chr);
+            continue;
+        }
+        match chars.peek() {
+            Some('#') => {
+                chars.next();
+                output.push('#');
+            }
+            Some(num @ '1'..='9') => {
+                let idx = *num as usize - '1' as usize;
+                match args.get(idx) {
+                    Some(arg) => output += arg,
+                    None => {
+                        output.push('#');
+                        output.push(*num);
+                    }
+                }
+                chars.next();
+            }
+            _ => output.push('#'),
+        }
+    }
+    output
+}
+
+fn mismatch_err(expected: usize, got: usize) -> VestiErr {
+    VestiErr {
+        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ArgumentCountMismatchErr {
+            expected,
+            got,
+        }),
+        location: None,
+    }
+}
+
+// The number of parameters of `defun` like `#1#2`
+fn params_num(params: &str) -> usize {
+    params.matches('#').count()
+}
+
+// Expand the definition named `name`. `body` is put between the beginning and
+// the end part of an environment, and it is ignored for functions.
+pub fn expand_definition(
+    latex: &Latex,
+    name: &str,
+    args: &[String],
+    body: &str,
+) -> error::Result<String> {
+    let mut definition: Option<&Statement> = None;
+    walk_latex(latex, &mut |stmt| match stmt {
+        Statement::FunctionDefine { name: def_name, .. }
+        | Statement::EnvironmentDefine { name: def_name, .. }
+            if *def_name == name =>
+        {
+            definition = Some(stmt);
+        }
+        _ => {}
+    });
+
+    match definition {
+        Some(Statement::FunctionDefine {
+            params,
+            spec,
+            body: fnt_body,
+            ..
+        }) => {
+            // every argument of a specification is given, even optional ones
+            let expected = match spec {
+                Some(spec) => parse_arg_spec(spec).map_or(0, |args| args.len()),
+                None => params_num(params),
+            };
+            if args.len() != expected {
+                return Err(mismatch_err(expected, args.len()));
+            }
+            Ok(substitute(latex_to_string(fnt_body).trim(), args))
+        }
+        Some(Statement::EnvironmentDefine {
+            args_num,
+            optional_arg,
+            begin_part,
+            end_part,
+            ..
+        }) => {
+            let expected = *args_num as usize;
+            let mut env_args: Vec<String> = Vec::with_capacity(expected);
+            // The first argument is optional if it has a default value
+            match optional_arg {
+                Some(default) if expected > 0 && args.len() + 1 == expected => {
+                    env_args.push(default.to_string());
+                }
+                _ if args.len() != expected => return Err(mismatch_err(expected, args.len())),
+                _ => {}
+            }
+            env_args.extend(args.iter().cloned());
+
+            let mut output = substitute(latex_to_string(begin_part).trim(), &env_args);
+            if !body.is_empty() {
+                output.push('\n');
+                output += body;
+            }
+            output.push('\n');
+            output += latex_to_string(end_part).trim();
+            Ok(output)
+        }
+        _ => Err(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::DefinitionNotFoundErr(
+                name.to_string(),
+            )),
+            location: None,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    fn expand(source: &str, name: &str, args: &[&str], body: &str) -> error::Result<String> {
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let latex = parser.parse_latex().unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        expand_definition(&latex, name, &args, body)
+    }
+
+    #[test]
+    fn test_expand_definition() {
+        let source = r#"docclass article
+defun pair (#!1#!2) { (#!1, #!2) }
+defenv boxed (2, red) { \begin{center}\color{#!1} #!2 } { \end{center} }
+document
+"#;
+        assert_eq!(expand(source, "pair", &["a", "b"], "").unwrap(), "(a, b)");
+        assert_eq!(
+            expand(source, "boxed", &["blue", "x"], "body").unwrap(),
+            "\\begin{center}\\color{blue} x\nbody\n\\end{center}"
+        );
+        assert_eq!(
+            expand(source, "boxed", &["x"], "").unwrap(),
+            "\\begin{center}\\color{red} x\n\\end{center}"
+        );
+        assert_eq!(
+            expand(source, "pair", &["a"], "").unwrap_err().err_kind,
+            VestiErrKind::UtilErr(VestiCommandUtilErr::ArgumentCountMismatchErr {
+                expected: 2,
+                got: 1
+            })
+        );
+        assert!(expand(source, "none", &[], "").is_err());
+    }
+}
@@ -0,0 +1,208 @@
+// Conversion of the pdf into an image after a successful compilation, so that
+// figures written in vesti can be used by web tools. Only the first page is
+// converted, and the image is written next to the pdf.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use serde::Deserialize;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Png,
+    Svg,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(Self::Png),
+            "svg" => Ok(Self::Svg),
+            _ => Err(format!("unknown format `{}`. Use one of png and svg", s)),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportTool {
+    Pdftoppm,
+    Pdf2svg,
+    Ghostscript,
+}
+
+pub const DEFAULT_DPI: u32 = 300;
+
+fn default_dpi() -> u32 {
+    DEFAULT_DPI
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ExportConfig {
+    pub format: ExportFormat,
+    /// Tool which converts the pdf. The default is `pdftoppm` for png and
+    /// `pdf2svg` for svg.
+    pub tool: Option<ExportTool>,
+    /// Resolution of png images. Svg images do not need it.
+    #[serde(default = "default_dpi")]
+    pub dpi: u32,
+}
+
+pub struct ExportCommand {
+    program: &'static str,
+    args: Vec<String>,
+    pub image: PathBuf,
+}
+
+fn export_err(tool: &str, msg: impl ToString) -> VestiErr {
+    VestiErr {
+        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ImageConvertErr {
+            tool: tool.to_string(),
+            msg: msg.to_string(),
+        }),
+        location: None,
+    }
+}
+
+impl ExportConfig {
+    pub fn new(format: ExportFormat, dpi: u32) -> Self {
+        Self {
+            format,
+            tool: None,
+            dpi,
+        }
+    }
+
+    pub fn command(&self, pdf_file: &Path) -> error::Result<ExportCommand> {
+        let tool = self.tool.unwrap_or(match self.format {
+            ExportFormat::Png => ExportTool::Pdftoppm,
+            ExportFormat::Svg => ExportTool::Pdf2svg,
+        });
+        let image = pdf_file.with_extension(match self.format {
+            ExportFormat::Png => "png",
+            ExportFormat::Svg => "svg",
+        });
+        let pdf = pdf_file.display().to_string();
+        let (program, args) = match (tool, self.format) {
+            // `pdftoppm` adds the extension to the output name
+            (ExportTool::Pdftoppm, ExportFormat::Png) => (
+                "pdftoppm",
+                vec![
+                    String::from("-png"),
+                    String::from("-singlefile"),
+                    String::from("-r"),
+                    self.dpi.to_string(),
+                    pdf,
+                    image.with_extension("").display().to_string(),
+                ],
+            ),
+            (ExportTool::Pdf2svg, ExportFormat::Svg) => (
+                "pdf2svg",
+                vec![pdf, image.display().to_string(), String::from("1")],
+            ),
+            (ExportTool::Ghostscript, ExportFormat::Png) => (
+                "gs",
+                vec![
+                    String::from("-sDEVICE=png16m"),
+                    String::from("-dNOPAUSE"),
+                    String::from("-dBATCH"),
+                    String::from("-dQUIET"),
+                    String::from("-dFirstPage=1"),
+                    String::from("-dLastPage=1"),
+                    format!("-r{}", self.dpi),
+                    format!("-sOutputFile={}", image.display()),
+                    pdf,
+                ],
+            ),
+            (tool, format) => {
+                return Err(export_err(
+                    &format!("{:?}", tool).to_lowercase(),
+                    format!("cannot make {:?} images", format).to_lowercase(),
+                ))
+            }
+        };
+        Ok(ExportCommand {
+            program,
+            args,
+            image,
+        })
+    }
+}
+
+impl ExportCommand {
+    pub fn run(&self) -> error::Result<()> {
+        let output = Command::new(self.program)
+            .args(&self.args)
+            .output()
+            .map_err(|err| {
+                if err.kind() == io::ErrorKind::NotFound {
+                    VestiErr {
+                        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::EngineNotFoundErr(
+                            self.program.to_string(),
+                        )),
+                        location: None,
+                    }
+                } else {
+                    VestiErr::from(err)
+                }
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(export_err(
+                self.program,
+                stderr.lines().next().unwrap_or_default().trim(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ExportCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "  {}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_export_command() {
+        let config: ExportConfig = toml::from_str("format = \"png\"\ndpi = 150").unwrap();
+        let command = config.command(Path::new("out/fig.pdf")).unwrap();
+        assert_eq!(command.image, Path::new("out/fig.png"));
+        assert_eq!(
+            command.to_string(),
+            "  pdftoppm -png -singlefile -r 150 out/fig.pdf out/fig"
+        );
+
+        let config: ExportConfig = toml::from_str("format = \"svg\"").unwrap();
+        assert_eq!(config.dpi, DEFAULT_DPI);
+        let command = config.command(Path::new("fig.pdf")).unwrap();
+        assert_eq!(command.to_string(), "  pdf2svg fig.pdf fig.svg 1");
+
+        let config: ExportConfig =
+            toml::from_str("format = \"png\"\ntool = \"ghostscript\"").unwrap();
+        let command = config.command(Path::new("fig.pdf")).unwrap();
+        assert!(command
+            .to_string()
+            .contains("-r300 -sOutputFile=fig.png fig.pdf"));
+
+        let config: ExportConfig =
+            toml::from_str("format = \"svg\"\ntool = \"ghostscript\"").unwrap();
+        assert!(config.command(Path::new("fig.pdf")).is_err());
+    }
+}
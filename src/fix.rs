@@ -0,0 +1,159 @@
+// `vesti fix` rewrites the source for lints which can be fixed mechanically.
+// Each fix is an edit of the text at the span of its warning, so edits are
+// applied from the end of the file and overlapping ones are skipped.
+// Empty environments can be placeholders to be filled later, so they are
+// removed only when it is asked for.
+
+use crate::error::warning::{VestiWarnKind, VestiWarning};
+use crate::location::{Location, Span};
+
+#[derive(Debug, PartialEq)]
+pub struct Edit {
+    // byte offsets of the source
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+// The edit which fixes the warning if there is
+pub fn edit_of(warning: &VestiWarning, source: &str, remove_empty_envs: bool) -> Option<Edit> {
+    let (start, end) = byte_range(source, warning.location?)?;
+    match &warning.warn_kind {
+        VestiWarnKind::EmptyEnvironment { .. } if !remove_empty_envs => None,
+        VestiWarnKind::UnusedDefinition { .. } | VestiWarnKind::EmptyEnvironment { .. } => {
+            let (start, end) = whole_lines(source, start, end);
+            Some(Edit {
+                start,
+                end,
+                replacement: String::new(),
+            })
+        }
+        VestiWarnKind::MathCommandInText { .. } => Some(Edit {
+            start,
+            end,
+            replacement: format!("\\( {} \\)", &source[start..end]),
+        }),
+        _ => None,
+    }
+}
+
+// Apply edits and return the new source with the number of applied edits
+pub fn apply_edits(source: &str, mut edits: Vec<Edit>) -> (String, usize) {
+    edits.sort_by_key(|edit| (edit.start, edit.end));
+    let mut output = String::with_capacity(source.len());
+    let mut applied = 0;
+    let mut copied = 0;
+    for edit in edits {
+        if edit.start < copied {
+            continue;
+        }
+        output += &source[copied..edit.start];
+        output += &edit.replacement;
+        copied = edit.end;
+        applied += 1;
+    }
+    output += &source[copied..];
+    (output, applied)
+}
+
+// Byte offsets of a span. Columns are counted as the lexer does.
+fn byte_range(source: &str, span: Span) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut loc = Location::default();
+    let mut chars = source.char_indices().peekable();
+    loop {
+        if start.is_none() && loc == span.start {
+            start = Some(chars.peek().map_or(source.len(), |(i, _)| *i));
+        }
+        if loc == span.end {
+            let end = chars.peek().map_or(source.len(), |(i, _)| *i);
+            return start.map(|start| (start, end));
+        }
+        let (_, chr) = chars.next()?;
+        match chr {
+            '\r' if chars.peek().map(|(_, chr)| *chr) == Some('\n') => {}
+            '\n' | '\r' => loc.move_next_line(),
+            _ => loc.move_right(Some(&chr)),
+        }
+    }
+}
+
+// A statement which is alone in its lines is removed with the lines
+fn whole_lines(source: &str, start: usize, end: usize) -> (usize, usize) {
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |i| end + i + 1);
+    let is_alone =
+        source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty();
+    if is_alone {
+        (line_start, line_end)
+    } else {
+        (start, end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn warning(
+        warn_kind: VestiWarnKind,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> VestiWarning {
+        VestiWarning {
+            warn_kind,
+            location: Some(Span {
+                start: Location::new(start.0, start.1),
+                end: Location::new(end.0, end.1),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let source = "defun foo {bar}\r\ndocument\nα is \\alpha\n  begenv center\n  endenv\nend\n";
+        let warnings = [
+            warning(
+                VestiWarnKind::UnusedDefinition {
+                    name: String::from("foo"),
+                    is_env: false,
+                },
+                (1, 1),
+                (1, 16),
+            ),
+            warning(
+                VestiWarnKind::MathCommandInText {
+                    name: String::from("alpha"),
+                },
+                (3, 6),
+                (3, 12),
+            ),
+            warning(
+                VestiWarnKind::EmptyEnvironment {
+                    name: String::from("center"),
+                },
+                (4, 3),
+                (5, 9),
+            ),
+            warning(VestiWarnKind::DollarInRawLatex, (6, 1), (6, 4)),
+        ];
+        let edits_of = |remove_empty_envs| -> Vec<Edit> {
+            warnings
+                .iter()
+                .filter_map(|warning| edit_of(warning, source, remove_empty_envs))
+                .collect()
+        };
+        let (fixed, applied) = apply_edits(source, edits_of(false));
+        assert_eq!(applied, 2);
+        assert_eq!(
+            fixed,
+            "document\nα is \\( \\alpha \\)\n  begenv center\n  endenv\nend\n"
+        );
+
+        let (fixed, applied) = apply_edits(source, edits_of(true));
+        assert_eq!(applied, 3);
+        assert_eq!(fixed, "document\nα is \\( \\alpha \\)\nend\n");
+    }
+}
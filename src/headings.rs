@@ -0,0 +1,109 @@
+// Labels of headings written by `section "Introduction"`. A label is made from
+// the title, like `sec:introduction`, so that headings can be referred without
+// writing their labels. How labels look is set in `[headings]` of `vesti.toml`.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// Commands of headings which can be written as keywords
+pub const HEADING_COMMANDS: [&str; 5] =
+    ["part", "chapter", "section", "subsection", "subsubsection"];
+
+const DEFAULT_PREFIXES: [(&str, &str); 5] = [
+    ("part", "part"),
+    ("chapter", "chap"),
+    ("section", "sec"),
+    ("subsection", "subsec"),
+    ("subsubsection", "subsubsec"),
+];
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HeadingConfig {
+    /// Make labels of headings. Starred headings never have labels.
+    pub auto_label: bool,
+    /// String put between words of titles.
+    pub separator: String,
+    /// Keep upper case letters of titles in labels.
+    pub keep_case: bool,
+    /// Prefixes of labels like `section = "sec"` which replace the default ones.
+    /// The prefix and the title are joined by `:`, and an empty prefix makes
+    /// labels of only titles.
+    pub prefixes: BTreeMap<String, String>,
+}
+
+impl Default for HeadingConfig {
+    fn default() -> Self {
+        Self {
+            auto_label: true,
+            separator: String::from("-"),
+            keep_case: false,
+            prefixes: BTreeMap::new(),
+        }
+    }
+}
+
+impl HeadingConfig {
+    // Words of the title are ascii letters and digits, so that every engine
+    // can read the label. `None` if there is no such word.
+    pub fn label(&self, heading: &str, title: &str) -> Option<String> {
+        let words: Vec<String> = title
+            .split(|chr: char| !chr.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                if self.keep_case {
+                    word.to_string()
+                } else {
+                    word.to_ascii_lowercase()
+                }
+            })
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        let slug = words.join(&self.separator);
+        let prefix = match self.prefixes.get(heading) {
+            Some(prefix) => prefix.as_str(),
+            None => DEFAULT_PREFIXES
+                .iter()
+                .find(|(name, _)| *name == heading)
+                .map_or("", |(_, prefix)| prefix),
+        };
+        Some(if prefix.is_empty() {
+            slug
+        } else {
+            format!("{}:{}", prefix, slug)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_heading_label() {
+        let config = HeadingConfig::default();
+        assert_eq!(
+            config.label("section", "Why vesti? (A short intro)"),
+            Some(String::from("sec:why-vesti-a-short-intro"))
+        );
+        assert_eq!(
+            config.label("subsection", "Part 2"),
+            Some(String::from("subsec:part-2"))
+        );
+        assert_eq!(config.label("section", "αβγ"), None);
+
+        let config: HeadingConfig =
+            toml::from_str("separator = \"_\"\nkeep_case = true\n[prefixes]\nsection = \"\"")
+                .unwrap();
+        assert_eq!(
+            config.label("section", "Main Result"),
+            Some(String::from("Main_Result"))
+        );
+        assert_eq!(
+            config.label("chapter", "Main Result"),
+            Some(String::from("chap:Main_Result"))
+        );
+    }
+}
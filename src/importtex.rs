@@ -0,0 +1,157 @@
+// Latex files of `importtex` are read after parsing, since the parser does not
+// know where the source is. A file is pasted as it is, or with `(split)`, its
+// preamble goes to the preamble of the document and its body goes to the body,
+// so that vesti files and latex files can share a legacy preamble.
+
+use crate::error::err_kind::VestiParseErr;
+use crate::error::{self, VestiErr};
+use crate::location::Span;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_latex_mut, Latex, Statement};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const BEGIN_DOCUMENT: &str = "\\begin{document}";
+const END_DOCUMENT: &str = "\\end{document}";
+
+// The document has its own class, so `\documentclass` of the file is dropped
+fn strip_documentclass(preamble: &str) -> String {
+    let Some(start) = preamble.find("\\documentclass") else {
+        return preamble.to_string();
+    };
+    let rest = &preamble[start..];
+    let end = rest.find('}').map_or(rest.len(), |idx| idx + 1);
+    let rest = rest[end..].trim_start_matches([' ', '\t', '\r']);
+    let rest = rest.strip_prefix('\n').unwrap_or(rest);
+    format!("{}{}", &preamble[..start], rest)
+}
+
+fn with_newline(mut text: String) -> String {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+// The preamble and the body of a latex file. A file without
+// `\begin{document}` is a preamble only.
+pub fn split_tex(source: &str) -> (String, String) {
+    let Some(begin) = source.find(BEGIN_DOCUMENT) else {
+        return (with_newline(strip_documentclass(source)), String::new());
+    };
+    let body = &source[begin + BEGIN_DOCUMENT.len()..];
+    let body = body.find(END_DOCUMENT).map_or(body, |end| &body[..end]);
+    let body = body.trim_start_matches([' ', '\t', '\r']);
+    let body = body.strip_prefix('\n').unwrap_or(body);
+    (
+        with_newline(strip_documentclass(&source[..begin])),
+        with_newline(body.to_string()),
+    )
+}
+
+fn read_tex_file(path: &str, path_span: Option<Span>, source_dir: &Path) -> error::Result<String> {
+    fs::read_to_string(source_dir.join(path)).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => VestiErr::make_parse_err(
+            VestiParseErr::TexFileNotFoundErr {
+                path: path.to_string(),
+            },
+            path_span,
+        ),
+        _ => VestiErr::from(err),
+    })
+}
+
+// `importtex` statements become raw latex. Parts of files imported in the
+// preamble which belong to the body are put right after `document`, and the
+// other way around, right before it.
+pub fn import_tex_files<'a>(
+    latex: &mut Latex<'a>,
+    source_dir: &Path,
+    arena: &'a Arena,
+) -> error::Result<()> {
+    let doc_start = latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart);
+    let mut preambles = String::new();
+    let mut bodies = String::new();
+    let mut output = Ok(());
+    for (idx, stmt) in latex.iter_mut().enumerate() {
+        let in_preamble = doc_start.is_some_and(|start| idx < start);
+        walk_latex_mut(std::slice::from_mut(stmt), &mut |stmt| {
+            let Statement::ImportTex {
+                path,
+                path_span,
+                split,
+            } = *stmt
+            else {
+                return;
+            };
+            if output.is_err() {
+                return;
+            }
+            let source = match read_tex_file(path, path_span, source_dir) {
+                Ok(source) => source,
+                Err(err) => {
+                    output = Err(err);
+                    return;
+                }
+            };
+            let text = match (split, doc_start) {
+                (false, _) => source,
+                (true, None) => {
+                    output = Err(VestiErr::make_parse_err(
+                        VestiParseErr::SplitTexWithoutDocumentErr,
+                        path_span,
+                    ));
+                    return;
+                }
+                (true, Some(_)) => {
+                    let (preamble, body) = split_tex(&source);
+                    if in_preamble {
+                        bodies.push_str(&body);
+                        preamble
+                    } else {
+                        preambles.push_str(&preamble);
+                        body
+                    }
+                }
+            };
+            *stmt = Statement::RawLatex(arena.alloc_str(&text));
+        });
+    }
+    output?;
+
+    if let Some(start) = doc_start {
+        if !bodies.is_empty() {
+            let bodies = arena.alloc_str(&bodies);
+            latex.insert(start + 1, Statement::RawLatex(bodies).into());
+        }
+        if !preambles.is_empty() {
+            let preambles = arena.alloc_str(&preambles);
+            latex.insert(start, Statement::RawLatex(preambles).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_tex() {
+        let source = "\\documentclass[11pt]{article}\n\\usepackage{amsmath}\n\\newcommand{\\R}{\\mathbb{R}}\n\\begin{document}\nHello\n\\end{document}\n";
+        assert_eq!(
+            split_tex(source),
+            (
+                String::from("\\usepackage{amsmath}\n\\newcommand{\\R}{\\mathbb{R}}\n"),
+                String::from("Hello\n")
+            )
+        );
+        assert_eq!(
+            split_tex("\\usepackage{xcolor}"),
+            (String::from("\\usepackage{xcolor}\n"), String::new())
+        );
+    }
+}
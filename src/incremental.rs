@@ -0,0 +1,398 @@
+// Documents which are parsed again only where they are changed, so that watch
+// mode stays responsive on long documents. The changed lines are parsed alone
+// as a part of the document body, and statements of other lines are kept with
+// their spans moved. A change which other lines depend on, like labels, lists
+// and imports of packages, makes the whole file parsed again.
+
+use crate::error::{self, warning::VestiWarning};
+use crate::lexer::Lexer;
+use crate::location::{Location, Span};
+use crate::packages;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_latex, walk_spans_mut, Latex, Spanned, Statement};
+use crate::parser::{self, DocumentContext, Parser};
+use self_cell::self_cell;
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+// The arena keeps every version of changed lines, so the whole file is parsed
+// again with a new arena when the arena becomes this many times larger than it
+const ARENA_GROWTH_LIMIT: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum Reparse {
+    Unchanged,
+    Full,
+    // rows of the new source which are parsed again
+    Lines(Range<usize>),
+}
+
+self_cell!(
+    // Statements with the arena which they borrow
+    struct ParsedDoc {
+        owner: Arena,
+
+        #[covariant]
+        dependent: Latex,
+    }
+);
+
+pub struct IncrementalDoc {
+    parsed: ParsedDoc,
+    warnings: Vec<VestiWarning>,
+    context: DocumentContext,
+    // index of the first statement of the document body
+    body_start: Option<usize>,
+    source: String,
+}
+
+impl IncrementalDoc {
+    // `configure` sets options of parsers, which should be the same for every
+    // version of the document
+    pub fn new(source: &str, configure: &dyn Fn(&mut Parser)) -> error::Result<Self> {
+        let mut warnings = Vec::new();
+        let mut context = DocumentContext::default();
+        let parsed = ParsedDoc::try_new(Arena::new(), |arena| {
+            let mut parser = Parser::new(Lexer::new(arena.alloc_str(source)), arena);
+            configure(&mut parser);
+            let latex = parser.parse_latex()?;
+            warnings = parser.take_warnings();
+            context = parser.document_context();
+            Ok::<_, error::VestiErr>(latex)
+        })?;
+        Ok(Self {
+            body_start: body_start(parsed.borrow_dependent(), source),
+            parsed,
+            warnings,
+            context,
+            source: source.to_string(),
+        })
+    }
+
+    pub fn latex(&self) -> Latex<'_> {
+        self.parsed.borrow_dependent().clone()
+    }
+
+    pub fn warnings(&self) -> &[VestiWarning] {
+        &self.warnings
+    }
+
+    pub fn context(&self) -> &DocumentContext {
+        &self.context
+    }
+
+    // Parse the new version of the document. If it cannot be parsed, the last
+    // version which is parsed is kept.
+    pub fn update(
+        &mut self,
+        source: &str,
+        configure: &dyn Fn(&mut Parser),
+    ) -> error::Result<Reparse> {
+        if source == self.source {
+            return Ok(Reparse::Unchanged);
+        }
+        if self.parsed.borrow_owner().allocated_bytes() <= ARENA_GROWTH_LIMIT * source.len() {
+            if let Some(rows) = self.reparse_lines(source, configure) {
+                self.source = source.to_string();
+                return Ok(Reparse::Lines(rows));
+            }
+        }
+        *self = Self::new(source, configure)?;
+        Ok(Reparse::Full)
+    }
+
+    // Parse the changed lines again and put their statements in place of old
+    // ones. Nothing is changed if the lines cannot be parsed alone.
+    fn reparse_lines(
+        &mut self,
+        source: &str,
+        configure: &dyn Fn(&mut Parser),
+    ) -> Option<Range<usize>> {
+        let body_start = self.body_start?;
+        let old_source = &self.source;
+        let context = &self.context;
+        let warnings = &mut self.warnings;
+        // The changed lines are parsed into the arena of the document
+        self.parsed.with_dependent_mut(|arena, latex| {
+            let old_lines: Vec<&str> = old_source.split_inclusive('\n').collect();
+            let new_lines: Vec<&str> = source.split_inclusive('\n').collect();
+            let prefix = old_lines
+                .iter()
+                .zip(&new_lines)
+                .take_while(|(old, new)| old == new)
+                .count();
+            let suffix = old_lines
+                .iter()
+                .rev()
+                .zip(new_lines.iter().rev())
+                .take(old_lines.len().min(new_lines.len()) - prefix)
+                .take_while(|(old, new)| old == new)
+                .count();
+            // Rows are counted from one. Nothing is removed if the last changed row
+            // is before the first one.
+            let first_changed = prefix + 1;
+            let last_changed = old_lines.len() - suffix;
+            let delta = new_lines.len() as isize - old_lines.len() as isize;
+
+            // Statements made after parsing, like the end of the document, have the
+            // default span and stay at the end
+            let stmts_end = latex
+                .iter()
+                .rposition(|stmt| stmt.span != Span::default())?
+                + 1;
+            let row = |idx: usize| latex[idx].span.start.row();
+            let starts_line = |idx: usize| latex[idx].span.start.column() == 1;
+            // Statements like `endenv` take whitespaces after them, which are not
+            // in their spans. Changed lines right after such a statement can be
+            // taken by it, and so can blank lines after the changed lines.
+            let follows_newline =
+                |idx: usize| idx > 0 && latex[idx - 1].span.end == Location::new(row(idx), 1);
+            let is_blank = |idx: usize| match latex[idx].node {
+                Statement::MainText(text) => text.trim().is_empty(),
+                _ => false,
+            };
+            let start = (body_start..stmts_end).rev().find(|&idx| {
+                starts_line(idx)
+                    && (row(idx) < first_changed
+                        || (row(idx) == first_changed && follows_newline(idx)))
+            })?;
+            let end = (start + 1..stmts_end)
+                .find(|&idx| row(idx) > last_changed && starts_line(idx) && !is_blank(idx))
+                .unwrap_or(stmts_end);
+            let start_row = row(start);
+            let old_end_row = if end < stmts_end {
+                row(end)
+            } else {
+                old_lines.len() + 1
+            };
+            let new_end_row = old_end_row.saturating_add_signed(delta);
+
+            let old_text = lines_text(&old_lines, start_row..old_end_row);
+            let new_text = lines_text(&new_lines, start_row..new_end_row);
+            // A list takes the items of the lines next to it
+            let neighbours = start.saturating_sub(1).max(body_start)..(end + 1).min(stmts_end);
+            if !is_independent(&latex[start..end])
+                || has_list(&latex[neighbours])
+                || parser::mentions_labels(&old_text)
+            {
+                return None;
+            }
+            let rest: usize = new_lines[..start_row - 1].iter().map(|l| l.len()).sum();
+            if end < stmts_end && !ends_at_row(&source[rest..], start_row, new_end_row) {
+                return None;
+            }
+
+            let (stmts, new_warnings, new_packages) = {
+                let text = arena.alloc_str(&new_text);
+                let mut parser = Parser::new(Lexer::with_start_row(text, start_row), arena);
+                configure(&mut parser);
+                parser.set_document_context(context);
+                let stmts = parser.parse_body().ok()?;
+                let packages = parser.missing_packages(&stmts);
+                (stmts, parser.take_warnings(), packages)
+            };
+            if !is_independent(&stmts) || has_list(&stmts) || parser::mentions_labels(&new_text) {
+                return None;
+            }
+            // Packages which the parser imports, like siunitx for `qty`, are
+            // made from the whole document
+            if required_packages(&old_text, start_row, context, configure)? != new_packages {
+                return None;
+            }
+            walk_spans_mut(&mut latex[end..stmts_end], &mut |span| {
+                span.shift_rows(delta)
+            });
+            latex.splice(start..end, stmts);
+
+            let is_changed = |span: &Option<Span>| {
+                span.is_some_and(|span| (start_row..old_end_row).contains(&span.start.row()))
+            };
+            warnings.retain(|warning| !is_changed(&warning.location));
+            for span in warnings.iter_mut().filter_map(|w| w.location.as_mut()) {
+                if span.start.row() >= old_end_row {
+                    span.shift_rows(delta);
+                }
+            }
+            let at = warnings
+                .iter()
+                .position(|w| w.location.is_some_and(|span| span.start.row() >= start_row))
+                .unwrap_or(warnings.len());
+            warnings.splice(at..at, new_warnings);
+
+            Some(start_row..new_end_row)
+        })
+    }
+}
+
+// The first statement after `document`, or after `docstartmode` which is a
+// part of the statement following it
+fn body_start(latex: &Latex, source: &str) -> Option<usize> {
+    if let Some(idx) = latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart)
+    {
+        return Some(idx + 1);
+    }
+    let lines: Vec<&str> = source.lines().collect();
+    latex
+        .iter()
+        .position(|stmt| {
+            lines
+                .get(stmt.span.start.row() - 1)
+                .is_some_and(|line| line.trim_start().starts_with("docstartmode"))
+        })
+        .map(|idx| idx + 1)
+}
+
+// A token of the changed lines may continue after them, like a raw latex
+// block which is not closed, and then the lines after them are parsed in
+// another way. `source` is the rest of the file from `start_row`.
+fn ends_at_row(source: &str, start_row: usize, end_row: usize) -> bool {
+    let end = Location::new(end_row, 1);
+    Lexer::with_start_row(source, start_row)
+        .find(|tok| {
+            let tok_end = tok.span.end;
+            tok.span.start.row() >= end_row
+                || tok_end.row() > end_row
+                || (tok_end.row() == end_row && tok_end.column() > 1)
+        })
+        .is_some_and(|tok| tok.span.start == end)
+}
+
+fn has_list(latex: &[Spanned<Statement>]) -> bool {
+    let mut found = false;
+    walk_latex(latex, &mut |stmt| {
+        found |= matches!(stmt, Statement::List { .. });
+    });
+    found
+}
+
+// Packages which the old version of changed lines needs. They are parsed with
+// an arena of their own so that the arena of the document does not grow.
+fn required_packages(
+    text: &str,
+    start_row: usize,
+    context: &DocumentContext,
+    configure: &dyn Fn(&mut Parser),
+) -> Option<BTreeSet<String>> {
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::with_start_row(text, start_row), &arena);
+    configure(&mut parser);
+    parser.set_document_context(context);
+    let stmts = parser.parse_body().ok()?;
+    Some(parser.missing_packages(&stmts))
+}
+
+fn lines_text(lines: &[&str], rows: Range<usize>) -> String {
+    lines[rows.start - 1..(rows.end - 1).min(lines.len())].concat()
+}
+
+// Statements which other parts of the document do not depend on. Imports of
+// packages are made from the whole document, and so are the bibliography and
+// the index.
+fn is_independent(latex: &[Spanned<Statement>]) -> bool {
+    let mut is_independent = !packages::requires_packages(latex);
+    walk_latex(latex, &mut |stmt| {
+        if matches!(
+            stmt,
+            Statement::PrintBib { .. } | Statement::Index(_) | Statement::Conditional { .. }
+        ) {
+            is_independent = false;
+        }
+    });
+    is_independent
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"docclass article
+import amsmath
+document
+
+\section{Introduction}
+Hello, \textbf{vesti}! This is \(x^2 + y^2\).
+
+begenv center
+    A centered text
+endenv
+
+\[
+    \int_0^1 f(x) dx
+\]
+The end.
+"#;
+
+    // Debug strings have spans of statements too
+    fn full_parse(source: &str) -> (String, usize) {
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let latex = parser.parse_latex().unwrap();
+        (format!("{:?}", latex), parser.take_warnings().len())
+    }
+
+    #[test]
+    fn test_incremental_parse() {
+        let configure = |_: &mut Parser| {};
+        let mut doc = IncrementalDoc::new(SOURCE, &configure).unwrap();
+        let lines: Vec<&str> = SOURCE.split_inclusive('\n').collect();
+        let edits = [
+            (6, "Hello, vesti! This is \\(x^3\\).\n"),
+            (6, "Hello\n\nworld\n"),
+            (9, "    Another text\n    and more\n"),
+            (13, "    \\sum_{n=1}^\\infty a_n\n"),
+            (15, "The end. Really!\n"),
+            (15, ""),
+        ];
+        for (row, line) in edits {
+            let mut new_lines = lines.clone();
+            new_lines[row - 1] = line;
+            let source = new_lines.concat();
+            let reparse = doc.update(&source, &configure).unwrap();
+            assert!(matches!(reparse, Reparse::Lines(_)), "{:?}", reparse);
+
+            let (latex, warnings) = full_parse(&source);
+            assert_eq!(format!("{:?}", doc.latex()), latex);
+            assert_eq!(doc.warnings().len(), warnings);
+        }
+
+        // Labels can be referred from anywhere
+        let source = SOURCE.replace("The end.", "The end.\\label{end}");
+        assert_eq!(doc.update(&source, &configure).unwrap(), Reparse::Full);
+        assert_eq!(doc.update(&source, &configure).unwrap(), Reparse::Unchanged);
+        assert!(doc.update("document\n\\[\n", &configure).is_err());
+        assert_eq!(format!("{:?}", doc.latex()), full_parse(&source).0);
+    }
+
+    #[test]
+    fn test_incremental_equals_full_parse() {
+        let source = "docclass article\ndocument\n\nText\nFirst line\n- one\n- two\nAfter\n\n\
+                      Speed is qty(3, m/s).\n\nLast line\n";
+        let lines: Vec<&str> = source.split_inclusive('\n').collect();
+        let edits = [
+            (5, "First line again\n"),
+            (5, "- zero\n"),
+            (8, "- three\n"),
+            (8, "  continued\n"),
+            (9, "- alone\n"),
+            (10, "Speed is fast.\n"),
+            (12, "Mass is qty(2, kg).\n"),
+            (12, "Last line, changed\n"),
+        ];
+        let configure = |_: &mut Parser| {};
+        for (row, line) in edits {
+            let mut doc = IncrementalDoc::new(source, &configure).unwrap();
+            let mut new_lines = lines.clone();
+            new_lines[row - 1] = line;
+            let new_source = new_lines.concat();
+            doc.update(&new_source, &configure).unwrap();
+            assert_eq!(
+                format!("{:?}", doc.latex()),
+                full_parse(&new_source).0,
+                "{:?}",
+                line
+            );
+        }
+    }
+}
@@ -0,0 +1,461 @@
+// Scaffolding of a new vesti project which is used by `vesti init`.
+// Each template makes a main vesti file, a `vesti.toml` and directories
+// which are usually used by that kind of documents.
+// Users can add their own templates in `~/.config/vesti/templates/<name>.ves`.
+// Variables like `{{project}}`, `{{author}}` and `{{date}}` in templates are
+// replaced when a project is made.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// File made by `vesti init` without any template
+const BARE_FILE_NAME: &str = "source.ves";
+const MAIN_FILE_NAME: &str = "main.ves";
+const TEMPLATE_EXTENSION: &str = "ves";
+const BARE_SOURCE: &str = "docclass article\n\ndocument\n";
+
+const CONFIG_SOURCE: &str = r#"# Flags which are considered to be defined at `#if` directives.
+defines = []
+
+# Words which become `\operatorname{...}` in math mode. Ones like `sin` already become `\sin`.
+# math_operators = ["tr", "rank"]
+
+# Engines which keep unicode characters like `α` in math mode instead of `\alpha`.
+# keep_unicode_math = ["xelatex", "lualatex"]
+
+# Interaction mode of the latex engine: batchmode, nonstopmode or errorstopmode.
+# interaction = "nonstopmode"
+
+# Compress the pdf after the engine makes it.
+# [compress]
+# tool = "ghostscript"
+# dpi = 150
+
+# Write quotes and dashes of texts in their typographic forms, like `"a"` as ``a''.
+# smart_typography = true
+
+# Write every `defenv` as `\NewDocumentEnvironment`, not only ones like `defenv name [s O{x} m]`.
+# xparse_environments = true
+
+# Labels of headings like `section "Introduction"`, which becomes `sec:introduction`.
+# [headings]
+# auto_label = true
+# separator = "-"
+# prefixes = { section = "sec" }
+
+# Notify when `vesti run -c` finishes compiling a file.
+# [notify]
+# desktop = true
+# webhook = "https://example.com/hook"
+
+# Targets compiled by `vesti build <target>`.
+# [targets.handout]
+# source = "main.ves"
+# defines = ["handout"]
+# engine = "pdflatex"
+# output = "handout"
+"#;
+
+const ARTICLE_SOURCE: &str = r#"docclass article
+import {
+    geometry (a4paper, margin = 1in)
+    amsmath
+    amssymb
+    graphicx
+    hyperref
+}
+
+\title{{{project}}}
+\author{{{author}}}
+
+document
+\maketitle
+
+\section{Introduction}
+"#;
+
+const BEAMER_SOURCE: &str = r#"docclass beamer
+import {
+    amsmath
+    graphicx
+}
+
+\title{{{project}}}
+\author{{{author}}}
+
+document
+begenv frame
+    \titlepage
+endenv
+
+begenv frame
+    \frametitle{Introduction}
+endenv
+"#;
+
+const BOOK_SOURCE: &str = r#"docclass book
+import {
+    geometry (a4paper, margin = 1in)
+    amsmath
+    amssymb
+    graphicx
+    hyperref
+}
+
+\title{{{project}}}
+\author{{{author}}}
+
+document
+\maketitle
+maketoc
+
+\input{chapters/introduction}
+"#;
+
+const BOOK_CHAPTER_SOURCE: &str = r#"docstartmode
+
+\chapter{Introduction}
+"#;
+
+const LETTER_SOURCE: &str = r#"docclass letter
+import geometry (a4paper, margin = 1in)
+
+\address{Sender Address}
+\signature{{{author}}}
+
+document
+begenv letter (Recipient Address)
+    \opening{Dear Sir or Madam,}
+
+    \closing{Yours faithfully,}
+endenv
+"#;
+
+const CV_SOURCE: &str = r#"docclass article
+import {
+    geometry (a4paper, margin = 0.8in)
+    enumitem
+    hyperref
+}
+
+\pagestyle{empty}
+
+document
+begenv center
+    {\huge {{author}}}
+
+    email@example.com
+endenv
+
+\section*{Education}
+
+\section*{Experience}
+
+\section*{Skills}
+"#;
+
+// A figure which is compiled by itself and included in a larger document
+const STANDALONE_SOURCE: &str = r#"docclass standalone (border = 2pt)
+import {
+    amsmath
+    tikz
+}
+
+document
+begenv tikzpicture
+    \draw (0, 0) -- (1, 1);
+endenv
+"#;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Template {
+    Article,
+    Beamer,
+    Book,
+    Letter,
+    Cv,
+    Standalone,
+}
+
+impl Template {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "article" => Some(Self::Article),
+            "beamer" => Some(Self::Beamer),
+            "book" => Some(Self::Book),
+            "letter" => Some(Self::Letter),
+            "cv" => Some(Self::Cv),
+            "standalone" | "figure" => Some(Self::Standalone),
+            _ => None,
+        }
+    }
+
+    fn directories(self) -> &'static [&'static str] {
+        match self {
+            Self::Article => &["figures"],
+            Self::Beamer => &["figures"],
+            Self::Book => &["chapters", "figures"],
+            Self::Letter => &[],
+            Self::Cv => &[],
+            Self::Standalone => &[],
+        }
+    }
+
+    fn main_source(self) -> &'static str {
+        match self {
+            Self::Article => ARTICLE_SOURCE,
+            Self::Beamer => BEAMER_SOURCE,
+            Self::Book => BOOK_SOURCE,
+            Self::Letter => LETTER_SOURCE,
+            Self::Cv => CV_SOURCE,
+            Self::Standalone => STANDALONE_SOURCE,
+        }
+    }
+
+    // Files except the main vesti file
+    fn extra_files(self) -> Vec<(&'static str, &'static str)> {
+        let mut output = vec![("vesti.toml", CONFIG_SOURCE)];
+        if self == Self::Book {
+            output.push(("chapters/introduction.ves", BOOK_CHAPTER_SOURCE));
+        }
+        output
+    }
+}
+
+enum ResolvedTemplate {
+    Bare,
+    Builtin(Template),
+    User(PathBuf),
+}
+
+// Values of variables which can be used in templates
+pub struct TemplateVars {
+    pub project: String,
+    pub author: String,
+    pub date: String,
+}
+
+impl TemplateVars {
+    // The project name is the name of the project directory, and the author is
+    // taken from the environment if it is not given.
+    pub fn new(project_dir: &Path, author: Option<String>) -> Self {
+        let project_dir = project_dir
+            .canonicalize()
+            .unwrap_or_else(|_| project_dir.to_path_buf());
+        let project = project_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let author = author
+            .or_else(|| env::var("VESTI_AUTHOR").ok())
+            .or_else(|| env::var("USER").ok())
+            .or_else(|| env::var("USERNAME").ok())
+            .unwrap_or_default();
+
+        Self {
+            project,
+            author,
+            date: today(),
+        }
+    }
+}
+
+// Today's date as YYYY-MM-DD in UTC
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Convert days since 1970-01-01 into a date of the proleptic Gregorian calendar.
+// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Only known variables are replaced, so braces of latex like `{{\bf x}}` are kept.
+fn interpolate(source: &str, vars: &TemplateVars) -> String {
+    source
+        .replace("{{project}}", &vars.project)
+        .replace("{{author}}", &vars.author)
+        .replace("{{date}}", &vars.date)
+}
+
+fn user_templates_dir() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("vesti").join("templates"))
+}
+
+// User templates come first so that they can override builtin ones.
+fn resolve_template(name: Option<&str>) -> error::Result<ResolvedTemplate> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(ResolvedTemplate::Bare),
+    };
+
+    if let Some(dir) = user_templates_dir() {
+        let path = dir.join(name).with_extension(TEMPLATE_EXTENSION);
+        if path.is_file() {
+            return Ok(ResolvedTemplate::User(path));
+        }
+    }
+
+    Template::from_name(name)
+        .map(ResolvedTemplate::Builtin)
+        .ok_or(VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::TemplateNotFoundErr(
+                name.to_string(),
+            )),
+            location: None,
+        })
+}
+
+fn generate_vesti_file(template: &ResolvedTemplate, vars: &TemplateVars) -> error::Result<String> {
+    let source = match template {
+        ResolvedTemplate::Bare => String::from(BARE_SOURCE),
+        ResolvedTemplate::Builtin(template) => String::from(template.main_source()),
+        ResolvedTemplate::User(path) => fs::read_to_string(path)?,
+    };
+
+    Ok(interpolate(&source, vars))
+}
+
+// Make a new project at `project_dir`. Nothing is written if one of files
+// already exists, so that an existing project is never overwritten.
+// Returns the paths of the created files and directories.
+pub fn init_project(
+    project_dir: &Path,
+    template: Option<&str>,
+    vars: &TemplateVars,
+) -> error::Result<Vec<PathBuf>> {
+    let template = resolve_template(template)?;
+    let main_source = generate_vesti_file(&template, vars)?;
+    let (mut files, directories) = match &template {
+        ResolvedTemplate::Bare => (vec![(BARE_FILE_NAME, main_source)], &[][..]),
+        ResolvedTemplate::Builtin(template) => {
+            (vec![(MAIN_FILE_NAME, main_source)], template.directories())
+        }
+        ResolvedTemplate::User(_) => (vec![(MAIN_FILE_NAME, main_source)], &[][..]),
+    };
+    match &template {
+        ResolvedTemplate::Bare => {}
+        ResolvedTemplate::Builtin(template) => files.extend(
+            template
+                .extra_files()
+                .into_iter()
+                .map(|(file_name, source)| (file_name, interpolate(source, vars))),
+        ),
+        ResolvedTemplate::User(_) => files.push(("vesti.toml", String::from(CONFIG_SOURCE))),
+    }
+
+    for (file_name, _) in &files {
+        let path = project_dir.join(file_name);
+        if path.exists() {
+            return Err(VestiErr {
+                err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::FileAlreadyExistsErr(
+                    path.display().to_string(),
+                )),
+                location: None,
+            });
+        }
+    }
+
+    let mut output = Vec::new();
+    fs::create_dir_all(project_dir)?;
+    for dir in directories {
+        let path = project_dir.join(dir);
+        if !path.is_dir() {
+            fs::create_dir_all(&path)?;
+            output.push(path);
+        }
+    }
+    for (file_name, contents) in files {
+        let path = project_dir.join(file_name);
+        fs::write(&path, contents)?;
+        output.push(path);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::VestiConfig;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    fn vars() -> TemplateVars {
+        TemplateVars {
+            project: String::from("thesis"),
+            author: String::from("Jane Doe"),
+            date: String::from("2021-03-04"),
+        }
+    }
+
+    #[test]
+    fn test_templates_are_valid() {
+        for template in [
+            Template::Article,
+            Template::Beamer,
+            Template::Book,
+            Template::Letter,
+            Template::Cv,
+            Template::Standalone,
+        ] {
+            let main_file = (MAIN_FILE_NAME, template.main_source());
+            for (file_name, source) in template.extra_files().into_iter().chain([main_file]) {
+                let source = interpolate(source, &vars());
+                if !file_name.ends_with(".ves") {
+                    assert!(toml::from_str::<VestiConfig>(&source).is_ok());
+                    continue;
+                }
+                let arena = Arena::new();
+                let mut parser = Parser::new(Lexer::new(&source), &arena);
+                assert!(
+                    parser.parse_latex().is_ok(),
+                    "{:?} template has an invalid file `{}`",
+                    template,
+                    file_name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(
+            interpolate(
+                "\\title{{{project}}}\n\\author{{{author}}}\n\\date{{{date}}} {{\\bf x}} {{foo}}",
+                &vars()
+            ),
+            "\\title{thesis}\n\\author{Jane Doe}\n\\date{2021-03-04} {{\\bf x}} {{foo}}"
+        );
+    }
+
+    #[test]
+    fn test_civil_from_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(18690), (2021, 3, 4));
+    }
+}
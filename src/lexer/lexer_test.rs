@@ -1,4 +1,5 @@
 use super::*;
+use std::borrow::Cow;
 
 #[test]
 fn test_lexing_symbols() {
@@ -25,10 +26,7 @@ fn test_lexing_symbols() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, source.to_string());
 }
@@ -52,10 +50,7 @@ fn test_lexing_whitespaces() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, "\t  \t\n\n \t\n");
 }
@@ -94,10 +89,7 @@ fn test_text_raw_latex() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, expected_literal);
 }
@@ -120,14 +112,95 @@ fn test_inline_raw_latex() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, expected_literal);
 }
 
+#[test]
+fn test_lexing_directives() {
+    let source = r#"#if draft # comment
+foo
+#else
+bar
+#endif
+# if this is a comment
+#iffy comment"#;
+    let expected_toktype = vec![
+        TokenType::PreIf,
+        TokenType::MainString,
+        TokenType::Newline,
+        TokenType::PreElse,
+        TokenType::MainString,
+        TokenType::Newline,
+        TokenType::PreEndif,
+    ];
+    let lex = Lexer::new(source);
+    let lexed_token = lex
+        .clone()
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
+    assert_eq!(lexed_token, expected_toktype);
+    assert_eq!(lexed_literal, "draftfoo\nbar\n");
+}
+
+#[test]
+fn test_lexing_directive_in_the_middle_of_line() {
+    let source = "  #if draft\nfoo #if draft\nbar #endif\n";
+    let expected_toktype = vec![
+        TokenType::Space,
+        TokenType::Space,
+        TokenType::PreIf,
+        TokenType::MainString,
+        TokenType::Space,
+        TokenType::MainString,
+        TokenType::Space,
+    ];
+    let lex = Lexer::new(source);
+    let lexed_token = lex
+        .clone()
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
+    assert_eq!(lexed_token, expected_toktype);
+    assert_eq!(lexed_literal, "  draftfoo bar ");
+}
+
+#[test]
+fn test_lexing_list_markers() {
+    let source = "- a -\n  1. b 2. c\n-1.";
+    let expected_toktype = vec![
+        TokenType::ItemizeMarker,
+        TokenType::Space,
+        TokenType::MainString,
+        TokenType::Space,
+        TokenType::Minus,
+        TokenType::Newline,
+        TokenType::Space,
+        TokenType::Space,
+        TokenType::EnumerateMarker,
+        TokenType::Space,
+        TokenType::MainString,
+        TokenType::Space,
+        TokenType::Integer,
+        TokenType::Period,
+        TokenType::Space,
+        TokenType::MainString,
+        TokenType::Newline,
+        TokenType::Integer,
+        TokenType::Period,
+    ];
+    let lex = Lexer::new(source);
+    let lexed_token = lex
+        .clone()
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
+    assert_eq!(lexed_token, expected_toktype);
+    assert_eq!(lexed_literal, source.to_string());
+}
+
 #[test]
 fn test_lexing_ascii_string() {
     let source = "This is a string!";
@@ -146,10 +219,7 @@ fn test_lexing_ascii_string() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, source.to_string());
 }
@@ -168,10 +238,7 @@ fn test_lexing_unicode_string() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, source.to_string());
 }
@@ -197,14 +264,45 @@ fn test_lex_number() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, source.to_string());
 }
 
+#[test]
+fn test_lex_dimension() {
+    let source = "3cm 0.5\\textwidth -1.25pt 2fill 3cmx 4\\alpha";
+    let expected_toktype = vec![
+        TokenType::Dimension,
+        TokenType::Space,
+        TokenType::Dimension,
+        TokenType::Space,
+        TokenType::Dimension,
+        TokenType::Space,
+        TokenType::Dimension,
+        TokenType::Space,
+        TokenType::Integer,
+        TokenType::MainString,
+        TokenType::Space,
+        TokenType::Integer,
+        TokenType::LatexFunction,
+    ];
+    let lex = Lexer::new(source);
+    let lexed_token = lex
+        .clone()
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
+    assert_eq!(lexed_token, expected_toktype);
+    assert_eq!(
+        lexed_literal,
+        "3cm 0.5\\textwidth -1.25pt 2fill 3cmx 4alpha"
+    );
+
+    let mut lex = Lexer::new("\\(2pt\\)");
+    assert_eq!(lex.nth(1).unwrap().token.toktype, TokenType::Integer);
+}
+
 #[test]
 fn lexing_keywords() {
     let source = "docclass begenv document mtxt import etxt endenv";
@@ -228,10 +326,7 @@ fn lexing_keywords() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, source.to_string());
 }
@@ -249,10 +344,7 @@ fn lexing_latex_functions() {
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, "foo bar@hand");
 }
@@ -319,8 +411,7 @@ endenv"#;
         TokenType::Space,
         TokenType::Equal,
         TokenType::Space,
-        TokenType::Float,
-        TokenType::MainString,
+        TokenType::Dimension,
         TokenType::Rparen,
         TokenType::Comma,
         TokenType::Newline,
@@ -404,10 +495,155 @@ endenv"#;
         .clone()
         .map(|lextok| lextok.token.toktype)
         .collect::<Vec<TokenType>>();
-    let lexed_literal = lex
-        .map(|lextok| lextok.token.literal)
-        .collect::<Vec<String>>()
-        .concat();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
     assert_eq!(lexed_token, expected_toktype);
     assert_eq!(lexed_literal, expected_literal);
 }
+
+#[test]
+fn test_lexing_file_directive() {
+    let source = "%!engine: xelatex\n- foo 10%!";
+    let expected_toktype = vec![
+        TokenType::FileDirective,
+        TokenType::ItemizeMarker,
+        TokenType::Space,
+        TokenType::MainString,
+        TokenType::Space,
+        TokenType::Integer,
+        TokenType::Percent,
+        TokenType::Bang,
+    ];
+    let lex = Lexer::new(source);
+    let lexed_token = lex
+        .clone()
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    let lexed_literal = lex.map(|lextok| lextok.token.literal).collect::<String>();
+    assert_eq!(lexed_token, expected_toktype);
+    assert_eq!(lexed_literal, "engine: xelatex- foo 10\\%!");
+}
+
+#[test]
+fn test_lexing_kept_comment() {
+    let source = "# gone\n#> kept  \nfoo #>inline\n";
+    let expected = vec![
+        (TokenType::Comment, " kept"),
+        (TokenType::Newline, "\n"),
+        (TokenType::MainString, "foo"),
+        (TokenType::Space, " "),
+        (TokenType::Comment, "inline"),
+        (TokenType::Newline, "\n"),
+    ];
+    let lexed = Lexer::new(source)
+        .map(|lextok| (lextok.token.toktype, lextok.token.literal.into_owned()))
+        .collect::<Vec<(TokenType, String)>>();
+    assert_eq!(
+        lexed,
+        expected
+            .into_iter()
+            .map(|(toktype, literal)| (toktype, String::from(literal)))
+            .collect::<Vec<(TokenType, String)>>()
+    );
+}
+
+#[test]
+fn test_lexing_math_font() {
+    let source = "bb{R} \\(bb{R} bb x\\)";
+    let expected = vec![
+        (TokenType::MainString, "bb"),
+        (TokenType::Lbrace, "{"),
+        (TokenType::MainString, "R"),
+        (TokenType::Rbrace, "}"),
+        (TokenType::Space, " "),
+        (TokenType::TextMathStart, "\\("),
+        (TokenType::MathFont, "bb"),
+        (TokenType::Lbrace, "{"),
+        (TokenType::MainString, "R"),
+        (TokenType::Rbrace, "}"),
+        (TokenType::Space, " "),
+        (TokenType::MainString, "bb"),
+        (TokenType::Space, " "),
+        (TokenType::MainString, "x"),
+        (TokenType::TextMathEnd, "\\)"),
+    ];
+    let lexed = Lexer::new(source)
+        .map(|lextok| (lextok.token.toktype, lextok.token.literal.into_owned()))
+        .collect::<Vec<(TokenType, String)>>();
+    assert_eq!(
+        lexed,
+        expected
+            .into_iter()
+            .map(|(toktype, literal)| (toktype, String::from(literal)))
+            .collect::<Vec<(TokenType, String)>>()
+    );
+}
+
+#[test]
+fn test_lexing_raw_latex_block() {
+    let source = "latex%\n\\def\\foo{{%\n  100% }\n%end\nlatex%%  \n%end\n  %%end\nlatex %";
+    let expected = vec![
+        (TokenType::RawLatexBlock, "\\def\\foo{{%\n  100% }\n"),
+        (TokenType::RawLatexBlock, "%end\n"),
+        (TokenType::MainString, "latex"),
+        (TokenType::Space, " "),
+        (TokenType::Percent, "\\%"),
+    ];
+    let lexed = Lexer::new(source)
+        .map(|lextok| (lextok.token.toktype, lextok.token.literal.into_owned()))
+        .collect::<Vec<(TokenType, String)>>();
+    assert_eq!(
+        lexed,
+        expected
+            .into_iter()
+            .map(|(toktype, literal)| (toktype, String::from(literal)))
+            .collect::<Vec<(TokenType, String)>>()
+    );
+
+    let lexed_toktype = Lexer::new("latex%\nfoo")
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    assert_eq!(lexed_toktype, vec![TokenType::UnclosedRawLatexBlock]);
+}
+
+#[test]
+fn test_literal_borrows_source() {
+    let source = "foo 3.5cm\r\n#-\\bar\r\nbaz-#";
+    let lexed = Lexer::new(source)
+        .map(|lextok| lextok.token.literal)
+        .collect::<Vec<_>>();
+    assert!(matches!(lexed[0], Cow::Borrowed("foo")));
+    assert!(matches!(lexed[2], Cow::Borrowed("3.5cm")));
+    // `\r\n` is read as `\n`, so such a literal cannot borrow the source
+    assert_eq!(lexed[3], "\n");
+    assert!(matches!(&lexed[4], Cow::Owned(raw) if raw == "\\bar\nbaz"));
+}
+
+#[test]
+fn test_lexing_dollar_math() {
+    let source = "$x$ $$y$$ $a$$b$ \\$";
+    let expected = vec![
+        TokenType::TextMathStart,
+        TokenType::MainString,
+        TokenType::TextMathEnd,
+        TokenType::Space,
+        TokenType::InlineMathStart,
+        TokenType::MainString,
+        TokenType::InlineMathEnd,
+        TokenType::Space,
+        TokenType::TextMathStart,
+        TokenType::MainString,
+        TokenType::TextMathEnd,
+        TokenType::TextMathStart,
+        TokenType::MainString,
+        TokenType::TextMathEnd,
+        TokenType::Space,
+        TokenType::Dollar,
+    ];
+    let mut lex = Lexer::new(source);
+    assert_eq!(lex.next().unwrap().token.toktype, TokenType::Dollar2);
+    lex.set_dollar_math(true);
+    let lexed_token = lex
+        .map(|lextok| lextok.token.toktype)
+        .collect::<Vec<TokenType>>();
+    assert_eq!(lexed_token, expected);
+}
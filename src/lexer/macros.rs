@@ -5,7 +5,7 @@ macro_rules! tokenize {
         Some(LexToken {
             token: Token {
                 toktype: TokenType::$toktype,
-                literal: String::from($literal),
+                literal: Cow::from($literal),
             },
             span: Span {
                 start: $start,
@@ -5,18 +5,60 @@ mod lexer_test;
 mod newline_handler;
 pub mod token;
 
+use crate::headings::HEADING_COMMANDS;
 use crate::location::{Location, Span};
+use crate::symbols;
 use newline_handler::Newlinehandler;
+use std::borrow::Cow;
 use token::{Token, TokenType};
 
+// Units of TeX and pdfTeX which can follow a number
+const LENGTH_UNITS: &[&str] = &[
+    "pt", "pc", "in", "bp", "cm", "mm", "dd", "cc", "sp", "em", "ex", "mu", "nd", "nc", "px",
+    "fil", "fill", "filll",
+];
+
+// Length registers which are usually multiplied like `0.5\textwidth`
+const LENGTH_REGISTERS: &[&str] = &[
+    "textwidth",
+    "textheight",
+    "linewidth",
+    "columnwidth",
+    "paperwidth",
+    "paperheight",
+    "hsize",
+    "vsize",
+    "baselineskip",
+    "parindent",
+    "parskip",
+    "tabcolsep",
+    "unitlength",
+    "fill",
+];
+
+// What follows `chapter` in `chapter import "chap1.ves"`
+const CHAPTER_IMPORT_SUFFIX: &str = " import";
+const DOC_COMMENT_PREFIX: &str = "///";
+// `date` takes one of these words instead of a string
+const DATE_WORDS: [&str; 2] = ["today", "none"];
+
+// The lexer reads `\r\n` and `\r` as `\n`, so only a literal with them is copied
+fn normalize_newlines(literal: &str) -> Cow<'_, str> {
+    if literal.contains('\r') {
+        Cow::Owned(literal.replace("\r\n", "\n").replace('\r', "\n"))
+    } else {
+        Cow::Borrowed(literal)
+    }
+}
+
 #[derive(Clone, Debug)]
-pub struct LexToken {
-    pub token: Token,
+pub struct LexToken<'a> {
+    pub token: Token<'a>,
     pub span: Span,
 }
 
-impl LexToken {
-    pub fn new(token: Token, start: Location, end: Location) -> Self {
+impl<'a> LexToken<'a> {
+    pub fn new(token: Token<'a>, start: Location, end: Location) -> Self {
         Self {
             token,
             span: Span { start, end },
@@ -33,23 +75,44 @@ impl LexToken {
 
 #[derive(Clone)]
 pub struct Lexer<'a> {
+    input: &'a str,
     source: Newlinehandler<'a>,
     chr0: Option<char>,
     chr1: Option<char>,
     chr2: Option<char>,
+    // byte offsets of chr0, chr1 and chr2 in the input
+    pos0: usize,
+    pos1: usize,
+    pos2: usize,
     current_loc: Location,
+    at_line_start: bool,
     pub math_started: bool,
+    // the row where the input starts in its file
+    start_row: usize,
+    // `$...$` and `$$...$$` are math as in latex
+    dollar_math: bool,
+    // whether the math is started by `$$`
+    is_display_dollar: bool,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new<T: AsRef<str> + ?Sized>(source: &'a T) -> Self {
+        let input = source.as_ref();
         let mut output = Self {
-            source: Newlinehandler::new(source),
+            input,
+            source: Newlinehandler::new(input),
             chr0: None,
             chr1: None,
             chr2: None,
+            pos0: 0,
+            pos1: 0,
+            pos2: 0,
             current_loc: Location::default(),
+            at_line_start: true,
             math_started: false,
+            start_row: 1,
+            dollar_math: false,
+            is_display_dollar: false,
         };
         output.next_char();
         output.next_char();
@@ -58,6 +121,21 @@ impl<'a> Lexer<'a> {
         output
     }
 
+    // A lexer of a part of a file, whose first line is the given row of the file
+    pub fn with_start_row<T: AsRef<str> + ?Sized>(source: &'a T, row: usize) -> Self {
+        let mut output = Self::new(source);
+        output.current_loc = Location::new(row, 1);
+        output.start_row = row;
+        output
+    }
+
+    // Read `$...$` and `$$...$$` as math, so that sources written for latex can
+    // be compiled as they are. The input is lexed again from the start.
+    pub fn set_dollar_math(&mut self, dollar_math: bool) {
+        *self = Self::with_start_row(self.input, self.start_row);
+        self.dollar_math = dollar_math;
+    }
+
     fn next_char(&mut self) {
         if self.chr0 == Some('\n') {
             self.current_loc.move_next_line();
@@ -66,10 +144,220 @@ impl<'a> Lexer<'a> {
         }
         self.chr0 = self.chr1;
         self.chr1 = self.chr2;
-        self.chr2 = self.source.next();
+        self.pos0 = self.pos1;
+        self.pos1 = self.pos2;
+        match self.source.next() {
+            Some((pos, chr)) => {
+                self.chr2 = Some(chr);
+                self.pos2 = pos;
+            }
+            None => {
+                self.chr2 = None;
+                self.pos2 = self.input.len();
+            }
+        }
+    }
+
+    fn is_chapter_import(&self) -> bool {
+        self.at_line_start
+            && !self.math_started
+            && self.input[self.pos0..].starts_with(CHAPTER_IMPORT_SUFFIX)
+            && self.input[self.pos0 + CHAPTER_IMPORT_SUFFIX.len()..].starts_with([' ', '\t', '"'])
+    }
+
+    // `section "Title"` or `section* "Title"`
+    fn is_heading_start(&self) -> bool {
+        self.at_line_start
+            && !self.math_started
+            && self.input[self.pos0..]
+                .trim_start_matches('*')
+                .trim_start_matches([' ', '\t'])
+                .starts_with('"')
+    }
+
+    // `frame` of beamer is a keyword only if the rest of its line is an optional
+    // overlay, title and options followed by the body, like
+    // `frame<2-> "Title" (fragile) {`, so that `frame (see fig)` stays a text
+    fn is_frame_start(&self) -> bool {
+        if !self.at_line_start || self.math_started {
+            return false;
+        }
+        let line = self.input[self.pos0..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        let mut rest = line.trim_start_matches([' ', '\t']);
+        if let Some(overlay) = rest.strip_prefix('<') {
+            let Some((_, after)) = overlay.split_once('>') else {
+                return false;
+            };
+            rest = after.trim_start_matches([' ', '\t']);
+        }
+        if let Some(title) = rest.strip_prefix('"') {
+            let Some(end) = title
+                .char_indices()
+                .find(|&(idx, chr)| chr == '"' && !title[..idx].ends_with('\\'))
+                .map(|(idx, _)| idx)
+            else {
+                return false;
+            };
+            rest = title[end + 1..].trim_start_matches([' ', '\t']);
+        }
+        if let Some(options) = rest.strip_prefix('(') {
+            let mut depth = 1;
+            let Some(end) = options.char_indices().find_map(|(idx, chr)| {
+                match chr {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(idx)
+            }) else {
+                return false;
+            };
+            rest = options[end + 1..].trim_start_matches([' ', '\t']);
+        }
+        rest.starts_with('{')
+    }
+
+    // `execute` is a common word in texts, so it is a keyword only in a line
+    // like `execute(python) {`
+    fn is_execute_start(&self) -> bool {
+        if !self.at_line_start || self.math_started {
+            return false;
+        }
+        let line = self.input[self.pos0..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        let Some((lang, rest)) = line.strip_prefix('(').and_then(|line| line.split_once(')'))
+        else {
+            return false;
+        };
+        let lang = lang.trim_matches([' ', '\t']);
+        !lang.is_empty()
+            && lang.chars().all(char::is_alphanumeric)
+            && rest.trim_end().trim_start_matches([' ', '\t']) == "{"
+    }
+
+    // `index "group"` in texts
+    fn is_index_start(&self) -> bool {
+        !self.math_started
+            && self.input[self.pos0..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('"')
+    }
+
+    // `///` lines right before `defun` or `defenv` document the definition.
+    // Other `///` are texts as before.
+    fn is_doc_comment_start(&self) -> bool {
+        if !self.at_line_start || self.math_started {
+            return false;
+        }
+        let mut rest = &self.input[self.pos0..];
+        while let Some(line) = rest.strip_prefix(DOC_COMMENT_PREFIX) {
+            rest = line
+                .split_once('\n')
+                .map_or("", |(_, next)| next)
+                .trim_start_matches([' ', '\t']);
+        }
+        ["defun", "defenv"].iter().any(|keyword| {
+            rest.strip_prefix(keyword)
+                .is_some_and(|rest| rest.starts_with([' ', '\t']))
+        })
+    }
+
+    // The literal is the lines of the doc comment with their `///`
+    fn lex_doc_comment(&mut self) -> LexToken<'a> {
+        let start_loc = self.current_loc;
+        let start = self.pos0;
+        let mut end_loc = self.current_loc;
+        let mut end = self.pos0;
+        while self.input[self.pos0..].starts_with(DOC_COMMENT_PREFIX) {
+            self.rest_of_line();
+            end_loc = self.current_loc;
+            end = self.pos0;
+            self.next_char();
+            while matches!(self.chr0, Some(' ' | '\t')) {
+                self.next_char();
+            }
+        }
+        LexToken::new(
+            Token::new(TokenType::DocComment, &self.input[start..end]),
+            start_loc,
+            end_loc,
+        )
+    }
+
+    // `title "..."`, `author "..."` and `date today` at the start of a line
+    fn is_metadata_start(&self, word: &str) -> bool {
+        if !["title", "author", "date"].contains(&word) || !self.at_line_start || self.math_started
+        {
+            return false;
+        }
+        let rest = self.input[self.pos0..].trim_start_matches([' ', '\t']);
+        rest.starts_with('"')
+            || (word == "date"
+                && DATE_WORDS.iter().any(|date| {
+                    rest.strip_prefix(date)
+                        .is_some_and(|rest| !rest.starts_with(char::is_alphanumeric))
+                }))
+    }
+
+    // `qty` is a keyword only if the parentheses after it are closed in the line
+    fn is_quantity_start(&self) -> bool {
+        self.chr0 == Some('(') && self.quantity_len().is_some()
+    }
+
+    // Length of `(value, unit)` with its parentheses. Parentheses can be nested
+    // for uncertainties like `1.23(4)`.
+    fn quantity_len(&self) -> Option<usize> {
+        let mut depth = 0;
+        for (idx, chr) in self.input[self.pos0..].char_indices() {
+            match chr {
+                '(' => depth += 1,
+                ')' if depth == 1 => return Some(idx + 1),
+                ')' => depth -= 1,
+                '\n' => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn lex_quantity(&mut self, start_loc: Location) -> LexToken<'a> {
+        let len = self.quantity_len().unwrap();
+        let end = self.pos0 + len - 1;
+        self.next_char();
+        let start = self.pos0;
+        while self.pos0 < end {
+            self.next_char();
+        }
+        let literal = self.literal_since(start);
+        self.next_char();
+        LexToken::new(
+            Token::new(TokenType::Quantity, literal),
+            start_loc,
+            self.current_loc,
+        )
+    }
+
+    // Whether the word just read is the only one in its line
+    fn is_line_alone(&self) -> bool {
+        self.at_line_start
+            && !self.math_started
+            && self.input[self.pos0..]
+                .split('\n')
+                .next()
+                .is_some_and(|rest| rest.trim().is_empty())
     }
 
-    fn take_tok(&mut self) -> Option<LexToken> {
+    // The source from `start` to the current character
+    fn literal_since(&self, start: usize) -> Cow<'a, str> {
+        normalize_newlines(&self.input[start..self.pos0])
+    }
+
+    fn take_tok(&mut self) -> Option<LexToken<'a>> {
         let start_loc = self.current_loc;
         match self.chr0 {
             Some('\0') | None => None,
@@ -83,9 +371,13 @@ impl<'a> Lexer<'a> {
                     tokenize!(self | RightArrow, "\\rightarrow "; start_loc)
                 }
                 Some(chr) if chr.is_ascii_digit() => Some(self.lex_number()),
+                Some(' ') if self.at_line_start && !self.math_started => {
+                    tokenize!(self | ItemizeMarker, "-"; start_loc)
+                }
                 _ => tokenize!(self | Minus, "-"; start_loc),
             },
             Some('*') => tokenize!(self | Star, "*"; start_loc),
+            Some('/') if self.is_doc_comment_start() => Some(self.lex_doc_comment()),
             Some('/') => tokenize!(self | Slash, "/";start_loc),
             Some('=') => tokenize!(self | Equal , "="; start_loc),
             Some('<') => match self.chr1 {
@@ -109,8 +401,19 @@ impl<'a> Lexer<'a> {
             }
             Some('!') => tokenize!(self | Bang, "!"; start_loc),
             Some('?') => tokenize!(self | Question, "?"; start_loc),
-            Some('@') => tokenize!(self | At, "@"; start_loc),
-            Some('%') => tokenize!(self | Percent, "\\%"; start_loc),
+            Some('@') => match self.chr1 {
+                Some('[') if !self.math_started => {
+                    self.next_char();
+                    tokenize!(self | CiteStart, "@["; start_loc)
+                }
+                _ => tokenize!(self | At, "@"; start_loc),
+            },
+            Some('%') => match self.chr1 {
+                Some('!') if self.at_line_start && !self.math_started => {
+                    Some(self.lex_file_directive())
+                }
+                _ => tokenize!(self | Percent, "\\%"; start_loc),
+            },
             Some('^') => tokenize!(self | Superscript, "^"; start_loc),
             Some('&') => tokenize!(self | Ampersand, "&"; start_loc),
             Some(';') => tokenize!(self | Semicolon, ";"; start_loc),
@@ -130,16 +433,26 @@ impl<'a> Lexer<'a> {
             Some('[') => tokenize!(self | Lsqbrace, "["; start_loc),
             Some(']') => tokenize!(self | Rsqbrace, "]"; start_loc),
             Some('$') => match self.chr1 {
-				Some('!') => {
-					self.next_char();
-					tokenize!(self | Dollar, "$"; start_loc)
-				}
-				_ => tokenize!(self | Dollar2, "\\$"; start_loc)
-			}
+                Some('!') => {
+                    self.next_char();
+                    tokenize!(self | Dollar, "$"; start_loc)
+                }
+                _ if self.dollar_math => self.lex_dollar_math(),
+                _ => tokenize!(self | Dollar2, "\\$"; start_loc),
+            },
             Some('#') => self.lex_sharp_char(),
             Some('\\') => self.lex_backslash(),
-            _ if self.chr0.map_or(false, |chr| chr.is_alphabetic()) => Some(self.lex_main_string()),
-            _ if self.chr0.map_or(false, |chr| chr.is_ascii_digit()) => Some(self.lex_number()),
+            Some(chr) if self.math_started && symbols::unicode_symbol(chr).is_some() => {
+                let start = self.pos0;
+                self.next_char();
+                Some(LexToken::new(
+                    Token::new(TokenType::UnicodeSymbol, self.literal_since(start)),
+                    start_loc,
+                    self.current_loc,
+                ))
+            }
+            _ if self.chr0.is_some_and(|chr| chr.is_alphabetic()) => Some(self.lex_main_string()),
+            _ if self.chr0.is_some_and(|chr| chr.is_ascii_digit()) => Some(self.lex_number()),
             _ => {
                 self.next_char();
                 Some(LexToken::illegal(start_loc, self.current_loc))
@@ -147,18 +460,59 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn lex_main_string(&mut self) -> LexToken {
+    fn lex_main_string(&mut self) -> LexToken<'a> {
         let start_loc = self.current_loc;
-        let mut literal = String::new();
+        if self.at_line_start && !self.math_started && self.is_raw_block_start() {
+            return self.lex_raw_block();
+        }
+        let start = self.pos0;
         while let Some(chr) = self.chr0 {
-            if !chr.is_alphanumeric() {
+            // `xα` in math mode is `x\alpha`
+            if !chr.is_alphanumeric()
+                || (self.math_started && symbols::unicode_symbol(chr).is_some())
+            {
                 break;
             }
-            literal.push(chr);
             self.next_char();
         }
-        let toktype = if let Some(toktype) = token::is_keyword(&literal) {
-            if &literal == "mnd" && self.chr0 == Some(' ') {
+        let mut literal = self.literal_since(start);
+        let toktype = if literal == "execute" && self.is_execute_start() {
+            TokenType::Execute
+        } else if literal == "qty" && self.is_quantity_start() {
+            return self.lex_quantity(start_loc);
+        } else if literal == "chapter" && self.is_chapter_import() {
+            // `chapter` is a common word too, so only `chapter import` at the
+            // start of a line is a keyword
+            for _ in 0..CHAPTER_IMPORT_SUFFIX.len() {
+                self.next_char();
+            }
+            literal = self.literal_since(start);
+            TokenType::ChapterImport
+        } else if HEADING_COMMANDS.contains(&literal.as_ref()) && self.is_heading_start() {
+            if self.chr0 == Some('*') {
+                self.next_char();
+                literal = self.literal_since(start);
+            }
+            TokenType::Heading
+        } else if literal == "frame" && self.is_frame_start() {
+            TokenType::Frame
+        } else if literal == "index" && self.is_index_start() {
+            // `index` is a common word too, so it is a keyword only before its term
+            TokenType::Index
+        } else if literal == "pause" && self.is_line_alone() {
+            TokenType::Pause
+        } else if self.is_metadata_start(&literal) {
+            TokenType::Metadata
+        } else if literal == "maketitle" && self.is_line_alone() {
+            TokenType::Maketitle
+        } else if self.math_started
+            && self.chr0 == Some('{')
+            && token::math_font(&literal).is_some()
+        {
+            // `bb` is a math font only right before its argument, like `bb{R}`
+            TokenType::MathFont
+        } else if let Some(toktype) = token::is_keyword(&literal) {
+            if literal == "mnd" && self.chr0 == Some(' ') {
                 self.next_char();
             }
             toktype
@@ -172,12 +526,12 @@ impl<'a> Lexer<'a> {
     // For example, one might write 000000 meaning that the number 0.
     // On the other hand, one might write 000000 meaning that the string 000000.
     // However, vesti understand both as 0, the number.
-    fn lex_number(&mut self) -> LexToken {
+    fn lex_number(&mut self) -> LexToken<'a> {
         let start_loc = self.current_loc;
-        let mut literal = String::new();
+        let start = self.pos0;
+        let is_negative = self.chr0 == Some('-');
 
-        if self.chr0 == Some('-') {
-            literal.push('-');
+        if is_negative {
             self.next_char();
         }
 
@@ -185,43 +539,110 @@ impl<'a> Lexer<'a> {
             if !chr.is_ascii_digit() {
                 break;
             }
-            literal.push(chr);
             self.next_char();
         }
 
-        let toktype =
-            if self.chr0 == Some('.') && self.chr1.map_or(false, |chr| chr.is_ascii_digit()) {
-                literal.push('.');
-                self.next_char();
-                TokenType::Float
-            } else {
-                TokenType::Integer
-            };
+        if self.at_line_start
+            && !self.math_started
+            && !is_negative
+            && self.chr0 == Some('.')
+            && self.chr1 == Some(' ')
+        {
+            self.next_char();
+            return LexToken::new(
+                Token::new(TokenType::EnumerateMarker, self.literal_since(start)),
+                start_loc,
+                self.current_loc,
+            );
+        }
 
-        if self.chr0.map_or(false, |chr| chr.is_ascii_digit()) {
-            while let Some(chr) = self.chr0 {
-                if !chr.is_ascii_digit() {
-                    break;
-                }
-                literal.push(chr);
-                self.next_char();
+        let toktype = if self.chr0 == Some('.') && self.chr1.is_some_and(|chr| chr.is_ascii_digit())
+        {
+            self.next_char();
+            TokenType::Float
+        } else {
+            TokenType::Integer
+        };
+
+        while let Some(chr) = self.chr0 {
+            if !chr.is_ascii_digit() {
+                break;
             }
+            self.next_char();
         }
 
-        LexToken::new(Token::new(toktype, literal), start_loc, self.current_loc)
+        // In math mode, `2pt` might be a product of variables
+        let toktype = if !self.math_started && self.lex_length_unit() {
+            TokenType::Dimension
+        } else {
+            toktype
+        };
+
+        LexToken::new(
+            Token::new(toktype, self.literal_since(start)),
+            start_loc,
+            self.current_loc,
+        )
+    }
+
+    // Take a unit right after a number if there is. Nothing is consumed if the
+    // following word is not a unit, so `3cmx` is still a number and a string.
+    fn lex_length_unit(&mut self) -> bool {
+        let mut probe = self.clone();
+        let is_register = probe.chr0 == Some('\\');
+        if is_register {
+            probe.next_char();
+        }
+        let start = probe.pos0;
+        while let Some(chr) = probe.chr0 {
+            if !chr.is_alphanumeric() {
+                break;
+            }
+            probe.next_char();
+        }
+
+        let unit = &self.input[start..probe.pos0];
+        let is_unit = if is_register {
+            LENGTH_REGISTERS.contains(&unit)
+        } else {
+            LENGTH_UNITS.contains(&unit)
+        };
+        if is_unit {
+            *self = probe;
+        }
+        is_unit
     }
 
-    fn lex_sharp_char(&mut self) -> Option<LexToken> {
+    fn lex_sharp_char(&mut self) -> Option<LexToken<'a>> {
         let start_loc = self.current_loc;
         match self.chr1 {
             Some('!') => {
                 self.next_char();
                 tokenize!(self | FntParam, "#"; start_loc)
             }
+            Some('(') if self.math_started => {
+                self.next_char();
+                tokenize!(self | LeftBig, "("; start_loc)
+            }
+            Some(')') if self.math_started => {
+                self.next_char();
+                tokenize!(self | RightBig, ")"; start_loc)
+            }
+            Some('{') if self.math_started => {
+                self.next_char();
+                tokenize!(self | LeftBig, "\\{"; start_loc)
+            }
+            Some('}') if self.math_started => {
+                self.next_char();
+                tokenize!(self | RightBig, "\\}"; start_loc)
+            }
             Some('@') => {
                 self.next_char();
                 tokenize!(self | Newline2, "\n"; start_loc)
             }
+            Some('[') if self.at_line_start && self.is_attribute_start() => {
+                Some(self.lex_attribute())
+            }
             Some('[') => {
                 self.next_char();
                 tokenize!(self | OptionalOpenBrace, "["; start_loc)
@@ -240,13 +661,14 @@ impl<'a> Lexer<'a> {
                 self.take_tok()
             }
             Some('-') => {
-                let mut literal = String::new();
                 self.next_char();
                 self.next_char();
+                let start = self.pos0;
                 while self.chr0 != Some('-') || self.chr1 != Some('#') {
-                    literal.push(self.chr0?);
+                    self.chr0?;
                     self.next_char();
                 }
+                let literal = self.literal_since(start);
                 self.next_char();
                 self.next_char();
                 Some(LexToken::new(
@@ -255,15 +677,27 @@ impl<'a> Lexer<'a> {
                     self.current_loc,
                 ))
             }
+            // `#>` is a comment which is written into the latex code
+            Some('>') => {
+                self.next_char();
+                self.next_char();
+                let literal = self.rest_of_line().trim_end();
+                Some(LexToken::new(
+                    Token::new(TokenType::Comment, literal),
+                    start_loc,
+                    self.current_loc,
+                ))
+            }
             Some('#') if self.chr2 == Some('-') => {
-                let mut literal = String::new();
                 self.next_char();
                 self.next_char();
                 self.next_char();
+                let start = self.pos0;
                 while self.chr0 != Some('-') || self.chr1 != Some('#') || self.chr2 != Some('#') {
-                    literal.push(self.chr0?);
+                    self.chr0?;
                     self.next_char();
                 }
+                let literal = self.literal_since(start);
                 self.next_char();
                 self.next_char();
                 self.next_char();
@@ -274,6 +708,13 @@ impl<'a> Lexer<'a> {
                 ))
             }
             _ => {
+                self.next_char();
+                // Directives are only at the start of a line after indentation
+                if self.at_line_start {
+                    if let Some(tok) = self.lex_directive(start_loc) {
+                        return Some(tok);
+                    }
+                }
                 while self.chr0? != '\n' {
                     self.next_char();
                 }
@@ -283,7 +724,201 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn lex_backslash(&mut self) -> Option<LexToken> {
+    // Since `#` also starts a comment, the word right after `#` is read first.
+    // If it is not a directive, the rest of the line is considered as a comment.
+    fn lex_directive(&mut self, start_loc: Location) -> Option<LexToken<'a>> {
+        let start = self.pos0;
+        while let Some(chr) = self.chr0 {
+            if !chr.is_ascii_alphabetic() {
+                break;
+            }
+            self.next_char();
+        }
+        if !matches!(self.chr0, Some(' ' | '\t' | '\n') | None) {
+            return None;
+        }
+        let toktype = token::is_directive(&self.input[start..self.pos0])?;
+
+        let literal = self.rest_of_line();
+        let end_loc = self.current_loc;
+        if self.chr0 == Some('\n') {
+            self.next_char();
+        }
+
+        // Only `#if` has an argument. Others ignore the rest of the line.
+        // A comment can follow after the argument.
+        let literal = if toktype == TokenType::PreIf {
+            literal.split('#').next().unwrap_or_default().trim()
+        } else {
+            ""
+        };
+        Some(LexToken::new(
+            Token::new(toktype, literal),
+            start_loc,
+            end_loc,
+        ))
+    }
+
+    // `%!key: value` at the start of a line is a file directive.
+    // The literal is the text after `%!` without the newline.
+    fn lex_file_directive(&mut self) -> LexToken<'a> {
+        let start_loc = self.current_loc;
+        self.next_char();
+        self.next_char();
+
+        let literal = self.rest_of_line();
+        let end_loc = self.current_loc;
+        if self.chr0 == Some('\n') {
+            self.next_char();
+        }
+
+        LexToken::new(
+            Token::new(TokenType::FileDirective, literal.trim()),
+            start_loc,
+            end_loc,
+        )
+    }
+
+    // Take characters until a newline which is not consumed
+    fn rest_of_line(&mut self) -> &'a str {
+        let start = self.pos0;
+        while self.chr0.is_some_and(|chr| chr != '\n') {
+            self.next_char();
+        }
+        &self.input[start..self.pos0]
+    }
+
+    fn is_attribute_start(&self) -> bool {
+        let mut lexer = self.clone();
+        for chr in "#[allow(".chars() {
+            if lexer.chr0 != Some(chr) {
+                return false;
+            }
+            lexer.next_char();
+        }
+        true
+    }
+
+    // `#[allow(lint1, lint2)]` in its own line is an attribute of the next statement.
+    // The literal is the text after `#[` without the newline.
+    fn lex_attribute(&mut self) -> LexToken<'a> {
+        let start_loc = self.current_loc;
+        self.next_char();
+        self.next_char();
+
+        let literal = self.rest_of_line();
+        let end_loc = self.current_loc;
+        if self.chr0 == Some('\n') {
+            self.next_char();
+        }
+
+        LexToken::new(
+            Token::new(TokenType::Attribute, literal.trim_end()),
+            start_loc,
+            end_loc,
+        )
+    }
+
+    // Check that the current line is `latex%`, `latex%%` and so on
+    fn is_raw_block_start(&self) -> bool {
+        if self.chr0 != Some('l') {
+            return false;
+        }
+        let mut lexer = self.clone();
+        for chr in "latex%".chars() {
+            if lexer.chr0 != Some(chr) {
+                return false;
+            }
+            lexer.next_char();
+        }
+        while lexer.chr0 == Some('%') {
+            lexer.next_char();
+        }
+        while matches!(lexer.chr0, Some(' ' | '\t')) {
+            lexer.next_char();
+        }
+        matches!(lexer.chr0, Some('\n') | None)
+    }
+
+    // A line `latex%` starts a raw latex block, and a line `%end` closes it.
+    // Lines between them are written as they are, so braces and percent signs need
+    // not be balanced. If the block has a line `%end`, use `latex%%` and `%%end`.
+    fn lex_raw_block(&mut self) -> LexToken<'a> {
+        let start_loc = self.current_loc;
+        for _ in 0.."latex".len() {
+            self.next_char();
+        }
+        let mut fence = String::new();
+        while let Some(chr) = self.chr0 {
+            if chr == '\n' {
+                break;
+            }
+            if chr == '%' {
+                fence.push(chr);
+            }
+            self.next_char();
+        }
+        fence.push_str("end");
+        self.next_char();
+
+        match self.take_raw_lines(&fence) {
+            Some(literal) => LexToken::new(
+                Token::new(TokenType::RawLatexBlock, literal),
+                start_loc,
+                self.current_loc,
+            ),
+            None => LexToken::new(
+                Token::new(TokenType::UnclosedRawLatexBlock, ""),
+                start_loc,
+                self.current_loc,
+            ),
+        }
+    }
+
+    // Take lines as they are until a line `end`, which is consumed with its newline.
+    // The parser also uses this for statements whose body must not be tokenized,
+    // so this should be called at the start of a line.
+    // Returns None if there is no such line.
+    pub fn take_raw_lines(&mut self, end: &str) -> Option<Cow<'a, str>> {
+        let start = self.pos0;
+        let end_pos = loop {
+            self.chr0?;
+            let line_start = self.pos0;
+            if self.rest_of_line().trim() == end {
+                break line_start;
+            }
+            if self.chr0 == Some('\n') {
+                self.next_char();
+            }
+        };
+        let literal = normalize_newlines(&self.input[start..end_pos]);
+        if self.chr0 == Some('\n') {
+            self.next_char();
+        }
+        self.at_line_start = true;
+
+        Some(literal)
+    }
+
+    // In a math started by `$`, `$$` ends it and starts another one, as latex
+    // reads `$a$$b$`
+    fn lex_dollar_math(&mut self) -> Option<LexToken<'a>> {
+        let start_loc = self.current_loc;
+        let is_display = self.chr1 == Some('$') && (!self.math_started || self.is_display_dollar);
+        if is_display {
+            self.next_char();
+        }
+        self.math_started = !self.math_started;
+        self.is_display_dollar = is_display && self.math_started;
+        match (is_display, self.math_started) {
+            (false, true) => tokenize!(self | TextMathStart, "\\("; start_loc),
+            (false, false) => tokenize!(self | TextMathEnd, "\\)"; start_loc),
+            (true, true) => tokenize!(self | InlineMathStart, "\\["; start_loc),
+            (true, false) => tokenize!(self | InlineMathEnd, "\\]"; start_loc),
+        }
+    }
+
+    fn lex_backslash(&mut self) -> Option<LexToken<'a>> {
         let start_loc = self.current_loc;
         match self.chr1 {
             Some('#') => {
@@ -350,7 +985,7 @@ impl<'a> Lexer<'a> {
                 self.next_char();
                 tokenize!(self | BackSlash, "\\\\"; start_loc)
             }
-            _ if self.chr1.map_or(false, token::is_latex_function_ident) => {
+            _ if self.chr1.is_some_and(token::is_latex_function_ident) => {
                 self.next_char();
                 let mut literal = String::new();
                 while let Some(chr) = self.chr0 {
@@ -375,8 +1010,24 @@ impl<'a> Lexer<'a> {
 }
 
 impl<'a> Iterator for Lexer<'a> {
-    type Item = LexToken;
+    type Item = LexToken<'a>;
     fn next(&mut self) -> Option<Self::Item> {
-        self.take_tok()
+        let tok = self.take_tok();
+        if let Some(lex_tok) = &tok {
+            match lex_tok.token.toktype {
+                // Directives consume the newline at the end of them
+                TokenType::Newline
+                | TokenType::PreIf
+                | TokenType::PreElse
+                | TokenType::PreEndif
+                | TokenType::FileDirective
+                | TokenType::RawLatexBlock
+                | TokenType::Attribute
+                | TokenType::DocComment => self.at_line_start = true,
+                TokenType::Space | TokenType::Tab => {}
+                _ => self.at_line_start = false,
+            }
+        }
+        tok
     }
 }
@@ -1,16 +1,18 @@
-use std::str::Chars;
+use std::str::CharIndices;
 
+// Characters with their byte offsets in the source. `\r\n` and `\r` become
+// `\n` whose offset is the one of `\r`.
 #[derive(Clone)]
 pub struct Newlinehandler<'a> {
-    source: Chars<'a>,
-    chr0: Option<char>,
-    chr1: Option<char>,
+    source: CharIndices<'a>,
+    chr0: Option<(usize, char)>,
+    chr1: Option<(usize, char)>,
 }
 
 impl<'a> Newlinehandler<'a> {
     pub fn new<T: AsRef<str> + ?Sized>(source: &'a T) -> Self {
         let mut nlh = Self {
-            source: source.as_ref().chars(),
+            source: source.as_ref().char_indices(),
             chr0: None,
             chr1: None,
         };
@@ -26,14 +28,14 @@ impl<'a> Newlinehandler<'a> {
 }
 
 impl<'a> Iterator for Newlinehandler<'a> {
-    type Item = char;
+    type Item = (usize, char);
     fn next(&mut self) -> Option<Self::Item> {
         let output = match (self.chr0, self.chr1) {
-            (Some('\r'), Some('\n')) => {
+            (Some((pos, '\r')), Some((_, '\n'))) => {
                 self.next_char();
-                self.chr0
+                Some((pos, '\n'))
             }
-            (Some('\r'), _) => Some('\n'),
+            (Some((pos, '\r')), _) => Some((pos, '\n')),
             _ => self.chr0,
         };
         self.next_char();
@@ -47,8 +49,15 @@ mod test {
 
     #[test]
     fn test_newline_handler() {
-        let input = Newlinehandler::new("\t\r\r\n\r\r\n\n\r").collect::<String>();
+        let input = Newlinehandler::new("\t\r\r\n\r\r\n\n\r")
+            .map(|(_, chr)| chr)
+            .collect::<String>();
         let expected = "\t\n\n\n\n\n\n";
         assert_eq!(input.as_str(), expected);
+
+        let offsets: Vec<usize> = Newlinehandler::new("a\r\nb\rc")
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(offsets, vec![0, 1, 3, 4, 5]);
     }
 }
@@ -1,19 +1,24 @@
+use std::borrow::Cow;
+
+// A literal borrows the source unless the lexer changes it, like `->` which
+// becomes `\rightarrow `.
 #[derive(Default, Clone, Debug)]
-pub struct Token {
+pub struct Token<'a> {
     pub toktype: TokenType,
-    pub literal: String,
+    pub literal: Cow<'a, str>,
 }
 
-impl Token {
-    pub fn new(toktype: TokenType, literal: impl ToString) -> Self {
+impl<'a> Token<'a> {
+    pub fn new(toktype: TokenType, literal: impl Into<Cow<'a, str>>) -> Self {
         Self {
             toktype,
-            literal: literal.to_string(),
+            literal: literal.into(),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default, Debug)]
 pub enum TokenType {
     // Whitespace
     Space,
@@ -27,9 +32,21 @@ pub enum TokenType {
     // Identifiers
     Integer,
     Float,
+    // `#(` and `#)` in math mode which become `\left(` and `\right)`
+    LeftBig,
+    RightBig,
+    // A unicode character like `α` in math mode which has a latex macro
+    UnicodeSymbol,
+    // A length like `3cm` or `0.5\textwidth`
+    Dimension,
     MainString,
     LatexFunction,
     RawLatex,
+    RawLatexBlock,         // latex% ... %end
+    UnclosedRawLatexBlock, // latex% without %end
+    Attribute,             // #[allow(...)]
+    Comment,               // #> ... which is kept in the latex code
+    DocComment,            // /// ... right before a definition
 
     // Keywords
     Docclass,
@@ -40,6 +57,35 @@ pub enum TokenType {
     Mtxt,
     Etxt,
     DocumentStartMode,
+    Tocdepth,
+    Secnumdepth,
+    Maketoc,
+    Minitoc,
+    Begtbl,
+    Endtbl,
+    Embedfile,
+    Importimg,
+    Importtex,
+    Importbib,
+    Printbib,
+    Bibstyle,
+    Codeblock,
+    Execute,
+    ChapterImport,
+    Frame,
+    Pause,
+    Heading,
+    Index,
+    MathFont, // bold{...}, bb{...}, cal{...} and frak{...} in math mode
+    Quantity, // qty(value, unit) whose literal is between the parentheses
+    Metadata, // title, author and date of the front matter
+    Maketitle,
+    Defun,
+    Defenv,
+    Deftheorem,
+    Batchmode,
+    Nonstopmode,
+    Errorstopmode,
 
     // Symbols
     Plus,           // +
@@ -91,20 +137,28 @@ pub enum TokenType {
     TextMathEnd,       // \)
     InlineMathStart,   // \[
     InlineMathEnd,     // \]
+    CiteStart,         // @[
+
+    // List markers which are placed at the start of a line
+    ItemizeMarker,   // -
+    EnumerateMarker, // 1.
+
+    // Preprocessor directives
+    PreIf,    // #if
+    PreElse,  // #else
+    PreEndif, // #endif
+
+    // File directive like `%!engine: xelatex`
+    FileDirective,
 
     // etc
     ArgSpliter,
 
     // error token
+    #[default]
     ILLEGAL,
 }
 
-impl Default for TokenType {
-    fn default() -> Self {
-        Self::ILLEGAL
-    }
-}
-
 pub fn is_keyword(string: &str) -> Option<TokenType> {
     match string {
         "docclass" => Some(TokenType::Docclass),
@@ -119,6 +173,45 @@ pub fn is_keyword(string: &str) -> Option<TokenType> {
         "dmst" => Some(TokenType::InlineMathStart),
         "dmnd" => Some(TokenType::InlineMathEnd),
         "docstartmode" => Some(TokenType::DocumentStartMode),
+        "tocdepth" => Some(TokenType::Tocdepth),
+        "secnumdepth" => Some(TokenType::Secnumdepth),
+        "maketoc" => Some(TokenType::Maketoc),
+        "minitoc" => Some(TokenType::Minitoc),
+        "begtbl" => Some(TokenType::Begtbl),
+        "endtbl" => Some(TokenType::Endtbl),
+        "embedfile" => Some(TokenType::Embedfile),
+        "importimg" => Some(TokenType::Importimg),
+        "importtex" => Some(TokenType::Importtex),
+        "importbib" => Some(TokenType::Importbib),
+        "printbib" => Some(TokenType::Printbib),
+        "bibstyle" => Some(TokenType::Bibstyle),
+        "codeblock" => Some(TokenType::Codeblock),
+        "defun" => Some(TokenType::Defun),
+        "defenv" => Some(TokenType::Defenv),
+        "deftheorem" => Some(TokenType::Deftheorem),
+        "batchmode" => Some(TokenType::Batchmode),
+        "nonstopmode" => Some(TokenType::Nonstopmode),
+        "errorstopmode" => Some(TokenType::Errorstopmode),
+        _ => None,
+    }
+}
+
+// Commands of math fonts which keywords like `bb{R}` are lowered into
+pub fn math_font(string: &str) -> Option<&'static str> {
+    match string {
+        "bold" => Some("mathbf"),
+        "bb" => Some("mathbb"),
+        "cal" => Some("mathcal"),
+        "frak" => Some("mathfrak"),
+        _ => None,
+    }
+}
+
+pub fn is_directive(string: &str) -> Option<TokenType> {
+    match string {
+        "if" => Some(TokenType::PreIf),
+        "else" => Some(TokenType::PreElse),
+        "endif" => Some(TokenType::PreEndif),
         _ => None,
     }
 }
@@ -138,10 +231,27 @@ impl TokenType {
             || self == TokenType::TextMathEnd
             || self == TokenType::InlineMathStart
             || self == TokenType::InlineMathEnd
+            || self == TokenType::Maketoc
+            || self == TokenType::Minitoc
+            || self == TokenType::Begtbl
+            || self == TokenType::Endtbl
+            || self == TokenType::Importimg
+            || self == TokenType::Printbib
+            || self == TokenType::Codeblock
+            || self == TokenType::Execute
+            || self == TokenType::ChapterImport
+            || self == TokenType::Frame
+            || self == TokenType::Pause
+            || self == TokenType::Heading
+            || self == TokenType::Index
     }
 
+    // `minitoc` is both a keyword and a package name.
     #[inline]
     pub fn can_pkg_name(&self) -> bool {
-        *self == TokenType::MainString || *self == TokenType::Minus || *self == TokenType::Integer
+        *self == TokenType::MainString
+            || *self == TokenType::Minus
+            || *self == TokenType::Integer
+            || *self == TokenType::Minitoc
     }
 }
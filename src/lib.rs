@@ -0,0 +1,117 @@
+//! Convert vesti sources into LaTeX.
+//!
+//! The simplest way is [`make_latex_format`], which parses a whole document
+//! and returns the generated LaTeX code.
+//!
+//! ```
+//! let latex = vesti::make_latex_format("docclass article\ndocument\nHello, vesti!\n").unwrap();
+//! assert!(latex.contains("\\begin{document}"));
+//! ```
+//!
+//! [`Lexer`] and [`Parser`] can be used directly to inspect the syntax tree
+//! before it is turned into LaTeX. Strings of the tree are allocated in an
+//! [`Arena`], so the tree borrows it. Errors are reported as [`VestiErr`], and
+//! [`error::pretty_print`] formats them with the source as the CLI does.
+
+pub mod error;
+pub mod lexer;
+pub mod location;
+pub mod parser;
+
+// Modules below are used by the `vesti` binary. They are public only so that
+// the binary can use them, and they may change without notice.
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod chapters;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod commands;
+#[doc(hidden)]
+pub mod compress;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod console;
+#[doc(hidden)]
+pub mod deps;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod directives;
+#[doc(hidden)]
+pub mod docgen;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+pub mod embed;
+#[doc(hidden)]
+pub mod engine;
+#[doc(hidden)]
+pub mod execute;
+#[doc(hidden)]
+pub mod exit;
+#[doc(hidden)]
+pub mod expand;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod fix;
+#[doc(hidden)]
+pub mod headings;
+#[doc(hidden)]
+pub mod importtex;
+#[doc(hidden)]
+pub mod incremental;
+#[doc(hidden)]
+pub mod initialization;
+#[doc(hidden)]
+pub mod lint;
+#[doc(hidden)]
+pub mod metadata;
+#[doc(hidden)]
+pub mod modules;
+#[doc(hidden)]
+pub mod notify;
+#[doc(hidden)]
+pub mod packages;
+#[doc(hidden)]
+pub mod pool;
+#[doc(hidden)]
+pub mod render;
+#[doc(hidden)]
+pub mod report;
+#[doc(hidden)]
+pub mod shutdown;
+#[doc(hidden)]
+pub mod snapshot;
+#[doc(hidden)]
+pub mod standalone;
+#[doc(hidden)]
+pub mod stats;
+#[doc(hidden)]
+pub mod symbols;
+#[doc(hidden)]
+pub mod synctex;
+#[doc(hidden)]
+pub mod texdist;
+#[doc(hidden)]
+pub mod timing;
+#[doc(hidden)]
+pub mod typography;
+#[doc(hidden)]
+pub mod units;
+#[doc(hidden)]
+pub mod watch;
+
+pub use error::{Result, VestiErr};
+pub use lexer::Lexer;
+pub use parser::arena::Arena;
+pub use parser::Parser;
+
+/// Parse a vesti document and generate its LaTeX code.
+pub fn make_latex_format(source: &str) -> Result<String> {
+    let arena = Arena::new();
+    Parser::new(Lexer::new(source), &arena).make_latex_format()
+}
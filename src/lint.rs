@@ -0,0 +1,348 @@
+// `vesti lint` checks mistakes which are still valid vesti, like definitions
+// which are never used. Every warning, including ones of the parser, has a
+// level set by `[lint]` of `vesti.toml`:
+//
+//     [lint]
+//     max_line_length = 120
+//     unused_definition = "deny"
+//     long_line = "allow"
+
+use crate::error::warning::{AllowedRegion, VestiWarnKind, VestiWarning};
+use crate::location::{Location, Span};
+use crate::parser::ast::{walk_spanned, ArgNeed, Latex, Spanned, Statement, TableRow};
+use crate::parser::maker::latex_to_string;
+use crate::symbols;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+
+const DEFAULT_MAX_LINE_LENGTH: usize = 100;
+// Environments whose contents are in math mode
+const MATH_ENVS: [&str; 9] = [
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "displaymath",
+];
+// Arguments of these functions are texts even in math mode
+const TEXT_FUNCTIONS: [&str; 9] = [
+    "text", "textrm", "textit", "textbf", "textsf", "texttt", "mbox", "hbox", "label",
+];
+// Math commands which latex also accepts in text mode
+const TEXT_SAFE_COMMANDS: [&str; 1] = ["ldots"];
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LintConfig {
+    pub max_line_length: usize,
+    // Levels of lints by their names. A lint which is not here warns.
+    #[serde(flatten)]
+    pub levels: BTreeMap<String, LintLevel>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            levels: BTreeMap::new(),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn level(&self, warning: &VestiWarning) -> LintLevel {
+        self.levels
+            .get(warning.warn_kind.lint_name())
+            .copied()
+            .unwrap_or(LintLevel::Warn)
+    }
+}
+
+// Lints of the parsed document which are not allowed by attributes
+pub fn lint_latex(
+    latex: &Latex,
+    source: &str,
+    config: &LintConfig,
+    allowed: &[AllowedRegion],
+) -> Vec<VestiWarning> {
+    let mut warnings = Vec::new();
+    check_definitions(latex, &mut warnings);
+    check_statements(latex, false, &mut warnings);
+    check_line_length(source, config.max_line_length, &mut warnings);
+    warnings.retain(|warning| !allowed.iter().any(|region| region.allows(warning)));
+    warnings.sort_by_key(|warning| {
+        warning
+            .location
+            .map(|span| (span.start.row(), span.start.column()))
+    });
+    warnings
+}
+
+fn warning(warn_kind: VestiWarnKind, span: Span) -> VestiWarning {
+    VestiWarning {
+        warn_kind,
+        location: Some(span),
+    }
+}
+
+// Definitions can be used before they are defined, inside of other definitions
+// and in raw latex, so every statement is seen before any warning.
+fn check_definitions<'a>(latex: &'a Latex, warnings: &mut Vec<VestiWarning>) {
+    let mut definitions: Vec<(&'a str, bool, Span)> = Vec::new();
+    let mut used: HashSet<(String, bool)> = HashSet::new();
+    let mut raw_latex = String::new();
+    let mut visit = |stmt: &'a Spanned<Statement>| match &stmt.node {
+        Statement::FunctionDefine { name, .. } => definitions.push((name, false, stmt.span)),
+        Statement::EnvironmentDefine { name, .. } => definitions.push((name, true, stmt.span)),
+        Statement::LatexFunction { name, .. } => {
+            used.insert((name.trim_end().trim_end_matches('*').to_string(), false));
+        }
+        Statement::Environment { name, .. } => {
+            used.insert((name.trim_end_matches('*').to_string(), true));
+        }
+        Statement::RawLatex(raw) => raw_latex += raw,
+        _ => {}
+    };
+    walk_spanned(latex, &mut visit);
+    // `walk_spanned` does not go into definitions
+    for body in definitions_bodies(latex) {
+        walk_spanned(body, &mut visit);
+    }
+
+    for (name, is_env, span) in definitions {
+        let in_raw = if is_env {
+            raw_latex.contains(&format!("{{{}}}", name))
+        } else {
+            raw_latex.contains(&format!("\\{}", name))
+        };
+        if !in_raw && !used.contains(&(name.to_string(), is_env)) {
+            warnings.push(warning(
+                VestiWarnKind::UnusedDefinition {
+                    name: name.to_string(),
+                    is_env,
+                },
+                span,
+            ));
+        }
+    }
+}
+
+fn definitions_bodies<'a, 's>(latex: &'a Latex<'s>) -> Vec<&'a Latex<'s>> {
+    let mut bodies = Vec::new();
+    walk_spanned(latex, &mut |stmt| match &stmt.node {
+        Statement::FunctionDefine { body, .. } => bodies.push(body),
+        Statement::EnvironmentDefine {
+            begin_part,
+            end_part,
+            ..
+        } => {
+            bodies.push(begin_part);
+            bodies.push(end_part);
+        }
+        _ => {}
+    });
+    bodies
+}
+
+// Lints which depend on whether a statement is in math mode
+fn check_statements(latex: &Latex, in_math: bool, warnings: &mut Vec<VestiWarning>) {
+    for stmt in latex {
+        match &stmt.node {
+            Statement::Environment { name, args, text } => {
+                if latex_to_string(text).trim().is_empty() {
+                    warnings.push(warning(
+                        VestiWarnKind::EmptyEnvironment {
+                            name: name.to_string(),
+                        },
+                        stmt.span,
+                    ));
+                }
+                for (_, arg) in args {
+                    check_statements(arg, in_math, warnings);
+                }
+                let in_math = in_math || MATH_ENVS.contains(name);
+                check_statements(text, in_math, warnings);
+            }
+            Statement::LatexFunction { name, args } => {
+                let name = name.trim_end();
+                if !in_math && symbols::is_math_command(name) && !TEXT_SAFE_COMMANDS.contains(&name)
+                {
+                    warnings.push(warning(
+                        VestiWarnKind::MathCommandInText {
+                            name: name.to_string(),
+                        },
+                        stmt.span,
+                    ));
+                }
+                if name == "label" {
+                    check_label(args, stmt.span, warnings);
+                }
+                let in_math = in_math && !TEXT_FUNCTIONS.contains(&name);
+                for (_, arg) in args {
+                    check_statements(arg, in_math, warnings);
+                }
+            }
+            Statement::Figure {
+                label: Some(label), ..
+            } if !label.is_ascii() => {
+                warnings.push(warning(
+                    VestiWarnKind::NonAsciiLabel {
+                        label: label.to_string(),
+                    },
+                    stmt.span,
+                ));
+            }
+            Statement::RawLatex(raw) if has_unescaped_dollar(raw) => {
+                warnings.push(warning(VestiWarnKind::DollarInRawLatex, stmt.span));
+            }
+            Statement::MathText { text, .. } | Statement::MathDelimiter { text, .. } => {
+                check_statements(text, true, warnings)
+            }
+            Statement::PlainTextInMath(text) => check_statements(text, false, warnings),
+            Statement::Table { width, rows, .. } => {
+                if let Some(width) = width {
+                    check_statements(width, in_math, warnings);
+                }
+                for row in rows {
+                    if let TableRow::Cells(cells) = row {
+                        for cell in cells {
+                            check_statements(&cell.text, in_math, warnings);
+                        }
+                    }
+                }
+            }
+            Statement::List { items, .. } => {
+                for item in items {
+                    check_statements(item, in_math, warnings);
+                }
+            }
+            Statement::Conditional {
+                enabled,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let branch = if *enabled { then_branch } else { else_branch };
+                check_statements(branch, in_math, warnings);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn check_label(args: &[(ArgNeed, Latex)], span: Span, warnings: &mut Vec<VestiWarning>) {
+    let label = args
+        .iter()
+        .find(|(need, _)| *need == ArgNeed::MainArg)
+        .map(|(_, arg)| latex_to_string(arg));
+    if let Some(label) = label.filter(|label| !label.is_ascii()) {
+        warnings.push(warning(VestiWarnKind::NonAsciiLabel { label }, span));
+    }
+}
+
+fn has_unescaped_dollar(raw: &str) -> bool {
+    let mut is_escaped = false;
+    for chr in raw.chars() {
+        match chr {
+            '$' if !is_escaped => return true,
+            '\\' => is_escaped = !is_escaped,
+            _ => is_escaped = false,
+        }
+    }
+    false
+}
+
+fn check_line_length(source: &str, limit: usize, warnings: &mut Vec<VestiWarning>) {
+    for (i, line) in source.lines().enumerate() {
+        let length = line.chars().count();
+        if length > limit {
+            let row = i + 1;
+            warnings.push(warning(
+                VestiWarnKind::LongLine { length, limit },
+                Span {
+                    start: Location::new(row, limit + 1),
+                    end: Location::new(row, length + 1),
+                },
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    fn lint_names(source: &str, config: &LintConfig) -> Vec<&'static str> {
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let latex = parser.parse_latex().unwrap();
+        let allowed = parser.take_allowed_regions();
+        lint_latex(&latex, source, config, &allowed)
+            .iter()
+            .map(|warning| warning.warn_kind.lint_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_lint_latex() {
+        let source = r#"docclass article
+defun used {foo}
+defun unused {bar}
+document
+\used and \alpha
+begenv center
+endenv
+\( \alpha \text{\label{α}} \)
+#- $x$ and \$ -#
+#[allow(empty_environment)]
+begenv center
+endenv
+"#;
+        assert_eq!(
+            lint_names(source, &LintConfig::default()),
+            vec![
+                "unused_definition",
+                "math_in_text",
+                "empty_environment",
+                "non_ascii_label",
+                "dollar_in_raw",
+            ]
+        );
+
+        let config = LintConfig {
+            max_line_length: 10,
+            ..LintConfig::default()
+        };
+        let names = lint_names("document\nshort\nthis line is long\n", &config);
+        assert_eq!(names, vec!["long_line"]);
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let config: LintConfig =
+            toml::from_str("max_line_length = 80\nlong_line = \"deny\"\nraw_latex = \"allow\"\n")
+                .unwrap();
+        assert_eq!(config.max_line_length, 80);
+        assert_eq!(config.levels["long_line"], LintLevel::Deny);
+        assert_eq!(config.levels["raw_latex"], LintLevel::Allow);
+        assert_eq!(
+            LintConfig::default().max_line_length,
+            DEFAULT_MAX_LINE_LENGTH
+        );
+    }
+}
@@ -1,16 +1,34 @@
-#[derive(Clone, Copy, PartialEq, Default, Debug)]
+use serde::Serialize;
+
+#[derive(Clone, Copy, PartialEq, Default, Debug, Serialize)]
 pub struct Span {
     pub start: Location,
     pub end: Location,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+impl Span {
+    pub fn contains(&self, other: &Span) -> bool {
+        self.start.position() <= other.start.position()
+            && other.end.position() <= self.end.position()
+    }
+
+    pub fn shift_rows(&mut self, delta: isize) {
+        self.start.shift_rows(delta);
+        self.end.shift_rows(delta);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
 pub struct Location {
     row: usize,
     col: usize,
 }
 
 impl Location {
+    pub fn new(row: usize, col: usize) -> Self {
+        Self { row, col }
+    }
+
     pub fn row(&self) -> usize {
         self.row
     }
@@ -19,6 +37,11 @@ impl Location {
         self.col
     }
 
+    // Locations are compared by this, rows first
+    fn position(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
     pub fn move_right(&mut self, current_char: Option<&char>) {
         match current_char {
             Some(chr) => {
@@ -28,6 +51,11 @@ impl Location {
         }
     }
 
+    // Move to the same column of another row, when lines above are added or removed
+    pub fn shift_rows(&mut self, delta: isize) {
+        self.row = self.row.saturating_add_signed(delta);
+    }
+
     pub fn move_next_line(&mut self) {
         self.row += 1;
         self.col = 1;
@@ -1,62 +1,186 @@
-mod commands;
-mod error;
-mod lexer;
-mod location;
-mod parser;
-
-use crate::commands::compile_vesti;
-use crate::error::pretty_print::pretty_print;
-use signal_hook::consts::signal::{SIGINT, SIGKILL, SIGTERM};
-use signal_hook::flag as signal_flag;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
+use vesti::commands::{
+    build_targets, compile_files, compile_stdin, expand_env, fix_files, init_project, lint_files,
+    print_ast, print_deps, print_metadata, print_stats, print_symbols, render_math, setup_tex,
+    synctex_edit, synctex_view, write_doc, CompileOption,
+};
+use vesti::console::Verbosity;
+use vesti::error::pretty_print::pretty_print;
+use vesti::exit::ExitCode;
+use vesti::snapshot::{run_snapshots, SnapshotOption};
+use vesti::{commands, directives, doctor, error, shutdown, texdist, watch};
 
 fn main() {
-    let args = commands::VestiOpt::from_args();
-    let is_continuous = args.is_continuous_compile();
+    let matches = commands::app().get_matches();
+    error::pretty_print::set_color_choice(commands::color_choice(&matches));
+    let args = commands::VestiOpt::from_clap(&matches);
+    if let commands::VestiOpt::Init {
+        template,
+        author,
+        project_dir,
+    } = &args
+    {
+        init_project(project_dir, template.as_deref(), author.clone());
+        return;
+    }
+    if let commands::VestiOpt::Meta { json, file_name } = &args {
+        print_metadata(file_name, *json);
+        return;
+    }
+    if let commands::VestiOpt::Stats { json, file_name } = &args {
+        print_stats(file_name, *json);
+        return;
+    }
+    if let commands::VestiOpt::Lint {
+        max_errors,
+        file_name,
+    } = &args
+    {
+        let code = lint_files(file_name, *max_errors);
+        if code != ExitCode::Success {
+            code.exit();
+        }
+        return;
+    }
+    if let commands::VestiOpt::Fix {
+        dry_run,
+        remove_empty_envs,
+        file_name,
+    } = &args
+    {
+        if !fix_files(file_name, *dry_run, *remove_empty_envs) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let commands::VestiOpt::Test {
+        bless,
+        diff_tool,
+        dir,
+    } = &args
+    {
+        let option = SnapshotOption {
+            bless: *bless,
+            diff_tool: diff_tool.clone(),
+        };
+        if !run_snapshots(dir, &option) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let commands::VestiOpt::Doc {
+        format,
+        output,
+        dir,
+    } = &args
+    {
+        if !write_doc(dir, *format, output.as_deref()) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let commands::VestiOpt::Deps { format, file_name } = &args {
+        if !print_deps(file_name, *format) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let commands::VestiOpt::Ast { compact, file_name } = &args {
+        print_ast(file_name, *compact);
+        return;
+    }
+    if let commands::VestiOpt::SetupTex { force } = &args {
+        setup_tex(*force);
+        return;
+    }
+    if let commands::VestiOpt::SynctexEdit { location, open } = &args {
+        synctex_edit(location, *open);
+        return;
+    }
+    if let commands::VestiOpt::SynctexView { file_name, line } = &args {
+        synctex_view(file_name, *line);
+        return;
+    }
+    if let commands::VestiOpt::Symbols { query } = &args {
+        print_symbols(&query.join(" "));
+        return;
+    }
+    if let commands::VestiOpt::ExpandEnv {
+        file,
+        body,
+        name,
+        args,
+    } = &args
+    {
+        expand_env(file, name, args, body);
+        return;
+    }
 
-    let trap = Arc::new(AtomicUsize::new(0));
-    #[cfg(not(target_os = "windows"))]
-    for signal in [SIGINT, SIGTERM].iter() {
-        signal_flag::register_usize(*signal, Arc::clone(&trap), *signal as usize)
-            .expect("Undefined behavior happened!");
+    texdist::use_installed_tex();
+    if let commands::VestiOpt::Doctor { no_compile } = &args {
+        if !doctor::run_doctor(!no_compile) {
+            ExitCode::EngineError.exit();
+        }
+        return;
     }
-    #[cfg(target_os = "windows")]
-    for signal in [SIGTERM, SIGKILL].iter() {
-        signal_flag::register_usize(*signal, Arc::clone(&trap), *signal as usize)
-            .expect("Undefined behavior happened!");
+    if let commands::VestiOpt::RenderMath {
+        expr,
+        png,
+        dpi,
+        engine,
+    } = &args
+    {
+        render_math(expr, png, *dpi, *engine);
+        return;
     }
+    let compile_option = args.compile_option();
+    if let commands::VestiOpt::Build { targets, .. } = &args {
+        build_targets(targets, compile_option);
+        return;
+    }
+    let is_quiet = compile_option.verbosity == Verbosity::Quiet;
+
+    shutdown::register_signals();
 
     let file_lists = match args.take_file_name() {
         Ok(inner) => inner,
         Err(err) => {
+            let code = ExitCode::of(&err);
             println!("{}", pretty_print(None, err, None));
-            std::process::exit(1);
+            code.exit();
         }
     };
 
-    let mut handle_vesti: Vec<JoinHandle<()>> = Vec::new();
-    for file_name in file_lists {
-        handle_vesti.push(thread::spawn(move || {
-            compile_vesti(file_name, is_continuous)
-        }));
+    let mut options = args.file_compile_options(&matches, file_lists.len());
+
+    if file_lists.len() == 1 && file_lists[0] == Path::new("-") {
+        compile_stdin(options.remove(0));
+        std::process::exit(0);
     }
 
-    if !is_continuous {
-        let _: Vec<()> = handle_vesti
-            .into_iter()
-            .map(|vesti| vesti.join().unwrap())
-            .collect();
-    } else {
-        println!("Press Ctrl+C to finish the program.");
-        while ![SIGINT, SIGTERM, SIGKILL].contains(&(trap.load(Ordering::Relaxed) as i32)) {
-            thread::sleep(Duration::from_millis(500));
+    let mut files: Vec<(PathBuf, CompileOption)> = file_lists.into_iter().zip(options).collect();
+    directives::apply_watch_overrides(&mut files);
+    // files with `%!vesti watch: false` are compiled once before the others are watched
+    let (watched, once): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|(_, option)| option.is_continuous && !option.is_dry_run);
+    if !once.is_empty() {
+        let code = compile_files(once, args.jobs(), args.report_file(), args.max_errors());
+        if shutdown::is_requested() {
+            println!("Interrupted");
+            ExitCode::Interrupted.exit();
         }
+        if code != ExitCode::Success && watched.is_empty() {
+            code.exit();
+        }
+    }
+    if !watched.is_empty() {
+        watch::watch(watched, args.watch_option(), shutdown::is_requested);
     }
 
-    println!("bye!");
+    if !is_quiet {
+        println!("bye!");
+    }
     std::process::exit(0);
 }
@@ -0,0 +1,147 @@
+use crate::parser::ast::{walk_latex, ArgNeed, DocDate, Latex, Statement};
+use crate::parser::maker::latex_to_string;
+use serde::Serialize;
+use std::fmt;
+
+const CITE_FUNCTIONS: [&str; 8] = [
+    "cite",
+    "citep",
+    "citet",
+    "nocite",
+    "parencite",
+    "textcite",
+    "autocite",
+    "footcite",
+];
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub docclass: Option<String>,
+    pub packages: Vec<String>,
+    pub labels: Vec<String>,
+    pub citations: Vec<String>,
+}
+
+impl Metadata {
+    pub fn from_latex(latex: &Latex) -> Self {
+        let mut output = Self::default();
+
+        walk_latex(latex, &mut |stmt| match stmt {
+            Statement::DocumentClass { name, .. } => output.docclass = Some(name.to_string()),
+            Statement::Usepackage { name, .. }
+                if !output.packages.iter().any(|pkg| pkg == name) =>
+            {
+                output.packages.push(name.to_string())
+            }
+            Statement::Figure {
+                label: Some(label), ..
+            }
+            | Statement::Heading {
+                label: Some(label), ..
+            } => output.labels.push(label.to_string()),
+            Statement::FrontMatter {
+                title,
+                authors,
+                date,
+            } => {
+                if let Some(title) = title {
+                    output.title = Some(title.to_string());
+                }
+                if !authors.is_empty() {
+                    let names: Vec<&str> = authors.iter().map(|author| author.name).collect();
+                    output.author = Some(names.join(", "));
+                }
+                match date {
+                    Some(DocDate::Today) => output.date = Some(String::from("\\today")),
+                    Some(DocDate::Text(date)) => output.date = Some(date.to_string()),
+                    Some(DocDate::Empty) | None => {}
+                }
+            }
+            Statement::LatexFunction { name, args } => {
+                let name = name.trim_end();
+                let main_arg = match first_main_arg(args) {
+                    Some(arg) => arg,
+                    None => return,
+                };
+                match name {
+                    "title" => output.title = Some(main_arg),
+                    "author" => output.author = Some(main_arg),
+                    "date" => output.date = Some(main_arg),
+                    "label" => output.labels.push(main_arg),
+                    _ if CITE_FUNCTIONS.contains(&name) => {
+                        for key in main_arg.split(',').map(str::trim) {
+                            if !key.is_empty() && !output.citations.iter().any(|c| c == key) {
+                                output.citations.push(key.to_string());
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        });
+
+        output
+    }
+}
+
+fn first_main_arg(args: &[(ArgNeed, Latex)]) -> Option<String> {
+    args.iter()
+        .find(|(need, _)| *need == ArgNeed::MainArg)
+        .map(|(_, arg)| latex_to_string(arg).trim().to_string())
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let none = String::from("-");
+        writeln!(f, "title:     {}", self.title.as_ref().unwrap_or(&none))?;
+        writeln!(f, "author:    {}", self.author.as_ref().unwrap_or(&none))?;
+        writeln!(f, "date:      {}", self.date.as_ref().unwrap_or(&none))?;
+        writeln!(f, "docclass:  {}", self.docclass.as_ref().unwrap_or(&none))?;
+        writeln!(f, "packages:  {}", self.packages.join(", "))?;
+        writeln!(f, "labels:    {}", self.labels.join(", "))?;
+        write!(f, "citations: {}", self.citations.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_extract_metadata() {
+        let source = r#"docclass article
+import {
+    amsmath
+    geometry (a4paper)
+}
+\title{Vesti Paper}
+\author{John Doe}
+
+document
+See \cite{knuth84, lamport94} and \ref{sec:intro}.
+\label{sec:intro}
+\cite{knuth84}"#;
+        let arena = Arena::new();
+        let latex = Parser::new(Lexer::new(source), &arena)
+            .parse_latex()
+            .unwrap();
+
+        let expected = Metadata {
+            title: Some(String::from("Vesti Paper")),
+            author: Some(String::from("John Doe")),
+            date: None,
+            docclass: Some(String::from("article")),
+            packages: vec![String::from("amsmath"), String::from("geometry")],
+            labels: vec![String::from("sec:intro")],
+            citations: vec![String::from("knuth84"), String::from("lamport94")],
+        };
+        assert_eq!(expected, Metadata::from_latex(&latex));
+    }
+}
@@ -0,0 +1,215 @@
+// Vesti files imported by `import defs.ves as m` are parsed after the main
+// file and pasted in place of the import. Functions and environments which a
+// module defines get the prefix, so `defun foo` of the module is `\mfoo` and
+// `defenv note` is `mnote`, and calls of them inside of the module are renamed
+// too. Raw latex of the module is kept as it is.
+
+use crate::error::err_kind::VestiParseErr;
+use crate::error::{self, VestiErr};
+use crate::exit::CompileFailure;
+use crate::lexer::Lexer;
+use crate::location::Span;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{walk_latex, walk_latex_mut, walk_spanned, Latex, Statement};
+use crate::parser::Parser;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Names defined by a module, without `*` of starred environments
+#[derive(Default)]
+struct ModuleNames<'a> {
+    functions: HashSet<&'a str>,
+    environments: HashSet<&'a str>,
+}
+
+fn module_names<'a>(latex: &Latex<'a>) -> ModuleNames<'a> {
+    let mut names = ModuleNames::default();
+    walk_latex(latex, &mut |stmt| match *stmt {
+        Statement::FunctionDefine { name, .. } => {
+            names.functions.insert(name);
+        }
+        Statement::EnvironmentDefine { name, .. } => {
+            names.environments.insert(name.trim_end_matches('*'));
+        }
+        _ => {}
+    });
+    names
+}
+
+fn prefixed<'a>(prefix: &str, name: &str, arena: &'a Arena) -> &'a str {
+    arena.alloc_str(&format!("{}{}", prefix, name))
+}
+
+// Definitions and uses of names of the module, including ones inside of
+// other definitions
+fn rename<'a>(latex: &mut Latex<'a>, names: &ModuleNames, prefix: &str, arena: &'a Arena) {
+    walk_latex_mut(latex, &mut |stmt| match stmt {
+        Statement::LatexFunction { name, .. }
+            if names
+                .functions
+                .contains(name.trim_end().trim_end_matches('*')) =>
+        {
+            *name = prefixed(prefix, name, arena);
+        }
+        Statement::Environment { name, .. }
+            if names.environments.contains(name.trim_end_matches('*')) =>
+        {
+            *name = prefixed(prefix, name, arena);
+        }
+        Statement::FunctionDefine { name, body, .. } => {
+            *name = prefixed(prefix, name, arena);
+            rename(body, names, prefix, arena);
+        }
+        Statement::EnvironmentDefine {
+            name,
+            begin_part,
+            end_part,
+            ..
+        } => {
+            *name = prefixed(prefix, name, arena);
+            rename(begin_part, names, prefix, arena);
+            rename(end_part, names, prefix, arena);
+        }
+        _ => {}
+    });
+}
+
+fn read_module(path: &str, path_span: Option<Span>, source_dir: &Path) -> error::Result<String> {
+    fs::read_to_string(source_dir.join(path)).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => VestiErr::make_parse_err(
+            VestiParseErr::ModuleNotFoundErr {
+                path: path.to_string(),
+            },
+            path_span,
+        ),
+        _ => VestiErr::from(err),
+    })
+}
+
+// Statements of a module, which has only a preamble. An error is the one of
+// the module file.
+fn parse_module<'a>(
+    prefix: Option<&str>,
+    source: &'a str,
+    arena: &'a Arena,
+    configure: &dyn Fn(&mut Parser),
+) -> error::Result<Latex<'a>> {
+    let mut parser = Parser::new(Lexer::new(source), arena);
+    configure(&mut parser);
+    let mut module = parser.parse_latex()?;
+    let mut misplaced = None;
+    walk_spanned(&module, &mut |stmt| {
+        let err_kind = match stmt.node {
+            Statement::DocumentStart => VestiParseErr::ModuleWithBodyErr,
+            Statement::ImportModule { .. } => VestiParseErr::NestedModuleErr,
+            _ => return,
+        };
+        misplaced.get_or_insert((err_kind, stmt.span));
+    });
+    if let Some((err_kind, span)) = misplaced {
+        return Err(VestiErr::make_parse_err(err_kind, Some(span)));
+    }
+    if let Some(prefix) = prefix {
+        let names = module_names(&module);
+        rename(&mut module, &names, prefix, arena);
+    }
+    Ok(module)
+}
+
+// Paste every module in place of its import, including imports inside of
+// `#if`. An error is a pretty printed one of the file where it is found.
+pub fn import_modules<'a>(
+    latex: &mut Latex<'a>,
+    source: &str,
+    file_name: &Path,
+    source_dir: &Path,
+    arena: &'a Arena,
+    configure: &dyn Fn(&mut Parser),
+) -> Result<(), CompileFailure> {
+    let mut idx = 0;
+    while idx < latex.len() {
+        match &mut latex[idx].node {
+            Statement::ImportModule {
+                path,
+                path_span,
+                prefix,
+            } => {
+                let (path, path_span, prefix) = (*path, *path_span, *prefix);
+                let module_source = read_module(path, path_span, source_dir)
+                    .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+                let module_file = source_dir.join(path);
+                let module_source = arena.alloc_str(&module_source);
+                let module =
+                    parse_module(prefix, module_source, arena, configure).map_err(|err| {
+                        CompileFailure::new(Some(module_source), err, Some(&module_file))
+                    })?;
+                let len = module.len();
+                latex.splice(idx..idx + 1, module);
+                idx += len;
+            }
+            Statement::Conditional {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                import_modules(then_branch, source, file_name, source_dir, arena, configure)?;
+                import_modules(else_branch, source, file_name, source_dir, arena, configure)?;
+                idx += 1;
+            }
+            _ => idx += 1,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::maker::latex_to_string;
+    use std::env;
+
+    #[test]
+    fn test_import_modules() {
+        let dir = env::temp_dir().join(format!("vesti-import-module-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let main_file = dir.join("main.ves");
+        fs::write(
+            dir.join("defs.ves"),
+            "defun pair (#!1#!2) { (#!1, #!2) }\ndefun twice (#!1) { \\pair{#!1}{#!1} }\n\
+             defenv note { \\begin{quote} } { \\end{quote} }\n",
+        )
+        .unwrap();
+        fs::write(dir.join("nested.ves"), "import defs.ves\n").unwrap();
+
+        let configure = |_: &mut Parser| {};
+        let arena = Arena::new();
+        let import = |source: &str| {
+            let source = arena.alloc_str(source);
+            let mut parser = Parser::new(Lexer::new(source), &arena);
+            let mut latex = parser.parse_latex().unwrap();
+            import_modules(&mut latex, source, &main_file, &dir, &arena, &configure)
+                .map(|_| latex_to_string(&latex))
+        };
+        let source = "docclass article\nimport defs.ves as m\ndocument\n\\(\\mtwice{x}\\)\n";
+        assert_eq!(
+            import(source).unwrap(),
+            "\\documentclass{article}\n\\def\\mpair#1#2{(#1, #2)}\n\\def\\mtwice#1{\\mpair{#1}{#1}}\n\
+             \\newenvironment{mnote}{\\begin{quote}}{\\end{quote}}\n\\begin{document}\n\
+             \\(\\mtwice{x}\\)\n\n\\end{document}\n"
+        );
+        // names are kept without a prefix
+        let output = import("import defs.ves\ndocument\n").unwrap();
+        assert!(output.contains("\\def\\twice#1{\\pair{#1}{#1}}"));
+
+        let failure = import("import nested.ves as n\ndocument\n").unwrap_err();
+        assert!(failure.msg.contains("Modules cannot import other modules"));
+        let failure = import("import missing.ves\ndocument\n").unwrap_err();
+        assert!(failure
+            .msg
+            .contains("Cannot find the vesti module `missing.ves`"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,72 @@
+// Notifications of watch mode, so that a long compilation can be left running
+// in the background. Failing to notify never stops watching; the result is
+// still printed in the terminal.
+
+use serde::Deserialize;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+#[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// Show a desktop notification using `notify-send` or `osascript`.
+    pub desktop: bool,
+    /// URL which receives a json summary of each compilation by a POST request.
+    pub webhook: Option<String>,
+}
+
+impl NotifyConfig {
+    pub fn notify(&self, file_name: &Path, is_ok: bool, elapsed: Duration) {
+        let status = if is_ok { "ok" } else { "error" };
+        if self.desktop {
+            let title = format!("vesti: {}", file_name.display());
+            let body = if is_ok {
+                format!("Compiled in {:.1}s", elapsed.as_secs_f64())
+            } else {
+                String::from("Compilation failed")
+            };
+            run_quietly(desktop_command(&title, &body), "desktop notification");
+        }
+        if let Some(url) = &self.webhook {
+            let payload = serde_json::json!({
+                "file": file_name.display().to_string(),
+                "status": status,
+                "seconds": elapsed.as_secs_f64(),
+            });
+            let mut command = Command::new("curl");
+            command
+                .args(["--silent", "--max-time", "10", "--request", "POST"])
+                .args(["--header", "Content-Type: application/json"])
+                .args(["--data", &payload.to_string(), url]);
+            run_quietly(Some(command), "webhook");
+        }
+    }
+}
+
+fn desktop_command(title: &str, body: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let script = format!("display notification {:?} with title {:?}", body, title);
+        let mut command = Command::new("osascript");
+        command.args(["-e", &script]);
+        Some(command)
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.args([title, body]);
+        Some(command)
+    } else {
+        None
+    }
+}
+
+fn run_quietly(command: Option<Command>, what: &str) {
+    let result = match command {
+        Some(mut command) => command.stdout(Stdio::null()).stderr(Stdio::null()).status(),
+        None => return,
+    };
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => println!("[notify] {} failed with {}", what, status),
+        Err(err) => println!("[notify] cannot send {}: {}", what, err),
+    }
+}
@@ -0,0 +1,173 @@
+// Packages which provide well known commands and environments. Vesti imports
+// them if a document uses such a command without importing its package.
+// Commands and environments defined by `defun` and `defenv` are not counted.
+// Commands of latex itself are listed too, so that `defun` does not silently
+// redefine them.
+
+use crate::parser::ast::{walk_latex, Latex, Spanned, Statement};
+use crate::symbols;
+use std::collections::BTreeSet;
+
+#[rustfmt::skip]
+const COMMAND_PACKAGES: &[(&str, &str)] = &[
+    ("includegraphics", "graphicx"), ("graphicspath", "graphicx"),
+    ("href", "hyperref"), ("url", "hyperref"), ("autoref", "hyperref"),
+    ("color", "xcolor"), ("textcolor", "xcolor"), ("colorbox", "xcolor"),
+    ("definecolor", "xcolor"),
+    ("text", "amsmath"), ("eqref", "amsmath"), ("dfrac", "amsmath"),
+    ("tfrac", "amsmath"), ("boldsymbol", "amsmath"), ("DeclareMathOperator", "amsmath"),
+    ("mathbb", "amssymb"), ("mathfrak", "amssymb"), ("mathscr", "mathrsfs"),
+    ("tikz", "tikz"), ("usetikzlibrary", "tikz"),
+    ("SI", "siunitx"), ("si", "siunitx"), ("qty", "siunitx"), ("unit", "siunitx"),
+    ("toprule", "booktabs"), ("midrule", "booktabs"), ("bottomrule", "booktabs"),
+    ("cref", "cleveref"), ("Cref", "cleveref"),
+    ("multirow", "multirow"), ("enquote", "csquotes"), ("cancel", "cancel"),
+    ("bm", "bm"), ("lipsum", "lipsum"), ("xspace", "xspace"),
+];
+
+#[rustfmt::skip]
+const ENVIRONMENT_PACKAGES: &[(&str, &str)] = &[
+    ("tikzpicture", "tikz"),
+    ("align", "amsmath"), ("gather", "amsmath"), ("multline", "amsmath"),
+    ("split", "amsmath"), ("aligned", "amsmath"), ("cases", "amsmath"),
+    ("matrix", "amsmath"), ("pmatrix", "amsmath"), ("bmatrix", "amsmath"),
+    ("vmatrix", "amsmath"), ("Vmatrix", "amsmath"),
+    ("lstlisting", "listings"), ("minted", "minted"), ("tabularx", "tabularx"),
+    ("longtable", "longtable"), ("subfigure", "subcaption"), ("wrapfigure", "wrapfig"),
+    ("multicols", "multicol"),
+];
+
+// Primitives of TeX and commands of the latex kernel which documents often use.
+// One letter commands like `\d` are accents, so they are listed too.
+#[rustfmt::skip]
+const CORE_COMMANDS: &[&str] = &[
+    "def", "edef", "gdef", "xdef", "let", "relax", "par", "end", "begin", "item",
+    "if", "else", "fi", "the", "box", "kern", "char", "over", "atop", "span",
+    "input", "include", "label", "ref", "pageref", "cite", "caption", "footnote",
+    "part", "chapter", "section", "subsection", "subsubsection", "paragraph",
+    "title", "author", "date", "thanks", "maketitle", "tableofcontents", "appendix",
+    "newcommand", "renewcommand", "providecommand", "newenvironment", "usepackage",
+    "documentclass", "newtheorem", "setlength", "setcounter", "today", "LaTeX", "TeX",
+    "emph", "textbf", "textit", "texttt", "textrm", "textsf", "textsc", "underline",
+    "mbox", "fbox", "hbox", "vbox", "parbox", "makebox", "framebox", "raisebox", "rule",
+    "hspace", "vspace", "hfill", "vfill", "quad", "qquad", "newline", "newpage",
+    "clearpage", "linebreak", "pagebreak", "noindent", "centering", "line", "circle",
+    "tiny", "small", "normalsize", "large", "Large", "huge", "Huge",
+    "bf", "it", "rm", "sf", "tt", "sc", "em", "ldots", "cdots", "dots",
+    "frac", "sqrt", "sum", "prod", "int", "oint", "lim", "sup", "inf", "max", "min",
+    "sin", "cos", "tan", "log", "ln", "exp", "det", "dim", "ker", "deg", "arg",
+    "left", "right", "big", "Big", "not", "in", "to", "gets", "mid", "vert", "Vert",
+    "le", "ge", "ne", "leq", "geq", "neq", "pm", "mp", "times", "div", "cdot",
+    "infty", "partial", "nabla", "forall", "exists", "emptyset", "ell", "Re", "Im",
+    "mathbf", "mathrm", "mathit", "mathsf", "mathtt", "mathcal", "mathnormal",
+    "vec", "hat", "bar", "tilde", "dot", "ddot", "check", "breve", "acute", "grave",
+    "overline", "underbrace", "overbrace", "displaystyle", "textstyle",
+    "alpha", "beta", "gamma", "delta", "epsilon", "theta", "lambda", "mu", "pi",
+    "sigma", "phi", "omega", "Gamma", "Delta", "Theta", "Lambda", "Pi", "Sigma",
+    "Phi", "Omega",
+    "b", "c", "d", "H", "i", "j", "k", "l", "L", "o", "O", "P", "S", "t", "u", "v",
+];
+
+// Whether latex defines the command before the document does
+pub fn is_core_command(name: &str) -> bool {
+    CORE_COMMANDS.contains(&name)
+}
+
+// The package which defines the command
+pub fn command_package(name: &str) -> Option<&'static str> {
+    package_of(COMMAND_PACKAGES, name)
+}
+
+fn package_of(table: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(used, _)| *used == name)
+        .map(|(_, package)| *package)
+}
+
+// Packages needed by commands and environments which the document uses
+pub fn packages_used_by(latex: &Latex) -> BTreeSet<String> {
+    let mut defined: Vec<&str> = Vec::new();
+    walk_latex(latex, &mut |stmt| match stmt {
+        Statement::FunctionDefine { name, .. } | Statement::EnvironmentDefine { name, .. } => {
+            defined.push(name)
+        }
+        _ => {}
+    });
+
+    let mut output = BTreeSet::new();
+    walk_latex(latex, &mut |stmt| {
+        let package = match stmt {
+            Statement::LatexFunction { name, .. } => {
+                let name = name.trim_end();
+                package_of(COMMAND_PACKAGES, name).filter(|_| !defined.contains(&name))
+            }
+            Statement::Environment { name, .. } => {
+                let name = name.trim_end_matches('*');
+                package_of(ENVIRONMENT_PACKAGES, name).filter(|_| !defined.contains(&name))
+            }
+            _ => None,
+        };
+        if let Some(package) = package {
+            output.insert(String::from(package));
+        }
+    });
+    output
+}
+
+// Whether statements need any package, including ones which the parser
+// imports for unicode symbols and operators in math mode
+pub fn requires_packages(latex: &[Spanned<Statement>]) -> bool {
+    let mut found = false;
+    walk_latex(latex, &mut |stmt| {
+        found |= match stmt {
+            Statement::LatexFunction { name, .. } => {
+                *name == "operatorname" || package_of(COMMAND_PACKAGES, name.trim_end()).is_some()
+            }
+            Statement::Environment { name, .. } => {
+                package_of(ENVIRONMENT_PACKAGES, name.trim_end_matches('*')).is_some()
+            }
+            Statement::MathSymbol { unicode, .. } => unicode
+                .chars()
+                .next()
+                .and_then(symbols::unicode_symbol)
+                .is_some_and(|(_, package)| package.is_some()),
+            _ => false,
+        };
+    });
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_packages_used_by() {
+        let source = r#"docclass article
+defun url (#!1) { \texttt{#!1} }
+document
+\includegraphics{cat.png} \url{vesti.dev}
+begenv tikzpicture
+endenv
+begenv align*
+    a &= b
+endenv
+"#;
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        parser.set_auto_import(false);
+        let latex = parser.parse_latex().unwrap();
+        let packages: Vec<String> = packages_used_by(&latex).into_iter().collect();
+        assert_eq!(packages, vec!["amsmath", "graphicx", "tikz"]);
+
+        let latex = Parser::new(Lexer::new(source), &arena)
+            .make_latex_format()
+            .unwrap();
+        assert!(latex.contains("\\usepackage{amsmath}\n\\usepackage{graphicx}\n\\usepackage{tikz}"));
+        assert!(!latex.contains("\\usepackage{hyperref}"));
+    }
+}
@@ -0,0 +1,112 @@
+// Strings of the syntax tree are allocated in an arena which lives as long as
+// the tree. Texts borrowed from the source are not copied at all, and strings
+// which vesti makes are packed into bump allocated chunks, so parsing a
+// document does not allocate every word separately. Statement lists are not
+// in the arena, since later passes edit them in place.
+
+use bumpalo::Bump;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct Arena {
+    bump: Bump,
+    // Bytes of strings, which do not count free room of chunks
+    len: Cell<usize>,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc_str(&self, s: &str) -> &str {
+        if s.is_empty() {
+            return "";
+        }
+        self.len.set(self.len.get() + s.len());
+        self.bump.alloc_str(s)
+    }
+
+    // Total bytes of strings in the arena
+    pub fn allocated_bytes(&self) -> usize {
+        self.len.get()
+    }
+}
+
+// An interned string. Two symbols of one interner are equal exactly when
+// their strings are, so names can be compared and hashed as integers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+// Strings which are made several times, like names of packages and commands,
+// share one allocation. Symbols are only keys of what the parser looks up,
+// like labels, packages and defined functions. Statements keep interned
+// strings instead, since codegen and later passes read names without the
+// interner, and the lexer borrows words from the source without interning.
+pub struct Interner<'a> {
+    arena: &'a Arena,
+    symbols: HashMap<&'a str, Symbol>,
+    strings: Vec<&'a str>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn new(arena: &'a Arena) -> Self {
+        Self {
+            arena,
+            symbols: HashMap::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    pub fn intern(&mut self, s: &str) -> &'a str {
+        let symbol = self.symbol(s);
+        self.resolve(symbol)
+    }
+
+    pub fn symbol(&mut self, s: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(s) {
+            return *symbol;
+        }
+        let interned = self.arena.alloc_str(s);
+        let symbol = Symbol(self.strings.len() as u32);
+        self.symbols.insert(interned, symbol);
+        self.strings.push(interned);
+        symbol
+    }
+
+    // The symbol of a string which is already interned. A string which is not
+    // interned is not equal to any symbol, so it is not added.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.symbols.get(s).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &'a str {
+        self.strings[symbol.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_arena() {
+        let arena = Arena::new();
+        let mut interner = Interner::new(&arena);
+        let foo = interner.intern("foo");
+        let long = "x".repeat(4097);
+        let long = interner.intern(&long);
+        let bar = interner.intern("bar");
+        assert_eq!((foo, bar), ("foo", "bar"));
+        assert_eq!(long.len(), 4097);
+        assert!(std::ptr::eq(foo, interner.intern("foo")));
+        assert_eq!(arena.allocated_bytes(), 4097 + 6);
+
+        let sym = interner.symbol("bar");
+        assert_eq!(Some(sym), interner.get("bar"));
+        assert_eq!(interner.resolve(sym), "bar");
+        assert_ne!(sym, interner.symbol("baz"));
+        assert_eq!(interner.get("qux"), None);
+    }
+}
@@ -0,0 +1,104 @@
+// Argument specifications of `\NewDocumentCommand` which `defun name [s O{x} m]`
+// writes. Only their shapes are checked here, and latex reads them as they are.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgSpec {
+    // `m`
+    Mandatory,
+    // `o` and `O{default}`
+    Optional(Option<String>),
+    // `s` and `t<token>`
+    Star(char),
+    // `r<open><close>`, `R<open><close>{default}`, `d<open><close>` and
+    // `D<open><close>{default}`
+    Delimited {
+        open: char,
+        close: char,
+        required: bool,
+    },
+    // `u{tokens}` takes everything until the tokens
+    Until(String),
+    // `v` takes a verbatim argument like `\verb`
+    Verbatim,
+}
+
+// A group like `{default}` right after the argument type. Nested braces are
+// kept in the group.
+fn braced_group(chars: &mut std::str::Chars) -> Option<String> {
+    if chars.next()? != '{' {
+        return None;
+    }
+    let mut group = String::new();
+    let mut nested = 0;
+    loop {
+        let chr = chars.next()?;
+        match chr {
+            '{' => nested += 1,
+            '}' if nested == 0 => return Some(group),
+            '}' => nested -= 1,
+            _ => {}
+        }
+        group.push(chr);
+    }
+}
+
+// `None` if the specification is not a valid one. Prefixes `+` and `!` change
+// only how latex reads an argument, so they are skipped.
+pub fn parse_arg_spec(spec: &str) -> Option<Vec<ArgSpec>> {
+    let mut chars = spec.chars();
+    let mut args = Vec::new();
+    while let Some(chr) = chars.next() {
+        let arg = match chr {
+            ' ' | '\t' | '+' | '!' => continue,
+            'm' => ArgSpec::Mandatory,
+            'o' => ArgSpec::Optional(None),
+            'O' => ArgSpec::Optional(Some(braced_group(&mut chars)?)),
+            's' => ArgSpec::Star('*'),
+            't' => ArgSpec::Star(chars.next()?),
+            'r' | 'R' | 'd' | 'D' => {
+                let open = chars.next()?;
+                let close = chars.next()?;
+                if chr.is_ascii_uppercase() {
+                    braced_group(&mut chars)?;
+                }
+                ArgSpec::Delimited {
+                    open,
+                    close,
+                    required: chr.eq_ignore_ascii_case(&'r'),
+                }
+            }
+            'u' => ArgSpec::Until(braced_group(&mut chars)?),
+            'v' => ArgSpec::Verbatim,
+            _ => return None,
+        };
+        args.push(arg);
+    }
+    // latex commands take at most nine arguments
+    (!args.is_empty() && args.len() <= 9).then_some(args)
+}
+
+// The specification of `\newenvironment{name}[args_num][default]`
+pub fn numbered_spec(args_num: u8, default: Option<&str>) -> String {
+    let mut args = Vec::with_capacity(args_num as usize);
+    for num in 0..args_num {
+        args.push(match default {
+            Some(default) if num == 0 => format!("O{{{}}}", default),
+            _ => String::from("m"),
+        });
+    }
+    args.join(" ")
+}
+
+// The number of `{}` groups which a call of the function has. `None` if some
+// arguments are not written as groups, so that calls cannot be checked.
+pub fn mandatory_count(args: &[ArgSpec]) -> Option<usize> {
+    let mut count = 0;
+    for arg in args {
+        match arg {
+            ArgSpec::Mandatory => count += 1,
+            ArgSpec::Optional(_) | ArgSpec::Star('*') => {}
+            _ => return None,
+        }
+    }
+    Some(count)
+}
@@ -1,49 +1,591 @@
-pub type Latex = Vec<Statement>;
+use crate::engine::{InteractionMode, LatexEngineType};
+use crate::location::Span;
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Statement {
+pub type Latex<'a> = Vec<Spanned<Statement<'a>>>;
+
+// A node with the span of the source which it is parsed from. Nodes which vesti
+// makes by itself, like imports of used packages, have the default span.
+// Two nodes are equal if they are the same regardless of where they are written.
+#[derive(Debug, Clone, Serialize)]
+pub struct Spanned<T> {
+    #[serde(flatten)]
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Self {
+        Self { node, span }
+    }
+}
+
+impl<T> From<T> for Spanned<T> {
+    fn from(node: T) -> Self {
+        Self::new(node, Span::default())
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.node
+    }
+}
+
+impl<T> DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.node
+    }
+}
+
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.node == other.node
+    }
+}
+
+// `vesti ast` prints statements as JSON objects like `{"kind": "MainText", "value": "foo"}`
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "kind", content = "value")]
+pub enum Statement<'a> {
     DocumentClass {
-        name: String,
-        options: Option<Vec<Latex>>,
+        name: &'a str,
+        options: Option<Vec<Latex<'a>>>,
     },
     Usepackage {
-        name: String,
-        options: Option<Vec<Latex>>,
+        name: &'a str,
+        options: Option<Vec<Latex<'a>>>,
     },
     MultiUsepackages {
-        pkgs: Vec<Statement>,
+        pkgs: Latex<'a>,
     },
     DocumentStart,
     DocumentEnd,
-    MainText(String),
+    MainText(&'a str),
     Integer(i64),
     Float(f64),
-    RawLatex(String),
+    // The number is kept as it is written, so `1.50cm` is not changed into `1.5cm`
+    Dimension {
+        value: &'a str,
+        unit: &'a str,
+    },
+    RawLatex(&'a str),
+    // `#> text` which becomes `% text` in the latex code
+    Comment(&'a str),
     MathText {
         state: MathState,
-        text: Vec<Statement>,
+        text: Latex<'a>,
+    },
+    PlainTextInMath(Latex<'a>),
+    // A unicode character like `α` in math mode which becomes a latex macro
+    MathSymbol {
+        unicode: &'a str,
+        latex: &'a str,
+    },
+    // `#( ... #)` which becomes `\left( ... \right)`
+    MathDelimiter {
+        left: &'a str,
+        right: &'a str,
+        text: Latex<'a>,
     },
-    PlainTextInMath(Latex),
     LatexFunction {
-        name: String,
-        args: Vec<(ArgNeed, Vec<Statement>)>,
+        name: &'a str,
+        args: Vec<(ArgNeed, Latex<'a>)>,
     },
     Environment {
-        name: String,
-        args: Vec<(ArgNeed, Vec<Statement>)>,
-        text: Latex,
+        name: &'a str,
+        args: Vec<(ArgNeed, Latex<'a>)>,
+        text: Latex<'a>,
+    },
+    SetCounter {
+        counter: &'a str,
+        value: i64,
+    },
+    TableOfContents {
+        minitoc: bool,
+    },
+    MiniToc,
+    Table {
+        width: Option<Latex<'a>>,
+        spec: &'a str,
+        rows: Vec<TableRow<'a>>,
+    },
+    Figure {
+        path: &'a str,
+        path_span: Option<Span>,
+        placement: Option<&'a str>,
+        options: Vec<&'a str>,
+        caption: Option<&'a str>,
+        label: Option<&'a str>,
+    },
+    // `defun name (#!1#!2) { body }`. `doc` is the text of `///` lines before it.
+    // `spec` is the argument specification of `defun name [s O{x} m] { body }`,
+    // and `params` is empty then.
+    FunctionDefine {
+        kind: DefineKind,
+        name: &'a str,
+        params: &'a str,
+        spec: Option<&'a str>,
+        body: Latex<'a>,
+        doc: Option<&'a str>,
+    },
+    // `defenv name (args_num, default) { begin_part } { end_part }`. `spec` is the
+    // argument specification of `\NewDocumentEnvironment`, which is given by
+    // `defenv name [s O{x} m]` or made from `args_num` and `default`.
+    EnvironmentDefine {
+        name: &'a str,
+        args_num: u8,
+        optional_arg: Option<&'a str>,
+        spec: Option<&'a str>,
+        begin_part: Latex<'a>,
+        end_part: Latex<'a>,
+        doc: Option<&'a str>,
+    },
+    CodeBlock {
+        env: CodeEnv,
+        lang: Option<&'a str>,
+        options: Vec<&'a str>,
+        code: &'a str,
+    },
+    // Lines of `title "..."`, `author "..." "affiliation"` and `date today`
+    // in the preamble
+    FrontMatter {
+        title: Option<&'a str>,
+        authors: Vec<Author<'a>>,
+        date: Option<DocDate<'a>>,
+    },
+    // `deftheorem theorem "Theorem" [section]`. A theorem shares the counter
+    // `shared` of another one like `(theorem)`, or its counter is reset at
+    // `within`. Starred theorems are not numbered.
+    TheoremDefine {
+        name: &'a str,
+        title: &'a str,
+        starred: bool,
+        within: Option<&'a str>,
+        shared: Option<&'a str>,
+    },
+    // `qty(9.81, m/s^2)`. `unit` is written in macros of siunitx like
+    // `\metre\per\second\squared`, and `symbol` is the unit as it is written.
+    Quantity {
+        value: &'a str,
+        unit: &'a str,
+        symbol: &'a str,
+    },
+    // `execute(lang) { ... }` whose standard output is written as raw latex.
+    // The output is `None` until the block is run.
+    ExecuteBlock {
+        lang: &'a str,
+        code: &'a str,
+        output: Option<String>,
+    },
+    // Interaction mode of the latex engine. This makes no latex code.
+    InteractionMode(InteractionMode),
+    // `%!key: value` at the top of the file. This makes no latex code.
+    FileDirective(FileDirective<'a>),
+    ImportBib {
+        path: &'a str,
+        style: Option<&'a str>,
+        backend: BibBackend,
+        // packages and the style are already set by `bibstyle`
+        has_bibstyle: bool,
+    },
+    BibStyle {
+        style: &'a str,
+        backend: BibBackend,
+    },
+    PrintBib {
+        path: &'a str,
+        backend: BibBackend,
+    },
+    List {
+        kind: ListKind,
+        items: Vec<Latex<'a>>,
+    },
+    EmbeddedFile {
+        name: &'a str,
+        // The data is written into the file, so JSON has only its name
+        #[serde(skip)]
+        data: Vec<u8>,
     },
+    // `importtex "legacy.tex"` pastes a latex file. With `(split)`, its preamble
+    // and its body are put into the ones of the document.
+    ImportTex {
+        path: &'a str,
+        path_span: Option<Span>,
+        split: bool,
+    },
+    // `chapter import "chap1.ves"` which is made into its own latex file and
+    // included by `\include{chap1}`
+    ChapterImport {
+        name: &'a str,
+        path: &'a str,
+    },
+    // `import defs.ves as m` pastes definitions of a vesti file, whose names
+    // get the prefix like `\mfoo`
+    ImportModule {
+        path: &'a str,
+        path_span: Option<Span>,
+        prefix: Option<&'a str>,
+    },
+    // `frame<2-> "Title" (fragile) { ... }` of beamer
+    Frame {
+        overlay: Option<&'a str>,
+        title: Option<&'a str>,
+        options: Vec<Latex<'a>>,
+        body: Latex<'a>,
+    },
+    // `pause` alone in its line
+    Pause,
+    // `index "group!abelian"`, whose `!` separates levels of the entry
+    Index(&'a str),
+    // `\makeindex` and `\printindex` which vesti adds if there are index entries
+    MakeIndex,
+    PrintIndex,
+    // `section "Introduction"` whose label is made from the title. `name` ends
+    // with `*` for starred headings.
+    Heading {
+        name: &'a str,
+        title: &'a str,
+        label: Option<&'a str>,
+    },
+    Conditional {
+        cond: &'a str,
+        enabled: bool,
+        then_branch: Latex<'a>,
+        else_branch: Latex<'a>,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum FileDirective<'a> {
+    Engine(LatexEngineType),
+    // the name of the output file without its extension
+    Output(&'a str),
+    // `false` compiles the file once even in watch mode
+    Watch(bool),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum BibBackend {
+    Bibtex,
+    Biber,
+}
+
+// Environment of code blocks which is chosen from the imported package
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum CodeEnv {
+    Lstlisting,
+    Minted,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum ListKind {
+    Itemize,
+    Enumerate,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub enum TableRow<'a> {
+    Hline,
+    Cells(Vec<TableCell<'a>>),
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Author<'a> {
+    pub name: &'a str,
+    // lines below the name, like a university or an email address
+    pub affiliations: Vec<&'a str>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum DocDate<'a> {
+    Today,
+    // `date none` hides the date
+    Empty,
+    Text(&'a str),
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TableCell<'a> {
+    // If span is bigger than one or align is given, it becomes a multicolumn
+    pub span: usize,
+    pub align: Option<&'a str>,
+    pub text: Latex<'a>,
+}
+
+// `defun` defines a new function, `defun!` redefines an existing one and
+// `defun?` defines it only if it does not exist
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum DefineKind {
+    Define,
+    Redefine,
+    Provide,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum ArgNeed {
     MainArg,
     Optional,
     StarArg,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
 pub enum MathState {
     Text,
     Inline,
 }
+
+// Visit every statement in the given latex including nested ones.
+// Only the branch of a conditional which will be compiled is visited.
+pub fn walk_latex<'a, 's, F>(latex: &'a [Spanned<Statement<'s>>], f: &mut F)
+where
+    F: FnMut(&'a Statement<'s>),
+{
+    walk_spanned(latex, &mut |stmt| f(&stmt.node));
+}
+
+// Same as `walk_latex`, but with spans of statements
+pub fn walk_spanned<'a, 's, F>(latex: &'a [Spanned<Statement<'s>>], f: &mut F)
+where
+    F: FnMut(&'a Spanned<Statement<'s>>),
+{
+    for stmt in latex {
+        f(stmt);
+        match &stmt.node {
+            Statement::DocumentClass {
+                options: Some(options),
+                ..
+            }
+            | Statement::Usepackage {
+                options: Some(options),
+                ..
+            } => {
+                for opt in options {
+                    walk_spanned(opt, f);
+                }
+            }
+            Statement::MultiUsepackages { pkgs } => walk_spanned(pkgs, f),
+            Statement::Frame { options, body, .. } => {
+                for opt in options {
+                    walk_spanned(opt, f);
+                }
+                walk_spanned(body, f);
+            }
+            Statement::MathText { text, .. } => walk_spanned(text, f),
+            Statement::PlainTextInMath(text) => walk_spanned(text, f),
+            Statement::MathDelimiter { text, .. } => walk_spanned(text, f),
+            Statement::LatexFunction { args, .. } => {
+                for (_, arg) in args {
+                    walk_spanned(arg, f);
+                }
+            }
+            Statement::Environment { args, text, .. } => {
+                for (_, arg) in args {
+                    walk_spanned(arg, f);
+                }
+                walk_spanned(text, f);
+            }
+            Statement::Table { width, rows, .. } => {
+                if let Some(width) = width {
+                    walk_spanned(width, f);
+                }
+                for row in rows {
+                    if let TableRow::Cells(cells) = row {
+                        for cell in cells {
+                            walk_spanned(&cell.text, f);
+                        }
+                    }
+                }
+            }
+            Statement::List { items, .. } => {
+                for item in items {
+                    walk_spanned(item, f);
+                }
+            }
+            Statement::Conditional {
+                enabled,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if *enabled {
+                    walk_spanned(then_branch, f);
+                } else {
+                    walk_spanned(else_branch, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Visit spans of every statement including both branches of conditionals and
+// bodies of definitions, like when statements are moved to other lines.
+pub fn walk_spans_mut<'s, F>(latex: &mut [Spanned<Statement<'s>>], f: &mut F)
+where
+    F: FnMut(&mut Span),
+{
+    for stmt in latex {
+        f(&mut stmt.span);
+        match &mut stmt.node {
+            Statement::DocumentClass {
+                options: Some(options),
+                ..
+            }
+            | Statement::Usepackage {
+                options: Some(options),
+                ..
+            } => {
+                for opt in options {
+                    walk_spans_mut(opt, f);
+                }
+            }
+            Statement::MultiUsepackages { pkgs } => walk_spans_mut(pkgs, f),
+            Statement::Frame { options, body, .. } => {
+                for opt in options {
+                    walk_spans_mut(opt, f);
+                }
+                walk_spans_mut(body, f);
+            }
+            Statement::MathText { text, .. }
+            | Statement::PlainTextInMath(text)
+            | Statement::MathDelimiter { text, .. } => walk_spans_mut(text, f),
+            Statement::LatexFunction { args, .. } => {
+                for (_, arg) in args {
+                    walk_spans_mut(arg, f);
+                }
+            }
+            Statement::Environment { args, text, .. } => {
+                for (_, arg) in args {
+                    walk_spans_mut(arg, f);
+                }
+                walk_spans_mut(text, f);
+            }
+            Statement::Table { width, rows, .. } => {
+                if let Some(width) = width {
+                    walk_spans_mut(width, f);
+                }
+                for row in rows {
+                    if let TableRow::Cells(cells) = row {
+                        for cell in cells {
+                            walk_spans_mut(&mut cell.text, f);
+                        }
+                    }
+                }
+            }
+            Statement::Figure {
+                path_span: Some(span),
+                ..
+            }
+            | Statement::ImportTex {
+                path_span: Some(span),
+                ..
+            }
+            | Statement::ImportModule {
+                path_span: Some(span),
+                ..
+            } => f(span),
+            Statement::FunctionDefine { body, .. } => walk_spans_mut(body, f),
+            Statement::EnvironmentDefine {
+                begin_part,
+                end_part,
+                ..
+            } => {
+                walk_spans_mut(begin_part, f);
+                walk_spans_mut(end_part, f);
+            }
+            Statement::List { items, .. } => {
+                for item in items {
+                    walk_spans_mut(item, f);
+                }
+            }
+            Statement::Conditional {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                walk_spans_mut(then_branch, f);
+                walk_spans_mut(else_branch, f);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Same as `walk_latex`, but statements can be changed.
+pub fn walk_latex_mut<'s, F>(latex: &mut [Spanned<Statement<'s>>], f: &mut F)
+where
+    F: FnMut(&mut Statement<'s>),
+{
+    for stmt in latex {
+        f(&mut stmt.node);
+        match &mut stmt.node {
+            Statement::DocumentClass {
+                options: Some(options),
+                ..
+            }
+            | Statement::Usepackage {
+                options: Some(options),
+                ..
+            } => {
+                for opt in options {
+                    walk_latex_mut(opt, f);
+                }
+            }
+            Statement::MultiUsepackages { pkgs } => walk_latex_mut(pkgs, f),
+            Statement::Frame { options, body, .. } => {
+                for opt in options {
+                    walk_latex_mut(opt, f);
+                }
+                walk_latex_mut(body, f);
+            }
+            Statement::MathText { text, .. } => walk_latex_mut(text, f),
+            Statement::PlainTextInMath(text) => walk_latex_mut(text, f),
+            Statement::MathDelimiter { text, .. } => walk_latex_mut(text, f),
+            Statement::LatexFunction { args, .. } => {
+                for (_, arg) in args {
+                    walk_latex_mut(arg, f);
+                }
+            }
+            Statement::Environment { args, text, .. } => {
+                for (_, arg) in args {
+                    walk_latex_mut(arg, f);
+                }
+                walk_latex_mut(text, f);
+            }
+            Statement::Table { width, rows, .. } => {
+                if let Some(width) = width {
+                    walk_latex_mut(width, f);
+                }
+                for row in rows {
+                    if let TableRow::Cells(cells) = row {
+                        for cell in cells {
+                            walk_latex_mut(&mut cell.text, f);
+                        }
+                    }
+                }
+            }
+            Statement::List { items, .. } => {
+                for item in items {
+                    walk_latex_mut(item, f);
+                }
+            }
+            Statement::Conditional {
+                enabled,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                if *enabled {
+                    walk_latex_mut(then_branch, f);
+                } else {
+                    walk_latex_mut(else_branch, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
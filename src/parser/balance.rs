@@ -0,0 +1,172 @@
+// Balance of delimiters in a whole source. The parser stops at the first
+// delimiter which it cannot match, and it sees only delimiters which it is
+// parsing, so this pass finds every unmatched `{` and math delimiter once the
+// parser fails with a delimiter error. `(` and `[` are delimiters only inside
+// of math, since prose like `item 1)` or `:-(` uses them alone.
+
+use crate::error::err_kind::VestiParseErr;
+use crate::error::VestiErr;
+use crate::lexer::token::TokenType;
+use crate::lexer::Lexer;
+use crate::location::{Location, Span};
+
+struct Open {
+    literal: String,
+    closed_by: TokenType,
+    span: Span,
+}
+
+fn closing_of(toktype: TokenType) -> Option<TokenType> {
+    match toktype {
+        TokenType::Lbrace => Some(TokenType::Rbrace),
+        TokenType::Lparen => Some(TokenType::Rparen),
+        TokenType::Lsqbrace => Some(TokenType::Rsqbrace),
+        TokenType::TextMathStart => Some(TokenType::TextMathEnd),
+        TokenType::InlineMathStart => Some(TokenType::InlineMathEnd),
+        _ => None,
+    }
+}
+
+fn is_paren(toktype: TokenType) -> bool {
+    matches!(
+        toktype,
+        TokenType::Lparen | TokenType::Rparen | TokenType::Lsqbrace | TokenType::Rsqbrace
+    )
+}
+
+fn is_closing(toktype: TokenType) -> bool {
+    matches!(
+        toktype,
+        TokenType::Rbrace
+            | TokenType::Rparen
+            | TokenType::Rsqbrace
+            | TokenType::TextMathEnd
+            | TokenType::InlineMathEnd
+    )
+}
+
+// Intervals like `[0, 1)` are valid in math, so `)` and `]` close each other
+// inside of math
+fn closes(open: &Open, toktype: TokenType, in_math: bool) -> bool {
+    let is_interval = |toktype| matches!(toktype, TokenType::Rparen | TokenType::Rsqbrace);
+    open.closed_by == toktype || (in_math && is_interval(open.closed_by) && is_interval(toktype))
+}
+
+fn unclosed(open: Open, before: Option<Location>) -> VestiErr {
+    VestiErr::make_parse_err(
+        VestiParseErr::UnclosedDelimiterErr {
+            open: open.literal,
+            expected: open.closed_by,
+            before,
+        },
+        Some(open.span),
+    )
+}
+
+// Errors of every unmatched delimiter in the order of their locations. An
+// unclosed delimiter is expected to be closed before the delimiter which closes
+// an outer one, or before the end of the file.
+pub fn unbalanced_delimiters(source: &str) -> Vec<VestiErr> {
+    let mut stack: Vec<Open> = Vec::new();
+    let mut errs = Vec::new();
+    for tok in Lexer::new(source) {
+        let toktype = tok.token.toktype;
+        let in_math = stack.iter().any(|open| {
+            matches!(
+                open.closed_by,
+                TokenType::TextMathEnd | TokenType::InlineMathEnd
+            )
+        });
+        if is_paren(toktype) && !in_math {
+            continue;
+        }
+        if let Some(closed_by) = closing_of(toktype) {
+            stack.push(Open {
+                literal: tok.token.literal.to_string(),
+                closed_by,
+                span: tok.span,
+            });
+            continue;
+        }
+        if !is_closing(toktype) {
+            continue;
+        }
+        match stack
+            .iter()
+            .rposition(|open| closes(open, toktype, in_math))
+        {
+            Some(idx) => {
+                let inner = stack.split_off(idx + 1);
+                errs.extend(
+                    inner
+                        .into_iter()
+                        .map(|open| unclosed(open, Some(tok.span.start))),
+                );
+                stack.pop();
+            }
+            None => errs.push(VestiErr::make_parse_err(
+                VestiParseErr::UnopenedDelimiterErr {
+                    got: tok.token.literal.to_string(),
+                },
+                Some(tok.span),
+            )),
+        }
+    }
+    errs.extend(stack.into_iter().map(|open| unclosed(open, None)));
+    errs.sort_by_key(|err| {
+        err.location
+            .map(|span| (span.start.row(), span.start.column()))
+    });
+    errs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::err_kind::VestiErrKind;
+
+    #[test]
+    fn test_unbalanced_delimiters() {
+        let source = "document\n\\textbf{a (b}\n\\([0, 1)\\) and c]\n\\(f(x\\) \\(y]\\)\n\\foo{d\n";
+        let errs: Vec<(VestiParseErr, Location)> = unbalanced_delimiters(source)
+            .into_iter()
+            .map(|err| match err.err_kind {
+                VestiErrKind::ParseErr(err_kind) => (err_kind, err.location.unwrap().start),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            errs,
+            vec![
+                (
+                    VestiParseErr::UnclosedDelimiterErr {
+                        open: String::from("("),
+                        expected: TokenType::Rparen,
+                        before: Some(Location::new(4, 6)),
+                    },
+                    Location::new(4, 4)
+                ),
+                (
+                    VestiParseErr::UnopenedDelimiterErr {
+                        got: String::from("]"),
+                    },
+                    Location::new(4, 12)
+                ),
+                (
+                    VestiParseErr::UnclosedDelimiterErr {
+                        open: String::from("{"),
+                        expected: TokenType::Rbrace,
+                        before: None,
+                    },
+                    Location::new(5, 5)
+                ),
+            ]
+        );
+        assert!(unbalanced_delimiters("document\n\\foo{(a)}[b]\n").is_empty());
+
+        // parentheses of prose are not delimiters
+        let errs = unbalanced_delimiters("document\nitem 1) here :-( \\textbf{bold\n");
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].location.unwrap().start, Location::new(2, 25));
+    }
+}
@@ -0,0 +1,268 @@
+// Generating ConTeXt code from statements. The preamble becomes setups and
+// modules, and environments become `\start...`/`\stop...` pairs. Statements
+// which both formats share, like texts and macros, are written as latex.
+
+use super::ast::*;
+use super::maker::{latex_to_string, write_io};
+use std::fmt::{self, Write};
+use std::io;
+
+// Latex packages whose features are built in ConTeXt
+const BUILTIN_PACKAGES: &[&str] = &[
+    "amsmath", "amssymb", "amsthm", "graphicx", "xcolor", "color", "hyperref", "geometry",
+    "inputenc", "fontenc", "enumitem", "array", "booktabs", "tabularx", "multicol", "listings",
+    "natbib", "biblatex",
+];
+
+// Latex environments with different names in ConTeXt
+const ENVIRONMENTS: &[(&str, &str)] = &[
+    ("center", "alignment[middle]"),
+    ("flushleft", "alignment[flushleft]"),
+    ("flushright", "alignment[flushright]"),
+    ("equation", "formula"),
+    ("equation*", "formula"),
+    ("quote", "quotation"),
+    ("verbatim", "typing"),
+    ("itemize", "itemize"),
+    ("enumerate", "itemize[n]"),
+    ("multicols", "columns"),
+];
+
+// Latex font commands with their ConTeXt switches
+const FONT_COMMANDS: &[(&str, &str)] = &[
+    ("textbf", "bf"),
+    ("textit", "it"),
+    ("textsl", "sl"),
+    ("texttt", "tt"),
+    ("textsc", "sc"),
+];
+
+pub fn latex_to_context(latex: &Latex) -> String {
+    let mut output = String::new();
+    // writing into a String never fails
+    let _ = write_context(&mut output, latex);
+    output
+}
+
+// Write ConTeXt code of a document piece by piece like `emit` does
+pub fn emit_context<W: io::Write>(latex: &Latex, writer: &mut W) -> io::Result<()> {
+    write_io(writer, |w| write_context(w, latex))
+}
+
+fn write_context<W: Write>(w: &mut W, latex: &[Spanned<Statement>]) -> fmt::Result {
+    for stmt in latex {
+        write_ctx_statement(w, &stmt.node)?;
+    }
+    Ok(())
+}
+
+fn write_ctx_statement<W: Write>(w: &mut W, stmt: &Statement) -> fmt::Result {
+    match stmt {
+        Statement::DocumentClass { name, options } => write_ctx_docclass(w, name, options),
+        Statement::Usepackage { name, options } => write_ctx_module(w, name, options),
+        Statement::MultiUsepackages { pkgs } => write_context(w, pkgs),
+        Statement::DocumentStart => w.write_str("\\starttext\n"),
+        Statement::DocumentEnd => w.write_str("\n\\stoptext\n"),
+        Statement::MathText { state, text } => match state {
+            MathState::Text => write!(w, "${}$", latex_to_string(text)),
+            MathState::Inline => write!(
+                w,
+                "\\startformula {} \\stopformula",
+                latex_to_string(text).trim()
+            ),
+        },
+        Statement::LatexFunction { name, args } => write_ctx_function(w, stmt, name, args),
+        Statement::Environment { name, args, text } => write_ctx_environment(w, name, args, text),
+        Statement::TableOfContents { .. } => w.write_str("\\completecontent\n"),
+        Statement::MiniToc => Ok(()),
+        Statement::Table { rows, .. } => write_ctx_table(w, rows),
+        Statement::Figure {
+            path,
+            options,
+            caption,
+            label,
+            ..
+        } => {
+            w.write_str("\\startplacefigure[")?;
+            if let Some(label) = label {
+                write!(w, "reference={},", label)?;
+            }
+            writeln!(w, "title={{{}}}]", caption.as_deref().unwrap_or(""))?;
+            write!(w, "\\externalfigure[{}]", path)?;
+            if !options.is_empty() {
+                write!(w, "[{}]", options.join(","))?;
+            }
+            w.write_str("\n\\stopplacefigure\n")
+        }
+        Statement::EnvironmentDefine {
+            name,
+            args_num: 0,
+            begin_part,
+            end_part,
+            ..
+        } => writeln!(
+            w,
+            "\\definestartstop[{}][before={{{}}},after={{{}}}]",
+            name,
+            latex_to_context(begin_part).trim(),
+            latex_to_context(end_part).trim()
+        ),
+        Statement::CodeBlock { code, .. } => {
+            let newline = if code.ends_with('\n') { "" } else { "\n" };
+            write!(w, "\\starttyping\n{}{}\\stoptyping\n", code, newline)
+        }
+        Statement::ImportBib { path, style, .. } => {
+            if let Some(style) = style {
+                writeln!(w, "\\usebtxdefinitions[{}]", style)?;
+            }
+            writeln!(w, "\\usebtxdataset[{}]", path)
+        }
+        Statement::BibStyle { style, .. } => writeln!(w, "\\usebtxdefinitions[{}]", style),
+        Statement::PrintBib { .. } => w.write_str("\\placelistofpublications\n"),
+        // levels of a register entry are separated by `+`
+        Statement::Index(term) => write!(w, "\\index{{{}}}", term.replace('!', "+")),
+        Statement::MakeIndex => Ok(()),
+        Statement::PrintIndex => w.write_str("\\placeindex\n"),
+        Statement::List { kind, items } => write_ctx_list(w, *kind, items),
+        Statement::Heading { name, title, label } => {
+            write!(w, "\\{}", name.trim_end_matches('*'))?;
+            if let Some(label) = label {
+                write!(w, "[{}]", label)?;
+            }
+            writeln!(w, "{{{}}}", title)
+        }
+        Statement::Conditional {
+            enabled,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if *enabled {
+                write_context(w, then_branch)
+            } else {
+                write_context(w, else_branch)
+            }
+        }
+        // texts and macros are the same in both formats
+        _ => write!(w, "{}", stmt),
+    }
+}
+
+// Paper sizes and font sizes of the class options become setups
+fn write_ctx_docclass<W: Write>(
+    w: &mut W,
+    name: &str,
+    options: &Option<Vec<Latex>>,
+) -> fmt::Result {
+    writeln!(w, "% documentclass: {}", name)?;
+    for option in options.iter().flatten() {
+        let option = latex_to_string(option);
+        let option = option.trim();
+        if let Some(paper) = option.strip_suffix("paper") {
+            let paper = match paper {
+                "letter" | "legal" | "executive" => paper.to_string(),
+                _ => paper.to_uppercase(),
+            };
+            writeln!(w, "\\setuppapersize[{}]", paper)?;
+        } else if option.ends_with("pt") {
+            writeln!(w, "\\setupbodyfont[{}]", option)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_ctx_module<W: Write>(w: &mut W, name: &str, options: &Option<Vec<Latex>>) -> fmt::Result {
+    if BUILTIN_PACKAGES.contains(&name) {
+        return Ok(());
+    }
+    write!(w, "\\usemodule[{}]", name)?;
+    if let Some(options) = options {
+        let options: Vec<String> = options
+            .iter()
+            .map(|option| latex_to_string(option).trim().to_string())
+            .collect();
+        write!(w, "[{}]", options.join(","))?;
+    }
+    w.write_char('\n')
+}
+
+fn write_ctx_function<W: Write>(
+    w: &mut W,
+    stmt: &Statement,
+    name: &str,
+    args: &[(ArgNeed, Latex)],
+) -> fmt::Result {
+    let main_args: Vec<&Latex> = args
+        .iter()
+        .filter(|(need, _)| *need == ArgNeed::MainArg)
+        .map(|(_, arg)| arg)
+        .collect();
+    if let Some((_, switch)) = FONT_COMMANDS.iter().find(|(command, _)| *command == name) {
+        if let [arg] = main_args.as_slice() {
+            return write!(w, "{{\\{} {}}}", switch, latex_to_context(arg));
+        }
+    }
+    match (name, main_args.as_slice()) {
+        ("label", [label]) => write!(w, "\\reference[{}]{{}}", latex_to_string(label)),
+        ("ref", [label]) => write!(w, "\\in[{}]", latex_to_string(label)),
+        ("cite", [keys]) => write!(w, "\\cite[{}]", latex_to_string(keys)),
+        ("url", [url]) => write!(w, "\\hyphenatedurl{{{}}}", latex_to_string(url)),
+        ("href", [url, text]) => write!(
+            w,
+            "\\goto{{{}}}[url({})]",
+            latex_to_context(text),
+            latex_to_string(url)
+        ),
+        ("maketitle", []) => Ok(()),
+        _ => write!(w, "{}", stmt),
+    }
+}
+
+fn write_ctx_environment<W: Write>(
+    w: &mut W,
+    name: &str,
+    args: &[(ArgNeed, Latex)],
+    text: &Latex,
+) -> fmt::Result {
+    let (start, stop) = match ENVIRONMENTS.iter().find(|(env, _)| *env == name) {
+        Some((_, context)) => (*context, context.split('[').next().unwrap_or(context)),
+        None => (name, name),
+    };
+    write!(w, "\\start{}", start)?;
+    for (need, arg) in args {
+        if *need != ArgNeed::StarArg {
+            write!(w, "[{}]", latex_to_context(arg))?;
+        }
+    }
+    write_context(w, text)?;
+    writeln!(w, "\\stop{}", stop)
+}
+
+fn write_ctx_table<W: Write>(w: &mut W, rows: &[TableRow]) -> fmt::Result {
+    w.write_str("\\bTABLE\n")?;
+    for row in rows {
+        if let TableRow::Cells(cells) = row {
+            w.write_str("\\bTR")?;
+            for cell in cells {
+                w.write_str(" \\bTD")?;
+                if cell.span > 1 {
+                    write!(w, "[nc={}]", cell.span)?;
+                }
+                write!(w, " {} \\eTD", latex_to_context(&cell.text).trim())?;
+            }
+            w.write_str(" \\eTR\n")?;
+        }
+    }
+    w.write_str("\\eTABLE\n")
+}
+
+fn write_ctx_list<W: Write>(w: &mut W, kind: ListKind, items: &[Latex]) -> fmt::Result {
+    match kind {
+        ListKind::Itemize => w.write_str("\\startitemize\n")?,
+        ListKind::Enumerate => w.write_str("\\startitemize[n]\n")?,
+    }
+    for item in items {
+        writeln!(w, "\\item {}", latex_to_context(item).trim_end())?;
+    }
+    w.write_str("\\stopitemize\n")
+}
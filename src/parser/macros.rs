@@ -27,12 +27,12 @@ macro_rules! take_name {
         let mut tmp = String::new();
         while $self
             .peek_tok()
-            .map_or(false, |toktype| toktype.can_pkg_name())
+            .is_some_and(|toktype| toktype.can_pkg_name())
         {
             tmp += &match $self.peek_tok() {
-                Some(TokenType::MainString) => $self.next_tok().unwrap().token.literal,
-                Some(TokenType::Minus) => $self.next_tok().unwrap().token.literal,
-                Some(TokenType::Integer) => $self.next_tok().unwrap().token.literal,
+                Some(toktype) if toktype.can_pkg_name() => {
+                    $self.next_tok().unwrap().token.literal.into_owned()
+                }
                 Some(toktype) => {
                     return Err(VestiErr::make_parse_err(
                         VestiParseErr::TypeMismatch {
@@ -54,7 +54,7 @@ macro_rules! take_name {
     };
     ($self: ident | define $name: ident as mut; $location: expr) => {
         let mut $name = if $self.peek_tok() == Some(TokenType::MainString) {
-            $self.next_tok().unwrap().token.literal
+            $self.next_tok().unwrap().token.literal.into_owned()
         } else if let Some(_) = $self.peek_tok() {
             return Err(VestiErr::make_parse_err(
                 VestiParseErr::BegenvNameMissErr,
@@ -1,139 +1,601 @@
-// Implementing ToString for Statement enum so that making full latex text easily.
+// Generating latex code from statements. Everything is written into a
+// `fmt::Write`, so the same code implements `Display`, makes a `String` and
+// streams a whole document into a file by `emit` without keeping it in memory.
 
 use super::ast::*;
+use std::fmt::{self, Write};
+use std::io;
 
-impl ToString for Statement {
-    fn to_string(&self) -> String {
-        match self {
-            Statement::DocumentClass { name, options } => docclass_to_string(name, options),
-            Statement::Usepackage { name, options } => usepackage_to_string(name, options),
-            Statement::MultiUsepackages { pkgs } => multiusepacakge_to_string(pkgs),
-            Statement::DocumentStart => String::from("\\begin{document}\n"),
-            Statement::DocumentEnd => String::from("\n\\end{document}\n"),
-            Statement::MainText(s) => s.clone(),
-            Statement::PlainTextInMath(latex) => plaintext_in_math_to_string(latex),
-            Statement::Integer(i) => i.to_string(),
-            Statement::Float(f) => f.to_string(),
-            Statement::RawLatex(s) => s.clone(),
-            Statement::MathText { state, text } => math_text_to_string(*state, text),
-            Statement::LatexFunction { name, args } => latex_function_to_string(name, args),
-            Statement::Environment { name, args, text } => environment_to_string(name, args, text),
-        }
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node.fmt(f)
     }
 }
 
-fn docclass_to_string(name: &str, options: &Option<Vec<Latex>>) -> String {
-    if let Some(opts) = options {
-        let mut options_str = String::new();
-        for o in opts {
-            options_str = options_str + &latex_to_string(o) + ",";
+impl fmt::Display for Statement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_statement(f, self)
+    }
+}
+
+fn write_statement<W: Write>(w: &mut W, stmt: &Statement) -> fmt::Result {
+    match stmt {
+        Statement::DocumentClass { name, options } => write_docclass(w, name, options),
+        Statement::Usepackage { name, options } => write_usepackage(w, name, options),
+        Statement::MultiUsepackages { pkgs } => write_multiusepackages(w, pkgs),
+        Statement::DocumentStart => w.write_str("\\begin{document}\n"),
+        Statement::DocumentEnd => w.write_str("\n\\end{document}\n"),
+        Statement::MainText(s) => w.write_str(s),
+        Statement::PlainTextInMath(latex) => write_plaintext_in_math(w, latex),
+        Statement::MathSymbol { latex, .. } => w.write_str(latex),
+        Statement::MathDelimiter { left, right, text } => {
+            write!(w, "\\left{}", left)?;
+            write_latex(w, text)?;
+            write!(w, "\\right{}", right)
+        }
+        Statement::Integer(i) => write!(w, "{}", i),
+        Statement::Float(f) => write!(w, "{}", f),
+        Statement::Dimension { value, unit } => write!(w, "{}{}", value, unit),
+        Statement::RawLatex(s) => w.write_str(s),
+        Statement::Comment(comment) => write!(w, "%{}", comment),
+        Statement::MathText { state, text } => write_math_text(w, *state, text),
+        Statement::LatexFunction { name, args } => write_latex_function(w, name, args),
+        Statement::Environment { name, args, text } => write_environment(w, name, args, text),
+        Statement::SetCounter { counter, value } => {
+            writeln!(w, "\\setcounter{{{}}}{{{}}}", counter, value)
+        }
+        Statement::TableOfContents { minitoc } => {
+            if *minitoc {
+                w.write_str("\\dominitoc\n")?;
+            }
+            w.write_str("\\tableofcontents\n")
+        }
+        Statement::MiniToc => w.write_str("\\minitoc\n"),
+        Statement::Table { width, spec, rows } => write_table(w, width, spec, rows),
+        Statement::Figure {
+            path,
+            placement,
+            options,
+            caption,
+            label,
+            ..
+        } => write_figure(w, path, placement, options, caption, label),
+        Statement::FunctionDefine {
+            kind,
+            name,
+            params,
+            spec,
+            body,
+            ..
+        } => write_function_define(w, *kind, name, params, spec, body),
+        Statement::EnvironmentDefine {
+            name,
+            args_num,
+            optional_arg,
+            spec,
+            begin_part,
+            end_part,
+            ..
+        } => write_environment_define(w, name, *args_num, optional_arg, spec, begin_part, end_part),
+        Statement::CodeBlock {
+            env,
+            lang,
+            options,
+            code,
+        } => write_codeblock(w, *env, lang, options, code),
+        Statement::FrontMatter {
+            title,
+            authors,
+            date,
+        } => write_front_matter(w, title, authors, date),
+        Statement::TheoremDefine {
+            name,
+            title,
+            starred: true,
+            ..
+        } => writeln!(w, "\\newtheorem*{{{}}}{{{}}}", name, title),
+        Statement::TheoremDefine {
+            name,
+            title,
+            within,
+            shared,
+            ..
+        } => {
+            write!(w, "\\newtheorem{{{}}}", name)?;
+            if let Some(shared) = shared {
+                write!(w, "[{}]", shared)?;
+            }
+            write!(w, "{{{}}}", title)?;
+            if let Some(within) = within {
+                write!(w, "[{}]", within)?;
+            }
+            writeln!(w)
+        }
+        Statement::Quantity { value, unit, .. } => write!(w, "\\SI{{{}}}{{{}}}", value, unit),
+        Statement::ExecuteBlock { output, .. } => w.write_str(output.as_deref().unwrap_or("")),
+        Statement::InteractionMode(_) | Statement::FileDirective(_) => Ok(()),
+        Statement::ImportBib {
+            path,
+            style,
+            backend,
+            has_bibstyle,
+        } => write_importbib(w, path, style, *backend, *has_bibstyle),
+        Statement::BibStyle { style, backend } => write_bibstyle(w, style, *backend),
+        Statement::PrintBib { path, backend } => write_printbib(w, path, *backend),
+        Statement::List { kind, items } => write_list(w, *kind, items),
+        Statement::EmbeddedFile { .. } => Ok(()),
+        Statement::ChapterImport { name, .. } => writeln!(w, "\\include{{{}}}", name),
+        // files are pasted before the latex is made, and latex reads the ones
+        // which are not, like those of fragments
+        Statement::ImportTex { path, .. } => writeln!(w, "\\input{{{}}}", path),
+        // latex cannot read vesti files, so modules are pasted before
+        Statement::ImportModule { .. } => Ok(()),
+        Statement::Frame {
+            overlay,
+            title,
+            options,
+            body,
+        } => write_frame(w, overlay, title, options, body),
+        Statement::Pause => w.write_str("\\pause"),
+        Statement::Index(term) => write!(w, "\\index{{{}}}", term),
+        Statement::MakeIndex => w.write_str("\\makeindex\n"),
+        Statement::PrintIndex => w.write_str("\\printindex\n"),
+        Statement::Heading { name, title, label } => {
+            write!(w, "\\{}{{{}}}", name, title)?;
+            if let Some(label) = label {
+                write!(w, "\\label{{{}}}", label)?;
+            }
+            w.write_char('\n')
         }
-        options_str.pop();
+        Statement::Conditional {
+            enabled,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if *enabled {
+                write_latex(w, then_branch)
+            } else {
+                write_latex(w, else_branch)
+            }
+        }
+    }
+}
 
-        format!("\\documentclass[{0}]{{{1}}}\n", options_str, name)
-    } else {
-        format!("\\documentclass{{{}}}\n", name)
+fn write_options<W: Write>(w: &mut W, options: &[Latex]) -> fmt::Result {
+    for (i, option) in options.iter().enumerate() {
+        if i > 0 {
+            w.write_char(',')?;
+        }
+        write_latex(w, option)?;
     }
+    Ok(())
 }
 
-fn usepackage_to_string(name: &str, options: &Option<Vec<Latex>>) -> String {
+fn write_docclass<W: Write>(w: &mut W, name: &str, options: &Option<Vec<Latex>>) -> fmt::Result {
+    w.write_str("\\documentclass")?;
     if let Some(opts) = options {
-        let mut options_str = String::new();
-        for o in opts {
-            options_str = options_str + &latex_to_string(o) + ",";
-        }
-        options_str.pop();
+        w.write_char('[')?;
+        write_options(w, opts)?;
+        w.write_char(']')?;
+    }
+    writeln!(w, "{{{}}}", name)
+}
 
-        format!("\\usepackage[{0}]{{{1}}}\n", options_str, name)
-    } else {
-        format!("\\usepackage{{{}}}\n", name)
+fn write_usepackage<W: Write>(w: &mut W, name: &str, options: &Option<Vec<Latex>>) -> fmt::Result {
+    w.write_str("\\usepackage")?;
+    if let Some(opts) = options {
+        w.write_char('[')?;
+        write_options(w, opts)?;
+        w.write_char(']')?;
     }
+    writeln!(w, "{{{}}}", name)
 }
 
-fn multiusepacakge_to_string(pkgs: &[Statement]) -> String {
-    let mut output = String::new();
+fn write_multiusepackages<W: Write>(w: &mut W, pkgs: &[Spanned<Statement>]) -> fmt::Result {
     for pkg in pkgs {
-        if let Statement::Usepackage { name, options } = pkg {
-            output += &usepackage_to_string(name, options);
+        if let Statement::Usepackage { name, options } = &pkg.node {
+            write_usepackage(w, name, options)?;
         }
     }
-    output
+    Ok(())
 }
 
-fn math_text_to_string(state: MathState, text: &[Statement]) -> String {
-    let mut output = String::new();
-    match state {
-        MathState::Text => {
-            output += "\\(";
-            for t in text {
-                output += &t.to_string();
+fn write_math_text<W: Write>(w: &mut W, state: MathState, text: &Latex) -> fmt::Result {
+    let (open, close) = match state {
+        MathState::Text => ("\\(", "\\)"),
+        MathState::Inline => ("\\[", "\\]"),
+    };
+    w.write_str(open)?;
+    write_latex(w, text)?;
+    w.write_str(close)
+}
+
+fn write_plaintext_in_math<W: Write>(w: &mut W, latex: &Latex) -> fmt::Result {
+    let text = latex_to_string(latex);
+    write!(w, "\\text{{{}}}", text.strip_suffix(' ').unwrap_or(&text))
+}
+
+fn write_args<W: Write>(w: &mut W, args: &[(ArgNeed, Latex)]) -> fmt::Result {
+    for (need, arg) in args {
+        match need {
+            ArgNeed::MainArg => {
+                w.write_char('{')?;
+                write_latex(w, arg)?;
+                w.write_char('}')?;
+            }
+            ArgNeed::Optional => {
+                w.write_char('[')?;
+                write_latex(w, arg)?;
+                w.write_char(']')?;
             }
-            output += "\\)";
+            ArgNeed::StarArg => w.write_char('*')?,
         }
-        MathState::Inline => {
-            output += "\\[";
-            for t in text {
-                output += &t.to_string();
+    }
+    Ok(())
+}
+
+fn write_latex_function<W: Write>(w: &mut W, name: &str, args: &[(ArgNeed, Latex)]) -> fmt::Result {
+    write!(w, "\\{}", name)?;
+    write_args(w, args)
+}
+
+fn write_environment<W: Write>(
+    w: &mut W,
+    name: &str,
+    args: &[(ArgNeed, Latex)],
+    text: &Latex,
+) -> fmt::Result {
+    write!(w, "\\begin{{{}}}", name)?;
+    write_args(w, args)?;
+    write_latex(w, text)?;
+    writeln!(w, "\\end{{{}}}", name)
+}
+
+fn write_frame<W: Write>(
+    w: &mut W,
+    overlay: &Option<&str>,
+    title: &Option<&str>,
+    options: &[Latex],
+    body: &Latex,
+) -> fmt::Result {
+    w.write_str("\\begin{frame}")?;
+    if let Some(overlay) = overlay {
+        write!(w, "<{}>", overlay)?;
+    }
+    if !options.is_empty() {
+        w.write_char('[')?;
+        write_options(w, options)?;
+        w.write_char(']')?;
+    }
+    if let Some(title) = title {
+        write!(w, "{{{}}}", title)?;
+    }
+    write_latex(w, body)?;
+    writeln!(w, "\\end{{frame}}")
+}
+
+fn write_table<W: Write>(
+    w: &mut W,
+    width: &Option<Latex>,
+    spec: &str,
+    rows: &[TableRow],
+) -> fmt::Result {
+    let env_name = if width.is_some() {
+        "tabularx"
+    } else {
+        "tabular"
+    };
+    write!(w, "\\begin{{{}}}", env_name)?;
+    if let Some(width) = width {
+        w.write_char('{')?;
+        write_latex(w, width)?;
+        w.write_char('}')?;
+    }
+    writeln!(w, "{{{}}}", spec)?;
+
+    for row in rows {
+        match row {
+            TableRow::Hline => w.write_str("\\hline\n")?,
+            TableRow::Cells(cells) => {
+                for (i, cell) in cells.iter().enumerate() {
+                    if i > 0 {
+                        w.write_str(" & ")?;
+                    }
+                    if cell.span > 1 || cell.align.is_some() {
+                        write!(
+                            w,
+                            "\\multicolumn{{{0}}}{{{1}}}{{",
+                            cell.span,
+                            cell.align.unwrap_or("c"),
+                        )?;
+                        write_latex(w, &cell.text)?;
+                        w.write_char('}')?;
+                    } else {
+                        write_latex(w, &cell.text)?;
+                    }
+                }
+                w.write_str(" \\\\\n")?;
             }
-            output += "\\]";
         }
     }
-    output
+
+    writeln!(w, "\\end{{{}}}", env_name)
 }
 
-fn plaintext_in_math_to_string(latex: &Latex) -> String {
-    let mut output = latex_to_string(latex);
-    if output.as_bytes()[output.len() - 1] == b' ' {
-        output.pop();
+fn write_figure<W: Write>(
+    w: &mut W,
+    path: &str,
+    placement: &Option<&str>,
+    options: &[&str],
+    caption: &Option<&str>,
+    label: &Option<&str>,
+) -> fmt::Result {
+    w.write_str("\\begin{figure}")?;
+    if let Some(placement) = placement {
+        write!(w, "[{}]", placement)?;
+    }
+    w.write_str("\n\\centering\n\\includegraphics")?;
+    if !options.is_empty() {
+        write!(w, "[{}]", options.join(","))?;
+    }
+    writeln!(w, "{{{}}}", path)?;
+    if let Some(caption) = caption {
+        writeln!(w, "\\caption{{{}}}", caption)?;
+    }
+    if let Some(label) = label {
+        writeln!(w, "\\label{{{}}}", label)?;
     }
+    w.write_str("\\end{figure}\n")
+}
 
-    format!("\\text{{{}}}", output)
+fn write_importbib<W: Write>(
+    w: &mut W,
+    path: &str,
+    style: &Option<&str>,
+    backend: BibBackend,
+    has_bibstyle: bool,
+) -> fmt::Result {
+    match backend {
+        BibBackend::Bibtex if has_bibstyle => Ok(()),
+        BibBackend::Biber if has_bibstyle => writeln!(w, "\\addbibresource{{{}}}", path),
+        BibBackend::Bibtex => writeln!(
+            w,
+            "\\bibliographystyle{{{}}}",
+            style.as_deref().unwrap_or("plain")
+        ),
+        BibBackend::Biber => {
+            w.write_str("\\usepackage[backend=biber")?;
+            if let Some(style) = style {
+                write!(w, ",style={}", style)?;
+            }
+            writeln!(w, "]{{biblatex}}\n\\addbibresource{{{}}}", path)
+        }
+    }
 }
 
-fn latex_function_to_string(name: &str, args: &Vec<(ArgNeed, Vec<Statement>)>) -> String {
-    let mut output = format!("\\{}", name);
-    for arg in args {
-        let mut tmp = String::new();
-        for t in &arg.1 {
-            tmp += &t.to_string();
+// biblatex knows styles by their names, but natbib needs a package option
+// and a bst file which make the style.
+fn write_bibstyle<W: Write>(w: &mut W, style: &str, backend: BibBackend) -> fmt::Result {
+    match backend {
+        BibBackend::Biber => writeln!(w, "\\usepackage[backend=biber,style={}]{{biblatex}}", style),
+        BibBackend::Bibtex => {
+            let (option, bst) = match style {
+                "numeric" => ("[numbers]", "plainnat"),
+                "authoryear" => ("[authoryear]", "plainnat"),
+                "alphabetic" => ("[numbers]", "alpha"),
+                _ => ("", style),
+            };
+            writeln!(
+                w,
+                "\\usepackage{}{{natbib}}\n\\bibliographystyle{{{}}}",
+                option, bst
+            )
         }
-        match arg.0 {
-            ArgNeed::MainArg => output = output + "{" + &tmp + "}",
-            ArgNeed::Optional => output = output + "[" + &tmp + "]",
-            ArgNeed::StarArg => output.push('*'),
+    }
+}
+
+fn write_printbib<W: Write>(w: &mut W, path: &str, backend: BibBackend) -> fmt::Result {
+    match backend {
+        // bibtex takes the name of the bib file without its extension
+        BibBackend::Bibtex => writeln!(
+            w,
+            "\\bibliography{{{}}}",
+            path.strip_suffix(".bib").unwrap_or(path)
+        ),
+        BibBackend::Biber => w.write_str("\\printbibliography\n"),
+    }
+}
+
+// Authors are separated by `\and`, and their affiliations are put below them
+fn write_front_matter<W: Write>(
+    w: &mut W,
+    title: &Option<&str>,
+    authors: &[Author],
+    date: &Option<DocDate>,
+) -> fmt::Result {
+    if let Some(title) = title {
+        writeln!(w, "\\title{{{}}}", title)?;
+    }
+    if !authors.is_empty() {
+        w.write_str("\\author{")?;
+        for (idx, author) in authors.iter().enumerate() {
+            if idx > 0 {
+                w.write_str(" \\and ")?;
+            }
+            w.write_str(author.name)?;
+            for affiliation in &author.affiliations {
+                write!(w, " \\\\ {}", affiliation)?;
+            }
         }
+        w.write_str("}\n")?;
+    }
+    match date {
+        Some(DocDate::Today) => w.write_str("\\date{\\today}\n"),
+        Some(DocDate::Empty) => w.write_str("\\date{}\n"),
+        Some(DocDate::Text(date)) => writeln!(w, "\\date{{{}}}", date),
+        None => Ok(()),
     }
-    output
 }
 
-fn environment_to_string(
+fn write_function_define<W: Write>(
+    w: &mut W,
+    kind: DefineKind,
     name: &str,
-    args: &Vec<(ArgNeed, Vec<Statement>)>,
-    text: &Latex,
-) -> String {
-    let mut output = format!("\\begin{{{}}}", name);
-    for arg in args {
-        let mut tmp = String::new();
-        for t in &arg.1 {
-            tmp += &t.to_string();
+    params: &str,
+    spec: &Option<&str>,
+    body: &Latex,
+) -> fmt::Result {
+    let body = latex_to_string(body);
+    let body = body.trim();
+    if let Some(spec) = spec {
+        let command = match kind {
+            DefineKind::Define => "NewDocumentCommand",
+            DefineKind::Redefine => "RenewDocumentCommand",
+            DefineKind::Provide => "ProvideDocumentCommand",
+        };
+        return writeln!(w, "\\{}{{\\{}}}{{{}}}{{{}}}", command, name, spec, body);
+    }
+    let command = match kind {
+        DefineKind::Define => return writeln!(w, "\\def\\{}{}{{{}}}", name, params, body),
+        DefineKind::Redefine => "renewcommand",
+        DefineKind::Provide => "providecommand",
+    };
+    write!(w, "\\{}{{\\{}}}", command, name)?;
+    // parameters are checked to be `#1#2...` by the parser
+    let params_num = params.matches('#').count();
+    if params_num > 0 {
+        write!(w, "[{}]", params_num)?;
+    }
+    writeln!(w, "{{{}}}", body)
+}
+
+fn write_environment_define<W: Write>(
+    w: &mut W,
+    name: &str,
+    args_num: u8,
+    optional_arg: &Option<&str>,
+    spec: &Option<&str>,
+    begin_part: &Latex,
+    end_part: &Latex,
+) -> fmt::Result {
+    if let Some(spec) = spec {
+        write!(w, "\\NewDocumentEnvironment{{{}}}{{{}}}", name, spec)?;
+    } else {
+        write!(w, "\\newenvironment{{{}}}", name)?;
+    }
+    if args_num > 0 && spec.is_none() {
+        write!(w, "[{}]", args_num)?;
+        if let Some(optional_arg) = optional_arg {
+            write!(w, "[{}]", optional_arg)?;
+        }
+    }
+    writeln!(
+        w,
+        "{{{}}}{{{}}}",
+        latex_to_string(begin_part).trim(),
+        latex_to_string(end_part).trim()
+    )
+}
+
+fn write_codeblock<W: Write>(
+    w: &mut W,
+    env: CodeEnv,
+    lang: &Option<&str>,
+    options: &[&str],
+    code: &str,
+) -> fmt::Result {
+    match env {
+        CodeEnv::Lstlisting => {
+            let options: Vec<String> = lang
+                .iter()
+                .map(|lang| format!("language={}", lang))
+                .chain(options.iter().map(|option| option.to_string()))
+                .collect();
+            if options.is_empty() {
+                w.write_str("\\begin{lstlisting}\n")?;
+            } else {
+                writeln!(w, "\\begin{{lstlisting}}[{}]", options.join(","))?;
+            }
         }
-        match arg.0 {
-            ArgNeed::MainArg => output = output + "{" + &tmp + "}",
-            ArgNeed::Optional => output = output + "[" + &tmp + "]",
-            ArgNeed::StarArg => output.push('*'),
+        CodeEnv::Minted => {
+            // minted needs a language, and `text` means no highlighting
+            let lang = lang.as_deref().unwrap_or("text");
+            if options.is_empty() {
+                writeln!(w, "\\begin{{minted}}{{{}}}", lang)?;
+            } else {
+                writeln!(w, "\\begin{{minted}}[{}]{{{}}}", options.join(","), lang)?;
+            }
         }
     }
-    for t in text {
-        output += &t.to_string();
+    w.write_str(code)?;
+    w.write_str(match env {
+        CodeEnv::Lstlisting => "\\end{lstlisting}\n",
+        CodeEnv::Minted => "\\end{minted}\n",
+    })
+}
+
+fn write_list<W: Write>(w: &mut W, kind: ListKind, items: &[Latex]) -> fmt::Result {
+    let env_name = match kind {
+        ListKind::Itemize => "itemize",
+        ListKind::Enumerate => "enumerate",
+    };
+    writeln!(w, "\\begin{{{}}}", env_name)?;
+    for item in items {
+        writeln!(w, "\\item {}", latex_to_string(item).trim_end())?;
     }
-    output = output + "\\end{" + name + "}\n";
-    output
+    writeln!(w, "\\end{{{}}}", env_name)
 }
 
-fn latex_to_string(latex: &Latex) -> String {
-    let mut output = String::new();
-    for l in latex {
-        output += &l.to_string();
+fn write_latex<W: Write>(w: &mut W, latex: &[Spanned<Statement>]) -> fmt::Result {
+    for stmt in latex {
+        write_statement(w, &stmt.node)?;
     }
+    Ok(())
+}
+
+pub fn latex_to_string(latex: &Latex) -> String {
+    let mut output = String::new();
+    // writing into a String never fails
+    let _ = write_latex(&mut output, latex);
     output
 }
+
+// Passes latex code to an `io::Write` and keeps the error of it, since
+// `fmt::Error` cannot tell why writing failed.
+pub(super) struct IoAdapter<'a, W> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> Write for IoAdapter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|err| {
+            self.error = Some(err);
+            fmt::Error
+        })
+    }
+}
+
+// Write latex code of a document piece by piece. Wrap files in a `BufWriter`,
+// since small pieces are written one by one.
+pub fn emit<W: io::Write>(latex: &Latex, writer: &mut W) -> io::Result<()> {
+    write_io(writer, |w| write_latex(w, latex))
+}
+
+// Formatters of other targets also stream through the adapter
+pub(super) fn write_io<W: io::Write>(
+    writer: &mut W,
+    write: impl FnOnce(&mut IoAdapter<'_, W>) -> fmt::Result,
+) -> io::Result<()> {
+    let mut adapter = IoAdapter {
+        inner: writer,
+        error: None,
+    };
+    write(&mut adapter).map_err(|_| {
+        adapter
+            .error
+            .take()
+            .unwrap_or_else(|| io::Error::other("cannot format latex"))
+    })
+}
@@ -0,0 +1,356 @@
+// Generating CommonMark from statements for documentation sites like mdBook
+// or Hugo. Math is passed through as `$...$` and `$$...$$` for MathJax or
+// KaTeX, and latex which has no markdown counterpart is kept as it is.
+
+use super::ast::*;
+use super::maker::latex_to_string;
+use std::fmt::Write;
+
+// Commands which are rendered as markdown headings
+const HEADINGS: &[(&str, usize)] = &[
+    ("part", 1),
+    ("chapter", 1),
+    ("section", 2),
+    ("subsection", 3),
+    ("subsubsection", 4),
+    ("paragraph", 5),
+    ("subparagraph", 6),
+];
+
+// Commands which only change the layout of a pdf
+const LAYOUT_COMMANDS: &[&str] = &[
+    "label",
+    "maketitle",
+    "newpage",
+    "clearpage",
+    "noindent",
+    "centering",
+    "vspace",
+    "hspace",
+    "smallskip",
+    "medskip",
+    "bigskip",
+    "vfill",
+    "hfill",
+];
+
+// Metadata which becomes the front matter
+const FRONT_MATTER: &[&str] = &["title", "author", "date"];
+
+const MATH_ENVIRONMENTS: &[&str] = &[
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "displaymath",
+];
+
+const QUOTE_ENVIRONMENTS: &[&str] = &["quote", "quotation", "verse"];
+
+pub fn latex_to_markdown(latex: &Latex) -> String {
+    let mut output = front_matter(latex);
+    let mut body = String::new();
+    write_markdown(&mut body, latex);
+    output += &collapse_blank_lines(body.trim());
+    output.push('\n');
+    output
+}
+
+fn front_matter(latex: &Latex) -> String {
+    let mut fields = Vec::new();
+    for stmt in latex {
+        if let Statement::LatexFunction { name, args } = &stmt.node {
+            if FRONT_MATTER.contains(name) {
+                if let Some(arg) = main_args(args).next() {
+                    let value = inline_markdown(arg);
+                    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                    fields.push(format!("{}: \"{}\"\n", name, value));
+                }
+            }
+        }
+    }
+    if fields.is_empty() {
+        String::new()
+    } else {
+        format!("---\n{}---\n\n", fields.concat())
+    }
+}
+
+fn main_args<'a, 's>(args: &'a [(ArgNeed, Latex<'s>)]) -> impl Iterator<Item = &'a Latex<'s>> {
+    args.iter()
+        .filter(|(need, _)| *need == ArgNeed::MainArg)
+        .map(|(_, arg)| arg)
+}
+
+// Markdown of a text which should be in one line like headings and cells
+fn inline_markdown(latex: &Latex) -> String {
+    let mut output = String::new();
+    write_markdown(&mut output, latex);
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn write_markdown(w: &mut String, latex: &[Spanned<Statement>]) {
+    for stmt in latex {
+        write_md_statement(w, &stmt.node);
+    }
+}
+
+fn write_md_statement(w: &mut String, stmt: &Statement) {
+    match stmt {
+        Statement::DocumentClass { .. }
+        | Statement::Usepackage { .. }
+        | Statement::MultiUsepackages { .. }
+        | Statement::DocumentStart
+        | Statement::DocumentEnd
+        | Statement::SetCounter { .. }
+        | Statement::TableOfContents { .. }
+        | Statement::MiniToc
+        | Statement::FunctionDefine { .. }
+        | Statement::EnvironmentDefine { .. }
+        | Statement::TheoremDefine { .. }
+        | Statement::FrontMatter { .. }
+        | Statement::InteractionMode(_)
+        | Statement::FileDirective(_)
+        | Statement::ImportBib { .. }
+        | Statement::BibStyle { .. }
+        | Statement::PrintBib { .. }
+        | Statement::EmbeddedFile { .. }
+        | Statement::ChapterImport { .. }
+        | Statement::ImportTex { .. }
+        | Statement::ImportModule { .. }
+        | Statement::Comment(_)
+        | Statement::Index(_)
+        | Statement::MakeIndex
+        | Statement::PrintIndex
+        | Statement::Pause => {}
+        Statement::MainText(text) => write_md_text(w, text),
+        Statement::Quantity { value, symbol, .. } => {
+            let _ = write!(w, "{} {}", value, symbol);
+        }
+        Statement::MathText { state, text } => {
+            let delimiter = match state {
+                MathState::Text => "$",
+                MathState::Inline => "$$",
+            };
+            let _ = write!(w, "{0}{1}{0}", delimiter, latex_to_string(text));
+        }
+        Statement::LatexFunction { name, args } => write_md_function(w, stmt, name, args),
+        Statement::Environment { name, text, .. } => write_md_environment(w, stmt, name, text),
+        Statement::Table { rows, .. } => write_md_table(w, rows),
+        Statement::Figure { path, caption, .. } => {
+            start_block(w);
+            let _ = writeln!(w, "![{}]({})", caption.as_deref().unwrap_or(""), path);
+            w.push('\n');
+        }
+        Statement::CodeBlock { lang, code, .. } => {
+            start_block(w);
+            let _ = writeln!(w, "```{}", lang.as_deref().unwrap_or(""));
+            w.push_str(code);
+            if !code.ends_with('\n') {
+                w.push('\n');
+            }
+            w.push_str("```\n\n");
+        }
+        Statement::List { kind, items } => write_md_list(w, *kind, items),
+        Statement::Heading { name, title, .. } => {
+            let name = name.trim_end_matches('*');
+            let level = HEADINGS
+                .iter()
+                .find(|(heading, _)| *heading == name)
+                .map_or(1, |(_, level)| *level);
+            start_block(w);
+            let _ = writeln!(w, "{} {}", "#".repeat(level), title);
+            w.push('\n');
+        }
+        // a slide becomes a section of the page
+        Statement::Frame { title, body, .. } => {
+            if let Some(title) = title {
+                start_block(w);
+                let _ = writeln!(w, "## {}", title);
+                w.push('\n');
+            }
+            write_markdown(w, body);
+        }
+        Statement::Conditional {
+            enabled,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            if *enabled {
+                write_markdown(w, then_branch)
+            } else {
+                write_markdown(w, else_branch)
+            }
+        }
+        // texts and latex which markdown cannot express are written as they are
+        _ => {
+            let _ = write!(w, "{}", stmt);
+        }
+    }
+}
+
+// Indentation of sources is removed since it makes code blocks in markdown
+fn write_md_text(w: &mut String, text: &str) {
+    let mut at_line_start = w.is_empty() || w.ends_with('\n');
+    for chr in text.chars() {
+        if at_line_start && (chr == ' ' || chr == '\t') {
+            continue;
+        }
+        at_line_start = chr == '\n';
+        w.push(chr);
+    }
+}
+
+fn write_md_function(w: &mut String, stmt: &Statement, name: &str, args: &[(ArgNeed, Latex)]) {
+    if let Some((_, level)) = HEADINGS.iter().find(|(heading, _)| *heading == name) {
+        if let Some(arg) = main_args(args).next() {
+            start_block(w);
+            let _ = writeln!(w, "{} {}", "#".repeat(*level), inline_markdown(arg));
+            w.push('\n');
+            return;
+        }
+    }
+    if LAYOUT_COMMANDS.contains(&name) || FRONT_MATTER.contains(&name) {
+        return;
+    }
+
+    let mut main_args = main_args(args);
+    let _ = match (name, main_args.next(), main_args.next()) {
+        ("textbf", Some(arg), None) => write!(w, "**{}**", inline_markdown(arg)),
+        ("textit" | "emph", Some(arg), None) => write!(w, "*{}*", inline_markdown(arg)),
+        ("texttt", Some(arg), None) => write!(w, "`{}`", latex_to_string(arg)),
+        ("url", Some(arg), None) => write!(w, "<{}>", latex_to_string(arg)),
+        ("href", Some(url), Some(text)) => {
+            write!(w, "[{}]({})", inline_markdown(text), latex_to_string(url))
+        }
+        _ => write!(w, "{}", stmt),
+    };
+}
+
+fn write_md_environment(w: &mut String, stmt: &Statement, name: &str, text: &Latex) {
+    if MATH_ENVIRONMENTS.contains(&name) {
+        start_block(w);
+        let _ = write!(w, "$$\n{}$$\n\n", stmt);
+    } else if QUOTE_ENVIRONMENTS.contains(&name) {
+        let mut inner = String::new();
+        write_markdown(&mut inner, text);
+        start_block(w);
+        for line in inner.trim().lines() {
+            let _ = writeln!(w, "> {}", line);
+        }
+        w.push('\n');
+    } else if name == "verbatim" {
+        start_block(w);
+        let _ = write!(
+            w,
+            "```\n{}\n```\n\n",
+            latex_to_string(text).trim_matches('\n')
+        );
+    } else {
+        // only the contents of layout environments like `center` remain
+        write_markdown(w, text);
+    }
+}
+
+fn write_md_table(w: &mut String, rows: &[TableRow]) {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .filter_map(|row| match row {
+            TableRow::Hline => None,
+            TableRow::Cells(cells) => Some(
+                cells
+                    .iter()
+                    .flat_map(|cell| {
+                        let text = inline_markdown(&cell.text).replace('|', "\\|");
+                        // a cell spanning several columns fills the rest with empty ones
+                        std::iter::once(text).chain(vec![String::new(); cell.span.max(1) - 1])
+                    })
+                    .collect(),
+            ),
+        })
+        .collect();
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    if columns == 0 {
+        return;
+    }
+
+    start_block(w);
+    for (i, row) in rows.iter().enumerate() {
+        let _ = write!(w, "|");
+        for col in 0..columns {
+            let _ = write!(w, " {} |", row.get(col).map_or("", String::as_str));
+        }
+        w.push('\n');
+        if i == 0 {
+            let _ = writeln!(w, "|{}", " --- |".repeat(columns));
+        }
+    }
+    w.push('\n');
+}
+
+fn write_md_list(w: &mut String, kind: ListKind, items: &[Latex]) {
+    start_block(w);
+    for (i, item) in items.iter().enumerate() {
+        let marker = match kind {
+            ListKind::Itemize => String::from("- "),
+            ListKind::Enumerate => format!("{}. ", i + 1),
+        };
+        let mut inner = String::new();
+        write_markdown(&mut inner, item);
+        // lines after the first one are indented to be in the same item
+        let indent = " ".repeat(marker.len());
+        for (j, line) in inner.trim().lines().enumerate() {
+            if j == 0 {
+                let _ = writeln!(w, "{}{}", marker, line);
+            } else if line.trim().is_empty() {
+                w.push('\n');
+            } else {
+                let _ = writeln!(w, "{}{}", indent, line);
+            }
+        }
+    }
+    w.push('\n');
+}
+
+// Blocks like headings and lists should start at a new line
+fn start_block(w: &mut String) {
+    if !w.is_empty() && !w.ends_with('\n') {
+        w.push('\n');
+    }
+}
+
+// Lines in code blocks are kept as they are
+fn collapse_blank_lines(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut blank_lines = 0;
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.starts_with("```") {
+            in_code = !in_code;
+        } else if in_code {
+            output += line;
+            output.push('\n');
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        output += line;
+        output.push('\n');
+    }
+    output.truncate(output.trim_end().len());
+    output
+}
@@ -1,20 +1,79 @@
 #[macro_use]
 mod macros;
+pub mod arena;
+pub mod argspec;
 pub mod ast;
+pub mod balance;
+pub mod context;
 pub mod maker;
+pub mod markdown;
 #[cfg(test)]
 mod parser_test;
 
+use crate::cache::BlockCache;
+use crate::config::VestiConfig;
+use crate::embed;
+use crate::engine::{InteractionMode, LatexEngineType};
 use crate::error::err_kind::VestiParseErr::BracketMismatchErr;
 use crate::error::err_kind::{VestiErrKind, VestiParseErr};
+use crate::error::warning::{AllowedRegion, VestiWarnKind, VestiWarning, LINT_NAMES};
 use crate::error::{self, VestiErr};
-use crate::lexer::token::TokenType;
+use crate::headings::HeadingConfig;
+use crate::lexer::token::{self, TokenType};
 use crate::lexer::{LexToken, Lexer};
-use crate::location::Span;
+use crate::location::{Location, Span};
+use crate::packages;
+use crate::symbols;
+use crate::units;
+use arena::{Arena, Interner, Symbol};
 use ast::*;
 use bitflags::bitflags;
+use maker::latex_to_string;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 const ENV_MATH_IDENT: [&str; 4] = ["equation", "align", "array", "eqnarray"];
+// `begenv autoalign` becomes an align environment whose alignment points are made automatically
+const AUTO_ALIGN_ENV: &str = "autoalign";
+const ALIGN_RELATIONS: [&str; 5] = ["=", "<", ">", "<=", ">="];
+// Log-like functions which latex already defines
+const MATH_OPERATORS: [&str; 32] = [
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "deg", "det", "dim",
+    "exp", "gcd", "hom", "inf", "ker", "lg", "lim", "liminf", "limsup", "ln", "log", "max", "min",
+    "Pr", "sec", "sin", "sinh", "sup", "tan", "tanh",
+];
+// Arguments of these functions are texts even in math mode
+const TEXT_FUNCTIONS: [&str; 16] = [
+    "text",
+    "textrm",
+    "textit",
+    "textbf",
+    "textsf",
+    "texttt",
+    "mbox",
+    "operatorname",
+    "mathrm",
+    "mathit",
+    "mathbf",
+    "mathsf",
+    "mathtt",
+    "label",
+    "ref",
+    "eqref",
+];
+// Functions which refer labels. Some of them take several labels separated by commas.
+const REF_FUNCTIONS: [&str; 9] = [
+    "ref", "eqref", "pageref", "autoref", "nameref", "vref", "cref", "Cref", "cpageref",
+];
+const DOCCLASS_WITHOUT_CHAPTER: [&str; 7] = [
+    "article",
+    "scrartcl",
+    "amsart",
+    "beamer",
+    "letter",
+    "standalone",
+    "minimal",
+];
 
 bitflags! {
     struct DocState: u8 {
@@ -29,29 +88,394 @@ impl DocState {
     }
 }
 
+// What statements of the document body need to know from the preamble, so
+// that a part of the body can be parsed alone
+#[derive(Clone, Debug, Default)]
+pub struct DocumentContext {
+    docclass: Option<String>,
+    packages: Vec<String>,
+    functions: Vec<(String, usize)>,
+    theorems: Vec<String>,
+}
+
+// Functions and labels which a file defines. Vesti files which other files
+// import share these, so that their calls and references are checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Definitions {
+    // numbers of mandatory parameters of functions defined by `defun`
+    pub functions: BTreeMap<String, usize>,
+    pub labels: BTreeSet<String>,
+}
+
+impl Definitions {
+    // Definitions which are found first are kept
+    pub fn merge(&mut self, other: &Definitions) {
+        for (name, params) in &other.functions {
+            self.functions.entry(name.clone()).or_insert(*params);
+        }
+        self.labels.extend(other.labels.iter().cloned());
+    }
+
+    // Definitions of a module imported with the prefix
+    pub fn with_prefix(&self, prefix: &str) -> Definitions {
+        Definitions {
+            functions: self
+                .functions
+                .iter()
+                .map(|(name, params)| (format!("{}{}", prefix, name), *params))
+                .collect(),
+            labels: self.labels.clone(),
+        }
+    }
+}
+
 pub struct Parser<'a> {
     source: Lexer<'a>,
-    peek_tok: Option<LexToken>,
+    // strings of statements which are not borrowed from the source
+    strings: Interner<'a>,
+    peek_tok: Option<LexToken<'a>>,
     document_state: DocState,
+    // Names which are looked up often are kept as symbols of `strings`
+    defines: HashSet<Symbol>,
+    // operators from `vesti.toml` which become `\operatorname{...}` in math mode
+    math_operators: HashSet<Symbol>,
+    headings: HeadingConfig,
+    // write every `defenv` as `\NewDocumentEnvironment`
+    xparse_environments: bool,
+    // depth of texts in math mode like `mtxt ... etxt` where operators are not prefixed
+    text_in_math_depth: usize,
+    block_cache: BlockCache,
+    // warn every raw latex to help migrating documents into vesti
+    strict_raw: bool,
+    // import packages of well known commands which the document uses
+    auto_import: bool,
+    // files outside of the directory of the source cannot be read
+    safe: bool,
+    docclass: Option<String>,
+    packages: Vec<Symbol>,
+    // packages needed by the latex code vesti makes, which are not imported by users
+    required_packages: BTreeSet<String>,
+    bibliography: Option<(String, BibBackend)>,
+    bib_style: Option<(String, BibBackend)>,
+    is_bib_printed: bool,
+    warnings: Vec<VestiWarning>,
+    // lints which are allowed by attributes of statements being parsed
+    allowed_lints: Vec<String>,
+    // statements with attributes, so that lints after parsing can see them
+    allowed_regions: Vec<AllowedRegion>,
+    labels: HashSet<Symbol>,
+    // labels defined by imported vesti files
+    imported_labels: HashSet<Symbol>,
+    // numbers of parameters of functions defined by `defun`
+    functions: HashMap<Symbol, usize>,
+    // theorems declared by `deftheorem`
+    theorems: HashSet<Symbol>,
+    // `title` and `date` which the front matter already has
+    front_matter: HashSet<&'static str>,
+    // `maketitle` in the front matter puts `\maketitle` after `\begin{document}`
+    maketitle: bool,
+    // depth of environments in the document, where definitions are local
+    local_scope_depth: usize,
+    // math font keyword like `bb` whose argument is being parsed
+    math_font: Option<&'a str>,
+    // references are checked after parsing since labels can be defined later
+    references: Vec<(Symbol, Option<Span>)>,
+    // indentation width of the current line where a tab counts as four spaces
+    line_indent: usize,
+    is_indent_counting: bool,
+    // The end of the last token taken and of the last one which is not a whitespace,
+    // with the number of such tokens. Spans of statements end at these.
+    last_end: (usize, Location),
+    last_text_end: (usize, Location),
 }
 
 impl<'a> Parser<'a> {
+    /// Make a parser which reads tokens from `source`. Strings of the syntax
+    /// tree are allocated in `arena`, so the tree lives as long as it.
     // Store Parser in the heap
-    pub fn new(source: Lexer<'a>) -> Box<Self> {
+    pub fn new(source: Lexer<'a>, arena: &'a Arena) -> Box<Self> {
         let mut output = Box::new(Self {
             source,
+            strings: Interner::new(arena),
             peek_tok: None,
             document_state: DocState::new(),
+            defines: HashSet::new(),
+            math_operators: HashSet::new(),
+            headings: HeadingConfig::default(),
+            xparse_environments: false,
+            text_in_math_depth: 0,
+            block_cache: BlockCache::disabled(),
+            strict_raw: false,
+            auto_import: true,
+            safe: false,
+            docclass: None,
+            packages: Vec::new(),
+            required_packages: BTreeSet::new(),
+            bibliography: None,
+            bib_style: None,
+            is_bib_printed: false,
+            warnings: Vec::new(),
+            allowed_lints: Vec::new(),
+            allowed_regions: Vec::new(),
+            labels: HashSet::new(),
+            imported_labels: HashSet::new(),
+            functions: HashMap::new(),
+            theorems: HashSet::new(),
+            front_matter: HashSet::new(),
+            maketitle: false,
+            local_scope_depth: 0,
+            math_font: None,
+            references: Vec::new(),
+            line_indent: 0,
+            is_indent_counting: true,
+            last_end: (0, Location::default()),
+            last_text_end: (0, Location::default()),
         });
         output.next_tok();
 
         output
     }
 
-    fn next_tok(&mut self) -> Option<LexToken> {
+    // Set flags which are turned on at `#if` directives.
+    pub fn set_defines<T: AsRef<str>>(&mut self, defines: &[T]) {
+        self.defines = defines
+            .iter()
+            .map(|d| self.strings.symbol(d.as_ref()))
+            .collect();
+    }
+
+    // Set operators which are written without a backslash in math mode.
+    pub fn set_math_operators<T: AsRef<str>>(&mut self, operators: &[T]) {
+        self.math_operators = operators
+            .iter()
+            .map(|o| self.strings.symbol(o.as_ref()))
+            .collect();
+    }
+
+    // Set how labels of headings like `section "Title"` are made.
+    pub fn set_heading_config(&mut self, config: &HeadingConfig) {
+        self.headings = config.clone();
+    }
+
+    // Set whether environments without argument specifications are lowered
+    // into `\NewDocumentEnvironment` too.
+    pub fn set_xparse_environments(&mut self, xparse_environments: bool) {
+        self.xparse_environments = xparse_environments;
+    }
+
+    // Set whether `$...$` and `$$...$$` are math. The first token is taken by
+    // `new` already, so the source is lexed again.
+    pub fn set_dollar_math(&mut self, dollar_math: bool) {
+        self.source.set_dollar_math(dollar_math);
+        self.peek_tok = self.source.next();
+    }
+
+    // Set the cache of expensive blocks like embedded files.
+    pub fn set_block_cache(&mut self, cache: BlockCache) {
+        self.block_cache = cache;
+    }
+
+    pub fn set_strict_raw(&mut self, strict_raw: bool) {
+        self.strict_raw = strict_raw;
+    }
+
+    // Functions and labels which this file defines. This should be called
+    // after parsing.
+    pub fn definitions(&self) -> Definitions {
+        Definitions {
+            functions: self
+                .functions
+                .iter()
+                .map(|(name, params)| (self.strings.resolve(*name).to_string(), *params))
+                .collect(),
+            labels: self
+                .labels
+                .iter()
+                .map(|label| self.strings.resolve(*label).to_string())
+                .collect(),
+        }
+    }
+
+    // Definitions of imported vesti files. Functions which the file defines
+    // again are checked by their new parameters.
+    pub fn set_imports(&mut self, imports: &Definitions) {
+        for (name, params) in &imports.functions {
+            let name = self.strings.symbol(name);
+            self.functions.insert(name, *params);
+        }
+        for label in &imports.labels {
+            let label = self.strings.symbol(label);
+            self.imported_labels.insert(label);
+        }
+    }
+
+    pub fn set_auto_import(&mut self, auto_import: bool) {
+        self.auto_import = auto_import;
+    }
+
+    pub fn set_safe(&mut self, safe: bool) {
+        self.safe = safe;
+    }
+
+    // The document class and packages imported by the preamble. This should
+    // be called after `parse_latex`.
+    pub fn document_context(&self) -> DocumentContext {
+        DocumentContext {
+            docclass: self.docclass.clone(),
+            packages: self
+                .packages
+                .iter()
+                .map(|pkg| self.strings.resolve(*pkg).to_string())
+                .collect(),
+            functions: self
+                .functions
+                .iter()
+                .map(|(name, params)| (self.strings.resolve(*name).to_string(), *params))
+                .collect(),
+            theorems: self
+                .theorems
+                .iter()
+                .map(|name| self.strings.resolve(*name).to_string())
+                .collect(),
+        }
+    }
+
+    pub fn set_document_context(&mut self, context: &DocumentContext) {
+        self.docclass = context.docclass.clone();
+        self.packages = context
+            .packages
+            .iter()
+            .map(|pkg| self.strings.symbol(pkg))
+            .collect();
+        for (name, params) in &context.functions {
+            let name = self.strings.symbol(name);
+            self.functions.insert(name, *params);
+        }
+        self.theorems = context
+            .theorems
+            .iter()
+            .map(|name| self.strings.symbol(name))
+            .collect();
+    }
+
+    // Packages which statements from `parse_body` need but the document does
+    // not import. The main file imports them for chapters.
+    pub fn missing_packages(&self, latex: &Latex) -> BTreeSet<String> {
+        let mut required = self.required_packages.clone();
+        if self.auto_import {
+            required.extend(packages::packages_used_by(latex));
+        }
+        required.retain(|name| !self.has_package(name));
+        required
+    }
+
+    // Absolute paths and paths with `..` can read any file of the machine
+    fn check_safe_path(&self, path: &str, location: Option<Span>) -> error::Result<()> {
+        if self.safe && !embed::is_valid_embed_path(path) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::UnsafePathErr {
+                    path: path.to_string(),
+                },
+                location,
+            ));
+        }
+        Ok(())
+    }
+
+    // Warnings found while parsing. This should be called after `parse_latex`.
+    pub fn take_warnings(&mut self) -> Vec<VestiWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Statements with `#[allow(...)]` and the lints they allow
+    pub fn take_allowed_regions(&mut self) -> Vec<AllowedRegion> {
+        std::mem::take(&mut self.allowed_regions)
+    }
+
+    fn is_allowed(&self, lint_name: &str) -> bool {
+        self.allowed_lints.iter().any(|lint| lint == lint_name)
+    }
+
+    // A name which is not interned yet cannot be in any set of symbols
+    fn contains_name(&self, symbols: &HashSet<Symbol>, name: &str) -> bool {
+        self.strings
+            .get(name)
+            .is_some_and(|sym| symbols.contains(&sym))
+    }
+
+    fn has_package(&self, name: &str) -> bool {
+        self.strings
+            .get(name)
+            .is_some_and(|sym| self.packages.contains(&sym))
+    }
+
+    // A package which a keyword needs is imported for it unless automatic
+    // imports are turned off by `--no-auto-import`
+    fn require_package(&mut self, name: &str, location: Option<Span>) -> error::Result<()> {
+        if self.has_package(name) {
+            return Ok(());
+        }
+        if !self.auto_import {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::PackageIsNotImportedErr {
+                    name: String::from(name),
+                },
+                location,
+            ));
+        }
+        self.required_packages.insert(String::from(name));
+        Ok(())
+    }
+
+    fn warn(&mut self, warn_kind: VestiWarnKind, location: Option<Span>) {
+        if !self.is_allowed(warn_kind.lint_name()) {
+            self.warnings.push(VestiWarning {
+                warn_kind,
+                location,
+            });
+        }
+    }
+
+    // Strings made by the parser are interned, and literals which borrow the
+    // source are used as they are
+    fn alloc(&mut self, s: &str) -> &'a str {
+        self.strings.intern(s)
+    }
+
+    fn alloc_literal(&mut self, literal: Cow<'a, str>) -> &'a str {
+        match literal {
+            Cow::Borrowed(s) => s,
+            Cow::Owned(s) => self.strings.intern(&s),
+        }
+    }
+
+    fn next_tok(&mut self) -> Option<LexToken<'a>> {
         let curr_tok = self.peek_tok.take();
         self.peek_tok = self.source.next();
 
+        if let Some(tok) = &curr_tok {
+            let end = (self.last_end.0 + 1, tok.span.end);
+            self.last_end = end;
+            if !matches!(
+                tok.token.toktype,
+                TokenType::Space | TokenType::Tab | TokenType::Newline
+            ) {
+                self.last_text_end = (self.last_text_end.0 + 1, tok.span.end);
+            }
+        }
+
+        match curr_tok.as_ref().map(|tok| tok.token.toktype) {
+            Some(TokenType::Newline) => {
+                self.line_indent = 0;
+                self.is_indent_counting = true;
+            }
+            Some(TokenType::Space) if self.is_indent_counting => self.line_indent += 1,
+            Some(TokenType::Tab) if self.is_indent_counting => self.line_indent += 4,
+            // the first token of the source is at the start of a line
+            None => {}
+            _ => self.is_indent_counting = false,
+        }
+
         curr_tok
     }
 
@@ -76,148 +500,1892 @@ impl<'a> Parser<'a> {
         }
     }
 
-    pub fn make_latex_format(&mut self) -> error::Result<String> {
-        let latex = self.parse_latex()?;
-        let mut output = String::new();
+    /// Parse the whole document and generate its LaTeX code.
+    pub fn make_latex_format(&mut self) -> error::Result<String> {
+        let latex = self.parse_latex()?;
+
+        Ok(latex_to_string(&latex))
+    }
+
+    /// Parse the whole document into statements with their spans.
+    pub fn parse_latex(&mut self) -> error::Result<Latex<'a>> {
+        let mut latex: Latex = Vec::new();
+        while self.peek_tok().is_some() {
+            self.check_text_before_document()?;
+            self.check_preamble_keyword()?;
+            latex.push(self.parse_statement()?);
+        }
+        if self.document_state == DocState::DOC_START {
+            // The bibliography is printed at the end unless `printbib` is used
+            if let (Some((path, backend)), false) = (&self.bibliography, self.is_bib_printed) {
+                latex.push(
+                    Statement::PrintBib {
+                        path: self.strings.intern(path),
+                        backend: *backend,
+                    }
+                    .into(),
+                );
+            }
+            latex.push(Statement::DocumentEnd.into());
+        }
+        if self.maketitle {
+            if let Some(doc_start) = latex
+                .iter()
+                .position(|stmt| stmt.node == Statement::DocumentStart)
+            {
+                latex.insert(doc_start + 1, Statement::RawLatex("\\maketitle\n").into());
+            }
+        }
+        if self.auto_import {
+            let used = packages::packages_used_by(&latex);
+            self.required_packages.extend(used);
+        }
+        // `minitoc` can be used after the table of contents, which needs
+        // `\dominitoc` if the package is imported for it
+        if self.required_packages.contains("minitoc") {
+            walk_latex_mut(&mut latex, &mut |stmt| {
+                if let Statement::TableOfContents { minitoc } = stmt {
+                    *minitoc = true;
+                }
+            });
+        }
+        merge_usepackages(&mut latex);
+        self.assemble_preamble(&mut latex);
+        if has_index_entries(&latex) {
+            insert_index_commands(&mut latex);
+        }
+        self.check_references();
+
+        Ok(latex)
+    }
+
+    // Parse statements of the document body without what is made after a whole
+    // file is parsed, like the end of the document and imports of used packages.
+    // Labels and references are not checked either, since they can be outside.
+    pub fn parse_body(&mut self) -> error::Result<Latex<'a>> {
+        self.document_state |= DocState::DOC_START | DocState::PREVENT_END_DOC;
+        let mut latex: Latex = Vec::new();
+        while self.peek_tok().is_some() {
+            self.check_preamble_keyword()?;
+            latex.push(self.parse_statement()?);
+        }
+        Ok(latex)
+    }
+
+    // Packages which vesti needs are added right before `document` after the
+    // whole file is parsed. They are sorted by their names, so the preamble does
+    // not change when statements using them are moved or added. `\newtheorem`
+    // should be after amsthm, so they are added before theorems if there are.
+    fn assemble_preamble(&mut self, latex: &mut Latex<'a>) {
+        let doc_start = match latex.iter().position(|stmt| {
+            matches!(
+                stmt.node,
+                Statement::DocumentStart | Statement::TheoremDefine { .. }
+            )
+        }) {
+            Some(doc_start) => doc_start,
+            None => return,
+        };
+        let missing: Vec<&str> = self
+            .required_packages
+            .iter()
+            .map(String::as_str)
+            .filter(|name| !self.has_package(name))
+            .collect();
+        let strings = &mut self.strings;
+        let pkgs: Latex = missing
+            .into_iter()
+            .map(|name| {
+                Statement::Usepackage {
+                    name: strings.intern(name),
+                    options: None,
+                }
+                .into()
+            })
+            .collect();
+        if !pkgs.is_empty() {
+            latex.insert(doc_start, Statement::MultiUsepackages { pkgs }.into());
+        }
+    }
+
+    // References to labels which are not defined anywhere in the file or the
+    // vesti files which it includes. Labels made by raw latex count, but ones
+    // of latex files cannot be known.
+    fn check_references(&mut self) {
+        for (label, location) in std::mem::take(&mut self.references) {
+            if !self.labels.contains(&label) && !self.imported_labels.contains(&label) {
+                let label = self.strings.resolve(label).to_string();
+                self.warnings.push(VestiWarning {
+                    warn_kind: VestiWarnKind::UndefinedReference { label },
+                    location,
+                });
+            }
+        }
+    }
+
+    // Texts in the preamble make latex errors which are hard to understand.
+    // Note that this should be checked only for top level statements since
+    // function arguments in the preamble can have texts.
+    fn check_text_before_document(&mut self) -> error::Result<()> {
+        if self.document_state.contains(DocState::DOC_START) {
+            return Ok(());
+        }
+        match &self.peek_tok {
+            Some(LexToken { token, span })
+                if matches!(
+                    token.toktype,
+                    TokenType::MainString
+                        | TokenType::Integer
+                        | TokenType::Float
+                        | TokenType::Dimension
+                ) =>
+            {
+                Err(VestiErr::make_parse_err(
+                    VestiParseErr::TextBeforeDocumentErr {
+                        text: token.literal.to_string(),
+                    },
+                    Some(*span),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Keywords of the preamble at the start of a line in the body used to be
+    // written as texts, and latex failed later with a confusing message.
+    // Keywords inside of a line are texts, since they are common words.
+    fn check_preamble_keyword(&self) -> error::Result<()> {
+        if !self.document_state.contains(DocState::DOC_START) {
+            return Ok(());
+        }
+        let Some(LexToken { token, span }) = &self.peek_tok else {
+            return Ok(());
+        };
+        if span.start.column() != 1 {
+            return Ok(());
+        }
+        let err_kind = match token.toktype {
+            TokenType::Document => VestiParseErr::DuplicatedDocumentErr,
+            toktype @ (TokenType::Docclass
+            | TokenType::Import
+            | TokenType::Importbib
+            | TokenType::Bibstyle
+            | TokenType::Deftheorem
+            | TokenType::Batchmode
+            | TokenType::Nonstopmode
+            | TokenType::Errorstopmode) => VestiParseErr::AfterDocumentErr { got: toktype },
+            _ => return Ok(()),
+        };
+        Err(VestiErr::make_parse_err(err_kind, Some(*span)))
+    }
+
+    fn parse_statement(&mut self) -> error::Result<Spanned<Statement<'a>>> {
+        self.parse_spanned(Self::parse_statement_node)
+    }
+
+    // A statement made by `parse` with its span. Whitespaces after the statement
+    // are not in the span unless the statement has only whitespaces.
+    fn parse_spanned<F>(&mut self, parse: F) -> error::Result<Spanned<Statement<'a>>>
+    where
+        F: FnOnce(&mut Self) -> error::Result<Statement<'a>>,
+    {
+        let start = self.peek_tok_location();
+        let (taken, text_taken) = (self.last_end.0, self.last_text_end.0);
+        let node = parse(self)?;
+
+        let span = match start {
+            Some(start) => Span {
+                start: start.start,
+                end: if self.last_text_end.0 > text_taken {
+                    self.last_text_end.1
+                } else if self.last_end.0 > taken {
+                    self.last_end.1
+                } else {
+                    start.start
+                },
+            },
+            None => Span::default(),
+        };
+        Ok(Spanned::new(node, span))
+    }
+
+    // The span from `start` to the last token taken which is not a whitespace
+    fn span_since(&self, start: Option<Span>) -> Span {
+        start.map_or_else(Span::default, |start| Span {
+            start: start.start,
+            end: self.last_text_end.1,
+        })
+    }
+
+    fn parse_statement_node(&mut self) -> error::Result<Statement<'a>> {
+        let is_doc_start = (self.document_state & DocState::DOC_START).bits();
+        match self.peek_tok() {
+            // Keywords
+            Some(TokenType::Docclass) if is_doc_start == 0 => self.parse_docclass(),
+            Some(TokenType::Import) if is_doc_start == 0 => self.parse_usepackage(),
+            Some(TokenType::Document) if is_doc_start == 0 => {
+                self.document_state |= DocState::DOC_START;
+                self.next_tok();
+                self.eat_whitespaces(true);
+                Ok(Statement::DocumentStart)
+            }
+            Some(TokenType::Tocdepth | TokenType::Secnumdepth) => self.parse_counter_depth(),
+            Some(
+                toktype
+                @ (TokenType::Batchmode | TokenType::Nonstopmode | TokenType::Errorstopmode),
+            ) if is_doc_start == 0 => {
+                self.next_tok();
+                self.eat_whitespaces(true);
+                Ok(Statement::InteractionMode(match toktype {
+                    TokenType::Batchmode => InteractionMode::Batchmode,
+                    TokenType::Nonstopmode => InteractionMode::Nonstopmode,
+                    _ => InteractionMode::Errorstopmode,
+                }))
+            }
+            Some(TokenType::Maketoc) if is_doc_start != 0 => self.parse_maketoc(),
+            Some(TokenType::Minitoc) if is_doc_start != 0 => self.parse_minitoc(),
+            Some(TokenType::Begtbl) if is_doc_start != 0 => self.parse_table(),
+            Some(TokenType::Importimg) if is_doc_start != 0 => self.parse_figure(),
+            Some(TokenType::Codeblock) if is_doc_start != 0 => self.parse_codeblock(),
+            Some(TokenType::Execute) if is_doc_start != 0 => self.parse_execute(),
+            Some(TokenType::ChapterImport) if is_doc_start != 0 => self.parse_chapter_import(),
+            Some(TokenType::Importtex) => self.parse_importtex(),
+            Some(TokenType::Frame) if is_doc_start != 0 => self.parse_frame(),
+            Some(TokenType::Heading) if is_doc_start != 0 => self.parse_heading(),
+            Some(TokenType::Index) if is_doc_start != 0 => self.parse_index(),
+            Some(TokenType::Pause) if is_doc_start != 0 => {
+                self.next_tok();
+                Ok(Statement::Pause)
+            }
+            Some(TokenType::Importbib) if is_doc_start == 0 => self.parse_importbib(),
+            Some(TokenType::Bibstyle) if is_doc_start == 0 => self.parse_bibstyle(),
+            Some(TokenType::Defun) if self.can_define() => self.parse_function_define(None),
+            Some(TokenType::Defenv) if self.can_define() => self.parse_environment_define(None),
+            Some(TokenType::Deftheorem) if is_doc_start == 0 => self.parse_theorem_define(),
+            Some(TokenType::Metadata | TokenType::Maketitle) if is_doc_start == 0 => {
+                self.parse_front_matter()
+            }
+            Some(TokenType::Maketitle) => {
+                self.next_tok();
+                Ok(Statement::RawLatex("\\maketitle"))
+            }
+            Some(TokenType::MainString) if is_doc_start != 0 && self.is_theorem_start() => {
+                self.parse_theorem()
+            }
+            Some(TokenType::DocComment) => self.parse_doc_comment(),
+            Some(TokenType::Printbib) if is_doc_start != 0 => self.parse_printbib(),
+            Some(TokenType::CiteStart) if is_doc_start != 0 => self.parse_cite(),
+            Some(TokenType::Endtbl) => Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidTokToParse {
+                    got: TokenType::Endtbl,
+                },
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::ItemizeMarker | TokenType::EnumerateMarker) if is_doc_start != 0 => {
+                self.parse_list()
+            }
+            Some(TokenType::Embedfile) => self.parse_embedded_file(),
+            Some(TokenType::Begenv) => self.parse_environment(),
+            Some(TokenType::Endenv) => Err(VestiErr::make_parse_err(
+                VestiParseErr::EndenvIsUsedWithoutBegenvPairErr,
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::Mtxt) => self.parse_text_in_math(),
+            Some(TokenType::LeftBig) => self.parse_math_delimiter(),
+            Some(TokenType::UnicodeSymbol) => self.parse_unicode_symbol(),
+            Some(TokenType::RightBig) => Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidTokToParse {
+                    got: TokenType::RightBig,
+                },
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::Etxt) => Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidTokToParse {
+                    got: TokenType::Etxt,
+                },
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::FileDirective) => self.parse_file_directive(),
+            Some(TokenType::PreIf) => self.parse_conditional(),
+            Some(toktype @ (TokenType::PreElse | TokenType::PreEndif)) => {
+                Err(VestiErr::make_parse_err(
+                    VestiParseErr::DirectiveIsUsedWithoutIfPairErr { got: toktype },
+                    self.peek_tok_location(),
+                ))
+            }
+            Some(TokenType::DocumentStartMode) => {
+                self.document_state |= DocState::PREVENT_END_DOC | DocState::DOC_START;
+                let loc = self.next_tok().map(|lex_tok| lex_tok.span);
+                expect_peek!(self | TokenType::Newline, TokenType::Newline2; loc);
+                self.parse_statement_node()
+            }
+
+            // Identifiers
+            Some(TokenType::LatexFunction) => self.parse_latex_function(),
+            Some(TokenType::RawLatex | TokenType::RawLatexBlock) => self.parse_raw_latex(),
+            Some(TokenType::Attribute) => self.parse_attribute(),
+            Some(TokenType::Comment) => {
+                let comment = self.next_tok().unwrap();
+                Ok(Statement::Comment(
+                    self.alloc_literal(comment.token.literal),
+                ))
+            }
+            Some(TokenType::UnclosedRawLatexBlock) => Err(VestiErr::make_parse_err(
+                VestiParseErr::RawLatexBlockIsNotClosedErr,
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::Integer) => self.parse_integer(),
+            Some(TokenType::Float) => self.parse_float(),
+            Some(TokenType::Dimension) => self.parse_dimension(),
+            Some(toktype) if toktype.should_not_use_before_doc() && is_doc_start == 0 => {
+                Err(VestiErr::make_parse_err(
+                    VestiParseErr::BeforeDocumentErr { got: toktype },
+                    self.peek_tok_location(),
+                ))
+            }
+
+            // Math related tokens
+            Some(TokenType::MathFont) if self.text_in_math_depth == 0 => self.parse_math_font(),
+            Some(TokenType::Quantity) => self.parse_quantity(),
+            Some(TokenType::TextMathStart) => self.parse_math_stmt(),
+            Some(TokenType::InlineMathStart) => self.parse_math_stmt(),
+            Some(TokenType::Superscript | TokenType::Subscript)
+                if !self.source.math_started && is_doc_start != 0 =>
+            {
+                self.parse_scripts()
+            }
+
+            Some(TokenType::TextMathEnd) => Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidTokToParse {
+                    got: TokenType::TextMathEnd,
+                },
+                self.peek_tok_location(),
+            )),
+            Some(TokenType::InlineMathEnd) => Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidTokToParse {
+                    got: TokenType::InlineMathEnd,
+                },
+                self.peek_tok_location(),
+            )),
+
+            Some(TokenType::ILLEGAL) => Err(VestiErr::make_parse_err(
+                VestiParseErr::IllegalCharacterFoundErr,
+                self.peek_tok_location(),
+            )),
+
+            _ => self.parse_main_stmt(),
+        }
+    }
+
+    fn parse_integer(&mut self) -> error::Result<Statement<'a>> {
+        let curr_tok = self.next_tok().unwrap();
+        let output = if let Ok(int) = curr_tok.token.literal.parse() {
+            int
+        } else {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::ParseIntErr,
+                Some(curr_tok.span),
+            ));
+        };
+
+        Ok(Statement::Integer(output))
+    }
+
+    fn parse_float(&mut self) -> error::Result<Statement<'a>> {
+        let curr_tok = self.next_tok().unwrap();
+        let output = if let Ok(float) = curr_tok.token.literal.parse() {
+            float
+        } else {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::ParseFloatErr,
+                Some(curr_tok.span),
+            ));
+        };
+
+        Ok(Statement::Float(output))
+    }
+
+    fn parse_dimension(&mut self) -> error::Result<Statement<'a>> {
+        let curr_tok = self.next_tok().unwrap();
+        let literal = self.alloc_literal(curr_tok.token.literal);
+        let unit_start = literal
+            .find(|chr: char| chr.is_alphabetic() || chr == '\\')
+            .unwrap_or(literal.len());
+        let (value, unit) = literal.split_at(unit_start);
+
+        Ok(Statement::Dimension { value, unit })
+    }
+
+    fn parse_raw_latex(&mut self) -> error::Result<Statement<'a>> {
+        let raw_tok = self.next_tok().unwrap();
+        if self.strict_raw {
+            self.warn(VestiWarnKind::RawLatex, Some(raw_tok.span));
+        }
+        for label in labels_in_raw_latex(&raw_tok.token.literal) {
+            self.labels.insert(self.strings.symbol(label));
+        }
+        Ok(Statement::RawLatex(
+            self.alloc_literal(raw_tok.token.literal),
+        ))
+    }
+
+    // Parse a string like "foo bar" into its literal. Escaped characters are
+    // written as they are in the latex code, for example, `%` becomes `\%`.
+    fn parse_string_literal(&mut self) -> error::Result<String> {
+        let open_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Doublequote; open_location);
+
+        let mut output = String::new();
+        while self.peek_tok() != Some(TokenType::Doublequote) {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    if tok.token.toktype == TokenType::LatexFunction {
+                        output.push('\\');
+                    }
+                    output += &tok.token.literal;
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketMismatchErr {
+                            expected: TokenType::Doublequote,
+                        },
+                        open_location,
+                    ))
+                }
+            }
+        }
+        expect_peek!(self | TokenType::Doublequote; self.peek_tok_location());
+
+        Ok(output)
+    }
+
+    // Lines starting with `-` or `1.` make a list. Lines which are more indented than
+    // the list marker are either a nested list or a continuation of the item.
+    // A blank line or a less indented line finishes the list.
+    fn parse_list(&mut self) -> error::Result<Statement<'a>> {
+        let indent = self.line_indent;
+        let marker = self.peek_tok();
+        let kind = if marker == Some(TokenType::ItemizeMarker) {
+            ListKind::Itemize
+        } else {
+            ListKind::Enumerate
+        };
+        let mut items: Vec<Latex> = Vec::new();
+
+        while self.peek_tok() == marker && self.line_indent == indent {
+            self.next_tok();
+            self.eat_whitespaces(false);
+
+            let mut item: Latex = Vec::new();
+            let mut is_line_start = false;
+            loop {
+                if is_line_start {
+                    is_line_start = false;
+                    match self.peek_tok() {
+                        Some(TokenType::ItemizeMarker | TokenType::EnumerateMarker)
+                            if self.line_indent > indent =>
+                        {
+                            item.push(Statement::MainText("\n").into());
+                            item.push(self.parse_spanned(Self::parse_list)?);
+                            is_line_start = true;
+                            continue;
+                        }
+                        Some(TokenType::Newline) | None => break,
+                        Some(_) if self.line_indent > indent => {
+                            item.push(Statement::MainText("\n").into());
+                        }
+                        // `pause` between items of beamer keeps the list
+                        Some(TokenType::Pause) => {
+                            item.push(Statement::MainText("\n").into());
+                        }
+                        Some(_) => break,
+                    }
+                }
+                match self.peek_tok() {
+                    None => break,
+                    Some(TokenType::Newline) => {
+                        self.next_tok();
+                        self.eat_whitespaces(false);
+                        is_line_start = true;
+                    }
+                    Some(_) => item.push(self.parse_statement()?),
+                }
+            }
+            items.push(item);
+        }
+
+        Ok(Statement::List { kind, items })
+    }
+
+    fn parse_embedded_file(&mut self) -> error::Result<Statement<'a>> {
+        let embed_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Embedfile; embed_location);
+        self.eat_whitespaces(false);
+
+        let name_location = self.peek_tok_location();
+        let name = self.parse_string_literal()?;
+        if !embed::is_valid_embed_path(&name) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidEmbedPathErr { path: name },
+                name_location,
+            ));
+        }
+        self.eat_whitespaces(true);
+
+        let data_location = self.peek_tok_location();
+        let data_tok = match self.next_tok() {
+            Some(tok) if tok.token.toktype == TokenType::RawLatex => tok,
+            Some(tok) => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::RawLatex],
+                        got: tok.token.toktype,
+                    },
+                    data_location,
+                ))
+            }
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::EOFErr,
+                    embed_location,
+                ))
+            }
+        };
+        let cache_key = BlockCache::key("embed", &data_tok.token.literal);
+        let data = match self.block_cache.get(&cache_key) {
+            Some(data) => data,
+            None => match embed::decode_base64(&data_tok.token.literal) {
+                Some(data) => {
+                    self.block_cache.put(&cache_key, &data);
+                    data
+                }
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidBase64Err,
+                        data_location,
+                    ))
+                }
+            },
+        };
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::EmbeddedFile {
+            name: self.alloc(&name),
+            data,
+        })
+    }
+
+    // `chapter import "chapters/intro.ves"` is `\include{chapters/intro}`. The
+    // chapter itself is parsed and made into a latex file after this file.
+    fn parse_chapter_import(&mut self) -> error::Result<Statement<'a>> {
+        let chapter_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::ChapterImport; chapter_location);
+        self.eat_whitespaces(false);
+
+        let path_location = self.peek_tok_location();
+        let path = self.parse_string_literal()?;
+        self.check_safe_path(&path, path_location)?;
+        let name = path.strip_suffix(".ves").unwrap_or(&path);
+        // `\include` cannot read file names with spaces
+        if !embed::is_valid_embed_path(name) || name.contains(char::is_whitespace) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidChapterPathErr { path },
+                path_location,
+            ));
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::ChapterImport {
+            name: self.alloc(name),
+            path: self.alloc(&path),
+        })
+    }
+
+    // `importtex "legacy.tex" (split)`. The file is read after parsing, since
+    // the parser does not know where the source is.
+    fn parse_importtex(&mut self) -> error::Result<Statement<'a>> {
+        let importtex_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Importtex; importtex_location);
+        self.eat_whitespaces(false);
+
+        let path_location = self.peek_tok_location();
+        let path = self.parse_string_literal()?;
+        self.check_safe_path(&path, path_location)?;
+        self.eat_whitespaces(false);
+
+        let mut split = false;
+        for (key, key_location, _) in self.parse_keyword_options()? {
+            match key.as_str() {
+                "split" => split = true,
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidImportTexOptionErr { name: key },
+                        key_location,
+                    ))
+                }
+            }
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::ImportTex {
+            path: self.alloc(&path),
+            path_span: path_location,
+            split,
+        })
+    }
+
+    // `index "group!abelian"` is `\index{group!abelian}`. Entries are printed by
+    // `\printindex` of makeidx.
+    fn parse_index(&mut self) -> error::Result<Statement<'a>> {
+        let index_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Index; index_location);
+        self.eat_whitespaces(false);
+
+        let term_location = self.peek_tok_location();
+        let term = self.parse_string_literal()?;
+        if term.split('!').any(|level| level.trim().is_empty()) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidIndexTermErr { term },
+                term_location,
+            ));
+        }
+        self.required_packages.insert(String::from("makeidx"));
+
+        Ok(Statement::Index(self.alloc(&term)))
+    }
+
+    // `section "Introduction"` is `\section{Introduction}\label{sec:introduction}`.
+    // A label which is already used gets a number like `sec:introduction-2`.
+    fn parse_heading(&mut self) -> error::Result<Statement<'a>> {
+        let heading_location = self.peek_tok_location();
+        let name = self
+            .peek_tok
+            .as_ref()
+            .map(|tok| tok.token.literal.to_string())
+            .unwrap_or_default();
+        expect_peek!(self | TokenType::Heading; heading_location);
+        self.eat_whitespaces(false);
+        let title = self.parse_string_literal()?;
+
+        let label = if name.ends_with('*') || !self.headings.auto_label {
+            None
+        } else {
+            self.headings.label(&name, &title).map(|label| {
+                let mut unique = label.clone();
+                let mut count = 1;
+                while self.labels.contains(&self.strings.symbol(&unique)) {
+                    count += 1;
+                    unique = format!("{}-{}", label, count);
+                }
+                self.labels.insert(self.strings.symbol(&unique));
+                unique
+            })
+        };
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::Heading {
+            name: self.alloc(&name),
+            title: self.alloc(&title),
+            label: label.map(|label| self.alloc(&label)),
+        })
+    }
+
+    // `frame<2-> "Title" (fragile, t) { ... }` is lowered into the frame
+    // environment of beamer. The overlay, the title and options can be omitted.
+    fn parse_frame(&mut self) -> error::Result<Statement<'a>> {
+        let frame_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Frame; frame_location);
+        self.eat_whitespaces(false);
+
+        let overlay = if self.peek_tok() == Some(TokenType::Less) {
+            Some(self.parse_overlay()?)
+        } else {
+            None
+        };
+        self.eat_whitespaces(false);
+        let title = if self.peek_tok() == Some(TokenType::Doublequote) {
+            Some(self.parse_string_literal()?)
+        } else {
+            None
+        };
+        let mut options = None;
+        self.parse_comma_args(&mut options)?;
+        self.eat_whitespaces(true);
+        // A body which is not closed is reported at `frame`, since the lexer
+        // makes it a keyword only with its whole header
+        let body_location = self.peek_tok_location();
+        let body = self
+            .in_local_scope(|parser| parser.parse_definition_body())
+            .map_err(|mut err| {
+                if err.location == body_location {
+                    err.location = frame_location;
+                }
+                err
+            })?;
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::Frame {
+            overlay: overlay.map(|overlay| self.alloc(&overlay)),
+            title: title.map(|title| self.alloc(&title)),
+            options: options.unwrap_or_default(),
+            body,
+        })
+    }
+
+    // Overlay specifications like `<2->` or `<+-| alert@+>` are kept as they are
+    fn parse_overlay(&mut self) -> error::Result<String> {
+        let open_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Less; open_location);
+        let mut overlay = String::new();
+        loop {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype == TokenType::Great => break,
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    overlay.push_str(&tok.token.literal)
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketNumberMatchedErr,
+                        open_location,
+                    ))
+                }
+            }
+        }
+        Ok(overlay)
+    }
+
+    // `importimg "path" (width=.5\textwidth, caption "...", label fig:x)`
+    // makes a figure environment. Whether the image exists is checked after parsing.
+    fn parse_figure(&mut self) -> error::Result<Statement<'a>> {
+        let importimg_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Importimg; importimg_location);
+        self.require_package("graphicx", importimg_location)?;
+        self.eat_whitespaces(false);
+
+        let path_span = self.peek_tok_location();
+        let path = self.parse_string_literal()?;
+        self.check_safe_path(&path, path_span)?;
+        self.eat_whitespaces(false);
+
+        let mut placement: Option<String> = None;
+        let mut options: Vec<String> = Vec::new();
+        let mut caption: Option<String> = None;
+        let mut label: Option<String> = None;
+        for (key, key_location, value) in self.parse_keyword_options()? {
+            match key.as_str() {
+                "caption" => caption = Some(value),
+                "label" => {
+                    self.labels.insert(self.strings.symbol(&value));
+                    label = Some(value);
+                }
+                "placement" => placement = Some(value),
+                "width" | "height" | "totalheight" | "scale" | "angle" | "page" | "trim"
+                | "viewport" | "clip" | "keepaspectratio" => {
+                    if value.is_empty() {
+                        options.push(key);
+                    } else {
+                        options.push(format!("{}={}", key, value));
+                    }
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidImgOptionErr { name: key },
+                        key_location,
+                    ))
+                }
+            }
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::Figure {
+            path: self.alloc(&path),
+            path_span,
+            placement: placement.map(|placement| self.alloc(&placement)),
+            options: options.iter().map(|option| self.alloc(option)).collect(),
+            caption: caption.map(|caption| self.alloc(&caption)),
+            label: label.map(|label| self.alloc(&label)),
+        })
+    }
+
+    fn parse_file_directive(&mut self) -> error::Result<Statement<'a>> {
+        let directive_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        if self.document_state.contains(DocState::DOC_START) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::FileDirectiveAfterDocumentErr,
+                directive_location,
+            ));
+        }
+
+        let directive = match parse_file_directive(&literal) {
+            Ok(FileDirective::Engine(engine)) => FileDirective::Engine(engine),
+            Ok(FileDirective::Output(name)) => FileDirective::Output(self.alloc(name)),
+            Ok(FileDirective::Watch(watch)) => FileDirective::Watch(watch),
+            Err(err) => return Err(VestiErr::make_parse_err(err, directive_location)),
+        };
+
+        Ok(Statement::FileDirective(directive))
+    }
+
+    fn parse_importbib(&mut self) -> error::Result<Statement<'a>> {
+        let importbib_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Importbib; importbib_location);
+        if self.bibliography.is_some() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::DuplicatedImportbibErr,
+                importbib_location,
+            ));
+        }
+        self.eat_whitespaces(false);
+
+        let path_location = self.peek_tok_location();
+        let path = self.parse_string_literal()?;
+        self.check_safe_path(&path, path_location)?;
+        self.eat_whitespaces(false);
+
+        let mut style: Option<String> = None;
+        let mut backend: Option<BibBackend> = None;
+        for (key, key_location, value) in self.parse_keyword_options()? {
+            match key.as_str() {
+                "style" => style = Some(value),
+                "backend" => backend = Some(parse_bib_backend(value, key_location)?),
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidBibOptionErr {
+                            keyword: String::from("importbib"),
+                            name: key,
+                        },
+                        key_location,
+                    ))
+                }
+            }
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        // `bibstyle` already decided the style and the backend
+        let has_bibstyle = self.bib_style.is_some();
+        if let Some((bib_style, bib_backend)) = &self.bib_style {
+            if style.as_ref().is_some_and(|style| style != bib_style)
+                || backend.is_some_and(|backend| backend != *bib_backend)
+            {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::BibstyleConflictErr,
+                    importbib_location,
+                ));
+            }
+            style = None;
+            backend = Some(*bib_backend);
+        }
+        let backend = backend.unwrap_or(BibBackend::Bibtex);
+
+        if backend == BibBackend::Biber && !has_bibstyle {
+            self.push_package("biblatex", importbib_location)?;
+        }
+        self.bibliography = Some((path.clone(), backend));
+
+        Ok(Statement::ImportBib {
+            path: self.alloc(&path),
+            style: style.map(|style| self.alloc(&style)),
+            backend,
+            has_bibstyle,
+        })
+    }
+
+    // `bibstyle (numeric, backend: biber)` sets the bibliography style with biblatex,
+    // and `backend: bibtex` does the same thing with natbib.
+    fn parse_bibstyle(&mut self) -> error::Result<Statement<'a>> {
+        let bibstyle_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Bibstyle; bibstyle_location);
+        if self.bib_style.is_some() || self.bibliography.is_some() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::BibstyleConflictErr,
+                bibstyle_location,
+            ));
+        }
+        self.eat_whitespaces(false);
+
+        let mut style = String::from("numeric");
+        let mut backend = BibBackend::Bibtex;
+        let options = self.parse_keyword_options()?;
+        for (i, (key, key_location, value)) in options.into_iter().enumerate() {
+            match key.as_str() {
+                // the first option without a value is the name of the style
+                _ if i == 0 && value.is_empty() => style = key,
+                "style" => style = value,
+                "backend" => backend = parse_bib_backend(value, key_location)?,
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidBibOptionErr {
+                            keyword: String::from("bibstyle"),
+                            name: key,
+                        },
+                        key_location,
+                    ))
+                }
+            }
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        let package = match backend {
+            BibBackend::Biber => "biblatex",
+            BibBackend::Bibtex => "natbib",
+        };
+        self.push_package(package, bibstyle_location)?;
+        self.bib_style = Some((style.clone(), backend));
+
+        Ok(Statement::BibStyle {
+            style: self.alloc(&style),
+            backend,
+        })
+    }
+
+    // `///` lines are the doc of the definition right after them. The lexer
+    // makes them only before `defun` and `defenv`.
+    fn parse_doc_comment(&mut self) -> error::Result<Statement<'a>> {
+        let doc_tok = self.next_tok().unwrap();
+        let doc = doc_tok
+            .token
+            .literal
+            .lines()
+            .map(|line| {
+                let line = line.trim_start().trim_start_matches('/');
+                line.strip_prefix(' ').unwrap_or(line).trim_end()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let doc = Some(self.alloc(doc.trim()));
+        match self.peek_tok() {
+            Some(TokenType::Defun) if self.can_define() => self.parse_function_define(doc),
+            Some(TokenType::Defenv) if self.can_define() => self.parse_environment_define(doc),
+            _ => self.parse_statement_node(),
+        }
+    }
+
+    // `defun name (#!1#!2) { body }` is lowered into `\def\name#1#2{body}`, and
+    // `defun name [s O{x} m] { body }` into `\NewDocumentCommand{\name}{s O{x} m}{body}`
+    // `defun!` and `defun?` use `\renewcommand` and `\providecommand` instead.
+    fn parse_function_define(&mut self, doc: Option<&'a str>) -> error::Result<Statement<'a>> {
+        let defun_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Defun; defun_location);
+        let kind = match self.peek_tok() {
+            Some(TokenType::Bang) => DefineKind::Redefine,
+            Some(TokenType::Question) => DefineKind::Provide,
+            _ => DefineKind::Define,
+        };
+        if kind != DefineKind::Define {
+            self.next_tok();
+        }
+        self.eat_whitespaces(false);
+        let name = self.parse_definition_name(defun_location)?;
+        self.eat_whitespaces(false);
+        if kind == DefineKind::Define {
+            self.check_redefinition(&name, defun_location);
+        }
+
+        let name_symbol = self.strings.symbol(&name);
+        let (params, spec) = if self.peek_tok() == Some(TokenType::Lsqbrace) {
+            let spec_location = self.peek_tok_location();
+            let spec = self.parse_argument_spec()?;
+            let args = match argspec::parse_arg_spec(&spec) {
+                Some(args) => args,
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidArgSpecErr { spec },
+                        spec_location,
+                    ))
+                }
+            };
+            // calls of functions with delimited arguments are not checked
+            match argspec::mandatory_count(&args) {
+                Some(count) => self.functions.insert(name_symbol, count),
+                None => self.functions.remove(&name_symbol),
+            };
+            (String::new(), Some(spec))
+        } else {
+            let params_location = self.peek_tok_location();
+            let params = self.parse_function_definition_argument()?;
+            let params_num = params.matches('#').count();
+            let plain_params: String = (1..=params_num).map(|num| format!("#{}", num)).collect();
+            if kind != DefineKind::Define && params != plain_params {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::RedefinitionParamsErr,
+                    params_location,
+                ));
+            }
+            self.functions.insert(name_symbol, params_num);
+            (params, None)
+        };
+        self.eat_whitespaces(true);
+        let body = self.parse_definition_body()?;
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::FunctionDefine {
+            kind,
+            name: self.alloc(&name),
+            params: self.alloc(&params),
+            spec: spec.map(|spec| self.alloc(&spec)),
+            body,
+            doc,
+        })
+    }
+
+    // `defenv name (2, default) { begin part } { end part }` is lowered into
+    // `\newenvironment{name}[2][default]{begin part}{end part}`, and
+    // `defenv name [s O{x} m] { begin part } { end part }` into
+    // `\NewDocumentEnvironment{name}{s O{x} m}{begin part}{end part}`
+    fn parse_environment_define(&mut self, doc: Option<&'a str>) -> error::Result<Statement<'a>> {
+        let defenv_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Defenv; defenv_location);
+        self.eat_whitespaces(false);
+        let name = self.parse_definition_name(defenv_location)?;
+        self.eat_whitespaces(false);
+
+        let mut args_num: u8 = 0;
+        let mut optional_arg: Option<String> = None;
+        let mut spec: Option<String> = None;
+        if self.peek_tok() == Some(TokenType::Lsqbrace) {
+            let spec_location = self.peek_tok_location();
+            let arg_spec = self.parse_argument_spec()?;
+            match argspec::parse_arg_spec(&arg_spec) {
+                Some(args) => args_num = args.len() as u8,
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::InvalidArgSpecErr { spec: arg_spec },
+                        spec_location,
+                    ))
+                }
+            }
+            spec = Some(arg_spec);
+        } else {
+            let mut options: Option<Vec<Latex>> = None;
+            let options_location = self.peek_tok_location();
+            self.parse_comma_args(&mut options)?;
+            let mut options = options.unwrap_or_default().into_iter();
+            if let Some(num) = options.next() {
+                args_num = match latex_to_string(&num).trim().parse() {
+                    Ok(num) if num <= 9 => num,
+                    _ => {
+                        return Err(VestiErr::make_parse_err(
+                            VestiParseErr::ParseIntErr,
+                            options_location,
+                        ))
+                    }
+                };
+            }
+            if let Some(default) = options.next() {
+                optional_arg = Some(latex_to_string(&default).trim().to_string());
+            }
+            if self.xparse_environments {
+                spec = Some(argspec::numbered_spec(args_num, optional_arg.as_deref()));
+            }
+        }
+
+        self.eat_whitespaces(true);
+        let begin_part = self.parse_definition_body()?;
+        self.eat_whitespaces(true);
+        let end_part = self.parse_definition_body()?;
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::EnvironmentDefine {
+            name: self.alloc(&name),
+            args_num,
+            optional_arg: optional_arg.map(|arg| self.alloc(&arg)),
+            spec: spec.map(|spec| self.alloc(&spec)),
+            begin_part,
+            end_part,
+            doc,
+        })
+    }
+
+    // `defun` and `defenv` are written in the preamble, or in environments of
+    // the document. Latex groups environments, so the latter ones are local.
+    fn can_define(&self) -> bool {
+        let is_doc_start = (self.document_state & DocState::DOC_START).bits();
+        is_doc_start == 0 || self.local_scope_depth > 0
+    }
+
+    // Functions defined in the scope are forgotten after it
+    fn in_local_scope<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> error::Result<T>,
+    ) -> error::Result<T> {
+        let functions = self.functions.clone();
+        self.local_scope_depth += 1;
+        let output = parse(self);
+        self.local_scope_depth -= 1;
+        self.functions = functions;
+        output
+    }
+
+    fn parse_definition_name(&mut self, location: Option<Span>) -> error::Result<String> {
+        let mut name = String::new();
+        while let Some(TokenType::MainString | TokenType::At) = self.peek_tok() {
+            name += &self.next_tok().unwrap().token.literal;
+        }
+        if name.is_empty() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::DefinitionNameMissErr,
+                location,
+            ));
+        }
+        Ok(name)
+    }
+
+    // Parameters of `defun` are kept as a raw TeX parameter string like `#1#2`
+    fn parse_function_definition_argument(&mut self) -> error::Result<String> {
+        let mut output = String::new();
+        if self.peek_tok() != Some(TokenType::Lparen) {
+            return Ok(output);
+        }
+        let open_brace_location = self.peek_tok_location();
+        self.next_tok();
+
+        while self.peek_tok() != Some(TokenType::Rparen) {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    output += &tok.token.literal;
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketNumberMatchedErr,
+                        open_brace_location,
+                    ))
+                }
+            }
+        }
+        expect_peek!(self | TokenType::Rparen; self.peek_tok_location());
+
+        Ok(output.trim().to_string())
+    }
+
+    // The argument specification between `[` and `]` is kept as it is
+    fn parse_argument_spec(&mut self) -> error::Result<String> {
+        let open_brace_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Lsqbrace; open_brace_location);
+
+        let mut output = String::new();
+        while self.peek_tok() != Some(TokenType::Rsqbrace) {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    if tok.token.toktype == TokenType::LatexFunction {
+                        output.push('\\');
+                    }
+                    output += &tok.token.literal;
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketMismatchErr {
+                            expected: TokenType::Rsqbrace,
+                        },
+                        open_brace_location,
+                    ))
+                }
+            }
+        }
+        expect_peek!(self | TokenType::Rsqbrace; self.peek_tok_location());
+
+        Ok(output.trim().to_string())
+    }
+
+    fn parse_definition_body(&mut self) -> error::Result<Latex<'a>> {
+        let open_brace_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Lbrace; open_brace_location);
+
+        let mut body: Latex = Vec::new();
+        let mut nested = 0;
+        while self.peek_tok() != Some(TokenType::Rbrace) || nested > 0 {
+            match self.peek_tok() {
+                Some(TokenType::Lbrace) => nested += 1,
+                Some(TokenType::Rbrace) => nested -= 1,
+                Some(_) => {}
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketNumberMatchedErr,
+                        open_brace_location,
+                    ))
+                }
+            }
+            body.push(self.parse_statement()?);
+        }
+        expect_peek!(self | TokenType::Rbrace; self.peek_tok_location());
+
+        Ok(body)
+    }
+
+    // `codeblock (rust)` takes lines until a line `endcode` as they are.
+    // The environment is `minted` if it is imported, and `lstlisting` otherwise.
+    fn parse_codeblock(&mut self) -> error::Result<Statement<'a>> {
+        let codeblock_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Codeblock; codeblock_location);
+        let env = if self.has_package("minted") {
+            CodeEnv::Minted
+        } else {
+            self.require_package("listings", codeblock_location)?;
+            CodeEnv::Lstlisting
+        };
+        self.eat_whitespaces(false);
+
+        let mut lang: Option<String> = None;
+        let mut options: Vec<String> = Vec::new();
+        let keyword_options = self.parse_keyword_options()?;
+        for (i, (key, _, value)) in keyword_options.into_iter().enumerate() {
+            match key.as_str() {
+                // the first option without a value is the language
+                _ if i == 0 && value.is_empty() => lang = Some(key),
+                "language" | "lang" => lang = Some(value),
+                _ if value.is_empty() => options.push(key),
+                _ => options.push(format!("{}={}", key, value)),
+            }
+        }
+
+        self.eat_whitespaces(false);
+        match self.peek_tok() {
+            Some(TokenType::Newline) => {}
+            Some(got) => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::Newline],
+                        got,
+                    },
+                    self.peek_tok_location(),
+                ))
+            }
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::EOFErr,
+                    codeblock_location,
+                ))
+            }
+        }
+        // The lexer is at the start of the next line since the newline is peeked.
+        // Instead of tokenizing it, the body is taken as it is.
+        let code = match self.source.take_raw_lines("endcode") {
+            Some(code) => self.alloc_literal(code),
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::CodeblockIsNotClosedErr,
+                    codeblock_location,
+                ))
+            }
+        };
+        self.peek_tok = self.source.next();
+        self.line_indent = 0;
+        self.is_indent_counting = true;
+
+        Ok(Statement::CodeBlock {
+            env,
+            lang: lang.map(|lang| self.alloc(&lang)),
+            options: options.iter().map(|option| self.alloc(option)).collect(),
+            code,
+        })
+    }
+
+    // `execute(python) {` and lines until `}`. The body is run after parsing
+    // only if it is allowed, so the parser keeps it as it is.
+    fn parse_execute(&mut self) -> error::Result<Statement<'a>> {
+        let execute_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Execute; execute_location);
+        expect_peek!(self | TokenType::Lparen; self.peek_tok_location());
+        self.eat_whitespaces(false);
+        let lang_location = self.peek_tok_location();
+        let lang = match self.next_tok() {
+            Some(tok) if tok.token.toktype == TokenType::MainString => {
+                self.alloc_literal(tok.token.literal)
+            }
+            Some(tok) => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::MainString],
+                        got: tok.token.toktype,
+                    },
+                    lang_location,
+                ))
+            }
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::EOFErr,
+                    execute_location,
+                ))
+            }
+        };
+        self.eat_whitespaces(false);
+        expect_peek!(self | TokenType::Rparen; self.peek_tok_location());
+        self.eat_whitespaces(false);
+        expect_peek!(self | TokenType::Lbrace; self.peek_tok_location());
+        self.eat_whitespaces(false);
+        match self.peek_tok() {
+            Some(TokenType::Newline) => {}
+            Some(got) => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::Newline],
+                        got,
+                    },
+                    self.peek_tok_location(),
+                ))
+            }
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::EOFErr,
+                    execute_location,
+                ))
+            }
+        }
+        let code = match self.source.take_raw_lines("}") {
+            Some(code) => self.alloc_literal(code),
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::ExecuteIsNotClosedErr,
+                    execute_location,
+                ))
+            }
+        };
+        self.peek_tok = self.source.next();
+        self.line_indent = 0;
+        self.is_indent_counting = true;
+
+        Ok(Statement::ExecuteBlock {
+            lang,
+            code,
+            output: None,
+        })
+    }
+
+    // `#[allow(lint1, lint2)]` silences lints in the next statement
+    fn parse_attribute(&mut self) -> error::Result<Statement<'a>> {
+        let attribute_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        let lints = match literal
+            .strip_prefix("allow(")
+            .and_then(|lints| lints.strip_suffix(")]"))
+        {
+            Some(lints) => lints,
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::InvalidAttributeErr,
+                    attribute_location,
+                ))
+            }
+        };
+        let lints: Vec<String> = lints
+            .split(',')
+            .map(|lint| lint.trim().to_string())
+            .filter(|lint| !lint.is_empty())
+            .collect();
+        for lint in &lints {
+            if !LINT_NAMES.contains(&lint.as_str()) {
+                self.warn(
+                    VestiWarnKind::UnknownLint { name: lint.clone() },
+                    attribute_location,
+                );
+            }
+        }
+
+        self.eat_whitespaces(true);
+        if self.peek_tok().is_none() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::EOFErr,
+                attribute_location,
+            ));
+        }
+        let lints_len = lints.len();
+        self.allowed_lints.extend(lints.iter().cloned());
+        let stmt = self.parse_statement_node();
+        let allowed_len = self.allowed_lints.len();
+        self.allowed_lints.truncate(allowed_len - lints_len);
+        self.allowed_regions.push(AllowedRegion {
+            span: self.span_since(attribute_location),
+            lints,
+        });
+
+        stmt
+    }
+
+    // biblatex and natbib define the same commands, so they cannot be used together.
+    fn push_package(&mut self, name: &str, location: Option<Span>) -> error::Result<()> {
+        let conflict = match name {
+            "biblatex" => "natbib",
+            "natbib" => "biblatex",
+            _ => "",
+        };
+        if !conflict.is_empty() && self.has_package(conflict) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::BiblatexNatbibConflictErr,
+                location,
+            ));
+        }
+        if self.has_package(name) {
+            self.warn(
+                VestiWarnKind::DuplicatedImport {
+                    name: name.to_string(),
+                },
+                location,
+            );
+        }
+        let name = self.strings.symbol(name);
+        self.packages.push(name);
+
+        Ok(())
+    }
+
+    fn parse_printbib(&mut self) -> error::Result<Statement<'a>> {
+        let printbib_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Printbib; printbib_location);
+        let (path, backend) = match &self.bibliography {
+            Some((path, backend)) => (path.clone(), *backend),
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::PrintbibWithoutImportbibErr,
+                    printbib_location,
+                ))
+            }
+        };
+        self.is_bib_printed = true;
 
-        for stmt in latex {
-            output += &stmt.to_string();
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
         }
 
-        Ok(output)
+        Ok(Statement::PrintBib {
+            path: self.alloc(&path),
+            backend,
+        })
     }
 
-    pub fn parse_latex(&mut self) -> error::Result<Latex> {
-        let mut latex: Latex = Vec::new();
-        while self.peek_tok().is_some() {
-            latex.push(self.parse_statement()?);
+    // `@[knuth84, lamport94]` is a shorthand of `\cite{knuth84,lamport94}`
+    fn parse_cite(&mut self) -> error::Result<Statement<'a>> {
+        let cite_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::CiteStart; cite_location);
+
+        let mut keys = String::new();
+        while self.peek_tok() != Some(TokenType::Rsqbrace) {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    keys += &tok.token.literal;
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketMismatchErr {
+                            expected: TokenType::Rsqbrace,
+                        },
+                        cite_location,
+                    ))
+                }
+            }
         }
-        if self.document_state == DocState::DOC_START {
-            latex.push(Statement::DocumentEnd);
+        expect_peek!(self | TokenType::Rsqbrace; self.peek_tok_location());
+
+        let keys: Vec<&str> = keys
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .collect();
+
+        Ok(Statement::LatexFunction {
+            name: "cite",
+            args: vec![(
+                ArgNeed::MainArg,
+                vec![Statement::MainText(self.alloc(&keys.join(","))).into()],
+            )],
+        })
+    }
+
+    // Parse options of keywords like `(key=value, key "string", key value)`.
+    // If there is no parenthesis, then there is no option.
+    fn parse_keyword_options(&mut self) -> error::Result<Vec<(String, Option<Span>, String)>> {
+        let mut output = Vec::new();
+        if self.peek_tok() != Some(TokenType::Lparen) {
+            return Ok(output);
         }
+        let open_brace_location = self.peek_tok_location();
+        self.next_tok();
+        self.eat_whitespaces(true);
 
-        Ok(latex)
-    }
+        while self.peek_tok() != Some(TokenType::Rparen) {
+            if self.peek_tok().is_none() {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::BracketNumberMatchedErr,
+                    open_brace_location,
+                ));
+            }
+            let key_location = self.peek_tok_location();
+            take_name!(self | define key);
+            if key.is_empty() {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::MainString],
+                        got: self.peek_tok().unwrap(),
+                    },
+                    key_location,
+                ));
+            }
+            self.eat_whitespaces(false);
+            if matches!(self.peek_tok(), Some(TokenType::Equal | TokenType::Colon)) {
+                self.next_tok();
+                self.eat_whitespaces(false);
+            }
 
-    fn parse_statement(&mut self) -> error::Result<Statement> {
-        let is_doc_start = (self.document_state & DocState::DOC_START).bits();
-        match self.peek_tok() {
-            // Keywords
-            Some(TokenType::Docclass) if is_doc_start == 0 => self.parse_docclass(),
-            Some(TokenType::Import) if is_doc_start == 0 => self.parse_usepackage(),
-            Some(TokenType::Document) if is_doc_start == 0 => {
-                self.document_state |= DocState::DOC_START;
+            let value = if self.peek_tok() == Some(TokenType::Doublequote) {
+                self.parse_string_literal()?
+            } else {
+                let mut tmp: Latex = Vec::new();
+                while !matches!(
+                    self.peek_tok(),
+                    Some(TokenType::Comma | TokenType::Rparen | TokenType::Newline) | None
+                ) {
+                    tmp.push(self.parse_statement()?);
+                }
+                latex_to_string(&tmp).trim().to_string()
+            };
+            output.push((key, key_location, value));
+
+            self.eat_whitespaces(true);
+            if self.peek_tok() == Some(TokenType::Comma) {
                 self.next_tok();
                 self.eat_whitespaces(true);
-                Ok(Statement::DocumentStart)
+            } else if self.peek_tok() != Some(TokenType::Rparen) {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::BracketNumberMatchedErr,
+                    open_brace_location,
+                ));
             }
-            Some(TokenType::Begenv) => self.parse_environment(),
-            Some(TokenType::Endenv) => Err(VestiErr::make_parse_err(
-                VestiParseErr::EndenvIsUsedWithoutBegenvPairErr,
-                self.peek_tok_location(),
-            )),
-            Some(TokenType::Mtxt) => self.parse_text_in_math(),
-            Some(TokenType::Etxt) => Err(VestiErr::make_parse_err(
-                VestiParseErr::InvalidTokToParse {
-                    got: TokenType::Etxt,
-                },
+        }
+        expect_peek!(self | TokenType::Rparen; self.peek_tok_location());
+
+        Ok(output)
+    }
+
+    fn parse_main_stmt(&mut self) -> error::Result<Statement<'a>> {
+        if self.peek_tok().is_none() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::EOFErr,
                 self.peek_tok_location(),
-            )),
-            Some(TokenType::DocumentStartMode) => {
-                self.document_state |= DocState::PREVENT_END_DOC | DocState::DOC_START;
-                let loc = self.next_tok().map(|lex_tok| lex_tok.span);
-                expect_peek!(self | TokenType::Newline, TokenType::Newline2; loc);
-                self.parse_statement()
+            ));
+        }
+        let is_math_word = self.peek_tok() == Some(TokenType::MainString)
+            && self.source.math_started
+            && self.text_in_math_depth == 0;
+        let literal = self.next_tok().unwrap().token.literal;
+        let text = self.alloc_literal(literal);
+
+        if is_math_word {
+            if MATH_OPERATORS.contains(&text) {
+                return Ok(Statement::LatexFunction {
+                    name: text,
+                    args: Vec::new(),
+                });
             }
-
-            // Identifiers
-            Some(TokenType::LatexFunction) => self.parse_latex_function(),
-            Some(TokenType::RawLatex) => self.parse_raw_latex(),
-            Some(TokenType::Integer) => self.parse_integer(),
-            Some(TokenType::Float) => self.parse_float(),
-            Some(toktype) if toktype.should_not_use_before_doc() && is_doc_start == 0 => {
-                Err(VestiErr::make_parse_err(
-                    VestiParseErr::BeforeDocumentErr { got: toktype },
-                    self.peek_tok_location(),
-                ))
+            if self.contains_name(&self.math_operators, text) {
+                self.required_packages.insert(String::from("amsmath"));
+                return Ok(Statement::LatexFunction {
+                    name: "operatorname",
+                    args: vec![(ArgNeed::MainArg, vec![Statement::MainText(text).into()])],
+                });
             }
+        }
 
-            // Math related tokens
-            Some(TokenType::TextMathStart) => self.parse_math_stmt(),
-            Some(TokenType::InlineMathStart) => self.parse_math_stmt(),
-            Some(TokenType::Superscript | TokenType::Subscript)
-                if !self.source.math_started && is_doc_start != 0 =>
-            {
-                self.parse_scripts()
+        Ok(Statement::MainText(text))
+    }
+
+    // Lines of `title`, `author`, `date` and `maketitle` in the preamble make
+    // one front matter. Strings after the name of an author are affiliations.
+    fn parse_front_matter(&mut self) -> error::Result<Statement<'a>> {
+        let mut title = None;
+        let mut authors = Vec::new();
+        let mut date = None;
+        loop {
+            match self.peek_tok() {
+                Some(TokenType::Metadata) => {}
+                Some(TokenType::Maketitle) => {
+                    self.next_tok();
+                    self.maketitle = true;
+                    self.eat_whitespaces(true);
+                    continue;
+                }
+                _ => break,
+            }
+            let metadata = self.next_tok().unwrap();
+            let location = Some(metadata.span);
+            let name = match metadata.token.literal.as_ref() {
+                "title" => "title",
+                "author" => "author",
+                _ => "date",
+            };
+            if !self.front_matter.insert(name) && name != "author" {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::DuplicatedMetadataErr {
+                        name: name.to_string(),
+                    },
+                    location,
+                ));
             }
+            self.eat_whitespaces(false);
+            match name {
+                "title" => {
+                    let text = self.parse_string_literal()?;
+                    title = Some(self.alloc(&text));
+                }
+                "author" => {
+                    let name = self.parse_string_literal()?;
+                    let mut affiliations = Vec::new();
+                    self.eat_whitespaces(false);
+                    while self.peek_tok() == Some(TokenType::Doublequote) {
+                        let affiliation = self.parse_string_literal()?;
+                        affiliations.push(self.alloc(&affiliation));
+                        self.eat_whitespaces(false);
+                    }
+                    authors.push(Author {
+                        name: self.alloc(&name),
+                        affiliations,
+                    });
+                }
+                _ if self.peek_tok() == Some(TokenType::Doublequote) => {
+                    let text = self.parse_string_literal()?;
+                    date = Some(DocDate::Text(self.alloc(&text)));
+                }
+                _ => {
+                    let word = self.next_tok().map(|tok| tok.token.literal);
+                    date = Some(match word.as_deref() {
+                        Some("today") => DocDate::Today,
+                        _ => DocDate::Empty,
+                    });
+                }
+            }
+            self.eat_whitespaces(true);
+        }
 
-            Some(TokenType::TextMathEnd) => Err(VestiErr::make_parse_err(
-                VestiParseErr::InvalidTokToParse {
-                    got: TokenType::TextMathEnd,
-                },
-                self.peek_tok_location(),
-            )),
-            Some(TokenType::InlineMathEnd) => Err(VestiErr::make_parse_err(
-                VestiParseErr::InvalidTokToParse {
-                    got: TokenType::InlineMathEnd,
-                },
-                self.peek_tok_location(),
-            )),
+        Ok(Statement::FrontMatter {
+            title,
+            authors,
+            date,
+        })
+    }
 
-            Some(TokenType::ILLEGAL) => Err(VestiErr::make_parse_err(
-                VestiParseErr::IllegalCharacterFoundErr,
-                self.peek_tok_location(),
-            )),
+    // `deftheorem theorem "Theorem" [section]` is lowered into
+    // `\newtheorem{theorem}{Theorem}[section]`, and `(theorem)` instead of
+    // `[section]` shares the counter of `theorem`
+    fn parse_theorem_define(&mut self) -> error::Result<Statement<'a>> {
+        let deftheorem_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Deftheorem; deftheorem_location);
+        let starred = self.peek_tok() == Some(TokenType::Star);
+        if starred {
+            self.next_tok();
+        }
+        self.eat_whitespaces(false);
+        let name = self.parse_definition_name(deftheorem_location)?;
+        self.eat_whitespaces(false);
+        let title = self.parse_string_literal()?;
+        self.eat_whitespaces(false);
 
-            _ => self.parse_main_stmt(),
+        let mut within = None;
+        let mut shared = None;
+        match self.peek_tok() {
+            Some(TokenType::Lsqbrace) => {
+                within = Some(self.parse_theorem_counter(TokenType::Lsqbrace, TokenType::Rsqbrace)?)
+            }
+            Some(TokenType::Lparen) => {
+                shared = Some(self.parse_theorem_counter(TokenType::Lparen, TokenType::Rparen)?)
+            }
+            _ => {}
+        }
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
         }
+
+        let name_symbol = self.strings.symbol(&name);
+        self.theorems.insert(name_symbol);
+        self.required_packages.insert(String::from("amsthm"));
+
+        Ok(Statement::TheoremDefine {
+            name: self.alloc(&name),
+            title: self.alloc(&title),
+            starred,
+            within: within.map(|within| self.alloc(&within)),
+            shared: shared.map(|shared| self.alloc(&shared)),
+        })
     }
 
-    fn parse_integer(&mut self) -> error::Result<Statement> {
-        let curr_tok = self.next_tok().unwrap();
-        let output = if let Ok(int) = curr_tok.token.literal.parse() {
-            int
-        } else {
-            return Err(VestiErr::make_parse_err(
-                VestiParseErr::ParseIntErr,
-                Some(curr_tok.span),
-            ));
-        };
+    fn parse_theorem_counter(
+        &mut self,
+        open: TokenType,
+        closed: TokenType,
+    ) -> error::Result<String> {
+        let open_location = self.peek_tok_location();
+        expect_peek!(self | open; open_location);
+        let mut counter = String::new();
+        while self.peek_tok() != Some(closed) {
+            match self.next_tok() {
+                Some(tok) if tok.token.toktype != TokenType::Newline => {
+                    counter += &tok.token.literal;
+                }
+                _ => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketMismatchErr { expected: closed },
+                        open_location,
+                    ))
+                }
+            }
+        }
+        expect_peek!(self | closed; self.peek_tok_location());
+        Ok(counter.trim().to_string())
+    }
 
-        Ok(Statement::Integer(output))
+    // A theorem declared by `deftheorem` at the start of a line, followed by
+    // its title or its body
+    fn is_theorem_start(&self) -> bool {
+        let is_theorem = self.peek_tok.as_ref().is_some_and(|tok| {
+            self.strings
+                .get(&tok.token.literal)
+                .is_some_and(|name| self.theorems.contains(&name))
+        });
+        if !is_theorem || !self.is_indent_counting || self.source.math_started {
+            return false;
+        }
+        let mut probe = self.source.clone();
+        loop {
+            match probe.next().map(|tok| tok.token.toktype) {
+                Some(TokenType::Space | TokenType::Tab) => {}
+                Some(TokenType::Doublequote | TokenType::Lbrace) => return true,
+                _ => return false,
+            }
+        }
     }
 
-    fn parse_float(&mut self) -> error::Result<Statement> {
-        let curr_tok = self.next_tok().unwrap();
-        let output = if let Ok(float) = curr_tok.token.literal.parse() {
-            float
-        } else {
-            return Err(VestiErr::make_parse_err(
-                VestiParseErr::ParseFloatErr,
-                Some(curr_tok.span),
+    // `theorem "Pythagoras" { ... }` is lowered into the theorem environment
+    // whose optional argument is the title
+    fn parse_theorem(&mut self) -> error::Result<Statement<'a>> {
+        let literal = self.next_tok().unwrap().token.literal;
+        let name = self.alloc_literal(literal);
+        self.eat_whitespaces(false);
+        let mut args = Vec::new();
+        if self.peek_tok() == Some(TokenType::Doublequote) {
+            let title = self.parse_string_literal()?;
+            args.push((
+                ArgNeed::Optional,
+                vec![Statement::MainText(self.alloc(&title)).into()],
             ));
-        };
+        }
+        self.eat_whitespaces(false);
+        let text = self.in_local_scope(|parser| parser.parse_definition_body())?;
 
-        Ok(Statement::Float(output))
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::Environment { name, args, text })
     }
 
-    fn parse_raw_latex(&mut self) -> error::Result<Statement> {
-        Ok(Statement::RawLatex(self.next_tok().unwrap().token.literal))
+    // `qty(9.81, m/s^2)` is lowered into `\SI{9.81}{\metre\per\second\squared}`
+    fn parse_quantity(&mut self) -> error::Result<Statement<'a>> {
+        let quantity_location = self.peek_tok_location();
+        let quantity = self.next_tok().unwrap().token.literal;
+        let (value, unit) = match quantity.split_once(',') {
+            Some((value, unit)) if !value.trim().is_empty() => (value.trim(), unit.trim()),
+            _ => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::InvalidQuantityErr {
+                        quantity: quantity.to_string(),
+                    },
+                    quantity_location,
+                ))
+            }
+        };
+        let lowered = units::lower_unit(unit).map_err(|part| {
+            VestiErr::make_parse_err(
+                VestiParseErr::InvalidUnitErr {
+                    unit: unit.to_string(),
+                    part,
+                },
+                quantity_location,
+            )
+        })?;
+        self.required_packages.insert(String::from("siunitx"));
+
+        Ok(Statement::Quantity {
+            value: self.alloc(value),
+            unit: self.alloc(&lowered),
+            symbol: self.alloc(unit),
+        })
     }
 
-    fn parse_main_stmt(&mut self) -> error::Result<Statement> {
-        if self.peek_tok().is_none() {
+    // `bb{R}` is lowered into `\mathbb{R}`. Math fonts cannot be nested since
+    // the inner one replaces the outer one.
+    fn parse_math_font(&mut self) -> error::Result<Statement<'a>> {
+        let font_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        let keyword = self.alloc_literal(literal);
+        if let Some(outer) = self.math_font {
             return Err(VestiErr::make_parse_err(
-                VestiParseErr::EOFErr,
-                self.peek_tok_location(),
+                VestiParseErr::NestedMathFontErr {
+                    outer: outer.to_string(),
+                    inner: keyword.to_string(),
+                },
+                font_location,
             ));
         }
-        let text = self.next_tok().unwrap().token.literal;
+        let name = token::math_font(keyword).unwrap();
+        if name == "mathbb" || name == "mathfrak" {
+            self.required_packages.insert(String::from("amssymb"));
+        }
 
-        Ok(Statement::MainText(text))
+        let mut args = Vec::new();
+        self.math_font = Some(keyword);
+        let parsed = self.parse_function_args_core(
+            &mut args,
+            TokenType::Lbrace,
+            TokenType::Rbrace,
+            ArgNeed::MainArg,
+        );
+        self.math_font = None;
+        parsed?;
+
+        Ok(Statement::LatexFunction { name, args })
     }
 
-    fn parse_math_stmt(&mut self) -> error::Result<Statement> {
+    fn parse_math_stmt(&mut self) -> error::Result<Statement<'a>> {
         let start_location = self.peek_tok_location();
         let mut text = Vec::new();
 
@@ -287,12 +2455,13 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn parse_text_in_math(&mut self) -> error::Result<Statement> {
+    fn parse_text_in_math(&mut self) -> error::Result<Statement<'a>> {
         let mut output: Latex = Vec::new();
 
         expect_peek!(self | TokenType::Mtxt; self.peek_tok_location());
         self.eat_whitespaces(false);
 
+        self.text_in_math_depth += 1;
         while self.peek_tok() != Some(TokenType::Etxt) {
             if self.peek_tok().is_none() {
                 return Err(VestiErr::make_parse_err(
@@ -304,22 +2473,80 @@ impl<'a> Parser<'a> {
             }
             output.push(self.parse_statement()?);
         }
+        self.text_in_math_depth -= 1;
 
         expect_peek!(self | TokenType::Etxt; self.peek_tok_location());
 
         Ok(Statement::PlainTextInMath(output))
     }
 
-    fn parse_scripts(&mut self) -> error::Result<Statement> {
+    // The right delimiter can differ from the left one like `#( 0, 1 #}`,
+    // but every `#(` must be closed in the same math text.
+    fn parse_math_delimiter(&mut self) -> error::Result<Statement<'a>> {
+        let left_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        let left = self.alloc_literal(literal);
+        let mut text: Latex = Vec::new();
+
+        loop {
+            match self.peek_tok() {
+                Some(TokenType::RightBig) => break,
+                None
+                | Some(TokenType::TextMathEnd | TokenType::InlineMathEnd | TokenType::Endenv) => {
+                    return Err(VestiErr::make_parse_err(
+                        BracketMismatchErr {
+                            expected: TokenType::RightBig,
+                        },
+                        left_location,
+                    ))
+                }
+                Some(_) => text.push(self.parse_statement()?),
+            }
+        }
+        let literal = self.next_tok().unwrap().token.literal;
+        let right = self.alloc_literal(literal);
+
+        Ok(Statement::MathDelimiter { left, right, text })
+    }
+
+    // Unicode characters in a text inside math are written as they are
+    fn parse_unicode_symbol(&mut self) -> error::Result<Statement<'a>> {
+        let literal = self.next_tok().unwrap().token.literal;
+        let unicode = self.alloc_literal(literal);
+        let symbol = unicode.chars().next().and_then(symbols::unicode_symbol);
+        match symbol {
+            Some((latex, package)) if self.text_in_math_depth == 0 => {
+                if let Some(package) = package {
+                    self.required_packages.insert(String::from(package));
+                }
+                // `αx` should not become `\alphax`
+                let latex =
+                    if let Some(TokenType::MainString | TokenType::Integer | TokenType::Float) =
+                        self.peek_tok()
+                    {
+                        self.alloc(&format!("{} ", latex))
+                    } else {
+                        latex
+                    };
+                Ok(Statement::MathSymbol { unicode, latex })
+            }
+            _ => Ok(Statement::MainText(unicode)),
+        }
+    }
+
+    fn parse_scripts(&mut self) -> error::Result<Statement<'a>> {
         let start_location = self.peek_tok_location();
         let state = MathState::Text;
         let mut text: Latex = Vec::new();
 
-        text.push(Statement::MainText(match self.peek_tok() {
-            Some(TokenType::Superscript) => String::from("^"),
-            Some(TokenType::Subscript) => String::from("_"),
-            _ => unreachable!(),
-        }));
+        text.push(Spanned::new(
+            Statement::MainText(match self.peek_tok() {
+                Some(TokenType::Superscript) => "^",
+                Some(TokenType::Subscript) => "_",
+                _ => unreachable!(),
+            }),
+            start_location.unwrap_or_default(),
+        ));
         self.next_tok();
 
         if self.peek_tok() == Some(TokenType::Lbrace) {
@@ -338,7 +2565,7 @@ impl<'a> Parser<'a> {
                 })?);
             }
             expect_peek!(self | TokenType::Rbrace; self.peek_tok_location());
-            text.push(Statement::MainText(String::from("}")));
+            text.push(Statement::MainText("}").into());
         } else {
             text.push(self.parse_statement().map_err(|err| {
                 if let VestiErrKind::ParseErr(VestiParseErr::EOFErr) = err.err_kind {
@@ -357,7 +2584,7 @@ impl<'a> Parser<'a> {
         Ok(Statement::MathText { state, text })
     }
 
-    fn parse_docclass(&mut self) -> error::Result<Statement> {
+    fn parse_docclass(&mut self) -> error::Result<Statement<'a>> {
         let mut options: Option<Vec<Latex>> = None;
 
         expect_peek!(self | TokenType::Docclass; self.peek_tok_location());
@@ -369,47 +2596,120 @@ impl<'a> Parser<'a> {
         if self.peek_tok() == Some(TokenType::Newline) {
             self.next_tok();
         }
+        let name = self.alloc(&name);
+        self.docclass = Some(name.to_string());
 
         Ok(Statement::DocumentClass { name, options })
     }
 
-    fn parse_usepackage(&mut self) -> error::Result<Statement> {
+    fn parse_usepackage(&mut self) -> error::Result<Statement<'a>> {
         expect_peek!(self | TokenType::Import; self.peek_tok_location());
         self.eat_whitespaces(false);
 
         if self.peek_tok() == Some(TokenType::Lbrace) {
             return self.parse_multiple_usepackages();
         }
-
-        let mut options: Option<Vec<Latex>> = None;
-        take_name!(self | define name);
-
-        self.parse_comma_args(&mut options)?;
+
+        let mut options: Option<Vec<Latex>> = None;
+        let name_location = self.peek_tok_location();
+        if self.peek_tok() == Some(TokenType::Doublequote) {
+            let path = self.parse_string_literal()?;
+            return self.parse_module_import(path, name_location);
+        }
+        take_name!(self | define name);
+        if matches!(self.peek_tok(), Some(TokenType::Period | TokenType::Slash)) {
+            return self.parse_module_import(name, name_location);
+        }
+
+        self.parse_comma_args(&mut options)?;
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+        self.push_package(&name, name_location)?;
+
+        Ok(Statement::Usepackage {
+            name: self.alloc(&name),
+            options,
+        })
+    }
+
+    // `import defs.ves as m` or `import "defs.ves"`, where `path` is the part
+    // of the path which is read already. The file is read after parsing, like
+    // ones of `importtex`.
+    fn parse_module_import(
+        &mut self,
+        mut path: String,
+        path_location: Option<Span>,
+    ) -> error::Result<Statement<'a>> {
+        while let Some(TokenType::Period | TokenType::Slash) = self.peek_tok() {
+            path += &self.next_tok().unwrap().token.literal;
+            take_name!(self | define name);
+            path += &name;
+        }
+        let path_span = Some(self.span_since(path_location));
+        if !path.ends_with(".ves") {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::InvalidModulePathErr { path },
+                path_span,
+            ));
+        }
+        self.check_safe_path(&path, path_span)?;
+        self.eat_whitespaces(false);
+
+        let has_prefix = self.peek_tok.as_ref().is_some_and(|tok| {
+            tok.token.toktype == TokenType::MainString && tok.token.literal == "as"
+        });
+        let mut prefix = None;
+        if has_prefix {
+            self.next_tok();
+            self.eat_whitespaces(false);
+            let prefix_location = self.peek_tok_location();
+            take_name!(self | define name);
+            // latex commands are made only of letters
+            if name.is_empty() || !name.chars().all(|chr| chr.is_ascii_alphabetic()) {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::InvalidModulePrefixErr { prefix: name },
+                    prefix_location,
+                ));
+            }
+            prefix = Some(self.alloc(&name));
+            self.eat_whitespaces(false);
+        }
         if self.peek_tok() == Some(TokenType::Newline) {
             self.next_tok();
         }
 
-        Ok(Statement::Usepackage { name, options })
+        Ok(Statement::ImportModule {
+            path: self.alloc(&path),
+            path_span,
+            prefix,
+        })
     }
 
-    fn parse_multiple_usepackages(&mut self) -> error::Result<Statement> {
-        let mut pkgs: Vec<Statement> = Vec::new();
+    fn parse_multiple_usepackages(&mut self) -> error::Result<Statement<'a>> {
+        let mut pkgs: Latex = Vec::new();
 
         expect_peek!(self | TokenType::Lbrace; self.peek_tok_location());
         self.eat_whitespaces(true);
 
         while self.peek_tok() != Some(TokenType::Rbrace) {
             let mut options: Option<Vec<Latex>> = None;
+            let name_location = self.peek_tok_location();
             take_name!(self | define name);
 
             self.parse_comma_args(&mut options)?;
+            self.push_package(&name, name_location)?;
+            let name = self.alloc(&name);
 
             match self.peek_tok() {
                 Some(TokenType::Newline) => self.eat_whitespaces(true),
                 Some(TokenType::MainString) => {}
                 Some(TokenType::RawLatex) => {}
                 Some(TokenType::Rbrace) => {
-                    pkgs.push(Statement::Usepackage { name, options });
+                    pkgs.push(Spanned::new(
+                        Statement::Usepackage { name, options },
+                        self.span_since(name_location),
+                    ));
                     break;
                 }
                 Some(tok_type) => {
@@ -434,7 +2734,10 @@ impl<'a> Parser<'a> {
                 }
             }
 
-            pkgs.push(Statement::Usepackage { name, options });
+            pkgs.push(Spanned::new(
+                Statement::Usepackage { name, options },
+                self.span_since(name_location),
+            ));
         }
 
         expect_peek!(self | TokenType::Rbrace; self.peek_tok_location());
@@ -447,7 +2750,294 @@ impl<'a> Parser<'a> {
         Ok(Statement::MultiUsepackages { pkgs })
     }
 
-    fn parse_environment(&mut self) -> error::Result<Statement> {
+    fn parse_counter_depth(&mut self) -> error::Result<Statement<'a>> {
+        let keyword_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        let counter = self.alloc_literal(literal);
+        self.eat_whitespaces(false);
+
+        let depth_tok = match self.next_tok() {
+            Some(tok) => tok,
+            None => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::EOFErr,
+                    keyword_location,
+                ))
+            }
+        };
+        if depth_tok.token.toktype != TokenType::Integer {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::TypeMismatch {
+                    expected: vec![TokenType::Integer],
+                    got: depth_tok.token.toktype,
+                },
+                Some(depth_tok.span),
+            ));
+        }
+        let value: i64 = match depth_tok.token.literal.parse() {
+            Ok(value) => value,
+            Err(_) => {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::ParseIntErr,
+                    Some(depth_tok.span),
+                ))
+            }
+        };
+        if !(-1..=5).contains(&value) {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::DepthOutOfRangeErr { got: value },
+                Some(depth_tok.span),
+            ));
+        }
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::SetCounter { counter, value })
+    }
+
+    fn parse_maketoc(&mut self) -> error::Result<Statement<'a>> {
+        expect_peek!(self | TokenType::Maketoc; self.peek_tok_location());
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        // `\dominitoc` should be placed before `\tableofcontents` to use minitoc.
+        Ok(Statement::TableOfContents {
+            minitoc: self.has_package("minitoc"),
+        })
+    }
+
+    fn parse_minitoc(&mut self) -> error::Result<Statement<'a>> {
+        let minitoc_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Minitoc; minitoc_location);
+
+        if let Some(docclass) = &self.docclass {
+            if DOCCLASS_WITHOUT_CHAPTER.contains(&docclass.as_str()) {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::NoChapterInDocclassErr {
+                        docclass: docclass.clone(),
+                    },
+                    minitoc_location,
+                ));
+            }
+        }
+        self.require_package("minitoc", minitoc_location)?;
+
+        self.eat_whitespaces(false);
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        Ok(Statement::MiniToc)
+    }
+
+    fn parse_table(&mut self) -> error::Result<Statement<'a>> {
+        let begtbl_location = self.peek_tok_location();
+        expect_peek!(self | TokenType::Begtbl; begtbl_location);
+        self.eat_whitespaces(false);
+
+        // `begtbl (spec)` makes tabular, and `begtbl (width)(spec)` makes tabularx
+        let mut width: Option<Latex> = None;
+        let mut spec: Option<String> = None;
+        if self.peek_tok() == Some(TokenType::Lparen) {
+            let first_arg = self.parse_table_paren_arg()?;
+            self.eat_whitespaces(false);
+            if self.peek_tok() == Some(TokenType::Lparen) {
+                width = Some(first_arg);
+                spec = Some(latex_to_string(&self.parse_table_paren_arg()?));
+            } else {
+                spec = Some(latex_to_string(&first_arg));
+            }
+            self.eat_whitespaces(false);
+        }
+        if width.is_some() {
+            self.require_package("tabularx", begtbl_location)?;
+        }
+        expect_peek!(self | TokenType::Newline; self.peek_tok_location());
+
+        let mut rows: Vec<TableRow> = Vec::new();
+        loop {
+            self.eat_whitespaces(true);
+            match self.peek_tok() {
+                Some(TokenType::Endtbl) => break,
+                Some(TokenType::Minus) => {
+                    while self.peek_tok() == Some(TokenType::Minus) {
+                        self.next_tok();
+                    }
+                    rows.push(TableRow::Hline);
+                }
+                Some(TokenType::Vert) => rows.push(self.parse_table_row()?),
+                Some(toktype) => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::TypeMismatch {
+                            expected: vec![TokenType::Vert, TokenType::Minus, TokenType::Endtbl],
+                            got: toktype,
+                        },
+                        self.peek_tok_location(),
+                    ))
+                }
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BegtblIsNotClosedErr,
+                        begtbl_location,
+                    ))
+                }
+            }
+        }
+        expect_peek!(self | TokenType::Endtbl; self.peek_tok_location());
+        if self.peek_tok() == Some(TokenType::Newline) {
+            self.next_tok();
+        }
+
+        // If the column specification is omitted, every column is left aligned.
+        let spec = match spec {
+            Some(spec) => spec.split_whitespace().collect(),
+            None => {
+                let column_num = rows
+                    .iter()
+                    .map(|row| match row {
+                        TableRow::Hline => 0,
+                        TableRow::Cells(cells) => cells.iter().map(|cell| cell.span).sum(),
+                    })
+                    .max()
+                    .unwrap_or(0);
+                "l".repeat(column_num)
+            }
+        };
+
+        Ok(Statement::Table {
+            width,
+            spec: self.alloc(&spec),
+            rows,
+        })
+    }
+
+    fn parse_table_paren_arg(&mut self) -> error::Result<Latex<'a>> {
+        let open_brace_location = self.peek_tok_location();
+        let mut nested = 0;
+        let mut output: Latex = Vec::new();
+        expect_peek!(self | TokenType::Lparen; open_brace_location);
+
+        while self.peek_tok() != Some(TokenType::Rparen) || nested > 0 {
+            match self.peek_tok() {
+                Some(TokenType::Lparen) => nested += 1,
+                Some(TokenType::Rparen) => nested -= 1,
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BracketNumberMatchedErr,
+                        open_brace_location,
+                    ))
+                }
+                _ => {}
+            }
+            output.push(self.parse_statement()?);
+        }
+        expect_peek!(self | TokenType::Rparen; self.peek_tok_location());
+
+        Ok(output)
+    }
+
+    fn parse_table_row(&mut self) -> error::Result<TableRow<'a>> {
+        expect_peek!(self | TokenType::Vert; self.peek_tok_location());
+
+        let mut cells: Vec<TableCell> = Vec::new();
+        loop {
+            self.eat_whitespaces(false);
+            match self.peek_tok() {
+                Some(TokenType::Newline) | None => break,
+                _ => cells.push(self.parse_table_cell()?),
+            }
+        }
+
+        // A row like `|---|---|` is also a horizontal line.
+        let is_hline = !cells.is_empty()
+            && cells.iter().all(|cell| {
+                cell.span == 1
+                    && !cell.text.is_empty()
+                    && cell
+                        .text
+                        .iter()
+                        .all(|stmt| stmt.node == Statement::MainText("-"))
+            });
+
+        Ok(if is_hline {
+            TableRow::Hline
+        } else {
+            TableRow::Cells(cells)
+        })
+    }
+
+    fn parse_table_cell(&mut self) -> error::Result<TableCell<'a>> {
+        let mut span = 1;
+        let mut align: Option<String> = None;
+
+        // Multicolumn cell starts with `<n>` or `<n align>` like `<2c>`
+        if self.peek_tok() == Some(TokenType::Less) {
+            let less_location = self.peek_tok_location();
+            self.next_tok();
+            let span_tok = self.next_tok().ok_or(VestiErr::make_parse_err(
+                VestiParseErr::EOFErr,
+                less_location,
+            ))?;
+            if span_tok.token.toktype != TokenType::Integer {
+                return Err(VestiErr::make_parse_err(
+                    VestiParseErr::TypeMismatch {
+                        expected: vec![TokenType::Integer],
+                        got: span_tok.token.toktype,
+                    },
+                    Some(span_tok.span),
+                ));
+            }
+            span = match span_tok.token.literal.parse() {
+                Ok(span) => span,
+                Err(_) => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::ParseIntErr,
+                        Some(span_tok.span),
+                    ))
+                }
+            };
+
+            let mut align_str = String::new();
+            while let Some(TokenType::MainString | TokenType::Vert) = self.peek_tok() {
+                align_str += &self.next_tok().unwrap().token.literal;
+            }
+            if !align_str.is_empty() {
+                align = Some(align_str);
+            }
+            expect_peek!(self | TokenType::Great; self.peek_tok_location());
+            self.eat_whitespaces(false);
+        }
+
+        let mut text: Latex = Vec::new();
+        while !matches!(
+            self.peek_tok(),
+            Some(TokenType::Vert | TokenType::Newline) | None
+        ) {
+            text.push(self.parse_statement()?);
+        }
+        if self.peek_tok() == Some(TokenType::Vert) {
+            self.next_tok();
+        }
+        while let Some(Statement::MainText(s)) = text.last().map(|stmt| &stmt.node) {
+            if !s.trim().is_empty() {
+                break;
+            }
+            text.pop();
+        }
+
+        Ok(TableCell {
+            span,
+            align: align.map(|align| self.alloc(&align)),
+            text,
+        })
+    }
+
+    fn parse_environment(&mut self) -> error::Result<Statement<'a>> {
         let begenv_location = self.peek_tok_location();
         let mut off_math_state = false;
 
@@ -461,7 +3051,7 @@ impl<'a> Parser<'a> {
             });
         }
         let mut name = match self.peek_tok() {
-            Some(TokenType::MainString) => self.next_tok().unwrap().token.literal,
+            Some(TokenType::MainString) => self.next_tok().unwrap().token.literal.into_owned(),
             Some(_) => {
                 return Err(VestiErr::make_parse_err(
                     VestiParseErr::BegenvNameMissErr,
@@ -476,6 +3066,12 @@ impl<'a> Parser<'a> {
             }
         };
 
+        let is_auto_align = name == AUTO_ALIGN_ENV;
+        if is_auto_align {
+            name = String::from("align");
+            self.required_packages.insert(String::from("amsmath"));
+        }
+
         // If name is math related one, then math mode will be turn on
         if ENV_MATH_IDENT.contains(&name.as_str()) {
             self.source.math_started = true;
@@ -494,17 +3090,19 @@ impl<'a> Parser<'a> {
             TokenType::Lsqbrace,
             TokenType::Rsqbrace,
         )?;
-        let mut text: Latex = Vec::new();
-
-        while self.peek_tok() != Some(TokenType::Endenv) {
-            if self.peek_tok().is_none() {
-                return Err(VestiErr::make_parse_err(
-                    VestiParseErr::BegenvIsNotClosedErr,
-                    begenv_location,
-                ));
+        let text = self.in_local_scope(|parser| {
+            let mut text: Latex = Vec::new();
+            while parser.peek_tok() != Some(TokenType::Endenv) {
+                if parser.peek_tok().is_none() {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::BegenvIsNotClosedErr,
+                        begenv_location,
+                    ));
+                }
+                text.push(parser.parse_statement()?);
             }
-            text.push(self.parse_statement()?);
-        }
+            Ok(text)
+        })?;
 
         expect_peek!(self | TokenType::Endenv; self.peek_tok_location());
 
@@ -515,19 +3113,85 @@ impl<'a> Parser<'a> {
         if self.peek_tok() == Some(TokenType::Newline) {
             self.next_tok();
         }
+        let text = if is_auto_align {
+            auto_align(text)
+        } else {
+            text
+        };
 
-        Ok(Statement::Environment { name, args, text })
+        Ok(Statement::Environment {
+            name: self.alloc(&name),
+            args,
+            text,
+        })
     }
 
-    fn parse_latex_function(&mut self) -> error::Result<Statement> {
-        let mut name = self
-            .next_tok()
-            .ok_or(VestiErr {
-                err_kind: VestiErrKind::ParseErr(VestiParseErr::EOFErr),
-                location: self.peek_tok_location(),
-            })?
-            .token
-            .literal;
+    fn parse_conditional(&mut self) -> error::Result<Statement<'a>> {
+        let if_location = self.peek_tok_location();
+        let literal = self.next_tok().unwrap().token.literal;
+        let cond = self.alloc_literal(literal);
+        if cond.is_empty() {
+            return Err(VestiErr::make_parse_err(
+                VestiParseErr::IfConditionMissErr,
+                if_location,
+            ));
+        }
+        let enabled = self.is_flag_satisfied(cond);
+
+        let mut then_branch: Latex = Vec::new();
+        let mut else_branch: Latex = Vec::new();
+        let mut is_else = false;
+
+        while self.peek_tok() != Some(TokenType::PreEndif) {
+            match self.peek_tok() {
+                Some(TokenType::PreElse) if !is_else => {
+                    self.next_tok();
+                    is_else = true;
+                }
+                Some(_) => {
+                    self.check_text_before_document()?;
+                    self.check_preamble_keyword()?;
+                    let stmt = self.parse_statement()?;
+                    if is_else {
+                        else_branch.push(stmt);
+                    } else {
+                        then_branch.push(stmt);
+                    }
+                }
+                None => {
+                    return Err(VestiErr::make_parse_err(
+                        VestiParseErr::IfDirectiveIsNotClosedErr,
+                        if_location,
+                    ))
+                }
+            }
+        }
+
+        expect_peek!(self | TokenType::PreEndif; self.peek_tok_location());
+
+        Ok(Statement::Conditional {
+            cond,
+            enabled,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    // A condition is either a flag name or its negation like `!draft`.
+    fn is_flag_satisfied(&self, cond: &str) -> bool {
+        match cond.strip_prefix('!') {
+            Some(flag) => !self.contains_name(&self.defines, flag.trim()),
+            None => self.contains_name(&self.defines, cond),
+        }
+    }
+
+    fn parse_latex_function(&mut self) -> error::Result<Statement<'a>> {
+        let name_tok = self.next_tok().ok_or(VestiErr {
+            err_kind: VestiErrKind::ParseErr(VestiParseErr::EOFErr),
+            location: self.peek_tok_location(),
+        })?;
+        let name_location = Some(name_tok.span);
+        let mut name = self.alloc_literal(name_tok.token.literal);
 
         let mut is_no_arg_but_space = false;
         if self.peek_tok() == Some(TokenType::Space) {
@@ -535,20 +3199,101 @@ impl<'a> Parser<'a> {
             self.eat_whitespaces(false);
         }
 
+        let is_text_function = TEXT_FUNCTIONS.contains(&name);
+        if is_text_function {
+            self.text_in_math_depth += 1;
+        }
         let args = self.parse_function_args(
             TokenType::Lbrace,
             TokenType::Rbrace,
             TokenType::OptionalOpenBrace,
             TokenType::Rsqbrace,
         )?;
+        if is_text_function {
+            self.text_in_math_depth -= 1;
+        }
+        self.track_label(name, &args, name_location);
+        self.check_argument_count(name, &args, name_location);
         if args.is_empty() && is_no_arg_but_space {
-            name += " ";
+            name = self.alloc(&format!("{} ", name));
         }
 
         Ok(Statement::LatexFunction { name, args })
     }
 
-    fn parse_comma_args(&mut self, options: &mut Option<Vec<Latex>>) -> error::Result<()> {
+    // Labels made by parameters of definitions like `\label{#1}` are not tracked.
+    fn track_label(&mut self, name: &str, args: &[(ArgNeed, Latex)], location: Option<Span>) {
+        let arg = match args.iter().find(|(need, _)| *need == ArgNeed::MainArg) {
+            Some((_, arg)) => latex_to_string(arg),
+            None => return,
+        };
+        if arg.contains(['#', '\\']) {
+            return;
+        }
+
+        if name == "label" {
+            self.labels.insert(self.strings.symbol(arg.trim()));
+        } else if REF_FUNCTIONS.contains(&name) && !self.is_allowed("undefined_reference") {
+            for label in arg.split(',').map(str::trim).filter(|l| !l.is_empty()) {
+                let label = self.strings.symbol(label);
+                self.references.push((label, location));
+            }
+        }
+    }
+
+    // Calls of functions defined by `defun` have as many arguments as their
+    // parameters. Empty arguments like `\foo{}` after them are allowed.
+    // `defun` over a command of latex or of an imported package replaces it
+    // silently since it is lowered into `\def`
+    fn check_redefinition(&mut self, name: &str, location: Option<Span>) {
+        let package = packages::command_package(name).filter(|package| self.has_package(package));
+        if package.is_some() || packages::is_core_command(name) {
+            self.warn(
+                VestiWarnKind::RedefinedCommand {
+                    name: name.to_string(),
+                    package: package.map(String::from),
+                },
+                location,
+            );
+        }
+    }
+
+    fn check_argument_count(
+        &mut self,
+        name: &str,
+        args: &[(ArgNeed, Latex)],
+        location: Option<Span>,
+    ) {
+        let expected = match self
+            .strings
+            .get(name)
+            .and_then(|name| self.functions.get(&name))
+        {
+            Some(expected) => *expected,
+            None => return,
+        };
+        let main_args: Vec<&Latex> = args
+            .iter()
+            .filter(|(need, _)| *need == ArgNeed::MainArg)
+            .map(|(_, arg)| arg)
+            .collect();
+        let mut got = main_args.len();
+        while got > expected && main_args[got - 1].is_empty() {
+            got -= 1;
+        }
+        if got != expected {
+            self.warn(
+                VestiWarnKind::ArgumentCountMismatch {
+                    name: name.to_string(),
+                    expected,
+                    got,
+                },
+                location,
+            );
+        }
+    }
+
+    fn parse_comma_args(&mut self, options: &mut Option<Vec<Latex<'a>>>) -> error::Result<()> {
         self.eat_whitespaces(false);
         if self.peek_tok() == Some(TokenType::Lparen) {
             let mut options_vec: Vec<Latex> = Vec::new();
@@ -608,8 +3353,8 @@ impl<'a> Parser<'a> {
         closed: TokenType,
         optional_open: TokenType,
         optional_closed: TokenType,
-    ) -> error::Result<Vec<(ArgNeed, Vec<Statement>)>> {
-        let mut args: Vec<(ArgNeed, Vec<Statement>)> = Vec::new();
+    ) -> error::Result<Vec<(ArgNeed, Latex<'a>)>> {
+        let mut args: Vec<(ArgNeed, Latex)> = Vec::new();
 
         if self.peek_tok() == Some(open)
             || self.peek_tok() == Some(optional_open)
@@ -647,7 +3392,7 @@ impl<'a> Parser<'a> {
 
     fn parse_function_args_core(
         &mut self,
-        args: &mut Vec<(ArgNeed, Vec<Statement>)>,
+        args: &mut Vec<(ArgNeed, Latex<'a>)>,
         open: TokenType,
         closed: TokenType,
         arg_need: ArgNeed,
@@ -657,7 +3402,7 @@ impl<'a> Parser<'a> {
         expect_peek!(self | open; open_brace_location);
 
         loop {
-            let mut tmp_vec: Vec<Statement> = Vec::new();
+            let mut tmp_vec: Latex = Vec::new();
             while (self.peek_tok() != Some(closed) || nested > 0)
                 && self.peek_tok() != Some(TokenType::ArgSpliter)
             {
@@ -691,3 +3436,240 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 }
+
+// Settings of vesti.toml which change how a file is parsed. Parsers of
+// included files and modules are configured with these too.
+pub fn configure_parser(parser: &mut Parser, config: &VestiConfig) {
+    parser.set_defines(&config.defines);
+    parser.set_math_operators(&config.math_operators);
+    parser.set_heading_config(&config.headings);
+    parser.set_xparse_environments(config.xparse_environments);
+    parser.set_dollar_math(config.dollar_math);
+}
+
+// The literal of `%!key: value`. Keys may be written after `vesti` like
+// `%!vesti output: thesis_main`, so that other tools do not take them.
+pub fn parse_file_directive(literal: &str) -> Result<FileDirective<'_>, VestiParseErr> {
+    let (key, value) = match literal.split_once(':') {
+        Some((key, value)) => (key.trim(), value.trim()),
+        None => (literal.trim(), ""),
+    };
+    let key = match key.split_once(char::is_whitespace) {
+        Some(("vesti", key)) => key.trim(),
+        _ => key,
+    };
+    let invalid_value = || VestiParseErr::InvalidFileDirectiveValueErr {
+        key: key.to_string(),
+        value: value.to_string(),
+    };
+    match key {
+        "engine" => value
+            .parse::<LatexEngineType>()
+            .map(FileDirective::Engine)
+            .map_err(|_| invalid_value()),
+        // the output is made next to the source, so directories are not allowed
+        "output" if is_output_name(value) => Ok(FileDirective::Output(value)),
+        "output" => Err(invalid_value()),
+        "watch" => value
+            .parse::<bool>()
+            .map(FileDirective::Watch)
+            .map_err(|_| invalid_value()),
+        _ => Err(VestiParseErr::UnknownFileDirectiveErr {
+            name: key.to_string(),
+        }),
+    }
+}
+
+fn is_output_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+fn parse_bib_backend(name: String, location: Option<Span>) -> error::Result<BibBackend> {
+    match name.as_str() {
+        "bibtex" => Ok(BibBackend::Bibtex),
+        "biber" => Ok(BibBackend::Biber),
+        _ => Err(VestiErr::make_parse_err(
+            VestiParseErr::InvalidBibBackendErr { name },
+            location,
+        )),
+    }
+}
+
+// The first relation of each line becomes the alignment point unless the line
+// already has `&`, and every line except the last one is broken with `\\`.
+// Blank lines are removed since a paragraph cannot end inside of align.
+fn auto_align(text: Latex) -> Latex {
+    let is_blank = |line: &Latex| latex_to_string(line).trim().is_empty();
+    let mut lines: Vec<Latex> = vec![Vec::new()];
+    for stmt in text {
+        let is_newline = matches!(&stmt.node, Statement::MainText(s) if *s == "\n");
+        lines.last_mut().unwrap().push(stmt);
+        if is_newline {
+            lines.push(Vec::new());
+        }
+    }
+    let last_line = lines.iter().rposition(|line| !is_blank(line));
+
+    let mut output = Latex::new();
+    for (idx, mut line) in lines.into_iter().enumerate() {
+        if is_blank(&line) {
+            let has_newline = is_newline_stmt(line.last());
+            if idx == 0 || !has_newline {
+                output.append(&mut line);
+            }
+            continue;
+        }
+        let has_ampersand = line
+            .iter()
+            .any(|stmt| matches!(&stmt.node, Statement::MainText(s) if *s == "&"));
+        let relation = line.iter().position(
+            |stmt| matches!(&stmt.node, Statement::MainText(s) if ALIGN_RELATIONS.contains(s)),
+        );
+        if let (false, Some(relation)) = (has_ampersand, relation) {
+            line.insert(relation, Statement::MainText("&").into());
+        }
+
+        let has_newline = is_newline_stmt(line.last());
+        let is_broken = latex_to_string(&line).trim_end().ends_with("\\\\");
+        if Some(idx) != last_line && !is_broken {
+            let at = if has_newline {
+                line.len() - 1
+            } else {
+                line.len()
+            };
+            line.insert(at, Statement::MainText(" \\\\").into());
+        }
+        output.append(&mut line);
+    }
+    output
+}
+
+fn is_newline_stmt(stmt: Option<&Spanned<Statement>>) -> bool {
+    matches!(stmt.map(|stmt| &stmt.node), Some(Statement::MainText(s)) if *s == "\n")
+}
+
+// `\label{...}` in raw latex
+fn labels_in_raw_latex(raw: &str) -> Vec<&str> {
+    raw.match_indices("\\label{")
+        .filter_map(|(idx, pat)| {
+            let rest = &raw[idx + pat.len()..];
+            rest.find('}').map(|end| rest[..end].trim())
+        })
+        .filter(|label| !label.contains('#'))
+        .collect()
+}
+
+// Whether a part of a document has entries of `index "term"`
+pub fn has_index_entries(latex: &Latex) -> bool {
+    let mut has_entries = false;
+    walk_latex(latex, &mut |stmt| {
+        has_entries |= matches!(stmt, Statement::Index(_));
+    });
+    has_entries
+}
+
+// `\makeindex` right before `document` and `\printindex` at the end of the body,
+// unless the document has them already. Chapters have index entries of the main
+// document too, so this is also used after they are parsed.
+pub fn insert_index_commands(latex: &mut Latex) {
+    let (mut has_makeindex, mut has_printindex) = (false, false);
+    walk_latex(latex, &mut |stmt| match stmt {
+        Statement::MakeIndex => has_makeindex = true,
+        Statement::PrintIndex => has_printindex = true,
+        Statement::LatexFunction { name, .. } => {
+            has_makeindex |= name.trim_end() == "makeindex";
+            has_printindex |= name.trim_end() == "printindex";
+        }
+        _ => {}
+    });
+    let Some(doc_start) = latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart)
+    else {
+        return;
+    };
+    if !has_printindex {
+        if let Some(doc_end) = latex
+            .iter()
+            .rposition(|stmt| stmt.node == Statement::DocumentEnd)
+        {
+            latex.insert(doc_end, Statement::PrintIndex.into());
+        }
+    }
+    if !has_makeindex {
+        latex.insert(doc_start, Statement::MakeIndex.into());
+    }
+}
+
+// Whether a part of a source may define or refer to labels. Labels made by
+// keyword options like `label fig:x` are counted by the word.
+pub fn mentions_labels(source: &str) -> bool {
+    Lexer::new(source).any(|tok| match tok.token.toktype {
+        TokenType::LatexFunction => {
+            let name = tok.token.literal.trim_end();
+            name == "label" || REF_FUNCTIONS.contains(&name)
+        }
+        TokenType::RawLatex | TokenType::RawLatexBlock => tok.token.literal.contains("\\label{"),
+        TokenType::MainString => tok.token.literal == "label",
+        TokenType::Heading => true,
+        _ => false,
+    })
+}
+
+// A package imported several times is imported once where it is imported first,
+// with options of every import. The order of imports is kept since some
+// packages should be loaded after others. Imports inside `#if` are not merged.
+fn merge_usepackages<'a>(latex: &mut Latex<'a>) {
+    let doc_start = latex
+        .iter()
+        .position(|stmt| stmt.node == Statement::DocumentStart)
+        .unwrap_or(latex.len());
+
+    let mut merged: HashMap<&str, Option<Vec<Latex>>> = HashMap::new();
+    let mut merge = |stmt: &Statement<'a>| {
+        if let Statement::Usepackage { name, options } = stmt {
+            let merged_options = merged.entry(*name).or_default();
+            for opt in options.iter().flatten() {
+                let merged_options = merged_options.get_or_insert_with(Vec::new);
+                let opt_str = latex_to_string(opt);
+                if !merged_options
+                    .iter()
+                    .any(|merged| latex_to_string(merged).trim() == opt_str.trim())
+                {
+                    merged_options.push(opt.clone());
+                }
+            }
+        }
+    };
+    for stmt in &latex[..doc_start] {
+        match &stmt.node {
+            Statement::MultiUsepackages { pkgs } => pkgs.iter().for_each(|pkg| merge(pkg)),
+            stmt => merge(stmt),
+        }
+    }
+
+    let body = latex.split_off(doc_start);
+    let mut imported: HashSet<&str> = HashSet::new();
+    let mut dedup = |pkg: Spanned<Statement<'a>>| match pkg.node {
+        Statement::Usepackage { name, .. } if !imported.insert(name) => None,
+        Statement::Usepackage { name, .. } => Some(Spanned::new(
+            Statement::Usepackage {
+                options: merged[name].clone(),
+                name,
+            },
+            pkg.span,
+        )),
+        stmt => Some(Spanned::new(stmt, pkg.span)),
+    };
+    for Spanned { node, span } in std::mem::take(latex) {
+        let stmt = match node {
+            Statement::MultiUsepackages { pkgs } => {
+                let pkgs: Latex = pkgs.into_iter().filter_map(&mut dedup).collect();
+                (!pkgs.is_empty()).then(|| Spanned::new(Statement::MultiUsepackages { pkgs }, span))
+            }
+            node => dedup(Spanned::new(node, span)),
+        };
+        latex.extend(stmt);
+    }
+    latex.extend(body);
+}
@@ -1,7 +1,13 @@
 use super::*;
 
+// Statements of the latex without their spans
+fn nodes(latex: Latex) -> Vec<Statement> {
+    latex.into_iter().map(|stmt| stmt.node).collect()
+}
+
 #[test]
 fn test_parse_docclass() {
+    let arena = Arena::new();
     let source1 = "docclass article";
     let source2 = "docclass standalone (tikz)";
     let source3 = "docclass standalone ( tikz  )";
@@ -21,12 +27,12 @@ fn test_parse_docclass() {
     let expected2 = "\\documentclass[tikz]{standalone}\n";
     let expected3 = "\\documentclass[korean,tikz,tcolorbox]{coprime}\n";
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
-    let mut parser3 = Parser::new(Lexer::new(source3));
-    let mut parser4 = Parser::new(Lexer::new(source4));
-    let mut parser5 = Parser::new(Lexer::new(source5));
-    let mut parser6 = Parser::new(Lexer::new(source6));
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
     assert_eq!(expected1, parser1.make_latex_format().unwrap());
     assert_eq!(expected2, parser2.make_latex_format().unwrap());
     assert_eq!(expected2, parser3.make_latex_format().unwrap());
@@ -37,6 +43,7 @@ fn test_parse_docclass() {
 
 #[test]
 fn test_parse_usepackage() {
+    let arena = Arena::new();
     let source1 = "import kotex";
     let source2 = "import tcolorbox (many)";
     let source3 = "import tcolorbox ( many )";
@@ -83,15 +90,15 @@ fn test_parse_usepackage() {
 \usepackage[a4paper,margin=0.4in]{geometry}
 "#;
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
-    let mut parser3 = Parser::new(Lexer::new(source3));
-    let mut parser4 = Parser::new(Lexer::new(source4));
-    let mut parser5 = Parser::new(Lexer::new(source5));
-    let mut parser6 = Parser::new(Lexer::new(source6));
-    let mut parser7 = Parser::new(Lexer::new(source7));
-    let mut parser8 = Parser::new(Lexer::new(source8));
-    let mut parser9 = Parser::new(Lexer::new(source9));
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
+    let mut parser7 = Parser::new(Lexer::new(source7), &arena);
+    let mut parser8 = Parser::new(Lexer::new(source8), &arena);
+    let mut parser9 = Parser::new(Lexer::new(source9), &arena);
     assert_eq!(expected1, parser1.make_latex_format().unwrap());
     assert_eq!(expected2, parser2.make_latex_format().unwrap());
     assert_eq!(expected2, parser3.make_latex_format().unwrap());
@@ -105,32 +112,34 @@ fn test_parse_usepackage() {
 
 #[test]
 fn parse_main_string() {
+    let arena = Arena::new();
     let source1 = "document This is vesti";
     let source2 = "document docclass";
 
     let expected_ast1 = vec![
         Statement::DocumentStart,
-        Statement::MainText(String::from("This")),
-        Statement::MainText(String::from(" ")),
-        Statement::MainText(String::from("is")),
-        Statement::MainText(String::from(" ")),
-        Statement::MainText(String::from("vesti")),
+        Statement::MainText("This"),
+        Statement::MainText(" "),
+        Statement::MainText("is"),
+        Statement::MainText(" "),
+        Statement::MainText("vesti"),
         Statement::DocumentEnd,
     ];
     let expected_ast2 = vec![
         Statement::DocumentStart,
-        Statement::MainText(String::from("docclass")),
+        Statement::MainText("docclass"),
         Statement::DocumentEnd,
     ];
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
-    assert_eq!(expected_ast1, parser1.parse_latex().unwrap());
-    assert_eq!(expected_ast2, parser2.parse_latex().unwrap());
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    assert_eq!(expected_ast1, nodes(parser1.parse_latex().unwrap()));
+    assert_eq!(expected_ast2, nodes(parser2.parse_latex().unwrap()));
 }
 
 #[test]
 fn parse_environment() {
+    let arena = Arena::new();
     let source1 = r#"document begenv center
     The Document.
 endenv"#;
@@ -196,13 +205,13 @@ endenv"#;
 \end{document}
 "#;
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
-    let mut parser3 = Parser::new(Lexer::new(source3));
-    let mut parser4 = Parser::new(Lexer::new(source4));
-    let mut parser5 = Parser::new(Lexer::new(source5));
-    let mut parser6 = Parser::new(Lexer::new(source6));
-    let mut parser7 = Parser::new(Lexer::new(source7));
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
+    let mut parser7 = Parser::new(Lexer::new(source7), &arena);
     assert_eq!(expected1, parser1.make_latex_format().unwrap());
     assert_eq!(expected2, parser2.make_latex_format().unwrap());
     assert_eq!(expected2, parser3.make_latex_format().unwrap());
@@ -214,6 +223,7 @@ endenv"#;
 
 #[test]
 fn parse_latex_functions() {
+    let arena = Arena::new();
     let source1 = "document \\foo";
     let source2 = "document \\foo{bar1}";
     let source3 = "document \\foo#[bar1]";
@@ -264,16 +274,16 @@ fn parse_latex_functions() {
 \end{document}
 "#;
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
-    let mut parser3 = Parser::new(Lexer::new(source3));
-    let mut parser4 = Parser::new(Lexer::new(source4));
-    let mut parser5 = Parser::new(Lexer::new(source5));
-    let mut parser6 = Parser::new(Lexer::new(source6));
-    let mut parser7 = Parser::new(Lexer::new(source7));
-    let mut parser8 = Parser::new(Lexer::new(source8));
-    let mut parser9 = Parser::new(Lexer::new(source9));
-    let mut parser10 = Parser::new(Lexer::new(source10));
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
+    let mut parser7 = Parser::new(Lexer::new(source7), &arena);
+    let mut parser8 = Parser::new(Lexer::new(source8), &arena);
+    let mut parser9 = Parser::new(Lexer::new(source9), &arena);
+    let mut parser10 = Parser::new(Lexer::new(source10), &arena);
     assert_eq!(expected1, parser1.make_latex_format().unwrap());
     assert_eq!(expected2, parser2.make_latex_format().unwrap());
     assert_eq!(expected3, parser3.make_latex_format().unwrap());
@@ -288,6 +298,7 @@ fn parse_latex_functions() {
 
 #[test]
 fn test_parse_math_stmt() {
+    let arena = Arena::new();
     let source1 = "document \\(\\sum_1^\\infty f(x)\\)";
     let source2 = "document \\[\\sum_1^\\infty f(x)\\]";
 
@@ -300,8 +311,1860 @@ fn test_parse_math_stmt() {
 \end{document}
 "#;
 
-    let mut parser1 = Parser::new(Lexer::new(source1));
-    let mut parser2 = Parser::new(Lexer::new(source2));
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+}
+
+#[test]
+fn test_parse_conditional() {
+    let arena = Arena::new();
+    let source = r#"document
+#if draft
+Draft
+#else
+Final
+#endif
+#if !draft
+Not a draft
+#endif"#;
+
+    let expected1 = r#"\begin{document}
+Draft
+
+\end{document}
+"#;
+    let expected2 = r#"\begin{document}
+Final
+Not a draft
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source), &arena);
+    parser1.set_defines(&["draft"]);
+    let mut parser2 = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+
+    let mut parser3 = Parser::new(Lexer::new("document\n#if draft\nfoo"), &arena);
+    let mut parser4 = Parser::new(Lexer::new("document\n#endif\n"), &arena);
+    assert_eq!(
+        parser3.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::IfDirectiveIsNotClosedErr)
+    );
+    assert_eq!(
+        parser4.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DirectiveIsUsedWithoutIfPairErr {
+            got: TokenType::PreEndif
+        })
+    );
+}
+
+#[test]
+fn test_parse_toc_directives() {
+    let arena = Arena::new();
+    let source1 = r#"docclass article
+tocdepth 2
+secnumdepth -1
+document
+maketoc
+Text"#;
+    let source2 = r#"docclass book
+import minitoc
+document
+maketoc
+\chapter{Intro}
+minitoc
+Text"#;
+    let source3 = "docclass article\nimport minitoc\ndocument\nminitoc";
+    let source4 = "docclass book\ndocument\nminitoc";
+    let source5 = "tocdepth 6";
+
+    let expected1 = r#"\documentclass{article}
+\setcounter{tocdepth}{2}
+\setcounter{secnumdepth}{-1}
+\begin{document}
+\tableofcontents
+Text
+\end{document}
+"#;
+    let expected2 = r#"\documentclass{book}
+\usepackage{minitoc}
+\begin{document}
+\dominitoc
+\tableofcontents
+\chapter{Intro}
+\minitoc
+Text
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+    assert_eq!(
+        parser3.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::NoChapterInDocclassErr {
+            docclass: String::from("article")
+        })
+    );
+    // minitoc is imported for the keyword unless `--no-auto-import` is given
+    assert_eq!(
+        parser4.make_latex_format().unwrap(),
+        "\\documentclass{book}\n\\usepackage{minitoc}\n\\begin{document}\n\\minitoc\n\n\\end{document}\n"
+    );
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    parser4.set_auto_import(false);
+    assert_eq!(
+        parser4.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::PackageIsNotImportedErr {
+            name: String::from("minitoc")
+        })
+    );
+    let source6 = "docclass book\ndocument\nmaketoc\nminitoc";
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
+    assert!(parser6
+        .make_latex_format()
+        .unwrap()
+        .contains("\\dominitoc\n\\tableofcontents\n"));
+    assert_eq!(
+        parser5.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DepthOutOfRangeErr { got: 6 })
+    );
+}
+
+#[test]
+fn test_parse_table() {
+    let arena = Arena::new();
+    let source1 = r#"document
+begtbl (l | c r)
+    | Name  | Age | City  |
+    ---
+    | Alice | 30  | Seoul |
+    |<2c> Merged  | x     |
+endtbl
+"#;
+    let source2 = r#"import tabularx
+document
+begtbl (\textwidth)(X l)
+| foo | \textbf{bar} |
+|-----|--------------|
+| $!x$! | baz
+endtbl"#;
+    let source3 = "document\nbegtbl\n| a | b |\n|<3> c |\n";
+    let source4 = "document\nbegtbl (\\textwidth)(X)\n| a |\nendtbl\n";
+
+    let expected1 = r#"\begin{document}
+\begin{tabular}{l|cr}
+Name & Age & City \\
+\hline
+Alice & 30 & Seoul \\
+\multicolumn{2}{c}{Merged} & x \\
+\end{tabular}
+
+\end{document}
+"#;
+    let expected2 = r#"\usepackage{tabularx}
+\begin{document}
+\begin{tabularx}{\textwidth}{Xl}
+foo & \textbf{bar} \\
+\hline
+$x$ & baz \\
+\end{tabularx}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+    assert_eq!(
+        parser3.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BegtblIsNotClosedErr)
+    );
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    assert!(parser4
+        .make_latex_format()
+        .unwrap()
+        .starts_with("\\usepackage{tabularx}\n"));
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    parser4.set_auto_import(false);
+    assert_eq!(
+        parser4.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::PackageIsNotImportedErr {
+            name: String::from("tabularx")
+        })
+    );
+}
+
+#[test]
+fn test_parse_embedded_file() {
+    let arena = Arena::new();
+    let source1 = "embedfile \"data/hello.txt\" #-dmVz\ndGkh-#\ndocument";
+    let source2 = "embedfile \"../hello.txt\" #-dmVzdGkh-#";
+    let source3 = "embedfile \"hello.txt\" #-dmVz*dGkh-#";
+
+    let expected1 = vec![
+        Statement::EmbeddedFile {
+            name: "data/hello.txt",
+            data: b"vesti!".to_vec(),
+        },
+        Statement::DocumentStart,
+        Statement::DocumentEnd,
+    ];
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    assert_eq!(expected1, nodes(parser1.parse_latex().unwrap()));
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidEmbedPathErr {
+            path: String::from("../hello.txt")
+        })
+    );
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidBase64Err)
+    );
+}
+
+#[test]
+fn test_parse_list() {
+    let arena = Arena::new();
+    let source = r#"document
+- foo
+- bar
+    1. one
+    2. two
+	   - deep
+- baz
+  continued
+
+After - this is not a list
+-5 is a number
+"#;
+
+    let expected = r#"\begin{document}
+\begin{itemize}
+\item foo
+\item bar
+\begin{enumerate}
+\item one
+\item two
+\begin{itemize}
+\item deep
+\end{itemize}
+\end{enumerate}
+\item baz
+continued
+\end{itemize}
+
+After - this is not a list
+-5 is a number
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+}
+
+#[test]
+fn test_text_before_document() {
+    let arena = Arena::new();
+    let source1 = "docclass article\n\\title{My Title}\nHello, world!\ndocument";
+    let source2 = "docstartmode\nHello, world!";
+    let source3 = "#if draft\n42\n#endif";
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    assert_eq!(
+        parser1.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::TextBeforeDocumentErr {
+            text: String::from("Hello")
+        })
+    );
+    assert_eq!("Hello, world!", parser2.make_latex_format().unwrap());
+    assert_eq!(
+        parser3.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::TextBeforeDocumentErr {
+            text: String::from("42")
+        })
+    );
+}
+
+#[test]
+fn test_preamble_keyword_in_body() {
+    let arena = Arena::new();
+    let source1 = "docclass article\ndocument\nfoo\ndocument\nbar";
+    let source2 = "document\nHello\ndocclass article";
+    let source3 = "docstartmode\n#if draft\nimport amsmath\n#endif";
+    let source4 = "document\nwe import the data and document it";
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let err1 = parser1.parse_latex().unwrap_err();
+    assert_eq!(
+        err1.err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DuplicatedDocumentErr)
+    );
+    assert_eq!(err1.location.unwrap().start, Location::new(4, 1));
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::AfterDocumentErr {
+            got: TokenType::Docclass
+        })
+    );
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::AfterDocumentErr {
+            got: TokenType::Import
+        })
+    );
+    assert_eq!(
+        "\\begin{document}\nwe import the data and document it\n\\end{document}\n",
+        parser4.make_latex_format().unwrap()
+    );
+}
+
+#[test]
+fn test_parse_figure() {
+    let arena = Arena::new();
+    let source1 = r#"import graphicx
+document
+importimg "figures/logo.png" (width=0.5\textwidth, caption "The logo of vesti", label fig:logo)
+importimg "plot" (
+    placement = htbp,
+    scale = 2,
+)
+"#;
+    let source2 = "document\nimportimg \"logo.png\"";
+    let source3 = "import graphicx\ndocument\nimportimg \"logo.png\" (size=3cm)";
+    let source4 = "import graphicx\ndocument\nimportimg \"logo.png\" (width=3cm";
+
+    let expected1 = r#"\usepackage{graphicx}
+\begin{document}
+\begin{figure}
+\centering
+\includegraphics[width=0.5\textwidth]{figures/logo.png}
+\caption{The logo of vesti}
+\label{fig:logo}
+\end{figure}
+\begin{figure}[htbp]
+\centering
+\includegraphics[scale=2]{plot}
+\end{figure}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert!(parser2
+        .make_latex_format()
+        .unwrap()
+        .starts_with("\\usepackage{graphicx}\n"));
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    parser2.set_auto_import(false);
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::PackageIsNotImportedErr {
+            name: String::from("graphicx")
+        })
+    );
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidImgOptionErr {
+            name: String::from("size")
+        })
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BracketNumberMatchedErr)
+    );
+}
+
+#[test]
+fn test_parse_bibliography() {
+    let arena = Arena::new();
+    let source1 = r#"importbib "refs.bib" (style=alpha)
+document
+Knuth wrote @[knuth84, knuth86].
+"#;
+    let source2 = r#"importbib "refs.bib" (backend=biber, style=numeric)
+document
+@[lamport94]
+printbib
+Appendix
+"#;
+    let source3 = "importbib \"a.bib\"\nimportbib \"b.bib\"";
+    let source4 = "importbib \"a.bib\" (backend=foo)";
+    let source5 = "document\nprintbib";
+
+    let expected1 = r#"\bibliographystyle{alpha}
+\begin{document}
+Knuth wrote \cite{knuth84,knuth86}.
+\bibliography{refs}
+
+\end{document}
+"#;
+    let expected2 = r#"\usepackage[backend=biber,style=numeric]{biblatex}
+\addbibresource{refs.bib}
+\begin{document}
+\cite{lamport94}
+\printbibliography
+Appendix
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DuplicatedImportbibErr)
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidBibBackendErr {
+            name: String::from("foo")
+        })
+    );
+    assert_eq!(
+        parser5.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::PrintbibWithoutImportbibErr)
+    );
+}
+
+#[test]
+fn test_parse_raw_latex_block() {
+    let arena = Arena::new();
+    let source1 = r#"docclass article
+latex%
+\newcommand{\half}{\frac{1}{2}} % a half }
+%end
+document
+- item
+latex%
+\begin{tikzpicture}
+  \draw (0,0) -- (1,1);
+\end{tikzpicture}
+%end
+- next item
+"#;
+    let source2 = "document\nlatex%\n\\foo{";
+
+    let expected1 = r#"\documentclass{article}
+\newcommand{\half}{\frac{1}{2}} % a half }
+\begin{document}
+\begin{itemize}
+\item item
+\end{itemize}
+\begin{tikzpicture}
+  \draw (0,0) -- (1,1);
+\end{tikzpicture}
+\begin{itemize}
+\item next item
+\end{itemize}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::RawLatexBlockIsNotClosedErr)
+    );
+}
+
+#[test]
+fn test_parse_codeblock() {
+    let arena = Arena::new();
+    let source1 = r#"import listings
+document
+codeblock (rust, numbers=left)
+fn main() {
+    println!("100% {}", "$x$"); # not a comment
+}
+endcode
+Done.
+"#;
+    let source2 = r#"import minted
+document
+begenv center
+    codeblock
+    \foo{
+    endcode
+endenv
+"#;
+    let source3 = "document\ncodeblock (rust)\nfn main() {}\nendcode";
+    let source4 = "import listings\ndocument\ncodeblock (rust)\nfn main() {}\n";
+
+    let expected1 = r#"\usepackage{listings}
+\begin{document}
+\begin{lstlisting}[language=rust,numbers=left]
+fn main() {
+    println!("100% {}", "$x$"); # not a comment
+}
+\end{lstlisting}
+Done.
+
+\end{document}
+"#;
+    let expected2 = r#"\usepackage{minted}
+\begin{document}
+\begin{center}
+    \begin{minted}{text}
+    \foo{
+\end{minted}
+\end{center}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(expected2, parser2.make_latex_format().unwrap());
+    assert!(parser3.make_latex_format().unwrap().contains(
+        "\\usepackage{listings}\n\\begin{document}\n\\begin{lstlisting}[language=rust]\n"
+    ));
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    parser3.set_auto_import(false);
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::PackageIsNotImportedErr {
+            name: String::from("listings")
+        })
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::CodeblockIsNotClosedErr)
+    );
+}
+
+#[test]
+fn test_parse_attribute() {
+    let arena = Arena::new();
+    let source1 = r#"import amsmath
+import amsmath
+#[allow(duplicated_import)]
+import {
+    amsmath
+    amssymb
+}
+#[allow(foo, duplicated_import)]
+import amssymb
+document
+"#;
+    let source2 = "#[allow(duplicated_import]\nimport amsmath";
+
+    let expected1 = r#"\usepackage{amsmath}
+\usepackage{amssymb}
+\begin{document}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    assert_eq!(
+        parser1
+            .take_warnings()
+            .into_iter()
+            .map(|warning| warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![
+            VestiWarnKind::DuplicatedImport {
+                name: String::from("amsmath")
+            },
+            VestiWarnKind::UnknownLint {
+                name: String::from("foo")
+            },
+        ]
+    );
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidAttributeErr)
+    );
+}
+
+#[test]
+fn test_parse_bibstyle() {
+    let arena = Arena::new();
+    let source1 = r#"bibstyle (numeric, backend: biber)
+importbib "refs.bib"
+document
+@[knuth84]
+"#;
+    let source2 = r#"bibstyle (authoryear)
+importbib "refs.bib"
+document
+@[knuth84]
+"#;
+    let source3 = "import natbib\nbibstyle (numeric, backend: biber)";
+    let source4 = "bibstyle (numeric, backend: biber)\nimportbib \"refs.bib\" (backend=bibtex)";
+    let source5 = "importbib \"refs.bib\"\nbibstyle (numeric)";
+    let source6 = "bibstyle (numeric, sorting: nyt)";
+
+    let expected1 = r#"\usepackage[backend=biber,style=numeric]{biblatex}
+\addbibresource{refs.bib}
+\begin{document}
+\cite{knuth84}
+\printbibliography
+
+\end{document}
+"#;
+    let expected2 = r#"\usepackage[authoryear]{natbib}
+\bibliographystyle{plainnat}
+\begin{document}
+\cite{knuth84}
+\bibliography{refs}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    let mut parser6 = Parser::new(Lexer::new(source6), &arena);
     assert_eq!(expected1, parser1.make_latex_format().unwrap());
     assert_eq!(expected2, parser2.make_latex_format().unwrap());
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BiblatexNatbibConflictErr)
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BibstyleConflictErr)
+    );
+    assert_eq!(
+        parser5.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BibstyleConflictErr)
+    );
+    assert_eq!(
+        parser6.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidBibOptionErr {
+            keyword: String::from("bibstyle"),
+            name: String::from("sorting"),
+        })
+    );
+}
+
+#[test]
+fn test_parse_file_directive() {
+    let arena = Arena::new();
+    let source1 = "%!engine: xelatex\ndocclass article\ndocument\nfoo";
+    let source2 = "%!engine: omega\ndocument";
+    let source3 = "%!vesti format: a4\ndocument";
+    let source4 = "document\n%!engine: xelatex";
+
+    let expected1 = vec![
+        Statement::FileDirective(FileDirective::Engine(LatexEngineType::Xelatex)),
+        Statement::DocumentClass {
+            name: "article",
+            options: None,
+        },
+        Statement::DocumentStart,
+        Statement::MainText("foo"),
+        Statement::DocumentEnd,
+    ];
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    assert_eq!(expected1, nodes(parser1.parse_latex().unwrap()));
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidFileDirectiveValueErr {
+            key: String::from("engine"),
+            value: String::from("omega"),
+        })
+    );
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::UnknownFileDirectiveErr {
+            name: String::from("format"),
+        })
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::FileDirectiveAfterDocumentErr)
+    );
+
+    let source5 =
+        "%!vesti output: thesis_main\n%!vesti watch: false\n%!vesti engine: lualatex\ndocument";
+    let expected5 = vec![
+        Statement::FileDirective(FileDirective::Output("thesis_main")),
+        Statement::FileDirective(FileDirective::Watch(false)),
+        Statement::FileDirective(FileDirective::Engine(LatexEngineType::Lualatex)),
+        Statement::DocumentStart,
+        Statement::DocumentEnd,
+    ];
+    let mut parser5 = Parser::new(Lexer::new(source5), &arena);
+    assert_eq!(expected5, nodes(parser5.parse_latex().unwrap()));
+
+    let mut parser6 = Parser::new(Lexer::new("%!vesti output: out/main\ndocument"), &arena);
+    assert_eq!(
+        parser6.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidFileDirectiveValueErr {
+            key: String::from("output"),
+            value: String::from("out/main"),
+        })
+    );
+}
+
+#[test]
+fn test_parse_definitions() {
+    let arena = Arena::new();
+    let source1 = r#"docclass article
+defun pair (#!1#!2) { (#!1, #!2) }
+defun bold { \textbf{bold} }
+defun norm [s O{2} m] { \IfBooleanTF{#!1}{#!3}{#!3_{#!2}} }
+defun pt [r() u{;}] { (#!1) #!2 }
+defenv boxed (2, red) {
+    \begin{center}\color{#!1} #!2
+} {
+    \end{center}
+}
+defenv empty { \relax } { }
+defenv note [s O{Note} m] { \textbf{#!2} #!3 } { }
+document
+"#;
+    let source2 = "defun (#!1) { #!1 }";
+    let source3 = "defenv foo (x) { } { }";
+    let source4 = "defun foo [m q] { #!1 }";
+
+    let expected1 = r#"\documentclass{article}
+\def\pair#1#2{(#1, #2)}
+\def\bold{\textbf{bold}}
+\NewDocumentCommand{\norm}{s O{2} m}{\IfBooleanTF{#1}{#3}{#3_{#2}}}
+\NewDocumentCommand{\pt}{r() u{;}}{(#1) #2}
+\newenvironment{boxed}[2][red]{\begin{center}\color{#1} #2}{\end{center}}
+\newenvironment{empty}{\relax}{}
+\NewDocumentEnvironment{note}{s O{Note} m}{\textbf{#2} #3}{}
+\begin{document}
+
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    let mut parser4 = Parser::new(Lexer::new(source4), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    let mut xparse = Parser::new(Lexer::new(source1), &arena);
+    xparse.set_xparse_environments(true);
+    assert_eq!(
+        expected1
+            .replace(
+                "\\newenvironment{boxed}[2][red]",
+                "\\NewDocumentEnvironment{boxed}{O{red} m}"
+            )
+            .replace(
+                "\\newenvironment{empty}",
+                "\\NewDocumentEnvironment{empty}{}"
+            ),
+        xparse.make_latex_format().unwrap()
+    );
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DefinitionNameMissErr)
+    );
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::ParseIntErr)
+    );
+    assert_eq!(
+        parser4.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidArgSpecErr {
+            spec: String::from("m q"),
+        })
+    );
+}
+
+#[test]
+fn test_parse_dimension() {
+    let arena = Arena::new();
+    let source = r#"docclass article (margin=1.0in, 12pt)
+document
+\vspace{1.50cm}\hspace*{-3.0mm}
+begenv minipage (0.50\textwidth)
+    x
+endenv
+"#;
+    let expected = r#"\documentclass[margin=1.0in,12pt]{article}
+\begin{document}
+\vspace{1.50cm}\hspace*{-3.0mm}
+\begin{minipage}{0.50\textwidth}
+    x
+\end{minipage}
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+}
+
+#[test]
+fn test_parse_math_operators() {
+    let arena = Arena::new();
+    let source = r#"document
+\(sin x + \sin y = max(tr A, sinx)\) and sin
+\[
+    lim_{n -> \infty} a_n mtxt for max etxt \text{log} \frac{ln x}{rank B}
+\]
+begenv align
+    det A &= 1
+endenv
+"#;
+    let expected = r#"\usepackage{amsmath}
+\begin{document}
+\(\sin x + \sin y = \max(\operatorname{tr} A, sinx)\) and sin
+\[
+    \lim_{n \rightarrow  \infty} a_n \text{for max} \text{log} \frac{\ln x}{\operatorname{rank} B}
+\]
+\begin{align}
+    \det A &= 1
+\end{align}
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    parser.set_math_operators(&["tr", "rank"]);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+}
+
+#[test]
+fn test_parse_auto_align() {
+    let arena = Arena::new();
+    let source = r#"document
+begenv autoalign
+    f(x) = x^2 + 1
+         <= 2x^2
+
+    g(x) &= \sin x = y \\
+    h(x) >= 0
+endenv
+begenv autoalign*
+    a = b
+endenv
+"#;
+    let expected = r#"\usepackage{amsmath}
+\begin{document}
+\begin{align}
+    f(x) &= x^2 + 1 \\
+         &<= 2x^2 \\
+    g(x) &= \sin x = y \\
+    h(x) &>= 0
+\end{align}
+\begin{align*}
+    a &= b
+\end{align*}
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    // Packages needed by vesti are not imported twice
+    let mut parser = Parser::new(
+        Lexer::new("import amsmath\ndocument\nbegenv autoalign\nendenv"),
+        &arena,
+    );
+    assert_eq!(
+        "\\usepackage{amsmath}\n\\begin{document}\n\\begin{align}\n\\end{align}\n\n\\end{document}\n",
+        parser.make_latex_format().unwrap()
+    );
+}
+
+#[test]
+fn test_parse_strict_raw() {
+    let arena = Arena::new();
+    let source = r#"document
+Text #-\relax-#
+#[allow(raw_latex)]
+latex%
+\newpage
+%end
+latex%
+\clearpage
+%end
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source), &arena);
+    parser1.set_strict_raw(true);
+    parser1.parse_latex().unwrap();
+    parser2.parse_latex().unwrap();
+    let warnings = parser1.take_warnings();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| &warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![&VestiWarnKind::RawLatex, &VestiWarnKind::RawLatex]
+    );
+    assert_eq!(warnings[1].location.unwrap().start.row(), 7);
+    assert!(parser2.take_warnings().is_empty());
+}
+
+#[test]
+fn test_parse_math_delimiter() {
+    let arena = Arena::new();
+    let source1 = r#"document
+\[
+    #( \frac{1}{2} + #{ x #} #) and #( 0, 1 #}
+\]
+(not #( grown #)
+"#;
+    let source2 = "document\n\\( #( x + #( y #) \\)";
+    let source3 = "document\n\\( x #) \\)";
+
+    let expected1 = r#"\begin{document}
+\[
+    \left( \frac{1}{2} + \left\{ x \right\} \right) and \left( 0, 1 \right\}
+\]
+(not 
+\end{document}
+"#;
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    assert_eq!(expected1, parser1.make_latex_format().unwrap());
+    let err = parser2.parse_latex().unwrap_err();
+    assert_eq!(
+        err.err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BracketMismatchErr {
+            expected: TokenType::RightBig
+        })
+    );
+    assert_eq!(err.location.unwrap().start.column(), 4);
+    assert_eq!(
+        parser3.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidTokToParse {
+            got: TokenType::RightBig
+        })
+    );
+}
+
+#[test]
+fn test_parse_unicode_math() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+\( α → β ≤ ∑ xα αx \) α
+\[ ℝ mtxt α etxt \]
+"#;
+    let expected = r#"\documentclass{article}
+\usepackage{amssymb}
+\begin{document}
+\( \alpha \to \beta \leq \sum x\alpha \alpha x \) α
+\[ \mathbb{R} \text{α} \]
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let mut latex = parser.parse_latex().unwrap();
+    crate::symbols::keep_unicode_math(&mut latex);
+    assert!(latex_to_string(&latex).contains(r"\( α → β ≤ ∑ xα αx \)"));
+}
+
+#[test]
+fn test_parse_undefined_reference() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+import graphicx
+document
+\section{Intro}\label{sec:intro}
+See \ref{sec:intro}, \eqref{eq:missing} and \cref{fig:cat, sec:none}.
+importimg "cat.png" (label fig:cat)
+#-\label{raw}-# \pageref{raw}
+#[allow(undefined_reference)]
+\ref{external}
+\ref{late}
+\label{late}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    parser.parse_latex().unwrap();
+    let warnings = parser.take_warnings();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| &warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![
+            &VestiWarnKind::UndefinedReference {
+                label: String::from("eq:missing")
+            },
+            &VestiWarnKind::UndefinedReference {
+                label: String::from("sec:none")
+            },
+        ]
+    );
+    assert_eq!(warnings[0].location.unwrap().start.row(), 5);
+}
+
+#[test]
+fn test_imported_definitions() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+\input{defs}
+\pair{a} and \ref{sec:more} and \ref{sec:none}
+"#;
+    let mut imports = Definitions::default();
+    imports.functions.insert(String::from("pair"), 2);
+    imports.labels.insert(String::from("sec:more"));
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    parser.set_imports(&imports);
+    parser.parse_latex().unwrap();
+    assert_eq!(
+        parser
+            .take_warnings()
+            .into_iter()
+            .map(|warning| warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![
+            VestiWarnKind::ArgumentCountMismatch {
+                name: String::from("pair"),
+                expected: 2,
+                got: 1,
+            },
+            VestiWarnKind::UndefinedReference {
+                label: String::from("sec:none")
+            },
+        ]
+    );
+
+    // chapters see functions of both the main file and imported files
+    let mut parser = Parser::new(Lexer::new("\\pair{a}{b}{c}\n"), &arena);
+    parser.set_imports(&imports);
+    parser.set_document_context(&DocumentContext::default());
+    parser.parse_body().unwrap();
+    assert_eq!(parser.take_warnings().len(), 1);
+}
+
+#[test]
+fn test_parse_module_import() {
+    let arena = Arena::new();
+    let source = "docclass article\nimport defs.ves as m\nimport lib/my-defs.ves\n\
+                  import \"lib/more defs.ves\"\nimport amsmath\ndocument\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let statements: Vec<_> = parser
+        .parse_latex()
+        .unwrap()
+        .into_iter()
+        .filter_map(|stmt| match stmt.node {
+            Statement::ImportModule { path, prefix, .. } => Some((path, prefix)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        statements,
+        vec![
+            ("defs.ves", Some("m")),
+            ("lib/my-defs.ves", None),
+            ("lib/more defs.ves", None)
+        ]
+    );
+
+    let mut parser = Parser::new(Lexer::new("import defs.ves as m1\ndocument\n"), &arena);
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidModulePrefixErr {
+            prefix: String::from("m1")
+        })
+    );
+    let mut parser = Parser::new(Lexer::new("import defs.sty\ndocument\n"), &arena);
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidModulePathErr {
+            path: String::from("defs.sty")
+        })
+    );
+}
+
+#[test]
+fn test_parse_merged_usepackages() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+import geometry (a4paper)
+import {
+    xcolor
+    geometry (margin=1in, a4paper)
+}
+#[allow(duplicated_import)]
+import xcolor (dvipsnames)
+document
+"#;
+    let expected = r#"\documentclass{article}
+\usepackage[a4paper,margin=1in]{geometry}
+\usepackage[dvipsnames]{xcolor}
+\begin{document}
+
+\end{document}
+"#;
+
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+}
+
+#[test]
+fn test_parse_safe_mode() {
+    let arena = Arena::new();
+    let source1 = "docclass article\nimport graphicx\ndocument\nimportimg \"/etc/cat.png\"\n";
+    let source2 = "docclass article\nimportbib \"../refs.bib\"\ndocument\n";
+    let source3 = "docclass article\nimport graphicx\ndocument\nimportimg \"img/cat.png\"\n";
+
+    let mut parser1 = Parser::new(Lexer::new(source1), &arena);
+    let mut parser2 = Parser::new(Lexer::new(source2), &arena);
+    let mut parser3 = Parser::new(Lexer::new(source3), &arena);
+    for parser in [&mut parser1, &mut parser2, &mut parser3] {
+        parser.set_safe(true);
+    }
+    assert_eq!(
+        parser1.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::UnsafePathErr {
+            path: String::from("/etc/cat.png")
+        })
+    );
+    assert_eq!(
+        parser2.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::UnsafePathErr {
+            path: String::from("../refs.bib")
+        })
+    );
+    assert!(parser3.parse_latex().is_ok());
+    assert!(Parser::new(Lexer::new(source1), &arena)
+        .parse_latex()
+        .is_ok());
+
+    let mut parser = Parser::new(Lexer::new("import ../defs.ves as m\ndocument\n"), &arena);
+    parser.set_safe(true);
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::UnsafePathErr {
+            path: String::from("../defs.ves")
+        })
+    );
+}
+
+#[test]
+fn test_parse_spans() {
+    let arena = Arena::new();
+    let source = "document\nfoo mst x mnd\nbegenv center\n    bar\nendenv\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    let mut spans = Vec::new();
+    walk_spanned(&latex, &mut |stmt| {
+        let Span { start, end } = stmt.span;
+        spans.push((
+            stmt.to_string(),
+            (start.row(), start.column()),
+            (end.row(), end.column()),
+        ));
+    });
+
+    let span_of = |text: &str| {
+        spans
+            .iter()
+            .find(|(stmt, _, _)| stmt == text)
+            .map(|(_, start, end)| (*start, *end))
+    };
+    assert_eq!(span_of("foo"), Some(((2, 1), (2, 4))));
+    assert_eq!(span_of("\\( x \\)"), Some(((2, 5), (2, 14))));
+    assert_eq!(span_of("x"), Some(((2, 9), (2, 10))));
+    // Whitespaces after a statement are not in its span
+    assert_eq!(
+        span_of("\\begin{center}\n    bar\n\\end{center}\n"),
+        Some(((3, 1), (5, 7)))
+    );
+    assert_eq!(span_of("bar"), Some(((4, 5), (4, 8))));
+}
+
+#[test]
+fn test_serialize_ast() {
+    let arena = Arena::new();
+    let source = "document\nfoo";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    let json = serde_json::to_value(&latex).unwrap();
+
+    assert_eq!(json[0]["kind"], "DocumentStart");
+    assert_eq!(json[1]["kind"], "MainText");
+    assert_eq!(json[1]["value"], "foo");
+    assert_eq!(json[1]["span"]["start"]["row"], 2);
+    assert_eq!(json[1]["span"]["end"]["col"], 4);
+}
+
+#[test]
+fn test_emit_latex() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+import amsmath
+document
+begenv center
+    $x^2$ and $y$ #table
+endenv
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    let mut output = Vec::new();
+    maker::emit(&latex, &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), latex_to_string(&latex));
+
+    // An error of the writer is returned as it is
+    let mut full = [0u8; 16];
+    let err = maker::emit(&latex, &mut &mut full[..]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn test_markdown_output() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+\title{Vesti "notes"}
+document
+\section{Intro \textbf{here}}
+Hello \textbf{world}, see \href{https://example.com}{the site}.
+Math is \(x^2\) and \[a+b\]
+
+- one
+- two
+  1. nested
+  2. list
+begenv quote
+    Quoted
+    text
+endenv
+begenv center
+    Centered
+endenv
+##-
+\LaTeX
+-##
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    assert_eq!(
+        markdown::latex_to_markdown(&latex),
+        r#"---
+title: "Vesti \"notes\""
+---
+
+## Intro **here**
+
+Hello **world**, see [the site](https://example.com).
+Math is $x^2$ and $$a+b$$
+
+- one
+- two
+  1. nested
+  2. list
+
+> Quoted
+> text
+
+Centered
+
+\LaTeX
+"#
+    );
+}
+
+#[test]
+fn test_context_output() {
+    let arena = Arena::new();
+    let source = r#"docclass article (a4paper, 11pt)
+import {
+    amsmath
+    tikz
+}
+document
+\section{Intro}
+Hello \textbf{world}\label{sec:intro} and \(x^2\)
+begenv center
+    Centered
+endenv
+- one
+- two
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    assert_eq!(
+        context::latex_to_context(&latex),
+        r#"% documentclass: article
+\setuppapersize[A4]
+\setupbodyfont[11pt]
+\usemodule[tikz]
+\starttext
+\section{Intro}
+Hello {\bf world}\reference[sec:intro]{} and $x^2$
+\startalignment[middle]
+    Centered
+\stopalignment
+\startitemize
+\item one
+\item two
+\stopitemize
+
+\stoptext
+"#
+    );
+}
+
+#[test]
+fn test_parse_execute() {
+    let arena = Arena::new();
+    let source = "document\nWe execute it.\nexecute(python) {\nprint({'a': 1})\n}\nfoo";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let latex = parser.parse_latex().unwrap();
+    let nodes = nodes(latex);
+    assert!(nodes.contains(&Statement::MainText("execute")));
+    assert!(nodes.contains(&Statement::ExecuteBlock {
+        lang: "python",
+        code: "print({'a': 1})\n",
+        output: None,
+    }));
+    assert_eq!(
+        nodes[nodes.len() - 2..],
+        [Statement::MainText("foo"), Statement::DocumentEnd]
+    );
+
+    let mut parser = Parser::new(
+        Lexer::new("document\nexecute(python) {\nprint(1)\n"),
+        &arena,
+    );
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::ExecuteIsNotClosedErr)
+    );
+    // prose which uses the word is a text
+    for line in [
+        "execute(this) carefully",
+        "We execute(it) {now}",
+        "execute(see below)",
+    ] {
+        let source = format!("document\n{}\n", line);
+        let mut parser = Parser::new(Lexer::new(&source), &arena);
+        assert_eq!(
+            parser.make_latex_format().unwrap(),
+            format!("\\begin{{document}}\n{}\n\n\\end{{document}}\n", line),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_parse_chapter_import() {
+    let arena = Arena::new();
+    let source = "docclass book\ndocument\nThe chapter import is below.\n\
+                  chapter import \"chapters/intro.ves\"\nchapter import \"outro\"\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        "\\documentclass{book}\n\\begin{document}\nThe chapter import is below.\n\
+         \\include{chapters/intro}\n\\include{outro}\n\n\\end{document}\n"
+    );
+
+    for path in ["../intro.ves", "my intro.ves"] {
+        let source = format!("document\nchapter import \"{}\"\n", path);
+        let mut parser = Parser::new(Lexer::new(&source), &arena);
+        assert_eq!(
+            parser.parse_latex().unwrap_err().err_kind,
+            VestiErrKind::ParseErr(VestiParseErr::InvalidChapterPathErr {
+                path: String::from(path)
+            })
+        );
+    }
+}
+
+#[test]
+fn test_parse_beamer_frame() {
+    let arena = Arena::new();
+    let source = r#"docclass beamer
+document
+frame<2-> "Intro" (fragile, t) {
+    - first
+    pause
+    - second
+}
+frame {
+    We frame it.
+    pause
+}
+pause for a while
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        r#"\documentclass{beamer}
+\begin{document}
+\begin{frame}<2->[fragile,t]{Intro}
+    \begin{itemize}
+\item first
+\pause
+\item second
+\end{itemize}
+\end{frame}
+\begin{frame}
+    We frame it.
+    \pause
+\end{frame}
+pause for a while
+
+\end{document}
+"#
+    );
+
+    let mut parser = Parser::new(Lexer::new("document\n  frame<2-> {\n\\foo\n"), &arena);
+    let err = parser.parse_latex().unwrap_err();
+    assert_eq!(
+        err.err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::BracketNumberMatchedErr)
+    );
+    assert_eq!(err.location.unwrap().start, Location::new(2, 3));
+
+    // prose which starts with `frame` is a text
+    for line in [
+        "frame (see fig) here",
+        "frame <b> of it",
+        "frame \"quoted\" text",
+        "frame<2- {",
+    ] {
+        let source = format!("document\n{}\n", line);
+        let mut parser = Parser::new(Lexer::new(&source), &arena);
+        assert_eq!(
+            parser.make_latex_format().unwrap(),
+            format!("\\begin{{document}}\n{}\n\n\\end{{document}}\n", line),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_parse_heading() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+section "Why vesti?"
+See \ref{sec:why-vesti-2}.
+section "Why vesti"
+subsection* "No label"
+section of a text is not a heading
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        r#"\documentclass{article}
+\begin{document}
+\section{Why vesti?}\label{sec:why-vesti}
+See \ref{sec:why-vesti-2}.
+\section{Why vesti}\label{sec:why-vesti-2}
+\subsection*{No label}
+section of a text is not a heading
+
+\end{document}
+"#
+    );
+    assert!(parser.take_warnings().is_empty());
+
+    let mut parser = Parser::new(Lexer::new("document\nchapter \"Main Result\"\n"), &arena);
+    parser.set_heading_config(&HeadingConfig {
+        separator: String::from("_"),
+        ..HeadingConfig::default()
+    });
+    assert_eq!(
+        nodes(parser.parse_latex().unwrap())[1],
+        Statement::Heading {
+            name: "chapter",
+            title: "Main Result",
+            label: Some("chap:main_result"),
+        }
+    );
+}
+
+#[test]
+fn test_parse_comment() {
+    let arena = Arena::new();
+    let source = "docclass article\n#> preamble\ndocument\n# stripped\n#> kept\nfoo #> why\nbar\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        "\\documentclass{article}\n% preamble\n\\begin{document}\n% kept\nfoo % why\nbar\n\n\
+         \\end{document}\n"
+    );
+}
+
+#[test]
+fn test_parse_argument_count() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+defun pair (#!1#!2) { (#!1, #!2) }
+defun bold { \textbf{bold} }
+defun norm [s O{2} m] { #!3 }
+document
+\pair{a}{b} \bold{} \bold \norm*#[1]{x}
+\pair{a}
+\norm{x}{y}
+#[allow(argument_count)]
+\pair{a}{b}{c}
+\bold{x}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    parser.parse_latex().unwrap();
+    let warnings = parser.take_warnings();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| &warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![
+            &VestiWarnKind::ArgumentCountMismatch {
+                name: String::from("pair"),
+                expected: 2,
+                got: 1,
+            },
+            &VestiWarnKind::ArgumentCountMismatch {
+                name: String::from("norm"),
+                expected: 1,
+                got: 2,
+            },
+            &VestiWarnKind::ArgumentCountMismatch {
+                name: String::from("bold"),
+                expected: 0,
+                got: 1,
+            },
+        ]
+    );
+    assert_eq!(warnings[0].location.unwrap().start.row(), 7);
+
+    // chapters know functions of the main file
+    let mut chapter = Parser::new(Lexer::new("\\pair{a}\n"), &arena);
+    chapter.set_document_context(&parser.document_context());
+    chapter.parse_body().unwrap();
+    assert_eq!(chapter.take_warnings().len(), 1);
+}
+
+#[test]
+fn test_parse_redefinition() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+import xcolor
+defun vec (#!1) { \mathbf{#!1} }
+defun color (#!1) { #!1 }
+defun url (#!1) { \texttt{#!1} }
+defun! d (#!1#!2) { \mathrm{d}#!1 }
+defun? R { \mathbb{R} }
+defun! norm [m O{2}] { \|#!1\|_{#!2} }
+#[allow(redefinition)]
+defun L { \mathcal{L} }
+document
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let output = parser.make_latex_format().unwrap();
+    assert!(output.contains(
+        "\\renewcommand{\\d}[2]{\\mathrm{d}#1}\n\\providecommand{\\R}{\\mathbb{R}}\n\
+         \\RenewDocumentCommand{\\norm}{m O{2}}"
+    ));
+    let warnings = parser.take_warnings();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| &warning.warn_kind)
+            .collect::<Vec<_>>(),
+        vec![
+            &VestiWarnKind::RedefinedCommand {
+                name: String::from("vec"),
+                package: None,
+            },
+            &VestiWarnKind::RedefinedCommand {
+                name: String::from("color"),
+                package: Some(String::from("xcolor")),
+            },
+        ]
+    );
+    assert_eq!(warnings[0].location.unwrap().start.row(), 3);
+
+    let mut parser = Parser::new(Lexer::new("defun! foo (#!2) { }"), &arena);
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::RedefinitionParamsErr)
+    );
+}
+
+#[test]
+fn test_parse_local_definitions() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+begenv center
+    defun half (#!1) { \frac{#!1}{2} }
+    \half{x}
+endenv
+\half{x}{y}
+defun text { x }
+"#;
+    let expected = r#"\documentclass{article}
+\begin{document}
+\begin{center}
+    \def\half#1{\frac{#1}{2}}
+    \half{x}
+\end{center}
+\half{x}{y}
+defun text { x }
+
+\end{document}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+    // the arity of `\half` is not known outside of the environment
+    assert!(parser.take_warnings().is_empty());
+}
+
+#[test]
+fn test_parse_math_font() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+\[ bold{v} in bb{R}^{n}, cal{A}_{frak{g}}, a bold b \text{bb{x}} \]
+"#;
+    let expected = r#"\documentclass{article}
+\usepackage{amsmath}
+\usepackage{amssymb}
+\begin{document}
+\[ \mathbf{v} in \mathbb{R}^{n}, \mathcal{A}_{\mathfrak{g}}, a bold b \text{bb{x}} \]
+
+\end{document}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    let mut parser = Parser::new(Lexer::new("\\( bb{bold{x}} \\)"), &arena);
+    assert_eq!(
+        parser.parse_body().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::NestedMathFontErr {
+            outer: String::from("bb"),
+            inner: String::from("bold"),
+        })
+    );
+}
+
+#[test]
+fn test_parse_quantity() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+document
+g = qty(9.81, m/s^2), qty(1.2(3), kJ/(mol K)) and qty(x
+"#;
+    let expected = r#"\documentclass{article}
+\usepackage{siunitx}
+\begin{document}
+g = \SI{9.81}{\metre\per\second\squared}, \SI{1.2(3)}{\kilo\joule\per\mole\per\kelvin} and qty(x
+
+\end{document}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    let mut parser = Parser::new(Lexer::new("qty(3, m/q)"), &arena);
+    assert_eq!(
+        parser.parse_body().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidUnitErr {
+            unit: String::from("m/q"),
+            part: String::from("q"),
+        })
+    );
+    let mut parser = Parser::new(Lexer::new("qty(3)"), &arena);
+    assert_eq!(
+        parser.parse_body().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidQuantityErr {
+            quantity: String::from("3"),
+        })
+    );
+}
+
+#[test]
+fn test_parse_theorem() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+deftheorem theorem "Theorem" [section]
+deftheorem lemma "Lemma" (theorem)
+deftheorem* remark "Remark"
+document
+theorem "Pythagoras" {
+    \(a^2 + b^2 = c^2\)
+}
+remark { Trivial. }
+The theorem {x} is a text.
+"#;
+    let expected = r#"\documentclass{article}
+\usepackage{amsthm}
+\newtheorem{theorem}{Theorem}[section]
+\newtheorem{lemma}[theorem]{Lemma}
+\newtheorem*{remark}{Remark}
+\begin{document}
+\begin{theorem}[Pythagoras]
+    \(a^2 + b^2 = c^2\)
+\end{theorem}
+\begin{remark} Trivial. \end{remark}
+The theorem {x} is a text.
+
+\end{document}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    // chapters know theorems of the main file
+    let mut chapter = Parser::new(Lexer::new("lemma { x }\n"), &arena);
+    chapter.set_document_context(&parser.document_context());
+    assert_eq!(
+        latex_to_string(&chapter.parse_body().unwrap()),
+        "\\begin{lemma} x \\end{lemma}\n"
+    );
+}
+
+#[test]
+fn test_parse_front_matter() {
+    let arena = Arena::new();
+    let source = r#"docclass article
+title "A \textbf{Vesti} Paper"
+author "Jane Roe" "University of Somewhere"
+author "John Doe"
+date today
+maketitle
+document
+The title "x" is a text.
+"#;
+    let expected = r#"\documentclass{article}
+\title{A \textbf{Vesti} Paper}
+\author{Jane Roe \\ University of Somewhere \and John Doe}
+\date{\today}
+\begin{document}
+\maketitle
+The title "x" is a text.
+
+\end{document}
+"#;
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(expected, parser.make_latex_format().unwrap());
+
+    let source = "date none\ntitle \"A\"\ndate \"2024\"\ndocument\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::DuplicatedMetadataErr {
+            name: String::from("date")
+        })
+    );
+}
+
+#[test]
+fn test_parse_importtex() {
+    let arena = Arena::new();
+    let source = "docclass article\nimporttex \"legacy.tex\" (split)\ndocument\n\
+                  importtex \"tables/results.tex\"\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    let statements: Vec<_> = parser
+        .parse_latex()
+        .unwrap()
+        .into_iter()
+        .filter_map(|stmt| match stmt.node {
+            Statement::ImportTex { path, split, .. } => Some((path, split)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        statements,
+        vec![("legacy.tex", true), ("tables/results.tex", false)]
+    );
+
+    // a file which is not resolved is left to latex
+    let mut parser = Parser::new(Lexer::new("document\nimporttex \"legacy.tex\"\n"), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        "\\begin{document}\n\\input{legacy.tex}\n\n\\end{document}\n"
+    );
+
+    let mut parser = Parser::new(
+        Lexer::new("document\nimporttex \"a.tex\" (inline)\n"),
+        &arena,
+    );
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidImportTexOptionErr {
+            name: String::from("inline")
+        })
+    );
+}
+
+#[test]
+fn test_parse_index() {
+    let arena = Arena::new();
+    let source = "docclass book\ndocument\nGroups index \"group\" and index \"group!abelian\".\n\
+                  The index of a set.\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        "\\documentclass{book}\n\\usepackage{makeidx}\n\\makeindex\n\\begin{document}\n\
+         Groups \\index{group} and \\index{group!abelian}.\nThe index of a set.\n\\printindex\n\
+         \n\\end{document}\n"
+    );
+
+    // the document prints the index by itself
+    let source = "document\nindex \"group\"\n\\printindex\n";
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    assert_eq!(
+        parser.make_latex_format().unwrap(),
+        "\\usepackage{makeidx}\n\\makeindex\n\\begin{document}\n\\index{group}\n\\printindex\n\
+         \n\\end{document}\n"
+    );
+
+    let mut parser = Parser::new(Lexer::new("document\nindex \"group!!abelian\"\n"), &arena);
+    assert_eq!(
+        parser.parse_latex().unwrap_err().err_kind,
+        VestiErrKind::ParseErr(VestiParseErr::InvalidIndexTermErr {
+            term: String::from("group!!abelian")
+        })
+    );
 }
@@ -0,0 +1,109 @@
+// Rendering of a math snippet written in vesti into a png image, so that tools
+// like chat bots can show vesti math. The snippet is put in a `standalone`
+// document whose pdf is cropped to the math, and then `pdftoppm` makes a png.
+// Pdfs of figure documents are cropped and converted by the same tools.
+
+use crate::engine::{EnginePlan, InteractionMode, LatexEngineType};
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use crate::lexer::Lexer;
+use crate::parser::arena::Arena;
+use crate::parser::maker::emit;
+use crate::parser::Parser;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// The snippet is on the last line, so that it can be found in error messages
+pub fn math_document(expr: &str) -> String {
+    format!(
+        "docclass standalone (border=2pt)\nimport amsmath\ndocument\n\\( \\displaystyle {} \\)\n",
+        expr
+    )
+}
+
+pub const PNG_DPI: u32 = 300;
+
+fn convert_err(tool: &str, msg: impl ToString) -> VestiErr {
+    VestiErr {
+        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::ImageConvertErr {
+            tool: tool.to_string(),
+            msg: msg.to_string(),
+        }),
+        location: None,
+    }
+}
+
+fn run_tool(tool: &str, command: &mut Command) -> error::Result<()> {
+    let status = command
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|err| convert_err(tool, err))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(convert_err(tool, status))
+    }
+}
+
+pub fn pdf_to_png(pdf: &Path, png: &Path, dpi: u32) -> error::Result<()> {
+    // `pdftoppm` adds the extension to the output name
+    run_tool(
+        "pdftoppm",
+        Command::new("pdftoppm")
+            .args(["-png", "-singlefile", "-r", &dpi.to_string()])
+            .arg(pdf)
+            .arg(png.with_extension("")),
+    )
+}
+
+// Remove margins around the contents of each page of the pdf
+pub fn crop_pdf(pdf: &Path) -> error::Result<()> {
+    let cropped = pdf.with_extension("crop.pdf");
+    run_tool("pdfcrop", Command::new("pdfcrop").arg(pdf).arg(&cropped))?;
+    fs::rename(&cropped, pdf)?;
+    Ok(())
+}
+
+// `source` is a document made by `math_document`
+pub fn render_math(
+    source: &str,
+    png: &Path,
+    dpi: u32,
+    engine: LatexEngineType,
+) -> error::Result<()> {
+    let arena = Arena::new();
+    let latex = Parser::new(Lexer::new(source), &arena).parse_latex()?;
+
+    let build_dir = env::temp_dir().join(format!("vesti-math-{}", std::process::id()));
+    fs::create_dir_all(&build_dir)?;
+    let tex_file = build_dir.join("math.tex");
+    let rendered = fs::File::create(&tex_file)
+        .and_then(|mut file| emit(&latex, &mut file))
+        .map_err(VestiErr::from)
+        .and_then(|_| EnginePlan::new(engine, InteractionMode::Batchmode, &tex_file, None).run())
+        .and_then(|_| pdf_to_png(&tex_file.with_extension("pdf"), png, dpi));
+    let _ = fs::remove_dir_all(&build_dir);
+
+    rendered
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::maker::latex_to_string;
+
+    #[test]
+    fn test_math_document() {
+        let source = math_document("α → β");
+        let arena = Arena::new();
+        let latex = Parser::new(Lexer::new(&source), &arena)
+            .parse_latex()
+            .unwrap();
+        assert_eq!(
+            latex_to_string(&latex),
+            "\\documentclass[border=2pt]{standalone}\n\\usepackage{amsmath}\n\\begin{document}\n\\( \\displaystyle \\alpha \\to \\beta \\)\n\n\\end{document}\n"
+        );
+    }
+}
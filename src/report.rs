@@ -0,0 +1,147 @@
+// The summary of `vesti run` with several files, which is printed as a table
+// at the end and written as JSON by `--report`. Files which are not compiled
+// because of an interruption are not in the report.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Ok,
+    Error,
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `pad` keeps the width of the table
+        f.pad(match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+        })
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileReport {
+    pub file: PathBuf,
+    pub status: FileStatus,
+    #[serde(rename = "seconds", serialize_with = "as_secs")]
+    pub duration: Duration,
+    // warnings of vesti, not the ones in the log of the engine
+    pub warnings: usize,
+    // the last file made from the source, like the pdf file with `--pdf`
+    pub output: PathBuf,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct BatchReport {
+    pub files: Vec<FileReport>,
+    #[serde(rename = "total_seconds", serialize_with = "as_secs")]
+    pub total: Duration,
+}
+
+fn as_secs<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl BatchReport {
+    pub fn failed(&self) -> impl Iterator<Item = &FileReport> {
+        self.files
+            .iter()
+            .filter(|report| report.status == FileStatus::Error)
+    }
+
+    pub fn write_json(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(path, json + "\n")
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let failed = self.failed().count();
+        writeln!(
+            f,
+            "Compiled {} files in {:.1}s: {} succeeded, {} failed",
+            self.files.len(),
+            self.total.as_secs_f64(),
+            self.files.len() - failed,
+            failed
+        )?;
+        let file_width = self
+            .files
+            .iter()
+            .map(|report| report.file.display().to_string().chars().count())
+            .max()
+            .unwrap_or_default()
+            .max("file".len());
+        write!(
+            f,
+            "  {:<file_width$}  {:<6}  {:>8}  {:>8}  output",
+            "file",
+            "status",
+            "time",
+            "warnings",
+            file_width = file_width
+        )?;
+        for report in &self.files {
+            write!(
+                f,
+                "\n  {:<file_width$}  {:<6}  {:>7.1}s  {:>8}  {}",
+                report.file.display(),
+                report.status,
+                report.duration.as_secs_f64(),
+                report.warnings,
+                match report.status {
+                    FileStatus::Ok => report.output.display().to_string(),
+                    FileStatus::Error => String::from("-"),
+                },
+                file_width = file_width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_batch_report() {
+        let report = BatchReport {
+            files: vec![
+                FileReport {
+                    file: PathBuf::from("a.ves"),
+                    status: FileStatus::Ok,
+                    duration: Duration::from_millis(1500),
+                    warnings: 2,
+                    output: PathBuf::from("a.tex"),
+                },
+                FileReport {
+                    file: PathBuf::from("long_name.ves"),
+                    status: FileStatus::Error,
+                    duration: Duration::from_millis(250),
+                    warnings: 0,
+                    output: PathBuf::from("long_name.tex"),
+                },
+            ],
+            total: Duration::from_secs(2),
+        };
+        assert_eq!(
+            report.to_string(),
+            "Compiled 2 files in 2.0s: 1 succeeded, 1 failed
+  file           status      time  warnings  output
+  a.ves          ok          1.5s         2  a.tex
+  long_name.ves  error       0.2s         0  -"
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["files"][0]["status"], "ok");
+        assert_eq!(json["files"][1]["seconds"], 0.25);
+        assert_eq!(json["total_seconds"], 2.0);
+    }
+}
@@ -0,0 +1,122 @@
+// Cancellation by Ctrl+C. A signal only sets a flag, and every place waiting for
+// an engine checks it and kills the engine, so that no engine keeps running
+// after vesti exits. The second signal exits vesti immediately.
+//
+// On windows, the C runtime installs a handler of the console for these
+// signals, which delivers Ctrl+C as SIGINT and Ctrl+Break as SIGBREAK.
+
+#[cfg(target_os = "windows")]
+use signal_hook::consts::signal::SIGBREAK;
+use signal_hook::consts::signal::{SIGINT, SIGTERM};
+use signal_hook::flag as signal_flag;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const WAIT_INTERVAL: Duration = Duration::from_millis(50);
+// The exit code of a program terminated by SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static REQUESTED: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn requested() -> &'static Arc<AtomicBool> {
+    REQUESTED.get_or_init(|| Arc::new(AtomicBool::new(false)))
+}
+
+// SIGKILL cannot be caught, so it is not registered on any platform
+#[cfg(not(target_os = "windows"))]
+const SIGNALS: [i32; 2] = [SIGINT, SIGTERM];
+#[cfg(target_os = "windows")]
+const SIGNALS: [i32; 3] = [SIGINT, SIGTERM, SIGBREAK];
+
+pub fn register_signals() {
+    for signal in SIGNALS.iter() {
+        // The conditional shutdown must be registered first so that it sees
+        // the flag before the signal sets it.
+        signal_flag::register_conditional_shutdown(
+            *signal,
+            INTERRUPTED_EXIT_CODE,
+            Arc::clone(requested()),
+        )
+        .expect("Undefined behavior happened!");
+        signal_flag::register(*signal, Arc::clone(requested()))
+            .expect("Undefined behavior happened!");
+    }
+}
+
+pub fn is_requested() -> bool {
+    requested().load(Ordering::SeqCst)
+}
+
+pub enum Waited {
+    Exited(ExitStatus),
+    Interrupted,
+    TimedOut,
+}
+
+// Wait for a child process. If the shutdown is requested or the deadline is
+// passed while waiting, the child is killed and reaped.
+pub fn wait_child(child: &mut Child, deadline: Option<Instant>) -> io::Result<Waited> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Waited::Exited(status));
+        }
+        let waited = if is_requested() {
+            Waited::Interrupted
+        } else if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Waited::TimedOut
+        } else {
+            thread::sleep(WAIT_INTERVAL);
+            continue;
+        };
+        // It might exit by itself just before this
+        let _ = child.kill();
+        child.wait()?;
+        return Ok(waited);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::process::Command;
+
+    // A command which exists on every platform vesti supports
+    fn shell(script: &str) -> Command {
+        if cfg!(target_os = "windows") {
+            let mut command = Command::new("cmd");
+            command.args(["/C", script]);
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.args(["-c", script]);
+            command
+        }
+    }
+
+    #[test]
+    fn test_wait_child() {
+        let mut child = shell("exit 3").spawn().unwrap();
+        match wait_child(&mut child, None).unwrap() {
+            Waited::Exited(status) => assert_eq!(status.code(), Some(3)),
+            _ => panic!("the child should exit by itself"),
+        }
+
+        let sleep = if cfg!(target_os = "windows") {
+            "ping -n 30 127.0.0.1 > NUL"
+        } else {
+            "sleep 30"
+        };
+        let mut child = shell(sleep).spawn().unwrap();
+        let started = Instant::now();
+        let deadline = Some(started + Duration::from_millis(100));
+        assert!(matches!(
+            wait_child(&mut child, deadline).unwrap(),
+            Waited::TimedOut
+        ));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,207 @@
+// Snapshot tests of vesti documents. Every `.ves` file under a directory is
+// transpiled and compared with the `.tex` file next to it, and `--bless`
+// writes the generated latex as the new snapshot.
+
+use crate::commands::{output_file_name, CompileOption, FrontEnd};
+use crate::config::VestiConfig;
+use crate::diff;
+use crate::error;
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::pretty_print::pretty_print;
+use crate::exit::CompileFailure;
+use crate::lexer::Lexer;
+use crate::parser::arena::Arena;
+use crate::parser::maker::latex_to_string;
+use crate::parser::Parser;
+use crate::timing::Timings;
+use crate::typography;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DIFF_CONTEXT: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Passed,
+    Blessed,
+    Failed(String),
+}
+
+pub struct SnapshotOption {
+    pub bless: bool,
+    // An external diff tool used instead of the builtin diff
+    pub diff_tool: Option<String>,
+}
+
+// Returns true if every snapshot matches or is blessed
+pub fn run_snapshots(dir: &Path, option: &SnapshotOption) -> bool {
+    let files = match snapshot_sources(dir) {
+        Ok(files) => files,
+        Err(err) => {
+            println!("{}", pretty_print(None, err, Some(dir)));
+            return false;
+        }
+    };
+
+    let (mut passed, mut blessed, mut failed) = (0, 0, 0);
+    for file_name in &files {
+        match run_snapshot(file_name, option) {
+            Outcome::Passed => {
+                passed += 1;
+                println!("test {} ... ok", file_name.display());
+            }
+            Outcome::Blessed => {
+                blessed += 1;
+                println!("test {} ... blessed", file_name.display());
+            }
+            Outcome::Failed(msg) => {
+                failed += 1;
+                println!("test {} ... FAILED", file_name.display());
+                if !msg.is_empty() {
+                    println!("{}", msg);
+                }
+            }
+        }
+    }
+    println!(
+        "\ntest result: {} passed; {} failed; {} blessed",
+        passed, failed, blessed
+    );
+    if failed > 0 && !option.bless {
+        println!("run `vesti test --bless` to update snapshots");
+    }
+
+    failed == 0
+}
+
+fn snapshot_sources(dir: &Path) -> error::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(dir) {
+        let entry = entry.map_err(|_| error::VestiErr {
+            err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::TakeFilesErr),
+            location: None,
+        })?;
+        if entry.path().extension().is_some_and(|ext| ext == "ves") {
+            files.push(entry.into_path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn transpile(file_name: &Path, source: &str) -> Result<String, CompileFailure> {
+    let config = VestiConfig::find(file_name)
+        .map_err(|err| CompileFailure::new(None, err, Some(file_name)))?;
+    // snapshots should not depend on the shell of the machine
+    let option = CompileOption {
+        is_safe: true,
+        is_dry_run: true,
+        ..Default::default()
+    };
+    let mut timings = Timings::new(file_name);
+    let front = FrontEnd::new(file_name, source, &option, &config, &mut timings)?;
+    let arena = Arena::new();
+    let mut parser = Parser::new(Lexer::new(source), &arena);
+    front.configure(&mut parser);
+    let mut latex = parser
+        .parse_latex()
+        .map_err(|err| CompileFailure::new(Some(source), err, Some(file_name)))?;
+    front.expand(&mut latex, &arena, &mut timings)?;
+    if config.smart_typography {
+        typography::smart_typography(&mut latex);
+    }
+    Ok(latex_to_string(&latex))
+}
+
+fn run_snapshot(file_name: &Path, option: &SnapshotOption) -> Outcome {
+    let source = match fs::read_to_string(file_name) {
+        Ok(source) => source,
+        Err(err) => return Outcome::Failed(pretty_print(None, err.into(), Some(file_name))),
+    };
+    let actual = match transpile(file_name, &source) {
+        Ok(actual) => actual,
+        Err(failure) => return Outcome::Failed(failure.msg),
+    };
+
+    let snapshot = output_file_name(file_name);
+    let expected = fs::read_to_string(&snapshot).ok();
+    if expected.as_deref() == Some(actual.as_str()) {
+        return Outcome::Passed;
+    }
+    if option.bless {
+        return match fs::write(&snapshot, actual) {
+            Ok(()) => Outcome::Blessed,
+            Err(err) => Outcome::Failed(pretty_print(None, err.into(), Some(&snapshot))),
+        };
+    }
+
+    let expected = match expected {
+        Some(expected) => expected,
+        None => {
+            return Outcome::Failed(format!("snapshot `{}` does not exist", snapshot.display()))
+        }
+    };
+    match &option.diff_tool {
+        Some(tool) => {
+            let actual_file = env::temp_dir().join(format!(
+                "vesti-snapshot-{}-{}",
+                std::process::id(),
+                snapshot.file_name().unwrap().to_string_lossy()
+            ));
+            let result = fs::write(&actual_file, actual)
+                .map_err(error::VestiErr::from)
+                .and_then(|_| diff::run_diff_tool(tool, &snapshot, &actual_file));
+            let _ = fs::remove_file(&actual_file);
+            match result {
+                Ok(()) => Outcome::Failed(String::new()),
+                Err(err) => Outcome::Failed(pretty_print(None, err, Some(&snapshot))),
+            }
+        }
+        None => Outcome::Failed(diff::render_diff(&expected, &actual, DIFF_CONTEXT)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_run_snapshot() {
+        let dir = env::temp_dir().join(format!("vesti-snapshot-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let file_name = dir.join("nested").join("doc.ves");
+        fs::write(&file_name, "docclass article\ndocument\nHello\n").unwrap();
+
+        let check = SnapshotOption {
+            bless: false,
+            diff_tool: None,
+        };
+        let bless = SnapshotOption {
+            bless: true,
+            diff_tool: None,
+        };
+        assert_eq!(snapshot_sources(&dir).unwrap(), vec![file_name.clone()]);
+        assert!(matches!(
+            run_snapshot(&file_name, &check),
+            Outcome::Failed(_)
+        ));
+        assert_eq!(run_snapshot(&file_name, &bless), Outcome::Blessed);
+        assert_eq!(run_snapshot(&file_name, &check), Outcome::Passed);
+
+        fs::write(&file_name, "docclass article\ndocument\nBye\n").unwrap();
+        match run_snapshot(&file_name, &check) {
+            Outcome::Failed(msg) => assert!(msg.contains("Bye")),
+            outcome => panic!("unexpected {:?}", outcome),
+        }
+
+        // modules are pasted as `vesti run` does
+        fs::write(dir.join("nested").join("defs.ves"), "defun hi { Hi }\n").unwrap();
+        fs::write(&file_name, "import defs.ves as m\ndocument\n\\mhi\n").unwrap();
+        assert_eq!(run_snapshot(&file_name, &bless), Outcome::Blessed);
+        let snapshot = fs::read_to_string(output_file_name(&file_name)).unwrap();
+        assert!(snapshot.contains("\\def\\mhi{Hi}"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
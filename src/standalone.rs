@@ -0,0 +1,111 @@
+// `--standalone` compiles a fragment like a single equation or a TikZ picture
+// which has no preamble. The fragment is parsed as the body of a document, and
+// a minimal preamble from `[standalone]` of `vesti.toml` is put around it, so
+// that the pdf or png of the fragment can be used in slides.
+
+use crate::lexer::token::TokenType;
+use crate::lexer::Lexer;
+use crate::parser::arena::Arena;
+use crate::parser::ast::{Latex, Statement};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StandaloneConfig {
+    /// Document class of fragments, like `standalone` or `article`.
+    pub docclass: String,
+    /// Options of the document class.
+    pub options: Vec<String>,
+    /// Packages imported by every fragment. Packages of commands which the
+    /// fragment uses are imported too.
+    pub packages: Vec<String>,
+}
+
+impl Default for StandaloneConfig {
+    fn default() -> Self {
+        Self {
+            docclass: String::from("standalone"),
+            options: vec![String::from("border=2pt")],
+            packages: vec![String::from("amsmath"), String::from("amssymb")],
+        }
+    }
+}
+
+// A file with `docclass` has its own preamble, so it is compiled as it is
+pub fn is_fragment(source: &str) -> bool {
+    !Lexer::new(source).any(|tok| tok.token.toktype == TokenType::Docclass)
+}
+
+// The document made from the body of a fragment. `missing` are packages which
+// the body needs.
+pub fn wrap_fragment<'a>(
+    body: Latex<'a>,
+    missing: &BTreeSet<String>,
+    config: &StandaloneConfig,
+    arena: &'a Arena,
+) -> Latex<'a> {
+    let options = (!config.options.is_empty()).then(|| {
+        config
+            .options
+            .iter()
+            .map(|option| vec![Statement::MainText(arena.alloc_str(option)).into()])
+            .collect()
+    });
+    let mut packages: Vec<&str> = config.packages.iter().map(String::as_str).collect();
+    for name in missing {
+        if !packages.contains(&name.as_str()) {
+            packages.push(name);
+        }
+    }
+
+    let mut latex: Latex = vec![Statement::DocumentClass {
+        name: arena.alloc_str(&config.docclass),
+        options,
+    }
+    .into()];
+    if !packages.is_empty() {
+        let pkgs = packages
+            .into_iter()
+            .map(|name| {
+                Statement::Usepackage {
+                    name: arena.alloc_str(name),
+                    options: None,
+                }
+                .into()
+            })
+            .collect();
+        latex.push(Statement::MultiUsepackages { pkgs }.into());
+    }
+    latex.push(Statement::DocumentStart.into());
+    latex.extend(body);
+    latex.push(Statement::DocumentEnd.into());
+    latex
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::maker::latex_to_string;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_wrap_fragment() {
+        let source = "begenv tikzpicture\n\\draw (0,0) -- (1,1);\nendenv\n";
+        assert!(is_fragment(source));
+        assert!(!is_fragment("docclass article\ndocument\nfoo\n"));
+
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let body = parser.parse_body().unwrap();
+        let missing = parser.missing_packages(&body);
+        let latex = wrap_fragment(body, &missing, &StandaloneConfig::default(), &arena);
+        assert_eq!(
+            latex_to_string(&latex),
+            "\\documentclass[border=2pt]{standalone}\n\\usepackage{amsmath}\n\
+             \\usepackage{amssymb}\n\\usepackage{tikz}\n\\begin{document}\n\
+             \\begin{tikzpicture}\n\\draw (0,0) -- (1,1);\n\\end{tikzpicture}\n\n\
+             \\end{document}\n"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+// Statistics of a document computed from its syntax tree, which are printed by
+// `vesti stats`. Only the enabled branches of `#if` directives are counted.
+
+use crate::parser::ast::{walk_latex, Latex, MathState, Spanned, Statement};
+use serde::Serialize;
+use std::fmt;
+
+const SECTION_FUNCTIONS: [&str; 6] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+];
+const EQUATION_ENVS: [&str; 5] = ["equation", "align", "gather", "multline", "eqnarray"];
+const TABLE_ENVS: [&str; 3] = ["tabular", "tabularx", "longtable"];
+// Average silent reading speed of technical texts
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct Stats {
+    pub sections: usize,
+    pub equations: usize,
+    pub figures: usize,
+    pub tables: usize,
+    pub definitions: usize,
+    pub raw_latex: usize,
+    pub words: usize,
+    pub reading_minutes: usize,
+}
+
+fn count_words(latex: &[Spanned<Statement>]) -> usize {
+    let mut words = 0;
+    walk_latex(latex, &mut |stmt| {
+        if let Statement::MainText(text) = stmt {
+            words += text
+                .split_whitespace()
+                .filter(|word| word.chars().any(char::is_alphanumeric))
+                .count();
+        }
+    });
+    words
+}
+
+impl Stats {
+    pub fn from_latex(latex: &Latex) -> Self {
+        let mut output = Self::default();
+        // words in math are also counted by `count_words`, so they are subtracted
+        let mut math_words = 0;
+
+        walk_latex(latex, &mut |stmt| match stmt {
+            Statement::LatexFunction { name, .. }
+                if SECTION_FUNCTIONS.contains(&name.trim_end()) =>
+            {
+                output.sections += 1
+            }
+            Statement::Heading { .. } => output.sections += 1,
+            Statement::MathText { state, text } => {
+                if *state == MathState::Inline {
+                    output.equations += 1;
+                }
+                math_words += count_words(text);
+            }
+            Statement::Environment { name, text, .. } => {
+                let name = name.trim_end_matches('*');
+                if EQUATION_ENVS.contains(&name) {
+                    output.equations += 1;
+                    math_words += count_words(text);
+                } else if name == "figure" {
+                    output.figures += 1;
+                } else if TABLE_ENVS.contains(&name) {
+                    output.tables += 1;
+                }
+            }
+            Statement::Figure { .. } => output.figures += 1,
+            Statement::Table { .. } => output.tables += 1,
+            Statement::FunctionDefine { .. } | Statement::EnvironmentDefine { .. } => {
+                output.definitions += 1
+            }
+            Statement::RawLatex(_) => output.raw_latex += 1,
+            _ => {}
+        });
+
+        // the preamble has no words which are read
+        let body_start = latex
+            .iter()
+            .position(|stmt| stmt.node == Statement::DocumentStart)
+            .unwrap_or(0);
+        output.words = count_words(&latex[body_start..]).saturating_sub(math_words);
+        output.reading_minutes = output.words.div_ceil(WORDS_PER_MINUTE);
+        output
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "sections:     {}", self.sections)?;
+        writeln!(f, "equations:    {}", self.equations)?;
+        writeln!(f, "figures:      {}", self.figures)?;
+        writeln!(f, "tables:       {}", self.tables)?;
+        writeln!(f, "definitions:  {}", self.definitions)?;
+        writeln!(f, "raw latex:    {}", self.raw_latex)?;
+        writeln!(f, "words:        {}", self.words)?;
+        write!(f, "reading time: {} min", self.reading_minutes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_document_stats() {
+        let source = r#"docclass article
+import graphicx
+defun pair (#!1#!2) { (#!1, #!2) }
+document
+\section{Intro}
+Vesti makes latex simple.
+\[ x + y = z \]
+begenv equation*
+    a = b
+endenv
+importimg "cat.png" (label fig:cat)
+#-\relax-#
+\subsection{More}
+The end."#;
+        let arena = Arena::new();
+        let latex = Parser::new(Lexer::new(source), &arena)
+            .parse_latex()
+            .unwrap();
+
+        let expected = Stats {
+            sections: 2,
+            equations: 2,
+            figures: 1,
+            tables: 0,
+            definitions: 1,
+            raw_latex: 1,
+            words: 8,
+            reading_minutes: 1,
+        };
+        assert_eq!(expected, Stats::from_latex(&latex));
+    }
+}
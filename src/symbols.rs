@@ -0,0 +1,373 @@
+// Table of math symbols which is searched by `vesti symbols <query>`.
+// Most symbols are written in vesti as they are in latex, but some of them
+// have shorter spellings in math mode.
+
+use crate::parser::ast::{walk_latex_mut, Latex, Statement};
+
+pub struct MathSymbol {
+    pub latex: &'static str,
+    // Spelling in the math mode of vesti if it differs from the latex one
+    pub vesti: Option<&'static str>,
+    pub package: Option<&'static str>,
+    pub description: &'static str,
+}
+
+macro_rules! symbols {
+    ($(($latex: expr, $vesti: expr, $package: expr, $description: expr)),* $(,)?) => {
+        &[$(MathSymbol {
+            latex: $latex,
+            vesti: $vesti,
+            package: $package,
+            description: $description,
+        }),*]
+    };
+}
+
+#[rustfmt::skip]
+const SYMBOLS: &[MathSymbol] = symbols![
+    // Greek letters
+    ("\\alpha", None, None, "greek small letter alpha"),
+    ("\\beta", None, None, "greek small letter beta"),
+    ("\\gamma", None, None, "greek small letter gamma"),
+    ("\\delta", None, None, "greek small letter delta"),
+    ("\\epsilon", None, None, "greek small letter epsilon"),
+    ("\\varepsilon", None, None, "greek small letter epsilon, variant"),
+    ("\\zeta", None, None, "greek small letter zeta"),
+    ("\\eta", None, None, "greek small letter eta"),
+    ("\\theta", None, None, "greek small letter theta"),
+    ("\\vartheta", None, None, "greek small letter theta, variant"),
+    ("\\iota", None, None, "greek small letter iota"),
+    ("\\kappa", None, None, "greek small letter kappa"),
+    ("\\lambda", None, None, "greek small letter lambda"),
+    ("\\mu", None, None, "greek small letter mu"),
+    ("\\nu", None, None, "greek small letter nu"),
+    ("\\xi", None, None, "greek small letter xi"),
+    ("\\pi", None, None, "greek small letter pi"),
+    ("\\varpi", None, None, "greek small letter pi, variant"),
+    ("\\rho", None, None, "greek small letter rho"),
+    ("\\varrho", None, None, "greek small letter rho, variant"),
+    ("\\sigma", None, None, "greek small letter sigma"),
+    ("\\varsigma", None, None, "greek small letter final sigma"),
+    ("\\tau", None, None, "greek small letter tau"),
+    ("\\upsilon", None, None, "greek small letter upsilon"),
+    ("\\phi", None, None, "greek small letter phi"),
+    ("\\varphi", None, None, "greek small letter phi, variant"),
+    ("\\chi", None, None, "greek small letter chi"),
+    ("\\psi", None, None, "greek small letter psi"),
+    ("\\omega", None, None, "greek small letter omega"),
+    ("\\Gamma", None, None, "greek capital letter gamma"),
+    ("\\Delta", None, None, "greek capital letter delta"),
+    ("\\Theta", None, None, "greek capital letter theta"),
+    ("\\Lambda", None, None, "greek capital letter lambda"),
+    ("\\Xi", None, None, "greek capital letter xi"),
+    ("\\Pi", None, None, "greek capital letter pi"),
+    ("\\Sigma", None, None, "greek capital letter sigma"),
+    ("\\Upsilon", None, None, "greek capital letter upsilon"),
+    ("\\Phi", None, None, "greek capital letter phi"),
+    ("\\Psi", None, None, "greek capital letter psi"),
+    ("\\Omega", None, None, "greek capital letter omega"),
+    // Arrows
+    ("\\rightarrow", Some("->"), None, "right arrow"),
+    ("\\leftarrow", Some("<-"), None, "left arrow"),
+    ("\\leftrightarrow", None, None, "left right arrow"),
+    ("\\Rightarrow", None, None, "double right arrow"),
+    ("\\Leftarrow", None, None, "double left arrow"),
+    ("\\Leftrightarrow", None, None, "double left right arrow, if and only if"),
+    ("\\longrightarrow", None, None, "long right arrow"),
+    ("\\longleftarrow", None, None, "long left arrow"),
+    ("\\Longrightarrow", None, None, "long double right arrow"),
+    ("\\Longleftarrow", None, None, "long double left arrow"),
+    ("\\implies", None, Some("amsmath"), "implies, long double right arrow"),
+    ("\\impliedby", None, Some("amsmath"), "implied by, long double left arrow"),
+    ("\\iff", None, None, "if and only if, long double left right arrow"),
+    ("\\mapsto", None, None, "maps to arrow"),
+    ("\\longmapsto", None, None, "long maps to arrow"),
+    ("\\hookrightarrow", None, None, "hook right arrow, inclusion"),
+    ("\\hookleftarrow", None, None, "hook left arrow"),
+    ("\\uparrow", None, None, "up arrow"),
+    ("\\downarrow", None, None, "down arrow"),
+    ("\\updownarrow", None, None, "up down arrow"),
+    ("\\nearrow", None, None, "north east arrow"),
+    ("\\searrow", None, None, "south east arrow"),
+    ("\\nwarrow", None, None, "north west arrow"),
+    ("\\swarrow", None, None, "south west arrow"),
+    ("\\to", None, None, "right arrow, tends to"),
+    ("\\gets", None, None, "left arrow, gets"),
+    ("\\twoheadrightarrow", None, Some("amssymb"), "two head right arrow, surjection"),
+    ("\\rightharpoonup", None, None, "right harpoon up, weak convergence"),
+    ("\\rightleftharpoons", None, None, "right left harpoons, equilibrium"),
+    ("\\xrightarrow", None, Some("amsmath"), "extensible right arrow with a label"),
+    ("\\xleftarrow", None, Some("amsmath"), "extensible left arrow with a label"),
+    // Relations
+    ("\\leq", None, None, "less than or equal to"),
+    ("\\geq", None, None, "greater than or equal to"),
+    ("\\leqslant", None, Some("amssymb"), "less than or slanted equal to"),
+    ("\\geqslant", None, Some("amssymb"), "greater than or slanted equal to"),
+    ("\\neq", None, None, "not equal to"),
+    ("\\ll", None, None, "much less than"),
+    ("\\gg", None, None, "much greater than"),
+    ("\\approx", None, None, "almost equal to, approximately"),
+    ("\\sim", None, None, "similar to, tilde operator"),
+    ("\\simeq", None, None, "similar or equal to"),
+    ("\\cong", None, None, "congruent to, isomorphic"),
+    ("\\equiv", None, None, "identical to, equivalent"),
+    ("\\propto", None, None, "proportional to"),
+    ("\\coloneqq", None, Some("mathtools"), "colon equals, defined as"),
+    ("\\in", None, None, "element of"),
+    ("\\notin", None, None, "not an element of"),
+    ("\\ni", None, None, "contains as member"),
+    ("\\subset", None, None, "subset of"),
+    ("\\supset", None, None, "superset of"),
+    ("\\subseteq", None, None, "subset of or equal to"),
+    ("\\supseteq", None, None, "superset of or equal to"),
+    ("\\subsetneq", None, Some("amssymb"), "subset of with not equal to"),
+    ("\\mid", None, None, "divides, such that"),
+    ("\\nmid", None, Some("amssymb"), "does not divide"),
+    ("\\parallel", None, None, "parallel to"),
+    ("\\perp", None, None, "perpendicular to"),
+    ("\\vdash", None, None, "right tack, proves"),
+    ("\\models", None, None, "models, satisfies"),
+    ("\\prec", None, None, "precedes"),
+    ("\\succ", None, None, "succeeds"),
+    // Binary operators
+    ("\\pm", None, None, "plus minus sign"),
+    ("\\mp", None, None, "minus plus sign"),
+    ("\\times", None, None, "multiplication sign, cross product"),
+    ("\\div", None, None, "division sign"),
+    ("\\cdot", None, None, "dot operator, multiplication"),
+    ("\\ast", None, None, "asterisk operator"),
+    ("\\star", None, None, "star operator"),
+    ("\\circ", None, None, "ring operator, composition"),
+    ("\\bullet", None, None, "bullet operator"),
+    ("\\oplus", None, None, "circled plus, direct sum"),
+    ("\\ominus", None, None, "circled minus"),
+    ("\\otimes", None, None, "circled times, tensor product"),
+    ("\\odot", None, None, "circled dot operator"),
+    ("\\cap", None, None, "intersection"),
+    ("\\cup", None, None, "union"),
+    ("\\setminus", None, None, "set minus, set difference"),
+    ("\\wedge", None, None, "logical and, wedge product"),
+    ("\\vee", None, None, "logical or"),
+    ("\\land", None, None, "logical and"),
+    ("\\lor", None, None, "logical or"),
+    ("\\neg", None, None, "logical not"),
+    ("\\sqcup", None, None, "square union, disjoint union"),
+    ("\\wr", None, None, "wreath product"),
+    // Big operators
+    ("\\sum", None, None, "summation"),
+    ("\\prod", None, None, "product"),
+    ("\\coprod", None, None, "coproduct"),
+    ("\\int", None, None, "integral"),
+    ("\\iint", None, Some("amsmath"), "double integral"),
+    ("\\iiint", None, Some("amsmath"), "triple integral"),
+    ("\\oint", None, None, "contour integral"),
+    ("\\bigcap", None, None, "big intersection"),
+    ("\\bigcup", None, None, "big union"),
+    ("\\bigoplus", None, None, "big circled plus, direct sum"),
+    ("\\bigotimes", None, None, "big circled times, tensor product"),
+    ("\\bigwedge", None, None, "big logical and"),
+    ("\\bigvee", None, None, "big logical or"),
+    // Operators
+    ("\\sin", Some("sin"), None, "sine operator"),
+    ("\\cos", Some("cos"), None, "cosine operator"),
+    ("\\tan", Some("tan"), None, "tangent operator"),
+    ("\\log", Some("log"), None, "logarithm operator"),
+    ("\\ln", Some("ln"), None, "natural logarithm operator"),
+    ("\\exp", Some("exp"), None, "exponential operator"),
+    ("\\lim", Some("lim"), None, "limit operator"),
+    ("\\limsup", Some("limsup"), None, "limit superior operator"),
+    ("\\liminf", Some("liminf"), None, "limit inferior operator"),
+    ("\\max", Some("max"), None, "maximum operator"),
+    ("\\min", Some("min"), None, "minimum operator"),
+    ("\\sup", Some("sup"), None, "supremum operator"),
+    ("\\inf", Some("inf"), None, "infimum operator"),
+    ("\\det", Some("det"), None, "determinant operator"),
+    ("\\ker", Some("ker"), None, "kernel operator"),
+    ("\\gcd", Some("gcd"), None, "greatest common divisor operator"),
+    ("\\operatorname", None, Some("amsmath"), "custom operator name"),
+    // Delimiters
+    ("\\left(", Some("#("), None, "grown left parenthesis"),
+    ("\\right)", Some("#)"), None, "grown right parenthesis"),
+    ("\\left\\{", Some("#{"), None, "grown left brace"),
+    ("\\right\\}", Some("#}"), None, "grown right brace"),
+    ("\\langle", None, None, "left angle bracket"),
+    ("\\rangle", None, None, "right angle bracket"),
+    ("\\lfloor", None, None, "left floor"),
+    ("\\rfloor", None, None, "right floor"),
+    ("\\lceil", None, None, "left ceiling"),
+    ("\\rceil", None, None, "right ceiling"),
+    ("\\lvert", None, Some("amsmath"), "left vertical bar, absolute value"),
+    ("\\rvert", None, Some("amsmath"), "right vertical bar, absolute value"),
+    ("\\lVert", None, Some("amsmath"), "left double vertical bar, norm"),
+    ("\\rVert", None, Some("amsmath"), "right double vertical bar, norm"),
+    // Miscellaneous
+    ("\\infty", None, None, "infinity"),
+    ("\\partial", None, None, "partial differential"),
+    ("\\nabla", None, None, "nabla, gradient"),
+    ("\\forall", None, None, "for all"),
+    ("\\exists", None, None, "there exists"),
+    ("\\nexists", None, Some("amssymb"), "there does not exist"),
+    ("\\emptyset", None, None, "empty set"),
+    ("\\varnothing", None, Some("amssymb"), "empty set, variant"),
+    ("\\therefore", None, Some("amssymb"), "therefore"),
+    ("\\because", None, Some("amssymb"), "because"),
+    ("\\aleph", None, None, "aleph, cardinal number"),
+    ("\\hbar", None, None, "planck constant over two pi"),
+    ("\\ell", None, None, "script small l"),
+    ("\\Re", None, None, "real part"),
+    ("\\Im", None, None, "imaginary part"),
+    ("\\angle", None, None, "angle"),
+    ("\\triangle", None, None, "triangle"),
+    ("\\square", None, Some("amssymb"), "square, end of proof"),
+    ("\\top", None, None, "down tack, top"),
+    ("\\bot", None, None, "up tack, bottom"),
+    ("\\dagger", None, None, "dagger"),
+    ("\\ldots", None, None, "horizontal ellipsis, low dots"),
+    ("\\cdots", None, None, "horizontal ellipsis, centered dots"),
+    ("\\vdots", None, None, "vertical ellipsis, dots"),
+    ("\\ddots", None, None, "diagonal ellipsis, dots"),
+    ("\\prime", None, None, "prime"),
+    ("\\sqrt", None, None, "square root"),
+    ("\\frac", None, None, "fraction"),
+    ("\\binom", None, Some("amsmath"), "binomial coefficient"),
+    // Fonts
+    ("\\mathbb", None, Some("amssymb"), "blackboard bold letters like the real numbers"),
+    ("\\mathcal", None, None, "calligraphic letters"),
+    ("\\mathfrak", None, Some("amssymb"), "fraktur letters"),
+    ("\\mathscr", None, Some("mathrsfs"), "script letters"),
+    ("\\mathbf", None, None, "bold letters"),
+    ("\\boldsymbol", None, Some("amsmath"), "bold symbols"),
+    ("\\mathrm", None, None, "upright roman letters"),
+    // Accents
+    ("\\hat", None, None, "hat accent"),
+    ("\\widehat", None, None, "wide hat accent"),
+    ("\\bar", None, None, "bar accent"),
+    ("\\overline", None, None, "overline"),
+    ("\\tilde", None, None, "tilde accent"),
+    ("\\widetilde", None, None, "wide tilde accent"),
+    ("\\vec", None, None, "vector arrow accent"),
+    ("\\dot", None, None, "dot accent, time derivative"),
+    ("\\ddot", None, None, "double dot accent"),
+    ("\\underbrace", None, None, "brace under an expression"),
+    ("\\overbrace", None, None, "brace over an expression"),
+];
+
+// Unicode characters which can be typed in math mode with their latex macros
+// and packages the macros need
+#[rustfmt::skip]
+const UNICODE_SYMBOLS: &[(char, &str, Option<&str>)] = &[
+    ('α', "\\alpha", None), ('β', "\\beta", None), ('γ', "\\gamma", None),
+    ('δ', "\\delta", None), ('ε', "\\varepsilon", None), ('ϵ', "\\epsilon", None),
+    ('ζ', "\\zeta", None), ('η', "\\eta", None), ('θ', "\\theta", None),
+    ('ϑ', "\\vartheta", None), ('ι', "\\iota", None), ('κ', "\\kappa", None),
+    ('λ', "\\lambda", None), ('μ', "\\mu", None), ('ν', "\\nu", None),
+    ('ξ', "\\xi", None), ('π', "\\pi", None), ('ϖ', "\\varpi", None),
+    ('ρ', "\\rho", None), ('ϱ', "\\varrho", None), ('σ', "\\sigma", None),
+    ('ς', "\\varsigma", None), ('τ', "\\tau", None), ('υ', "\\upsilon", None),
+    ('φ', "\\varphi", None), ('ϕ', "\\phi", None), ('χ', "\\chi", None),
+    ('ψ', "\\psi", None), ('ω', "\\omega", None), ('Γ', "\\Gamma", None),
+    ('Δ', "\\Delta", None), ('Θ', "\\Theta", None), ('Λ', "\\Lambda", None),
+    ('Ξ', "\\Xi", None), ('Π', "\\Pi", None), ('Σ', "\\Sigma", None),
+    ('Υ', "\\Upsilon", None), ('Φ', "\\Phi", None), ('Ψ', "\\Psi", None),
+    ('Ω', "\\Omega", None),
+    ('→', "\\to", None), ('←', "\\gets", None), ('↔', "\\leftrightarrow", None),
+    ('⇒', "\\Rightarrow", None), ('⇐', "\\Leftarrow", None), ('⇔', "\\Leftrightarrow", None),
+    ('↦', "\\mapsto", None), ('↑', "\\uparrow", None), ('↓', "\\downarrow", None),
+    ('⟶', "\\longrightarrow", None), ('⟹', "\\Longrightarrow", None),
+    ('↪', "\\hookrightarrow", None),
+    ('≤', "\\leq", None), ('≥', "\\geq", None), ('≠', "\\neq", None),
+    ('≈', "\\approx", None), ('≡', "\\equiv", None), ('∼', "\\sim", None),
+    ('≃', "\\simeq", None), ('≅', "\\cong", None), ('∝', "\\propto", None),
+    ('≪', "\\ll", None), ('≫', "\\gg", None), ('∈', "\\in", None),
+    ('∉', "\\notin", None), ('∋', "\\ni", None), ('⊂', "\\subset", None),
+    ('⊃', "\\supset", None), ('⊆', "\\subseteq", None), ('⊇', "\\supseteq", None),
+    ('∣', "\\mid", None), ('∥', "\\parallel", None), ('⊥', "\\perp", None),
+    ('⊢', "\\vdash", None), ('⊨', "\\models", None), ('≺', "\\prec", None),
+    ('≻', "\\succ", None),
+    ('±', "\\pm", None), ('∓', "\\mp", None), ('×', "\\times", None),
+    ('÷', "\\div", None), ('·', "\\cdot", None), ('⋅', "\\cdot", None),
+    ('∗', "\\ast", None), ('∘', "\\circ", None), ('∙', "\\bullet", None),
+    ('⊕', "\\oplus", None), ('⊗', "\\otimes", None), ('⊙', "\\odot", None),
+    ('∩', "\\cap", None), ('∪', "\\cup", None), ('∖', "\\setminus", None),
+    ('∧', "\\wedge", None), ('∨', "\\vee", None), ('¬', "\\neg", None),
+    ('∑', "\\sum", None), ('∏', "\\prod", None), ('∐', "\\coprod", None),
+    ('∫', "\\int", None), ('∬', "\\iint", Some("amsmath")), ('∭', "\\iiint", Some("amsmath")),
+    ('∮', "\\oint", None), ('⋂', "\\bigcap", None), ('⋃', "\\bigcup", None),
+    ('⨁', "\\bigoplus", None), ('⨂', "\\bigotimes", None),
+    ('∞', "\\infty", None), ('∂', "\\partial", None), ('∇', "\\nabla", None),
+    ('∀', "\\forall", None), ('∃', "\\exists", None), ('∄', "\\nexists", Some("amssymb")),
+    ('∅', "\\emptyset", None), ('ℵ', "\\aleph", None), ('ℏ', "\\hbar", None),
+    ('ℓ', "\\ell", None), ('ℜ', "\\Re", None), ('ℑ', "\\Im", None),
+    ('∠', "\\angle", None), ('△', "\\triangle", None), ('⊤', "\\top", None),
+    ('†', "\\dagger", None), ('…', "\\ldots", None), ('⋯', "\\cdots", None),
+    ('⋮', "\\vdots", None), ('⋱', "\\ddots", None), ('′', "\\prime", None),
+    ('√', "\\surd", None), ('⟨', "\\langle", None), ('⟩', "\\rangle", None),
+    ('⌊', "\\lfloor", None), ('⌋', "\\rfloor", None), ('⌈', "\\lceil", None),
+    ('⌉', "\\rceil", None),
+    ('ℕ', "\\mathbb{N}", Some("amssymb")), ('ℤ', "\\mathbb{Z}", Some("amssymb")),
+    ('ℚ', "\\mathbb{Q}", Some("amssymb")), ('ℝ', "\\mathbb{R}", Some("amssymb")),
+    ('ℂ', "\\mathbb{C}", Some("amssymb")),
+];
+
+// The latex macro of a unicode character in math mode and the package it needs
+pub fn unicode_symbol(chr: char) -> Option<(&'static str, Option<&'static str>)> {
+    UNICODE_SYMBOLS
+        .iter()
+        .find(|(unicode, _, _)| *unicode == chr)
+        .map(|(_, latex, package)| (*latex, *package))
+}
+
+// Write unicode symbols as they are instead of latex macros. This is used for
+// engines like xelatex or lualatex which can typeset them with `unicode-math`.
+pub fn keep_unicode_math(latex: &mut Latex) {
+    walk_latex_mut(latex, &mut |stmt| {
+        if let Statement::MathSymbol { unicode, .. } = stmt {
+            *stmt = Statement::MainText(unicode);
+        }
+    });
+}
+
+// Whether `\\name` is a math command in the table
+pub fn is_math_command(name: &str) -> bool {
+    SYMBOLS
+        .iter()
+        .any(|symbol| symbol.latex.strip_prefix('\\') == Some(name))
+}
+
+// Symbols whose name or description contains every word of the query
+pub fn search(query: &str) -> Vec<&'static MathSymbol> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    SYMBOLS
+        .iter()
+        .filter(|symbol| {
+            let haystack = format!("{} {}", symbol.latex, symbol.description).to_lowercase();
+            words.iter().all(|word| haystack.contains(word.as_str()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_search_symbols() {
+        let found: Vec<&str> = search("right arrow").iter().map(|s| s.latex).collect();
+        assert!(found.contains(&"\\rightarrow"));
+        assert!(found.contains(&"\\Rightarrow"));
+        assert!(!found.contains(&"\\leftarrow"));
+
+        let found = search("IMPLIES");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].package, Some("amsmath"));
+
+        assert!(search("no such symbol").is_empty());
+    }
+
+    #[test]
+    fn test_unicode_symbol() {
+        assert_eq!(unicode_symbol('α'), Some(("\\alpha", None)));
+        assert_eq!(unicode_symbol('ℝ'), Some(("\\mathbb{R}", Some("amssymb"))));
+        assert_eq!(unicode_symbol('a'), None);
+    }
+}
@@ -0,0 +1,175 @@
+// SyncTeX for vesti files. Engines write SyncTeX data relating the pdf to the
+// generated latex file, and vesti relates latex lines to vesti lines by
+// aligning the two files, since vesti keeps most lines as they are. Lines with
+// the same words are matched first, and the lines between them follow the
+// nearest matched line above.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// How many vesti lines are searched to match a latex line
+const SEARCH_WINDOW: usize = 30;
+
+pub struct SourceMap {
+    // vesti row of each latex line where both start from 1
+    rows: Vec<usize>,
+}
+
+fn words(line: &str) -> Vec<&str> {
+    line.split(|chr: char| !chr.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+impl SourceMap {
+    pub fn new(vesti: &str, latex: &str) -> Self {
+        let vesti_words: Vec<Vec<&str>> = vesti.lines().map(words).collect();
+        let mut anchors: Vec<(usize, usize)> = Vec::new();
+        let mut next_row = 0;
+        for (line, text) in latex.lines().enumerate() {
+            let text_words = words(text);
+            if text_words.is_empty() {
+                continue;
+            }
+            let end = (next_row + SEARCH_WINDOW).min(vesti_words.len());
+            if let Some(offset) = vesti_words[next_row.min(end)..end]
+                .iter()
+                .position(|vesti_words| *vesti_words == text_words)
+            {
+                anchors.push((line, next_row + offset));
+                next_row += offset + 1;
+            }
+        }
+
+        let vesti_len = vesti_words.len().max(1);
+        let rows = (0..latex.lines().count())
+            .map(|line| {
+                let idx = anchors.partition_point(|(anchor, _)| *anchor <= line);
+                let (anchor_line, anchor_row) = match idx {
+                    0 => (0, 0),
+                    _ => anchors[idx - 1],
+                };
+                let limit = anchors.get(idx).map_or(vesti_len, |(_, row)| *row).max(1);
+                (anchor_row + line - anchor_line).min(limit - 1) + 1
+            })
+            .collect();
+
+        Self { rows }
+    }
+
+    pub fn vesti_row(&self, latex_line: usize) -> usize {
+        match latex_line.checked_sub(1).and_then(|idx| self.rows.get(idx)) {
+            Some(row) => *row,
+            None => self.rows.last().copied().unwrap_or(1),
+        }
+    }
+
+    pub fn latex_line(&self, vesti_row: usize) -> usize {
+        self.rows
+            .iter()
+            .position(|row| *row >= vesti_row)
+            .map_or(self.rows.len().max(1), |idx| idx + 1)
+    }
+}
+
+fn synctex_err(msg: impl ToString) -> VestiErr {
+    VestiErr {
+        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::SynctexErr(msg.to_string())),
+        location: None,
+    }
+}
+
+fn run_synctex(args: &[String]) -> error::Result<String> {
+    let output = Command::new("synctex")
+        .args(args)
+        .output()
+        .map_err(synctex_err)?;
+    if !output.status.success() {
+        return Err(synctex_err(String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn field<'a>(output: &'a str, name: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+}
+
+fn source_map_of(tex_file: &Path) -> error::Result<(PathBuf, SourceMap)> {
+    let vesti_file = tex_file.with_extension("ves");
+    let vesti = fs::read_to_string(&vesti_file)?;
+    let latex = fs::read_to_string(tex_file)?;
+    Ok((vesti_file, SourceMap::new(&vesti, &latex)))
+}
+
+// `location` is either `file.tex:line` given by pdf viewers, or
+// `page:x:y:file.pdf` which is resolved by `synctex edit`.
+pub fn edit(location: &str) -> error::Result<(PathBuf, usize)> {
+    let (tex_file, line) = if location.ends_with(".pdf") {
+        let output = run_synctex(&[
+            String::from("edit"),
+            String::from("-o"),
+            location.to_string(),
+        ])?;
+        let input = field(&output, "Input").ok_or_else(|| synctex_err("no input is found"))?;
+        let line = field(&output, "Line").and_then(|line| line.trim().parse().ok());
+        (PathBuf::from(input), line.unwrap_or(1))
+    } else {
+        let (tex_file, line) = location
+            .rsplit_once(':')
+            .and_then(|(file, line)| Some((file, line.parse().ok()?)))
+            .ok_or_else(|| synctex_err(format!("`{}` is not `file.tex:line`", location)))?;
+        (PathBuf::from(tex_file), line)
+    };
+
+    let (vesti_file, map) = source_map_of(&tex_file)?;
+    Ok((vesti_file, map.vesti_row(line)))
+}
+
+// Positions in the pdf like `Page:1` and `x:72.2` which `synctex view` reports
+pub fn view(vesti_file: &Path, row: usize) -> error::Result<Vec<String>> {
+    let tex_file = vesti_file.with_extension("tex");
+    let (_, map) = source_map_of(&tex_file)?;
+    let input = format!("{}:0:{}", map.latex_line(row), tex_file.display());
+    let output = run_synctex(&[
+        String::from("view"),
+        String::from("-i"),
+        input,
+        String::from("-o"),
+        vesti_file.with_extension("pdf").display().to_string(),
+    ])?;
+
+    Ok(output
+        .lines()
+        .filter(|line| {
+            ["Page:", "x:", "y:"]
+                .iter()
+                .any(|name| line.starts_with(name))
+        })
+        .map(String::from)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_source_map() {
+        let vesti = "docclass article\nimport amsmath\n\ndocument\nHello, vesti!\nbegenv center\n    centered text\nendenv\nThe end.\n";
+        let latex = "\\documentclass{article}\n\\usepackage{amsmath}\n\n\\begin{document}\nHello, vesti!\n\\begin{center}\n    centered text\n\\end{center}\nThe end.\n\n\\end{document}\n";
+        let map = SourceMap::new(vesti, latex);
+
+        assert_eq!(map.vesti_row(5), 5);
+        assert_eq!(map.vesti_row(6), 6);
+        assert_eq!(map.vesti_row(7), 7);
+        assert_eq!(map.vesti_row(9), 9);
+        assert_eq!(map.vesti_row(11), 9);
+        assert_eq!(map.latex_line(7), 7);
+        assert_eq!(map.latex_line(9), 9);
+    }
+}
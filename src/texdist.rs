@@ -0,0 +1,309 @@
+// A minimal TeX distribution which vesti installs by `vesti setup-tex`, so that
+// documents can be compiled on a machine without TeX. TinyTeX is downloaded
+// with `curl`, checked against its pinned checksum and unpacked with `tar`
+// into the data directory of vesti. Its binaries are searched after the ones
+// in `PATH`, so an installed TeX wins.
+//
+// Installers of MiKTeX and TeX Live do not always add their binaries to `PATH`,
+// especially on windows, so their default directories are searched before
+// TinyTeX.
+
+use crate::error::err_kind::{VestiCommandUtilErr, VestiErrKind};
+use crate::error::{self, VestiErr};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Every distribution has this, so a directory with it has the engines too
+const KPSEWHICH: &str = "kpsewhich";
+
+// Bump this only after checking that the builtin templates compile with it.
+const TINYTEX_VERSION: &str = "v2024.09";
+const TINYTEX_RELEASES: &str = "https://github.com/rstudio/tinytex-releases/releases/download";
+// SHA-256 of each archive of TINYTEX_VERSION, which should be updated with it.
+// An archive without a checksum here is never unpacked.
+const TINYTEX_SHA256: &[(&str, &str)] = &[];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TexDist {
+    Miktex,
+    TexLive,
+    TinyTex,
+}
+
+impl TexDist {
+    // The command which installs a package of latex
+    pub fn install_command(self, bin_dir: &Path) -> String {
+        match self {
+            Self::Miktex => format!("{} packages install", bin_dir.join("miktex").display()),
+            Self::TexLive | Self::TinyTex => format!("{} install", bin_dir.join("tlmgr").display()),
+        }
+    }
+}
+
+impl std::fmt::Display for TexDist {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Miktex => "MiKTeX",
+            Self::TexLive => "TeX Live",
+            Self::TinyTex => "TinyTeX",
+        })
+    }
+}
+
+// A program in `dir`, which has `.exe` on windows
+fn program_in(dir: &Path, program: &str) -> Option<PathBuf> {
+    let path = dir.join(format!("{}{}", program, env::consts::EXE_SUFFIX));
+    path.is_file().then_some(path)
+}
+
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?).find_map(|dir| program_in(&dir, program))
+}
+
+// TeX Live is installed in a directory of its year like `C:\texlive\2024`.
+// The newest one is used if there are several years.
+fn texlive_bin_dir(root: &Path) -> Option<PathBuf> {
+    let mut years: Vec<PathBuf> = fs::read_dir(root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.len() == 4 && name.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect();
+    years.sort();
+    years.into_iter().rev().find_map(|year| bin_dir(&year))
+}
+
+// Default directories of MiKTeX and TeX Live for this platform
+fn known_bin_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(target_os = "windows") {
+        let miktex_bin = Path::new("MiKTeX").join("miktex").join("bin").join("x64");
+        if let Some(local) = env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local).join("Programs").join(&miktex_bin));
+        }
+        if let Some(programs) = env::var_os("ProgramFiles") {
+            dirs.push(PathBuf::from(programs).join(&miktex_bin));
+        }
+        let mut texlive = env::var_os("SystemDrive").unwrap_or_else(|| "C:".into());
+        texlive.push("\\texlive");
+        dirs.extend(texlive_bin_dir(Path::new(&texlive)));
+    } else {
+        dirs.extend(texlive_bin_dir(Path::new("/usr/local/texlive")));
+        if cfg!(target_os = "macos") {
+            dirs.push(PathBuf::from("/Library/TeX/texbin"));
+        }
+    }
+    dirs
+}
+
+pub fn distribution_of(bin_dir: &Path) -> TexDist {
+    let is_tinytex = tex_dir().is_some_and(|tex_dir| bin_dir.starts_with(tex_dir));
+    if is_tinytex {
+        TexDist::TinyTex
+    } else if program_in(bin_dir, "miktex").is_some() || program_in(bin_dir, "initexmf").is_some() {
+        TexDist::Miktex
+    } else {
+        TexDist::TexLive
+    }
+}
+
+// The binary directory of the distribution which engines are run from. This
+// includes TinyTeX after `use_installed_tex`.
+pub fn active_bin_dir() -> Option<PathBuf> {
+    Some(find_in_path(KPSEWHICH)?.parent()?.to_path_buf())
+}
+
+// The binary directory of an installed distribution which is not TinyTeX of vesti
+pub fn find_installed_tex() -> Option<PathBuf> {
+    active_bin_dir()
+        .into_iter()
+        .chain(known_bin_dirs())
+        .find(|dir| {
+            program_in(dir, KPSEWHICH).is_some() && distribution_of(dir) != TexDist::TinyTex
+        })
+}
+
+fn data_dir() -> Option<PathBuf> {
+    let data_dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ if cfg!(target_os = "windows") => PathBuf::from(env::var_os("APPDATA")?),
+        _ => PathBuf::from(env::var_os("HOME")?)
+            .join(".local")
+            .join("share"),
+    };
+    Some(data_dir.join("vesti"))
+}
+
+fn tex_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("tex"))
+}
+
+fn archive_name() -> String {
+    let extension = if cfg!(target_os = "windows") {
+        "zip"
+    } else if cfg!(target_os = "macos") {
+        "tgz"
+    } else {
+        "tar.gz"
+    };
+    format!("TinyTeX-1-{}.{}", TINYTEX_VERSION, extension)
+}
+
+// TinyTeX has only one directory like `bin/x86_64-linux` for its platform
+fn bin_dir(tex_dir: &Path) -> Option<PathBuf> {
+    let bin_root = fs::read_dir(tex_dir).ok()?.find_map(|entry| {
+        let path = entry.ok()?.path().join("bin");
+        path.is_dir().then_some(path)
+    })?;
+    fs::read_dir(bin_root)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.is_dir())
+}
+
+fn setup_err(step: &str, msg: impl ToString) -> VestiErr {
+    VestiErr {
+        err_kind: VestiErrKind::UtilErr(VestiCommandUtilErr::TexSetupErr {
+            step: step.to_string(),
+            msg: msg.to_string(),
+        }),
+        location: None,
+    }
+}
+
+fn sha256_of(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// A downloaded archive which is not the pinned one is removed
+fn verify_archive(archive: &Path, expected: &str) -> error::Result<()> {
+    let actual = sha256_of(archive);
+    if actual.as_deref().is_ok_and(|actual| actual == expected) {
+        return Ok(());
+    }
+    let _ = fs::remove_file(archive);
+    Err(match actual {
+        Ok(actual) => setup_err(
+            "verify",
+            format!("the checksum is {} but {} is expected", actual, expected),
+        ),
+        Err(err) => setup_err("verify", err),
+    })
+}
+
+fn run(step: &str, command: &mut Command) -> error::Result<()> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(setup_err(step, status)),
+        Err(err) => Err(setup_err(step, err)),
+    }
+}
+
+// Install TinyTeX unless MiKTeX, TeX Live or TinyTeX is already installed, and
+// return the binary directory of the distribution.
+pub fn setup_tex(force: bool) -> error::Result<PathBuf> {
+    let tex_dir = tex_dir().ok_or_else(|| setup_err("find", "there is no home directory"))?;
+    if !force {
+        if let Some(bin_dir) = find_installed_tex().or_else(|| bin_dir(&tex_dir)) {
+            return Ok(bin_dir);
+        }
+    }
+
+    let sha256 = TINYTEX_SHA256
+        .iter()
+        .find(|(name, _)| *name == archive_name())
+        .map(|(_, sha256)| *sha256)
+        .ok_or_else(|| {
+            setup_err(
+                "verify",
+                format!("no checksum is known for {}", archive_name()),
+            )
+        })?;
+    let archive = env::temp_dir().join(archive_name());
+    let url = format!(
+        "{}/{}/{}",
+        TINYTEX_RELEASES,
+        TINYTEX_VERSION,
+        archive_name()
+    );
+    println!("Downloading {}", url);
+    run(
+        "download",
+        Command::new("curl")
+            .args(["--location", "--fail", "--progress-bar", "--output"])
+            .arg(&archive)
+            .arg(&url),
+    )?;
+    verify_archive(&archive, sha256)?;
+
+    if tex_dir.exists() {
+        fs::remove_dir_all(&tex_dir)?;
+    }
+    fs::create_dir_all(&tex_dir)?;
+    println!("Unpacking into {}", tex_dir.display());
+    let unpacked = run(
+        "unpack",
+        Command::new("tar")
+            .arg("-xf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&tex_dir),
+    );
+    let _ = fs::remove_file(&archive);
+    unpacked?;
+
+    bin_dir(&tex_dir).ok_or_else(|| setup_err("unpack", "the archive has no binary directory"))
+}
+
+// Make engines of an installed distribution available to the commands vesti
+// runs if they are not in `PATH`. This should be called before any thread is
+// spawned.
+pub fn use_installed_tex() {
+    if find_in_path(KPSEWHICH).is_some() {
+        return;
+    }
+    let bin_dir = match find_installed_tex().or_else(|| bin_dir(&tex_dir()?)) {
+        Some(bin_dir) => bin_dir,
+        None => return,
+    };
+    let mut paths: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    paths.push(bin_dir);
+    if let Ok(path) = env::join_paths(paths) {
+        env::set_var("PATH", path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify_archive() {
+        let archive = env::temp_dir().join(format!("vesti-texdist-test-{}", std::process::id()));
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        fs::write(&archive, "abc").unwrap();
+        assert!(verify_archive(&archive, sha256).is_ok());
+        assert!(archive.exists());
+
+        fs::write(&archive, "abd").unwrap();
+        assert!(verify_archive(&archive, sha256).is_err());
+        assert!(!archive.exists());
+    }
+}
@@ -0,0 +1,136 @@
+// Time spent in each phase of compiling a file, which `--timing` prints.
+// Lexing is lazy and happens while parsing, so the lexer alone is measured by
+// an extra pass over the source only when the timing is asked.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimingFormat {
+    Text,
+    Json,
+}
+
+impl TimingFormat {
+    // `--timing-json` wins over `--timing`
+    pub fn from_flags(timing: bool, timing_json: bool) -> Option<Self> {
+        match (timing, timing_json) {
+            (_, true) => Some(Self::Json),
+            (true, false) => Some(Self::Text),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Phase {
+    pub name: String,
+    #[serde(rename = "ms", serialize_with = "as_millis")]
+    pub elapsed: Duration,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct Timings {
+    pub file: String,
+    pub phases: Vec<Phase>,
+}
+
+fn as_millis<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(millis(*duration))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+impl Timings {
+    pub fn new(file_name: &Path) -> Self {
+        Self {
+            file: file_name.display().to_string(),
+            phases: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, elapsed: Duration) {
+        self.phases.push(Phase {
+            name: name.into(),
+            elapsed,
+        });
+    }
+
+    pub fn measure<T>(&mut self, name: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let output = f();
+        self.add(name, started.elapsed());
+        output
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|phase| phase.elapsed).sum()
+    }
+
+    pub fn report(&self, format: TimingFormat) -> String {
+        match format {
+            TimingFormat::Text => self.to_string(),
+            TimingFormat::Json => serde_json::json!({
+                "file": self.file,
+                "phases": self.phases,
+                "total_ms": millis(self.total()),
+            })
+            .to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .phases
+            .iter()
+            .map(|phase| phase.name.len())
+            .max()
+            .unwrap_or_default()
+            .max("total".len());
+        writeln!(f, "timing {}", self.file)?;
+        for phase in &self.phases {
+            writeln!(
+                f,
+                "  {:<width$}  {:>10.2} ms",
+                phase.name,
+                millis(phase.elapsed),
+                width = width
+            )?;
+        }
+        write!(
+            f,
+            "  {:<width$}  {:>10.2} ms",
+            "total",
+            millis(self.total()),
+            width = width
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_timing_report() {
+        let mut timings = Timings::new(Path::new("a.ves"));
+        timings.add("parse", Duration::from_micros(1500));
+        timings.add("pdflatex #1", Duration::from_millis(20));
+
+        assert_eq!(
+            timings.report(TimingFormat::Text),
+            "timing a.ves\n  parse              1.50 ms\n  pdflatex #1       20.00 ms\n  total             21.50 ms"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&timings.report(TimingFormat::Json)).unwrap();
+        assert_eq!(json["phases"][1]["name"], "pdflatex #1");
+        assert_eq!(json["phases"][0]["ms"], 1.5);
+        assert_eq!(json["total_ms"], 21.5);
+    }
+}
@@ -0,0 +1,174 @@
+// Typographic forms of texts which `smart_typography = true` of `vesti.toml`
+// turns on. Straight quotes become latex quotes like ``` ``a'' ```, a hyphen
+// between spaces becomes an en dash, and a space before a reference becomes a
+// non-breaking one. Only texts are changed, so math, verbatim environments and
+// arguments like urls or labels are written as they are.
+
+use crate::parser::ast::{Latex, Spanned, Statement, TableRow};
+
+// Functions whose arguments are names or code rather than texts
+const LITERAL_FUNCTIONS: [&str; 14] = [
+    "url",
+    "href",
+    "path",
+    "verb",
+    "label",
+    "input",
+    "include",
+    "includegraphics",
+    "cite",
+    "citep",
+    "citet",
+    "nocite",
+    "hypersetup",
+    "usepackage",
+];
+const REF_FUNCTIONS: [&str; 9] = [
+    "ref", "eqref", "pageref", "autoref", "nameref", "vref", "cref", "Cref", "cpageref",
+];
+// Environments whose contents are math or code
+const LITERAL_ENVS: [&str; 19] = [
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "displaymath",
+    "math",
+    "verbatim",
+    "verbatim*",
+    "Verbatim",
+    "lstlisting",
+    "minted",
+    "comment",
+    "tikzpicture",
+];
+
+pub fn smart_typography(latex: &mut Latex) {
+    transform(latex, &mut None);
+}
+
+fn is_space(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|chr| chr == ' ' || chr == '\t')
+}
+
+// A quote after a space or an opening bracket opens a quotation
+fn opens_quote(prev: Option<char>) -> bool {
+    prev.is_none_or(|chr| chr.is_whitespace() || "([{`~".contains(chr))
+}
+
+// `prev` is the last character written before the statements
+fn transform(latex: &mut [Spanned<Statement>], prev: &mut Option<char>) {
+    for idx in 0..latex.len() {
+        let next = latex.get(idx + 1).map(|stmt| &stmt.node);
+        let next_is_space = matches!(next, Some(Statement::MainText(text)) if is_space(text));
+        let next_is_ref = matches!(
+            next,
+            Some(Statement::LatexFunction { name, .. }) if REF_FUNCTIONS.contains(&name.trim_end())
+        );
+        match &mut latex[idx].node {
+            Statement::MainText(text) => {
+                *text = match *text {
+                    "\"" if opens_quote(*prev) => "``",
+                    "\"" => "''",
+                    "'" if opens_quote(*prev) => "`",
+                    "-" if prev.is_some_and(|chr| chr == ' ') && next_is_space => "--",
+                    text if is_space(text) && next_is_ref => "~",
+                    text => text,
+                };
+                *prev = text.chars().last().or(*prev);
+            }
+            Statement::LatexFunction { name, args } => {
+                let name = name.trim_end();
+                if !LITERAL_FUNCTIONS.contains(&name) && !REF_FUNCTIONS.contains(&name) {
+                    for (_, arg) in args {
+                        transform(arg, prev);
+                    }
+                }
+                *prev = Some('}');
+            }
+            Statement::Environment { name, text, .. } => {
+                if !LITERAL_ENVS.contains(name) {
+                    transform(text, &mut Some('\n'));
+                }
+                *prev = Some('\n');
+            }
+            Statement::List { items, .. } => {
+                for item in items {
+                    transform(item, &mut Some(' '));
+                }
+                *prev = Some('\n');
+            }
+            Statement::Table { rows, .. } => {
+                for row in rows {
+                    if let TableRow::Cells(cells) = row {
+                        for cell in cells {
+                            transform(&mut cell.text, &mut None);
+                        }
+                    }
+                }
+                *prev = Some('\n');
+            }
+            Statement::Frame { body, .. } => {
+                transform(body, &mut Some('\n'));
+                *prev = Some('\n');
+            }
+            Statement::Conditional {
+                enabled,
+                then_branch,
+                else_branch,
+                ..
+            } => transform(if *enabled { then_branch } else { else_branch }, prev),
+            Statement::DocumentStart
+            | Statement::Heading { .. }
+            | Statement::Figure { .. }
+            | Statement::CodeBlock { .. } => *prev = Some('\n'),
+            // math and macros are parts of words
+            _ => *prev = Some('\\'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::arena::Arena;
+    use crate::parser::maker::latex_to_string;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_smart_typography() {
+        let source = r#"docclass article
+document
+"Hello" and 'quoted', it's 1 - 2, see \ref{x}.
+\textbf{"bold"} \(a "b"\) \url{a--"b"}
+begenv verbatim
+"raw"
+endenv
+"#;
+        let arena = Arena::new();
+        let mut parser = Parser::new(Lexer::new(source), &arena);
+        let mut latex = parser.parse_latex().unwrap();
+        smart_typography(&mut latex);
+        assert_eq!(
+            latex_to_string(&latex),
+            r#"\documentclass{article}
+\usepackage{hyperref}
+\begin{document}
+``Hello'' and `quoted', it's 1 -- 2, see~\ref{x}.
+\textbf{``bold''} \(a "b"\) \url{a--"b"}
+\begin{verbatim}
+"raw"
+\end{verbatim}
+
+\end{document}
+"#
+        );
+    }
+}
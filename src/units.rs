@@ -0,0 +1,184 @@
+// Units of quantities like `qty(9.81, m/s^2)` which become macros of siunitx,
+// like `\metre\per\second\squared`. A unit is a product of symbols with
+// optional powers, and `/` divides by the next symbol or a group in
+// parentheses. Units with a backslash are written as they are.
+
+#[rustfmt::skip]
+const UNITS: &[(&str, &str)] = &[
+    ("m", "metre"), ("g", "gram"), ("s", "second"), ("A", "ampere"), ("K", "kelvin"),
+    ("mol", "mole"), ("cd", "candela"), ("Hz", "hertz"), ("N", "newton"), ("Pa", "pascal"),
+    ("J", "joule"), ("W", "watt"), ("C", "coulomb"), ("V", "volt"), ("F", "farad"),
+    ("Ω", "ohm"), ("ohm", "ohm"), ("S", "siemens"), ("Wb", "weber"), ("T", "tesla"),
+    ("H", "henry"), ("lm", "lumen"), ("lx", "lux"), ("Bq", "becquerel"), ("Gy", "gray"),
+    ("Sv", "sievert"), ("kat", "katal"), ("rad", "radian"), ("sr", "steradian"),
+    ("°C", "degreeCelsius"), ("°", "degree"), ("L", "litre"), ("l", "litre"),
+    ("min", "minute"), ("h", "hour"), ("d", "day"), ("t", "tonne"), ("eV", "electronvolt"),
+    ("Da", "dalton"), ("bar", "bar"), ("%", "percent"),
+];
+
+// `da` is before `d` so that the longer one is tried first
+#[rustfmt::skip]
+const PREFIXES: &[(&str, &str)] = &[
+    ("Y", "yotta"), ("Z", "zetta"), ("E", "exa"), ("P", "peta"), ("T", "tera"),
+    ("G", "giga"), ("M", "mega"), ("k", "kilo"), ("h", "hecto"), ("da", "deca"),
+    ("d", "deci"), ("c", "centi"), ("m", "milli"), ("µ", "micro"), ("μ", "micro"),
+    ("u", "micro"), ("n", "nano"), ("p", "pico"), ("f", "femto"), ("a", "atto"),
+    ("z", "zepto"), ("y", "yocto"),
+];
+
+fn find(table: &[(&str, &'static str)], symbol: &str) -> Option<&'static str> {
+    table
+        .iter()
+        .find(|(name, _)| *name == symbol)
+        .map(|(_, macro_name)| *macro_name)
+}
+
+// `km` is `\kilo\metre`. A whole symbol is tried first, so `cd` is candela
+// rather than centiday.
+fn lower_symbol(symbol: &str) -> Option<String> {
+    if let Some(unit) = find(UNITS, symbol) {
+        return Some(format!("\\{}", unit));
+    }
+    PREFIXES.iter().find_map(|(prefix, prefix_name)| {
+        let unit = find(UNITS, symbol.strip_prefix(prefix)?)?;
+        Some(format!("\\{}\\{}", prefix_name, unit))
+    })
+}
+
+fn lower_power(power: i32, unit: &str, output: &mut String) {
+    if power < 0 {
+        output.push_str("\\per");
+    }
+    output.push_str(unit);
+    match power.abs() {
+        1 => {}
+        2 => output.push_str("\\squared"),
+        3 => output.push_str("\\cubed"),
+        power => output.push_str(&format!("\\tothe{{{}}}", power)),
+    }
+}
+
+struct UnitParser<'u> {
+    rest: &'u str,
+}
+
+impl<'u> UnitParser<'u> {
+    fn skip_separators(&mut self) {
+        self.rest = self.rest.trim_start_matches([' ', '*', '.', '·']);
+    }
+
+    fn symbol(&mut self) -> &'u str {
+        let end = self
+            .rest
+            .find(|chr: char| !(chr.is_alphabetic() || "°%".contains(chr)))
+            .unwrap_or(self.rest.len());
+        let (symbol, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        symbol
+    }
+
+    fn power(&mut self) -> Result<i32, String> {
+        let Some(rest) = self.rest.strip_prefix('^') else {
+            return Ok(1);
+        };
+        let rest = rest.trim_start_matches('{');
+        let end = rest
+            .char_indices()
+            .find(|(idx, chr)| !(chr.is_ascii_digit() || (*idx == 0 && *chr == '-')))
+            .map_or(rest.len(), |(idx, _)| idx);
+        let (power, rest) = rest.split_at(end);
+        self.rest = rest.strip_prefix('}').unwrap_or(rest);
+        match power.parse() {
+            Ok(0) | Err(_) => Err(format!("^{}", power)),
+            Ok(power) => Ok(power),
+        }
+    }
+
+    // Factors until the end or `)`. `sign` is -1 inside of a denominator.
+    fn product(&mut self, sign: i32, output: &mut String) -> Result<(), String> {
+        loop {
+            self.skip_separators();
+            if self.rest.is_empty() || self.rest.starts_with(')') {
+                return Ok(());
+            }
+            self.factor(sign, output)?;
+        }
+    }
+
+    fn factor(&mut self, sign: i32, output: &mut String) -> Result<(), String> {
+        let mut sign = sign;
+        if let Some(rest) = self.rest.strip_prefix('/') {
+            self.rest = rest.trim_start();
+            sign = -sign;
+        }
+        if let Some(rest) = self.rest.strip_prefix('(') {
+            self.rest = rest;
+            self.product(sign, output)?;
+            self.rest = self.rest.strip_prefix(')').ok_or("(")?;
+            return Ok(());
+        }
+        let symbol = self.symbol();
+        let unit = lower_symbol(symbol).ok_or_else(|| {
+            if symbol.is_empty() {
+                self.rest
+                    .chars()
+                    .next()
+                    .map(String::from)
+                    .unwrap_or_default()
+            } else {
+                symbol.to_string()
+            }
+        })?;
+        let power = self.power()?;
+        lower_power(sign * power, &unit, output);
+        Ok(())
+    }
+}
+
+// `Err` has the part of the unit which cannot be read
+pub fn lower_unit(unit: &str) -> Result<String, String> {
+    let unit = unit.trim();
+    if unit.contains('\\') {
+        return Ok(unit.to_string());
+    }
+    if unit.is_empty() {
+        return Err(String::new());
+    }
+    let mut parser = UnitParser { rest: unit };
+    let mut output = String::new();
+    parser.product(1, &mut output)?;
+    if !parser.rest.is_empty() {
+        return Err(parser.rest.to_string());
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lower_unit() {
+        assert_eq!(
+            lower_unit("m/s^2").as_deref(),
+            Ok("\\metre\\per\\second\\squared")
+        );
+        assert_eq!(
+            lower_unit("kg*m^2 s^-3").as_deref(),
+            Ok("\\kilo\\gram\\metre\\squared\\per\\second\\cubed")
+        );
+        assert_eq!(
+            lower_unit("J/(mol K)").as_deref(),
+            Ok("\\joule\\per\\mole\\per\\kelvin")
+        );
+        assert_eq!(lower_unit("cd").as_deref(), Ok("\\candela"));
+        assert_eq!(
+            lower_unit("µm^{4}").as_deref(),
+            Ok("\\micro\\metre\\tothe{4}")
+        );
+        assert_eq!(lower_unit("\\metre").as_deref(), Ok("\\metre"));
+        assert_eq!(lower_unit("m/xyz"), Err(String::from("xyz")));
+        assert_eq!(lower_unit("m^x"), Err(String::from("^")));
+        assert_eq!(lower_unit("J/(mol"), Err(String::from("(")));
+    }
+}
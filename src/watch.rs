@@ -0,0 +1,266 @@
+// Continuous compilation of several vesti files.
+// Every watched file shares one cache of content hashes and one pool of
+// workers, so that simultaneous edits are compiled together as a batch.
+
+use crate::commands::{compile_incremental, CompileOption};
+use crate::config::VestiConfig;
+use crate::console::{self, Verbosity};
+use crate::directives::FileOverrides;
+use crate::error::pretty_print::pretty_print;
+use crate::incremental::IncrementalDoc;
+use crate::pool::WorkerPool;
+use crate::shutdown;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Options of `vesti watch`. `vesti run -c` uses the default one.
+#[derive(Clone, Default, Debug)]
+pub struct WatchOption {
+    // A changed file is compiled after it is not changed for this time
+    pub debounce: Duration,
+    // Shell commands run before and after each batch of compilations
+    pub pre_build: Option<String>,
+    pub post_build: Option<String>,
+    // Clear the terminal before each batch
+    pub clear: bool,
+    // The number of files compiled at once
+    pub jobs: usize,
+}
+
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn modified_time(file_name: &Path) -> Option<SystemTime> {
+    file_name.metadata().and_then(|meta| meta.modified()).ok()
+}
+
+// Files of the batch are given by `VESTI_FILES` separated by newlines, and the
+// post-build hook also gets `VESTI_STATUS` which is either `ok` or `error`.
+fn run_hook(name: &str, hook: &str, files: &[PathBuf], status: Option<&str>) {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", hook]);
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.args(["-c", hook]);
+        command
+    };
+    let files: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+    command.env("VESTI_FILES", files.join("\n"));
+    if let Some(status) = status {
+        command.env("VESTI_STATUS", status);
+    }
+    match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => println!("[hook]  {} failed with {}", name, status),
+        Err(err) => println!("[hook]  cannot run {}: {}", name, err),
+    }
+}
+
+// `vesti.toml` of a file with its modified time. The configuration is loaded
+// again if this is changed, including when `vesti.toml` is made or removed.
+type ConfigState = Option<(PathBuf, Option<SystemTime>)>;
+
+fn config_state(file_name: &Path) -> ConfigState {
+    VestiConfig::find_file_of(file_name).map(|path| {
+        let mtime = modified_time(&path);
+        (path, mtime)
+    })
+}
+
+fn load_config(state: &ConfigState) -> crate::error::Result<VestiConfig> {
+    match state {
+        Some((path, _)) => VestiConfig::from_file(path),
+        None => Ok(VestiConfig::default()),
+    }
+}
+
+// Watch files until `is_stopped` returns true. A file is compiled again only if
+// its contents are changed, so saving a file without any change does nothing.
+// Each file has its own option since `--emit` differs by files. If `vesti.toml`
+// is changed, then the files using it are compiled again with the new one.
+pub fn watch<F: Fn() -> bool>(
+    files: Vec<(PathBuf, CompileOption)>,
+    watch_option: WatchOption,
+    is_stopped: F,
+) {
+    let (files, options): (Vec<PathBuf>, Vec<CompileOption>) = files.into_iter().unzip();
+    let options: HashMap<PathBuf, Arc<CompileOption>> = files
+        .iter()
+        .cloned()
+        .zip(options.into_iter().map(Arc::new))
+        .collect();
+    let pool = WorkerPool::new(watch_option.jobs.min(files.len()));
+
+    // content hashes of sources which are compiled last time
+    let cache: Arc<Mutex<HashMap<PathBuf, u64>>> = Arc::new(Mutex::new(HashMap::new()));
+    // syntax trees of the last versions, so that only changed lines are parsed
+    let docs: Arc<Mutex<HashMap<PathBuf, IncrementalDoc>>> = Arc::new(Mutex::new(HashMap::new()));
+    // files which are being compiled now
+    let in_progress: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+    let mut mtimes: HashMap<PathBuf, SystemTime> = HashMap::new();
+    // changed files which are waiting for the debounce time with the time they are changed
+    let mut settling: HashMap<PathBuf, (SystemTime, Instant)> = HashMap::new();
+    let poll_interval = if watch_option.debounce.is_zero() {
+        POLL_INTERVAL
+    } else {
+        POLL_INTERVAL.min(watch_option.debounce.max(MIN_POLL_INTERVAL))
+    };
+    let watch_option = Arc::new(watch_option);
+
+    let mut configs: HashMap<PathBuf, Arc<VestiConfig>> = HashMap::new();
+    let mut config_states: HashMap<PathBuf, ConfigState> = HashMap::new();
+
+    println!("Press Ctrl+C to finish the program.");
+    while !is_stopped() {
+        for file_name in &files {
+            let state = config_state(file_name);
+            if config_states.get(file_name) == Some(&state) {
+                continue;
+            }
+            // An invalid configuration keeps the previous one until it is fixed
+            match load_config(&state) {
+                Ok(config) => {
+                    let is_reloaded = configs
+                        .insert(file_name.clone(), Arc::new(config))
+                        .is_some();
+                    if is_reloaded {
+                        println!("[config] {}", file_name.display());
+                        cache.lock().unwrap().remove(file_name);
+                        docs.lock().unwrap().remove(file_name);
+                        mtimes.remove(file_name);
+                    }
+                }
+                Err(err) => println!("{}", pretty_print(None, err, Some(file_name))),
+            }
+            config_states.insert(file_name.clone(), state);
+        }
+
+        let mut batch: Vec<(PathBuf, String)> = Vec::new();
+        for file_name in &files {
+            let config_loaded = configs.contains_key(file_name);
+            let mtime = modified_time(file_name);
+            if !config_loaded || mtime.is_none() || mtimes.get(file_name) == mtime.as_ref() {
+                continue;
+            }
+            if in_progress.lock().unwrap().contains(file_name) {
+                // Try again at the next poll
+                continue;
+            }
+            let mtime = mtime.unwrap();
+            let changed = settling
+                .entry(file_name.clone())
+                .or_insert((mtime, Instant::now()));
+            if changed.0 != mtime {
+                *changed = (mtime, Instant::now());
+            }
+            if changed.1.elapsed() < watch_option.debounce {
+                continue;
+            }
+            settling.remove(file_name);
+            mtimes.insert(file_name.clone(), mtime);
+
+            let source = match fs::read_to_string(file_name) {
+                Ok(source) => source,
+                Err(err) => {
+                    println!("{}", pretty_print(None, err.into(), Some(file_name)));
+                    continue;
+                }
+            };
+            let hash = content_hash(&source);
+            if cache.lock().unwrap().insert(file_name.clone(), hash) == Some(hash) {
+                continue;
+            }
+            batch.push((file_name.clone(), source));
+        }
+
+        if !batch.is_empty() {
+            if watch_option.clear {
+                print!("\x1b[2J\x1b[H");
+            }
+            if let Some(hook) = &watch_option.pre_build {
+                let files: Vec<PathBuf> = batch.iter().map(|(f, _)| f.clone()).collect();
+                run_hook("pre-build", hook, &files, None);
+            }
+        }
+        if batch.len() > 1 {
+            println!("Compiling {} documents", batch.len());
+        }
+        // The post-build hook runs when the last compilation of the batch finishes
+        let batch_files: Arc<Vec<PathBuf>> =
+            Arc::new(batch.iter().map(|(f, _)| f.clone()).collect());
+        let remaining = Arc::new(AtomicUsize::new(batch.len()));
+        let has_failed = Arc::new(AtomicBool::new(false));
+        for (file_name, source) in batch {
+            in_progress.lock().unwrap().insert(file_name.clone());
+            let option = Arc::clone(&options[&file_name]);
+            let config = Arc::clone(&configs[&file_name]);
+            let in_progress = Arc::clone(&in_progress);
+            let docs = Arc::clone(&docs);
+            let watch_option = Arc::clone(&watch_option);
+            let batch_files = Arc::clone(&batch_files);
+            let remaining = Arc::clone(&remaining);
+            let has_failed = Arc::clone(&has_failed);
+            pool.execute(Box::new(move || {
+                if shutdown::is_requested() {
+                    in_progress.lock().unwrap().remove(&file_name);
+                    return;
+                }
+                let output =
+                    FileOverrides::scan(&source).output_file_name(&file_name, option.target);
+                let started = Instant::now();
+                // A file is compiled by one worker at once, so its tree is taken
+                let mut doc = docs.lock().unwrap().remove(&file_name);
+                let result =
+                    compile_incremental(&file_name, &source, &output, &option, &config, &mut doc);
+                if let Some(doc) = doc {
+                    docs.lock().unwrap().insert(file_name.clone(), doc);
+                }
+                match &result {
+                    Ok(()) if option.verbosity > Verbosity::Quiet => {
+                        console::print_block(&format!("[ok]    {}", file_name.display()))
+                    }
+                    Ok(()) => {}
+                    Err(msg) => {
+                        console::print_block(&format!("[error] {}\n{}", file_name.display(), msg))
+                    }
+                }
+                config
+                    .notify
+                    .notify(&file_name, result.is_ok(), started.elapsed());
+                in_progress.lock().unwrap().remove(&file_name);
+
+                if result.is_err() {
+                    has_failed.store(true, Ordering::SeqCst);
+                }
+                if remaining.fetch_sub(1, Ordering::SeqCst) == 1 {
+                    if let Some(hook) = &watch_option.post_build {
+                        let status = if has_failed.load(Ordering::SeqCst) {
+                            "error"
+                        } else {
+                            "ok"
+                        };
+                        run_hook("post-build", hook, &batch_files, Some(status));
+                    }
+                }
+            }));
+        }
+
+        thread::sleep(poll_interval);
+    }
+}
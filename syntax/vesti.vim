@@ -14,14 +14,24 @@ syn region vestiBrackets       contained extend keepend matchgroup=Bold start=+\
 
 syn keyword vestiKeyword       docclass begenv nextgroup=vestiEnv skipwhite
 syn keyword vestiKeyword       import document endenv mst mnd docstartmode
+syn keyword vestiKeyword       tocdepth secnumdepth maketoc minitoc begtbl endtbl embedfile defun defenv deftheorem maketitle
+syn keyword vestiKeyword       importimg importbib importtex printbib bibstyle batchmode nonstopmode errorstopmode
 syn keyword vestiMathKeyword   mtxt etxt
+syn match   vestiMathKeyword   "\<\(bold\|bb\|cal\|frak\)\ze{" contained
 
+syn match   vestiKeyword         "\<qty\ze("
 syn match   vestiFunction        "\v\\([a-zA-Z\@]+|\$|\\|#)"
 syn match   vestiEnv             "[a-zA-Z_][a-zA-Z0-9_]*" contained
 syn region  vestiComment         start="#" end="$" contains=vestiTodo
 syn region  vestiComment         start="#\*" end="\*#" contains=vestiTodo,@Spell
+syn match   vestiPreProc         "^\s*#\(if\|else\|endif\)\>.*$"
+syn match   vestiPreProc         "^%!.*$"
+syn match   vestiPreProc         "^#\[allow(.*)\]\s*$"
 syn region  vestiVerbatim        start="#-" end="-#"
 syn region  vestiVerbatimInline  start="##-" end="-##"
+syn region  vestiVerbatim        start="\<execute(.*{\s*$" end="^\s*}\s*$"
+syn region  vestiVerbatim        start="\<codeblock\>.*$" end="^\s*endcode\s*$"
+syn region  vestiVerbatim        start="^latex%\+\s*$" end="^\s*%\+end\s*$"
 syn region  vestiTextMath        start="\\(" end="\\)" contains=vestiMathKeyword,vestiFunction
 syn region  vestiInlineMath      start="\\\[" end="\\\]" contains=vestiMathKeyword,vestiFunction
 syn match   vestiSharp           "#!"
@@ -55,6 +65,7 @@ HiLink vestiFunction Identifier
 HiLink vestiComment Comment
 HiLink vestiTodo Todo
 HiLink vestiNumber Number
+HiLink vestiPreProc PreProc
 HiLink vestiVerbatim PreProc 
 HiLink vestiVerbatimInline PreProc 
 hi vestiSharp ctermfg=LightBlue guifg=LightBlue